@@ -0,0 +1,86 @@
+//! A small facade to [tauri-plugin-rstate](https://docs.rs/tauri-plugin-rstate), for
+//! other plugins of the same app.
+//!
+//! A plugin (e.g. a custom sync or notification plugin) depending on this crate can
+//! dispatch actions to the app-wide store and subscribe to its state through the
+//! `AppHandle`, without linking against the full plugin:
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate_client::RstateClientExt;
+//!
+//! let client = app.rstate_client()?;
+//! client.dispatch("NOTIFICATION_SHOWN", Some(serde_json::json!({ "id": id })))?;
+//! let id = client.subscribe(Some("settings.notifications"), Box::new(|enabled| {
+//!     // ...
+//! }))?;
+//! ```
+//!
+//! The rstate plugin registers its client during its own setup: fetch the client
+//! when it is needed, rather than in a setup hook that may run first.
+
+use std::sync::Arc;
+use tauri::{Manager, Runtime};
+
+pub use serde_json::Value as JsonValue;
+
+/// Result type of the [`RstateClient`] methods.
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Called with the subscribed state, or the value at the subscribed key.
+pub type Listener = Box<dyn Fn(&JsonValue) + Send + Sync>;
+
+/// Error of an [`RstateClient`] call.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ClientError {
+    /// The rstate plugin isn't initialized (yet)
+    #[error("rstate plugin is not initialized")]
+    Unavailable,
+
+    /// Error reported by the rstate plugin
+    #[error("{0}")]
+    Rstate(String),
+}
+
+/// Access to the app-wide store of the rstate plugin.
+pub trait RstateClient: Send + Sync {
+    /// Dispatch an action of `kind`, with an optional `payload`, and return the new state.
+    fn dispatch(&self, kind: &str, payload: Option<JsonValue>) -> Result<JsonValue>;
+
+    /// Get the part of the state at `key` (dot notation; empty for the full state).
+    fn get_state(&self, key: &str) -> Result<Option<JsonValue>>;
+
+    /// Call `listener` with the state, or the value at `key`: now, then after every
+    /// change. Returns the subscription id, to pass to [`unsubscribe`](Self::unsubscribe).
+    fn subscribe(&self, key: Option<&str>, listener: Listener) -> Result<u64>;
+
+    /// End a subscription. Returns `false` if there was none with this id.
+    fn unsubscribe(&self, id: u64) -> bool;
+}
+
+/// The client registered by the rstate plugin, as managed state.
+///
+/// Only the rstate plugin creates it; use [`RstateClientExt::rstate_client`].
+pub struct RstateClientHandle(Arc<dyn RstateClient>);
+
+impl RstateClientHandle {
+    /// Wrap the plugin's client, to be managed by the app.
+    pub fn new(client: impl RstateClient + 'static) -> Self {
+        Self(Arc::new(client))
+    }
+}
+
+/// Extensions to [`tauri::App`], [`tauri::AppHandle`] and [`tauri::Window`] to access the
+/// rstate plugin's client.
+pub trait RstateClientExt<R: Runtime> {
+    /// The rstate plugin's client, or [`ClientError::Unavailable`] if the plugin isn't
+    /// initialized.
+    fn rstate_client(&self) -> Result<Arc<dyn RstateClient>>;
+}
+
+impl<R: Runtime, T: Manager<R>> RstateClientExt<R> for T {
+    fn rstate_client(&self) -> Result<Arc<dyn RstateClient>> {
+        self.try_state::<RstateClientHandle>()
+            .map(|handle| handle.0.clone())
+            .ok_or(ClientError::Unavailable)
+    }
+}
@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{JsonValue, RstateError};
+
+/// An action to be dispatched to the state manager.
+///
+/// Actions are the primary way to modify state. Each action has a `kind` (type)
+/// and an optional `payload` containing data for the action.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use tauri_plugin_rstate_core::Action;
+///
+/// // Create a simple action
+/// let action = Action::new("INCREMENT");
+///
+/// // Create an action with a payload
+/// let action = Action::with_payload("ADD_TODO", "Buy groceries")?;
+///
+/// // In a handler, extract the payload
+/// let text: String = action.require_payload()?;
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Action {
+    /// A string label for the action (e.g., "INCREMENT")
+    pub kind: String,
+    /// An optional payload for the action
+    pub payload: Option<JsonValue>,
+    /// Optional metadata about the dispatch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ActionMeta>,
+}
+
+/// Metadata attached to an [`Action`].
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionMeta {
+    /// A correlation id tracing the dispatch end-to-end, e.g. from a frontend click
+    /// through the handler to the resulting state update event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Where the action originated
+    #[serde(default)]
+    pub source: ActionSource,
+    /// A unique id of the dispatch, set by the plugin for actions from the frontend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// When the action was dispatched, in milliseconds since the Unix epoch. Set by
+    /// the plugin for actions from the frontend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Label of the window that dispatched the action, set by the plugin for actions
+    /// from the frontend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+}
+
+/// Where an [`Action`] originated.
+///
+/// The plugin tags actions coming from the webview and from remote clients itself,
+/// so guards can trust the source (see the plugin's `Builder::guard`).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub enum ActionSource {
+    /// Dispatched from Rust code (the default)
+    #[default]
+    Rust,
+    /// Dispatched by a webview through the `dispatch` command
+    Frontend,
+    /// Dispatched by a remote client, e.g. over WebSocket
+    Remote,
+    /// Replayed from a recorded log
+    Replay,
+    /// Dispatched by a schedule, e.g. a delayed action
+    Schedule,
+}
+
+impl Action {
+    /// Create a new action with a kind and no payload
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let action = Action::new("INCREMENT");
+    /// ```
+    pub fn new(kind: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            payload: None,
+            meta: None,
+        }
+    }
+
+    /// Create a new action with a kind and typed payload
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let action = Action::with_payload("SET_COUNT", 42)?;
+    /// let action = Action::with_payload("ADD_TODO", TodoItem { text: "Buy milk".into() })?;
+    /// ```
+    pub fn with_payload<T: Serialize>(kind: impl Into<String>, payload: T) -> crate::Result<Self> {
+        Ok(Self {
+            kind: kind.into(),
+            payload: Some(
+                serde_json::to_value(payload)
+                    .map_err(|e| RstateError::serialization(e.to_string()))?,
+            ),
+            meta: None,
+        })
+    }
+
+    /// Create a new action with a kind and raw JSON payload
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use serde_json::json;
+    /// let action = Action::with_json("SET_CONFIG", json!({"theme": "dark"}));
+    /// ```
+    pub fn with_json(kind: impl Into<String>, payload: JsonValue) -> Self {
+        Self {
+            kind: kind.into(),
+            payload: Some(payload),
+            meta: None,
+        }
+    }
+
+    /// Attach a trace id to the action
+    ///
+    /// The id is visible to handlers, written to the action log and included in the
+    /// resulting [`StateUpdate`](crate::StateUpdate) when update envelopes are enabled.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let action = Action::new("SAVE").with_trace_id("click-42");
+    /// ```
+    #[must_use]
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.meta.get_or_insert_with(ActionMeta::default).trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// Get the trace id of the action, if any
+    pub fn trace_id(&self) -> Option<&str> {
+        self.meta.as_ref()?.trace_id.as_deref()
+    }
+
+    /// Tag the action with its source
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let action = Action::new("RSTATE_RESTORE_SNAPSHOT").with_source(ActionSource::Replay);
+    /// ```
+    #[must_use]
+    pub fn with_source(mut self, source: ActionSource) -> Self {
+        self.meta.get_or_insert_with(ActionMeta::default).source = source;
+        self
+    }
+
+    /// Get the source of the action ([`ActionSource::Rust`] if untagged)
+    pub fn source(&self) -> ActionSource {
+        self.meta
+            .as_ref()
+            .map(|meta| meta.source)
+            .unwrap_or_default()
+    }
+
+    /// Get the id of the dispatch, if any
+    pub fn dispatch_id(&self) -> Option<&str> {
+        self.meta.as_ref()?.id.as_deref()
+    }
+
+    /// Get when the action was dispatched (milliseconds since the Unix epoch), if known
+    pub fn timestamp(&self) -> Option<u64> {
+        self.meta.as_ref()?.timestamp
+    }
+
+    /// Get the label of the window that dispatched the action, if any
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder.on("CLOSE_PANEL", |state, action| {
+    ///     if let Some(label) = action.origin_window() {
+    ///         state.open_panels.remove(label);
+    ///     }
+    ///     Ok(())
+    /// })
+    /// ```
+    pub fn origin_window(&self) -> Option<&str> {
+        self.meta.as_ref()?.window.as_deref()
+    }
+
+    /// Get the payload as a specific type, returning None if missing or invalid
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// if let Some(count) = action.payload_as::<i32>()? {
+    ///     println!("Count: {}", count);
+    /// }
+    /// ```
+    pub fn payload_as<T: DeserializeOwned>(&self) -> crate::Result<Option<T>> {
+        match &self.payload {
+            Some(value) => serde_json::from_value(value.clone())
+                .map(Some)
+                .map_err(|e| RstateError::invalid_payload(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the payload as a specific type, returning an error if missing
+    ///
+    /// This is the most common way to extract a payload in action handlers.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // In an action handler:
+    /// let todo: TodoItem = action.require_payload()?;
+    /// let count: i32 = action.require_payload()?;
+    /// ```
+    pub fn require_payload<T: DeserializeOwned>(&self) -> crate::Result<T> {
+        match &self.payload {
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| RstateError::invalid_payload(e.to_string())),
+            None => Err(RstateError::missing_payload(&self.kind)),
+        }
+    }
+
+    /// Check if the action has a payload
+    pub fn has_payload(&self) -> bool {
+        self.payload.is_some()
+    }
+
+    /// Check if the action matches a specific kind
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// if action.is("INCREMENT") {
+    ///     // handle increment
+    /// }
+    /// ```
+    pub fn is(&self, kind: &str) -> bool {
+        self.kind == kind
+    }
+}
+
+impl From<&str> for Action {
+    fn from(kind: &str) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<String> for Action {
+    fn from(kind: String) -> Self {
+        Self::new(kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_action_payload_helpers() {
+        let action = Action::with_payload("SET_COUNT", 42)
+            .unwrap()
+            .with_trace_id("t-1");
+        assert!(action.is("SET_COUNT"));
+        assert_eq!(action.require_payload::<i32>().unwrap(), 42);
+        assert!(action.payload_as::<String>().is_err());
+        assert_eq!(action.trace_id(), Some("t-1"));
+        assert_eq!(action.source(), ActionSource::Rust);
+
+        // The wire format the frontend sends
+        let action: Action =
+            serde_json::from_value(json!({ "kind": "RESET", "payload": null })).unwrap();
+        assert!(!action.has_payload());
+        assert!(matches!(
+            action.require_payload::<i32>(),
+            Err(RstateError::MissingPayload(kind)) if kind == "RESET"
+        ));
+    }
+}
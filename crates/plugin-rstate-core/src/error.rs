@@ -0,0 +1,171 @@
+use serde::{Serialize, ser::Serializer};
+
+pub type Result<T> = std::result::Result<T, RstateError>;
+
+/// Error type for the rstate plugin.
+///
+/// This enum represents all possible errors that can occur when using the rstate plugin.
+#[derive(Debug, thiserror::Error)]
+pub enum RstateError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Error invoking the plugin's native mobile side
+    #[error("Plugin invoke error: {0}")]
+    PluginInvoke(String),
+
+    /// Generic state-related error
+    #[error("State error: {0}")]
+    State(String),
+
+    /// Error when emitting events
+    #[error("Event emission error: {0}")]
+    Emit(String),
+
+    /// Error during serialization/deserialization
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+
+    /// Action handler not found for the given action kind
+    #[error("Action not found: {0}")]
+    ActionNotFound(String),
+
+    /// Invalid or malformed payload
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(String),
+
+    /// Missing required payload for an action
+    #[error("Missing payload for action: {0}")]
+    MissingPayload(String),
+
+    /// State manager was not registered
+    #[error("State manager not registered")]
+    NotRegistered,
+
+    /// The state manager is still being loaded in the background
+    #[error("State manager is loading")]
+    Loading,
+
+    /// A state manager is already registered
+    #[error("State manager already registered")]
+    AlreadyRegistered,
+
+    /// No store was created for the given window label
+    #[error("No store for window: {0}")]
+    WindowStoreNotFound(String),
+
+    /// Action kind violates the naming policy
+    #[error("Invalid action kind: {0}")]
+    InvalidActionKind(String),
+
+    /// Several handlers were registered for the same action kinds
+    #[error("Duplicate handlers for: {0}")]
+    DuplicateHandlers(String),
+
+    /// Action was rejected by a guard
+    #[error("Action rejected: {0}")]
+    Rejected(String),
+
+    /// The calling window isn't allowed to dispatch the action, by its capabilities
+    #[error("Action forbidden: {0}")]
+    Forbidden(String),
+
+    /// Action payload exceeds the size limit set with the plugin's
+    /// `Builder::max_payload_size`
+    #[error("Payload of {kind} too large: {size} bytes (limit: {limit})")]
+    PayloadTooLarge {
+        kind: String,
+        size: usize,
+        limit: usize,
+    },
+
+    /// Mutex lock was poisoned
+    #[error("Lock poisoned: {0}")]
+    LockPoisoned(String),
+
+    /// An action handler panicked. The state was rolled back.
+    #[error("Handler panicked: {0}")]
+    HandlerPanic(String),
+}
+
+impl RstateError {
+    /// Create a state error with a message
+    pub fn state(msg: impl Into<String>) -> Self {
+        Self::State(msg.into())
+    }
+
+    /// Create an invalid payload error with a message
+    pub fn invalid_payload(msg: impl Into<String>) -> Self {
+        Self::InvalidPayload(msg.into())
+    }
+
+    /// Create a missing payload error for an action
+    pub fn missing_payload(action: impl Into<String>) -> Self {
+        Self::MissingPayload(action.into())
+    }
+
+    /// Create an action not found error
+    pub fn action_not_found(action: impl Into<String>) -> Self {
+        Self::ActionNotFound(action.into())
+    }
+
+    /// Create an error rejecting an action
+    pub fn rejected(msg: impl Into<String>) -> Self {
+        Self::Rejected(msg.into())
+    }
+
+    /// Create an error for an action the calling window isn't allowed to dispatch
+    pub fn forbidden(msg: impl Into<String>) -> Self {
+        Self::Forbidden(msg.into())
+    }
+
+    /// Create an error for an action kind violating the naming policy
+    pub fn invalid_action_kind(msg: impl Into<String>) -> Self {
+        Self::InvalidActionKind(msg.into())
+    }
+
+    /// Create a serialization error
+    pub fn serialization(msg: impl Into<String>) -> Self {
+        Self::Serialization(msg.into())
+    }
+}
+
+// `std::io::Error` isn't `Clone`: a cloned I/O error keeps its kind and message
+impl Clone for RstateError {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Io(err) => Self::Io(std::io::Error::new(err.kind(), err.to_string())),
+            Self::PluginInvoke(msg) => Self::PluginInvoke(msg.clone()),
+            Self::State(msg) => Self::State(msg.clone()),
+            Self::Emit(msg) => Self::Emit(msg.clone()),
+            Self::Serialization(msg) => Self::Serialization(msg.clone()),
+            Self::ActionNotFound(msg) => Self::ActionNotFound(msg.clone()),
+            Self::InvalidPayload(msg) => Self::InvalidPayload(msg.clone()),
+            Self::MissingPayload(msg) => Self::MissingPayload(msg.clone()),
+            Self::NotRegistered => Self::NotRegistered,
+            Self::Loading => Self::Loading,
+            Self::AlreadyRegistered => Self::AlreadyRegistered,
+            Self::WindowStoreNotFound(msg) => Self::WindowStoreNotFound(msg.clone()),
+            Self::InvalidActionKind(msg) => Self::InvalidActionKind(msg.clone()),
+            Self::DuplicateHandlers(msg) => Self::DuplicateHandlers(msg.clone()),
+            Self::Rejected(msg) => Self::Rejected(msg.clone()),
+            Self::Forbidden(msg) => Self::Forbidden(msg.clone()),
+            Self::PayloadTooLarge { kind, size, limit } => Self::PayloadTooLarge {
+                kind: kind.clone(),
+                size: *size,
+                limit: *limit,
+            },
+            Self::LockPoisoned(msg) => Self::LockPoisoned(msg.clone()),
+            Self::HandlerPanic(msg) => Self::HandlerPanic(msg.clone()),
+        }
+    }
+}
+
+impl Serialize for RstateError {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_ref())
+    }
+}
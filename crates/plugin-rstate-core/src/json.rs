@@ -0,0 +1,58 @@
+pub use serde_json::Value as JsonValue;
+
+/// Helper function to get a specific part of the state by key (supports dot notation).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use serde_json::json;
+/// use tauri_plugin_rstate_core::get_state;
+///
+/// let state = json!({
+///     "user": {
+///         "profile": {
+///             "name": "John"
+///         }
+///     }
+/// });
+///
+/// let name = get_state(&state, "user.profile.name");
+/// assert_eq!(name, Some(json!("John")));
+///
+/// // Empty key returns the full state
+/// let full = get_state(&state, "");
+/// assert_eq!(full, Some(state.clone()));
+/// ```
+pub fn get_state(state: &JsonValue, key: &str) -> Option<JsonValue> {
+    if key.is_empty() {
+        return Some(state.clone());
+    }
+
+    // Convert the key to a JSON pointer path (e.g., "theme.is_dark" -> "/theme/is_dark")
+    let pointer_path = format!("/{}", key.replace('.', "/"));
+
+    // Use the built-in JSON pointer to get the value (more efficient)
+    state.pointer(&pointer_path).cloned()
+}
+
+/// Helper function to check if a specific part of the state has changed.
+///
+/// This can be used for targeted updates when you only care about specific fields.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// if state_changed(&old_state, &new_state, "user.settings.theme") {
+///     // Theme changed, update UI
+/// }
+/// ```
+pub fn state_changed(old_state: &JsonValue, new_state: &JsonValue, key: &str) -> bool {
+    let old_value = get_state(old_state, key);
+    let new_value = get_state(new_state, key);
+
+    match (old_value, new_value) {
+        (Some(old), Some(new)) => old != new,
+        (None, None) => false,
+        _ => true, // One exists and the other doesn't, so it changed
+    }
+}
@@ -0,0 +1,34 @@
+//! Types shared by [tauri-plugin-rstate](https://docs.rs/tauri-plugin-rstate) and the
+//! frontends talking to it.
+//!
+//! This crate doesn't depend on Tauri, so it compiles for `wasm32-unknown-unknown`
+//! as well as natively: a frontend written in Rust (e.g. with Leptos or Yew) can
+//! build the same [`Action`]s as the backend, and deserialize the state updates and
+//! patches it receives into the same types, instead of duplicating them.
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate_core::{Action, get_state};
+//!
+//! let action = Action::with_payload("ADD_TODO", "Buy groceries")?;
+//! let name = get_state(&state, "user.profile.name");
+//! ```
+//!
+//! A [`Mirror`] keeps a frontend's copy of the state up to date from the plugin's
+//! events.
+//!
+//! The plugin re-exports the shared types; depend on this crate directly only where
+//! the plugin isn't available.
+
+mod action;
+mod error;
+mod json;
+mod mirror;
+mod patch;
+mod update;
+
+pub use crate::action::{Action, ActionMeta, ActionSource};
+pub use crate::error::{Result, RstateError};
+pub use crate::json::{JsonValue, get_state, state_changed};
+pub use crate::mirror::Mirror;
+pub use crate::patch::{PatchOperation, STATE_PATCH_EVENT, StatePatch, apply_patch, diff};
+pub use crate::update::{Changes, DryRun, STATE_UPDATE_EVENT, StateUpdate, UNCHANGED_KEY};
@@ -0,0 +1,227 @@
+//! A frontend's copy of the app-wide store.
+//!
+//! A frontend written in Rust keeps its copy of the state up to date by feeding the
+//! payloads of the plugin's events to a [`Mirror`]:
+//!
+//! - [`STATE_UPDATE_EVENT`](crate::STATE_UPDATE_EVENT) payloads to
+//!   [`apply_update`](Mirror::apply_update), whether they are bare states,
+//!   [`StateUpdate`](crate::StateUpdate) envelopes or trimmed updates;
+//! - [`STATE_PATCH_EVENT`](crate::STATE_PATCH_EVENT) payloads to
+//!   [`apply_patch`](Mirror::apply_patch);
+//! - the response of the `get_changes_since` command to
+//!   [`apply_changes`](Mirror::apply_changes).
+//!
+//! An update the mirror can't apply (a patch or a trimmed update for a revision it
+//! doesn't hold) is reported, and the frontend resyncs:
+//!
+//! ```rust,ignore
+//! if !mirror.apply_patch(&patch) {
+//!     let since = mirror.resync_since();
+//!     let changes = invoke("plugin:rstate|get_changes_since", json!({ "since": since })).await?;
+//!     mirror.apply_changes(since, changes);
+//! }
+//! ```
+
+use crate::{Changes, JsonValue, StatePatch, UNCHANGED_KEY, apply_patch};
+
+// The fields of a `StateUpdate` envelope
+const ENVELOPE_KEYS: [&str; 4] = ["revision", "state", "traceId", "meta"];
+
+/// A frontend's copy of a store's state, kept up to date from the plugin's updates.
+///
+/// The `apply_*` methods return `false` when the mirror is out of sync and should be
+/// resynced with the `get_changes_since` command; the mirror is left as it was.
+/// Updates for a revision older than the mirror's are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mirror {
+    state: JsonValue,
+    revision: Option<u64>,
+}
+
+impl Mirror {
+    /// Create an empty mirror, to be synced with `get_changes_since`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The mirrored state (`null` until synced)
+    pub fn state(&self) -> &JsonValue {
+        &self.state
+    }
+
+    /// The revision of the mirrored state, if known
+    ///
+    /// Bare state updates (without [update envelopes](crate::StateUpdate)) carry no
+    /// revision.
+    pub fn revision(&self) -> Option<u64> {
+        self.revision
+    }
+
+    /// The revision to pass to `get_changes_since`: the mirror's own, or one the
+    /// plugin never had if unknown, so it answers with the full state
+    pub fn resync_since(&self) -> u64 {
+        self.revision.unwrap_or(u64::MAX)
+    }
+
+    /// Apply the payload of a state update event
+    #[must_use]
+    pub fn apply_update(&mut self, payload: JsonValue) -> bool {
+        let (revision, mut state) = match open_envelope(payload) {
+            Ok(update) => update,
+            Err(state) => {
+                self.state = state;
+                self.revision = None;
+                return true;
+            }
+        };
+        if self.is_stale(revision) {
+            return true;
+        }
+        // Trimmed keys are kept from the revision they are unchanged since
+        if let JsonValue::Object(fields) = &mut state {
+            for (key, value) in fields.iter_mut() {
+                let Some(since) = unchanged_since(value) else {
+                    continue;
+                };
+                match self.state.get(key) {
+                    Some(kept) if self.revision == Some(since) => *value = kept.clone(),
+                    _ => return false,
+                }
+            }
+        }
+        self.state = state;
+        self.revision = Some(revision);
+        true
+    }
+
+    /// Apply the payload of a state patch event
+    #[must_use]
+    pub fn apply_patch(&mut self, patch: &StatePatch) -> bool {
+        if self.is_stale(patch.revision) {
+            return true;
+        }
+        if self.revision.is_none() || self.revision != patch.revision.checked_sub(1) {
+            return false;
+        }
+        if apply_patch(&mut self.state, &patch.patch).is_err() {
+            return false;
+        }
+        self.revision = Some(patch.revision);
+        true
+    }
+
+    /// Apply the response of `get_changes_since`, called with the revision `since`
+    #[must_use]
+    pub fn apply_changes(&mut self, since: u64, changes: Changes) -> bool {
+        if self.is_stale(changes.revision) {
+            return true;
+        }
+        match (changes.state, changes.patch) {
+            (Some(state), _) => self.state = state,
+            (None, Some(patch)) => {
+                // The mirror may have moved on while the changes were requested
+                if self.revision != Some(since) || apply_patch(&mut self.state, &patch).is_err() {
+                    return false;
+                }
+            }
+            (None, None) => return false,
+        }
+        self.revision = Some(changes.revision);
+        true
+    }
+
+    // Whether the mirror already holds `revision`, or a later one
+    fn is_stale(&self, revision: u64) -> bool {
+        self.revision.is_some_and(|known| revision <= known)
+    }
+}
+
+// Split a `StateUpdate` envelope into its revision and state, or give back a bare state
+fn open_envelope(payload: JsonValue) -> Result<(u64, JsonValue), JsonValue> {
+    match payload {
+        JsonValue::Object(mut fields)
+            if fields.contains_key("state")
+                && fields
+                    .keys()
+                    .all(|key| ENVELOPE_KEYS.contains(&key.as_str())) =>
+        {
+            match fields.get("revision").and_then(JsonValue::as_u64) {
+                Some(revision) => Ok((revision, fields.remove("state").unwrap_or_default())),
+                None => Err(JsonValue::Object(fields)),
+            }
+        }
+        payload => Err(payload),
+    }
+}
+
+// The revision of an `{"$unchanged": revision}` sentinel
+fn unchanged_since(value: &JsonValue) -> Option<u64> {
+    let fields = value.as_object().filter(|fields| fields.len() == 1)?;
+    fields.get(UNCHANGED_KEY)?.as_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PatchOperation;
+    use serde_json::json;
+
+    fn patch(revision: u64, counter: u64) -> StatePatch {
+        StatePatch {
+            revision,
+            patch: vec![PatchOperation::Replace {
+                path: "/counter".into(),
+                value: json!(counter),
+            }],
+            trace_id: None,
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn test_mirror_follows_updates_and_patches() {
+        let mut mirror = Mirror::new();
+        assert_eq!(mirror.resync_since(), u64::MAX);
+
+        let update = json!({ "revision": 4, "state": { "counter": 4, "todos": ["a"] } });
+        assert!(mirror.apply_update(update));
+        assert_eq!(mirror.revision(), Some(4));
+
+        // Trimmed keys come from the mirror, if it holds the revision they refer to
+        let trimmed =
+            json!({ "revision": 5, "state": { "counter": 5, "todos": { UNCHANGED_KEY: 4 } } });
+        assert!(mirror.apply_update(trimmed.clone()));
+        assert_eq!(mirror.state(), &json!({ "counter": 5, "todos": ["a"] }));
+        let mut behind = Mirror::new();
+        assert!(behind.apply_update(json!({ "revision": 3, "state": {} })));
+        assert!(!behind.apply_update(trimmed));
+        assert_eq!(behind.revision(), Some(3));
+
+        // Patches apply to the previous revision only; stale ones are ignored
+        assert!(mirror.apply_patch(&patch(6, 6)));
+        assert!(mirror.apply_patch(&patch(5, 0)));
+        assert!(!mirror.apply_patch(&patch(8, 8)));
+        assert_eq!(mirror.state(), &json!({ "counter": 6, "todos": ["a"] }));
+        assert_eq!(mirror.resync_since(), 6);
+
+        let changes = Changes {
+            revision: 8,
+            patch: Some(vec![PatchOperation::Add {
+                path: "/todos/-".into(),
+                value: json!("b"),
+            }]),
+            state: None,
+        };
+        assert!(!mirror.apply_changes(5, changes.clone()));
+        assert!(mirror.apply_changes(6, changes));
+        assert_eq!(
+            mirror.state(),
+            &json!({ "counter": 6, "todos": ["a", "b"] })
+        );
+
+        // A bare state has no revision, even if it looks a bit like an envelope
+        let bare = json!({ "state": "idle", "revision": 2, "user": null });
+        assert!(mirror.apply_update(bare.clone()));
+        assert_eq!((mirror.state(), mirror.revision()), (&bare, None));
+    }
+}
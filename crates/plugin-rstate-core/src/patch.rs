@@ -0,0 +1,232 @@
+//! JSON Patch updates.
+//!
+//! With the plugin's `Builder::emit_patches` enabled, a dispatch
+//! that changed the app-wide state emits a [`StatePatch`] on [`STATE_PATCH_EVENT`]
+//! instead of the full state. The patch is an [RFC 6902](https://www.rfc-editor.org/rfc/rfc6902)
+//! document transforming the state of the previous revision into the new one:
+//!
+//! ```json
+//! { "revision": 42, "patch": [{ "op": "replace", "path": "/counter", "value": 42 }] }
+//! ```
+//!
+//! A window holding the state of revision 41 applies the patch; any other window
+//! should fetch the full state with `get_initial_state`. The full state is still
+//! emitted on the regular update event when a patch wouldn't be smaller, for
+//! coalesced and forced updates, and for window stores.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{JsonValue, Result, RstateError};
+
+/// Event name used for state patches.
+pub const STATE_PATCH_EVENT: &str = "rstate://state-patch";
+
+/// A single RFC 6902 operation.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    /// Add `value` at `path`
+    Add { path: String, value: JsonValue },
+    /// Remove the value at `path`
+    Remove { path: String },
+    /// Replace the value at `path` with `value`
+    Replace { path: String, value: JsonValue },
+}
+
+impl PatchOperation {
+    /// The JSON pointer the operation applies at
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Add { path, .. } | Self::Remove { path } | Self::Replace { path, .. } => path,
+        }
+    }
+}
+
+/// Payload of [`STATE_PATCH_EVENT`] events.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StatePatch {
+    /// Revision of the store after this patch; it applies to the state of `revision - 1`
+    pub revision: u64,
+    /// The operations, in order
+    pub patch: Vec<PatchOperation>,
+    /// Trace id of the action that caused the update, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Metadata of the action that caused the update, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<crate::ActionMeta>,
+}
+
+/// Compute the operations transforming `old` into `new`.
+///
+/// Applying the operations to `old`, in order, gives `new`.
+pub fn diff(old: &JsonValue, new: &JsonValue) -> Vec<PatchOperation> {
+    let mut patch = Vec::new();
+    diff_at(&mut String::new(), old, new, &mut patch);
+    patch
+}
+
+/// Apply the operations of a patch to `state`, in order.
+///
+/// Fails, leaving `state` untouched, if an operation doesn't apply (e.g. it removes a
+/// missing value): `state` isn't the state the patch was computed from.
+pub fn apply_patch(state: &mut JsonValue, patch: &[PatchOperation]) -> Result<()> {
+    let mut patched = state.clone();
+    for operation in patch {
+        apply_operation(&mut patched, operation).ok_or_else(|| {
+            RstateError::state(format!("patch doesn't apply at {:?}", operation.path()))
+        })?;
+    }
+    *state = patched;
+    Ok(())
+}
+
+fn apply_operation(state: &mut JsonValue, operation: &PatchOperation) -> Option<()> {
+    let Some((parent, segment)) = operation.path().rsplit_once('/') else {
+        // The empty pointer targets the whole document
+        match operation {
+            PatchOperation::Add { value, .. } | PatchOperation::Replace { value, .. } => {
+                *state = value.clone();
+                return Some(());
+            }
+            PatchOperation::Remove { .. } => return None,
+        }
+    };
+    let key = segment.replace("~1", "/").replace("~0", "~");
+    match (state.pointer_mut(parent)?, operation) {
+        (JsonValue::Object(fields), PatchOperation::Add { value, .. }) => {
+            fields.insert(key, value.clone());
+        }
+        (JsonValue::Object(fields), PatchOperation::Replace { value, .. }) => {
+            *fields.get_mut(&key)? = value.clone();
+        }
+        (JsonValue::Object(fields), PatchOperation::Remove { .. }) => {
+            fields.remove(&key)?;
+        }
+        (JsonValue::Array(items), PatchOperation::Add { value, .. }) => {
+            let index = match key.as_str() {
+                "-" => items.len(),
+                index => index.parse().ok().filter(|index| *index <= items.len())?,
+            };
+            items.insert(index, value.clone());
+        }
+        (JsonValue::Array(items), PatchOperation::Replace { value, .. }) => {
+            *items.get_mut(key.parse::<usize>().ok()?)? = value.clone();
+        }
+        (JsonValue::Array(items), PatchOperation::Remove { .. }) => {
+            let index = key.parse().ok().filter(|index| *index < items.len())?;
+            items.remove(index);
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+fn diff_at(path: &mut String, old: &JsonValue, new: &JsonValue, patch: &mut Vec<PatchOperation>) {
+    match (old, new) {
+        (JsonValue::Object(old), JsonValue::Object(new)) => {
+            for (key, old_value) in old {
+                with_segment(path, key, |path| match new.get(key) {
+                    Some(new_value) => diff_at(path, old_value, new_value, patch),
+                    None => patch.push(PatchOperation::Remove { path: path.clone() }),
+                });
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    with_segment(path, key, |path| {
+                        patch.push(PatchOperation::Add {
+                            path: path.clone(),
+                            value: new_value.clone(),
+                        })
+                    });
+                }
+            }
+        }
+        (JsonValue::Array(old), JsonValue::Array(new)) => {
+            for (index, (old_value, new_value)) in old.iter().zip(new).enumerate() {
+                with_segment(path, &index.to_string(), |path| {
+                    diff_at(path, old_value, new_value, patch)
+                });
+            }
+            // Remove from the end, so the indices of the remaining items stay valid
+            for index in (new.len()..old.len()).rev() {
+                with_segment(path, &index.to_string(), |path| {
+                    patch.push(PatchOperation::Remove { path: path.clone() })
+                });
+            }
+            for (index, new_value) in new.iter().enumerate().skip(old.len()) {
+                with_segment(path, &index.to_string(), |path| {
+                    patch.push(PatchOperation::Add {
+                        path: path.clone(),
+                        value: new_value.clone(),
+                    })
+                });
+            }
+        }
+        (old, new) if old != new => patch.push(PatchOperation::Replace {
+            path: path.clone(),
+            value: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+// Run `f` with `segment` appended to the JSON pointer `path`
+fn with_segment(path: &mut String, segment: &str, f: impl FnOnce(&mut String)) {
+    let len = path.len();
+    path.push('/');
+    path.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    f(path);
+    path.truncate(len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_produces_rfc6902_operations() {
+        let old =
+            json!({ "counter": 1, "todos": ["a", "b", "c"], "a/b": { "x": 1 }, "gone": true });
+        let new = json!({ "counter": 2, "todos": ["a"], "a/b": { "x": 1, "y": 2 }, "added": null });
+
+        let patch = serde_json::to_value(diff(&old, &new)).unwrap();
+        assert_eq!(
+            patch,
+            json!([
+                { "op": "add", "path": "/a~1b/y", "value": 2 },
+                { "op": "replace", "path": "/counter", "value": 2 },
+                { "op": "remove", "path": "/gone" },
+                { "op": "remove", "path": "/todos/2" },
+                { "op": "remove", "path": "/todos/1" },
+                { "op": "add", "path": "/added", "value": null }
+            ])
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_apply_patch_reverses_diff() {
+        let old =
+            json!({ "counter": 1, "todos": ["a", "b", "c"], "a/b": { "x": 1 }, "gone": true });
+        let new =
+            json!({ "counter": 2, "todos": ["a", "d"], "a/b": { "x": 1, "y": 2 }, "added": null });
+
+        let mut state = old.clone();
+        apply_patch(&mut state, &diff(&old, &new)).unwrap();
+        assert_eq!(state, new);
+
+        // A patch for another state fails and leaves the state as is
+        let remove = PatchOperation::Remove {
+            path: "/todos/5".into(),
+        };
+        let add = PatchOperation::Add {
+            path: "/todos/-".into(),
+            value: json!("e"),
+        };
+        assert!(apply_patch(&mut state, &[add, remove]).is_err());
+        assert_eq!(state, new);
+    }
+}
@@ -0,0 +1,126 @@
+//! State update payloads.
+//!
+//! The plugin emits the state of the app-wide store on [`STATE_UPDATE_EVENT`]: the
+//! bare state, or a [`StateUpdate`] envelope carrying the store's revision and the
+//! metadata of the action that caused it. A trimmed update replaces each unchanged
+//! top-level key by a sentinel object:
+//!
+//! ```json
+//! { "revision": 42, "state": { "todos": { "$unchanged": 41 }, "counter": 42 } }
+//! ```
+//!
+//! meaning "`todos` is identical to its value at revision 41".
+
+use serde::{Deserialize, Serialize};
+
+use crate::JsonValue;
+use crate::patch::PatchOperation;
+
+/// Event name used for state updates.
+pub const STATE_UPDATE_EVENT: &str = "rstate://state-update";
+
+/// Key of the sentinel object replacing unchanged subtrees in trimmed updates.
+pub const UNCHANGED_KEY: &str = "$unchanged";
+
+/// Payload of state update events when update envelopes or trimming are enabled
+/// (the plugin's `Builder::envelope_updates` and `Builder::trim_unchanged`).
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StateUpdate {
+    /// Revision of the store after this update
+    pub revision: u64,
+    /// The updated state (with unchanged top-level subtrees replaced by sentinels when trimming)
+    pub state: JsonValue,
+    /// Trace id of the action that caused the update, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Metadata of the action that caused the update, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<crate::ActionMeta>,
+}
+
+/// Changes bringing a window from a known revision up to date.
+///
+/// Exactly one of `patch` and `state` is set.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Changes {
+    /// The current revision of the store
+    pub revision: u64,
+    /// The operations turning the state of the known revision into the current one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch: Option<Vec<PatchOperation>>,
+    /// The full state, when the state of the known revision is no longer available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<JsonValue>,
+}
+
+/// Result of a dry-run dispatch: the state the action would produce, without
+/// modifying the store.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRun {
+    /// The state the action would produce
+    pub state: JsonValue,
+    /// The operations turning the current state into it
+    pub diff: Vec<PatchOperation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActionMeta, ActionSource};
+    use serde_json::json;
+
+    #[test]
+    fn test_updates_round_trip_through_json() {
+        let update = StateUpdate {
+            revision: 42,
+            state: json!({ "todos": { UNCHANGED_KEY: 41 }, "counter": 42 }),
+            trace_id: Some("click-42".into()),
+            meta: Some(ActionMeta {
+                source: ActionSource::Frontend,
+                window: Some("main".into()),
+                ..ActionMeta::default()
+            }),
+        };
+        let json = serde_json::to_value(&update).unwrap();
+        assert_eq!(json["traceId"], "click-42");
+        assert_eq!(json["meta"]["source"], "frontend");
+        assert_eq!(serde_json::from_value::<StateUpdate>(json).unwrap(), update);
+
+        let changes: Changes = serde_json::from_value(json!({
+            "revision": 45,
+            "patch": [{ "op": "replace", "path": "/counter", "value": 45 }]
+        }))
+        .unwrap();
+        assert_eq!(
+            changes.patch,
+            Some(vec![PatchOperation::Replace {
+                path: "/counter".into(),
+                value: json!(45)
+            }])
+        );
+        assert_eq!(changes.state, None);
+    }
+
+    #[test]
+    fn test_dry_runs_carry_a_json_patch() {
+        let old = json!({ "counter": 1, "draft": "hi" });
+        let state = json!({ "counter": 2 });
+        let dry_run = DryRun {
+            diff: crate::diff(&old, &state),
+            state,
+        };
+        assert_eq!(
+            serde_json::to_value(&dry_run).unwrap(),
+            json!({
+                "state": { "counter": 2 },
+                "diff": [
+                    { "op": "replace", "path": "/counter", "value": 2 },
+                    { "op": "remove", "path": "/draft" }
+                ]
+            })
+        );
+    }
+}
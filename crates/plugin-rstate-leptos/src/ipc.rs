@@ -0,0 +1,61 @@
+//! Calls to the Tauri IPC, through the `__TAURI_INTERNALS__` the webview always has
+//! (`withGlobalTauri` isn't needed).
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::json;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+
+use crate::{Error, Result};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = __TAURI_INTERNALS__, js_name = invoke, catch)]
+    async fn tauri_invoke(cmd: &str, args: JsValue) -> std::result::Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_namespace = __TAURI_INTERNALS__, js_name = transformCallback)]
+    fn transform_callback(callback: &js_sys::Function, once: bool) -> u32;
+}
+
+// Invoke the command `cmd`, deserializing its response
+pub(crate) async fn invoke<T: DeserializeOwned>(cmd: &str, args: impl Serialize) -> Result<T> {
+    let response = tauri_invoke(cmd, to_js(&args)?)
+        .await
+        .map_err(|e| Error::Rstate(describe(&e)))?;
+    from_js(&response)
+}
+
+// Call `handler` with the payload of every `event` emitted to this webview, for as
+// long as the page lives
+pub(crate) async fn listen(event: &str, handler: impl Fn(JsValue) + 'static) -> Result<()> {
+    let callback = Closure::<dyn Fn(JsValue)>::new(move |event: JsValue| {
+        // The callback gets `{ event, id, payload }`
+        if let Ok(payload) = js_sys::Reflect::get(&event, &JsValue::from_str("payload")) {
+            handler(payload);
+        }
+    });
+    let handler = transform_callback(callback.as_ref().unchecked_ref(), false);
+    callback.forget();
+    let args = json!({ "event": event, "target": { "kind": "Any" }, "handler": handler });
+    invoke::<u32>("plugin:event|listen", args).await?;
+    Ok(())
+}
+
+fn to_js(value: &impl Serialize) -> Result<JsValue> {
+    let json = serde_json::to_string(value).map_err(|e| Error::Serialization(e.to_string()))?;
+    js_sys::JSON::parse(&json).map_err(|e| Error::Serialization(describe(&e)))
+}
+
+pub(crate) fn from_js<T: DeserializeOwned>(value: &JsValue) -> Result<T> {
+    // `undefined` has no JSON form, e.g. the response of a command returning `()`
+    let json = js_sys::JSON::stringify(value)
+        .ok()
+        .and_then(|json| JsValue::from(json).as_string())
+        .unwrap_or_else(|| "null".to_owned());
+    serde_json::from_str(&json).map_err(|e| Error::Serialization(e.to_string()))
+}
+
+// The message of a rejection; the plugin's errors are strings
+fn describe(error: &JsValue) -> String {
+    error.as_string().unwrap_or_else(|| format!("{error:?}"))
+}
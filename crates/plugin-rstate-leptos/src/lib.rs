@@ -0,0 +1,140 @@
+//! [Leptos](https://leptos.dev) bindings to [tauri-plugin-rstate](https://docs.rs/tauri-plugin-rstate).
+//!
+//! A Leptos frontend gets the app-wide store's state as signals, and dispatches
+//! actions with the same [`Action`] type as the backend, without going through the
+//! JavaScript API:
+//!
+//! ```rust,ignore
+//! use leptos::prelude::*;
+//! use tauri_plugin_rstate_leptos::{Action, spawn_dispatch, use_rstate_key};
+//!
+//! #[component]
+//! fn Counter() -> impl IntoView {
+//!     let counter = use_rstate_key("counter", 0_i64);
+//!     view! {
+//!         <button on:click=move |_| spawn_dispatch(Action::new("INCREMENT"))>
+//!             {move || counter.get()}
+//!         </button>
+//!     }
+//! }
+//! ```
+//!
+//! Every signal of a webview is fed by a single [`Mirror`] of the store, kept up to
+//! date from the plugin's update and patch events (whichever the plugin emits) and
+//! resynced with `get_changes_since` when it misses some. The signals are only
+//! written by the store: a value set locally is replaced on the next change.
+//!
+//! The crate only works in a Tauri webview, compiled for `wasm32-unknown-unknown`.
+
+mod ipc;
+mod store;
+
+use leptos::prelude::*;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::json;
+
+pub use tauri_plugin_rstate_core::{Action, JsonValue, Mirror, get_state};
+
+/// Result type of the dispatch helpers.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error of a call to the rstate plugin.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    /// Error reported by the rstate plugin
+    #[error("{0}")]
+    Rstate(String),
+
+    /// A value couldn't be converted to or from JSON
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+}
+
+/// A signal of the app-wide store's state.
+///
+/// Holds `initial` until the store is synced, then the state after every change.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let state = use_rstate(AppState::default());
+/// let theme = move || state.with(|state| state.theme.clone());
+/// ```
+pub fn use_rstate<T>(initial: T) -> RwSignal<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    use_rstate_key("", initial)
+}
+
+/// A signal of the part of the app-wide store's state at `key` (dot notation).
+///
+/// Holds `initial` until the store is synced, or while there is no value at `key`.
+/// A value that doesn't deserialize into `T` is logged and skipped.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let dark = use_rstate_key("settings.theme.dark", false);
+/// ```
+pub fn use_rstate_key<T>(key: impl Into<String>, initial: T) -> RwSignal<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    let signal = RwSignal::new(initial);
+    let key = key.into();
+    let id = store::subscribe(std::rc::Rc::new(move |state: &JsonValue| {
+        let Some(value) = get_state(state, &key) else {
+            return;
+        };
+        match serde_json::from_value(value) {
+            Ok(value) => signal.set(value),
+            Err(e) => leptos::logging::warn!("rstate: invalid value at {key:?}: {e}"),
+        }
+    }));
+    on_cleanup(move || store::unsubscribe(id));
+    signal
+}
+
+/// Dispatch an action to the app-wide store, resolving to the new state.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let state = dispatch(Action::with_payload("ADD_TODO", "Buy milk")?).await?;
+/// ```
+pub async fn dispatch(action: impl Into<Action>) -> Result<JsonValue> {
+    ipc::invoke("plugin:rstate|dispatch", json!({ "action": action.into() })).await
+}
+
+/// Dispatch an action of `kind` with a typed `payload` to the app-wide store.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// dispatch_with("SET_COUNT", 42).await?;
+/// ```
+pub async fn dispatch_with<P: Serialize>(kind: &str, payload: P) -> Result<JsonValue> {
+    let action =
+        Action::with_payload(kind, payload).map_err(|e| Error::Serialization(e.to_string()))?;
+    dispatch(action).await
+}
+
+/// Dispatch an action in the background, logging a failure.
+///
+/// Handy in event handlers, which can't await.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// view! { <button on:click=move |_| spawn_dispatch("RESET")>"Reset"</button> }
+/// ```
+pub fn spawn_dispatch(action: impl Into<Action>) {
+    let action = action.into();
+    leptos::task::spawn_local(async move {
+        let kind = action.kind.clone();
+        if let Err(e) = dispatch(action).await {
+            leptos::logging::warn!("rstate: dispatching {kind} failed: {e}");
+        }
+    });
+}
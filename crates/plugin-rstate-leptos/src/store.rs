@@ -0,0 +1,134 @@
+//! The webview's mirror of the app-wide store, shared by every signal.
+//!
+//! The first subscription starts listening to the plugin's update and patch events
+//! and syncs the mirror with `get_changes_since`; a payload the mirror can't apply
+//! triggers another sync. Subscribers are called with the state after every change.
+
+use serde_json::json;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use tauri_plugin_rstate_core::{
+    Changes, JsonValue, Mirror, STATE_PATCH_EVENT, STATE_UPDATE_EVENT, StatePatch,
+};
+use wasm_bindgen::JsValue;
+
+use crate::ipc;
+
+type Subscriber = Rc<dyn Fn(&JsonValue)>;
+
+#[derive(Default)]
+struct Store {
+    mirror: RefCell<Mirror>,
+    subscribers: RefCell<BTreeMap<u64, Subscriber>>,
+    next_id: Cell<u64>,
+    started: Cell<bool>,
+    syncing: Cell<bool>,
+}
+
+thread_local! {
+    static STORE: Store = Store::default();
+}
+
+// Call `subscriber` with the state now if synced, then after every change
+pub(crate) fn subscribe(subscriber: Subscriber) -> u64 {
+    let (id, synced) = STORE.with(|store| {
+        let id = store.next_id.get();
+        store.next_id.set(id + 1);
+        store
+            .subscribers
+            .borrow_mut()
+            .insert(id, subscriber.clone());
+        let mirror = store.mirror.borrow();
+        let synced = (!mirror.state().is_null()).then(|| mirror.state().clone());
+        (id, synced)
+    });
+    if let Some(state) = synced {
+        subscriber(&state);
+    }
+    start();
+    id
+}
+
+pub(crate) fn unsubscribe(id: u64) {
+    STORE.with(|store| store.subscribers.borrow_mut().remove(&id));
+}
+
+// Listen to the plugin's events and sync, once per webview
+fn start() {
+    if STORE.with(|store| store.started.replace(true)) {
+        return;
+    }
+    leptos::task::spawn_local(async {
+        // Listen first, so no update is missed while syncing
+        let listening = async {
+            ipc::listen(STATE_UPDATE_EVENT, |payload| {
+                on_payload(payload, |mirror, update| mirror.apply_update(update))
+            })
+            .await?;
+            ipc::listen(STATE_PATCH_EVENT, |payload| {
+                on_payload(payload, |mirror, patch: StatePatch| {
+                    mirror.apply_patch(&patch)
+                })
+            })
+            .await
+        };
+        match listening.await {
+            Ok(()) => sync().await,
+            Err(e) => leptos::logging::warn!("rstate: cannot listen to the store: {e}"),
+        }
+    });
+}
+
+// Apply the payload of an event, then notify or sync
+fn on_payload<T: serde::de::DeserializeOwned>(
+    payload: JsValue,
+    apply: impl FnOnce(&mut Mirror, T) -> bool,
+) {
+    let payload = match ipc::from_js(&payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            leptos::logging::warn!("rstate: invalid store event: {e}");
+            return;
+        }
+    };
+    if STORE.with(|store| apply(&mut store.mirror.borrow_mut(), payload)) {
+        notify();
+    } else {
+        leptos::task::spawn_local(sync());
+    }
+}
+
+// Bring the mirror up to date with `get_changes_since`
+async fn sync() {
+    if STORE.with(|store| store.syncing.replace(true)) {
+        return;
+    }
+    let since = STORE.with(|store| store.mirror.borrow().resync_since());
+    let changes =
+        ipc::invoke::<Changes>("plugin:rstate|get_changes_since", json!({ "since": since })).await;
+    STORE.with(|store| store.syncing.set(false));
+    match changes {
+        Ok(changes) => {
+            if STORE.with(|store| store.mirror.borrow_mut().apply_changes(since, changes)) {
+                notify();
+            } else {
+                // The mirror moved on in the meantime
+                leptos::task::spawn_local(sync());
+            }
+        }
+        Err(e) => leptos::logging::warn!("rstate: cannot sync the store: {e}"),
+    }
+}
+
+fn notify() {
+    // Subscribers may subscribe or unsubscribe in turn
+    let (state, subscribers) = STORE.with(|store| {
+        let state = store.mirror.borrow().state().clone();
+        let subscribers: Vec<Subscriber> = store.subscribers.borrow().values().cloned().collect();
+        (state, subscribers)
+    });
+    for subscriber in subscribers {
+        subscriber(&state);
+    }
+}
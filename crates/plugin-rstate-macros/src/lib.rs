@@ -0,0 +1,241 @@
+//! Procedural macros for [tauri-plugin-rstate](https://docs.rs/tauri-plugin-rstate).
+//!
+//! Use them through the plugin crate, which re-exports them.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, FnArg, ImplItem, ImplItemFn, ItemImpl, LitStr, Pat, ReturnType, parse_macro_input,
+};
+
+/// Register the `&mut self` methods of an impl block as action handlers.
+///
+/// Each method handles the action kind named after it in upper case (`set_counter`
+/// handles `SET_COUNTER`). A single argument receives the whole payload; several
+/// arguments receive the fields of an object payload, by their camelCase names.
+/// Methods return nothing, or a `Result` whose error converts into `RstateError`, of
+/// `()` or of a `Next` chaining follow-up actions.
+/// Other methods (`&self`, associated functions) are left alone.
+///
+/// Override the kind with `#[rstate(kind = "...")]`, or leave a `&mut self` method out
+/// with `#[rstate(skip)]`. The handlers are registered with `StateBuilder::handlers`.
+///
+/// ```rust,ignore
+/// use tauri_plugin_rstate as rstate;
+///
+/// #[rstate::handlers]
+/// impl AppState {
+///     fn increment(&mut self) {
+///         self.counter += 1;
+///     }
+///
+///     fn set_counter(&mut self, value: i32) {
+///         self.counter = value;
+///     }
+///
+///     // Payload: { "text": "...", "dueDate": "..." }
+///     fn add_todo(&mut self, text: String, due_date: Option<String>) -> rstate::Result<()> {
+///         self.todos.push(Todo::new(text, due_date)?);
+///         Ok(())
+///     }
+///
+///     #[rstate(kind = "todos/CLEAR")]
+///     fn clear_todos(&mut self) {
+///         self.todos.clear();
+///     }
+/// }
+///
+/// let manager = StateBuilder::new(AppState::default()).handlers().build();
+/// ```
+#[proc_macro_attribute]
+pub fn handlers(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            TokenStream2::from(attr).span(),
+            "#[handlers] takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let mut item = parse_macro_input!(item as ItemImpl);
+    match expand(&mut item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(item: &mut ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(syn::Error::new(
+            path.span(),
+            "#[handlers] only applies to inherent impl blocks",
+        ));
+    }
+
+    let mut registrations = Vec::new();
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+        let options = take_options(&mut method.attrs)?;
+        if options.skip || !takes_mut_self(method) {
+            continue;
+        }
+        registrations.push(registration(method, options.kind)?);
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+
+        impl #impl_generics ::tauri_plugin_rstate::ActionHandlers for #self_ty #where_clause {
+            fn register(
+                builder: ::tauri_plugin_rstate::StateBuilder<Self>,
+            ) -> ::tauri_plugin_rstate::StateBuilder<Self> {
+                builder #(#registrations)*
+            }
+        }
+    })
+}
+
+// Options set on a method with `#[rstate(...)]`
+#[derive(Default)]
+struct Options {
+    kind: Option<LitStr>,
+    skip: bool,
+}
+
+// Remove the `#[rstate(...)]` attributes of a method, returning their options
+fn take_options(attrs: &mut Vec<Attribute>) -> syn::Result<Options> {
+    let mut options = Options::default();
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("rstate") {
+            return true;
+        }
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("kind") {
+                options.kind = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("skip") {
+                options.skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `kind = \"...\"` or `skip`"))
+            }
+        });
+        if let Err(err) = parsed {
+            result = Err(err);
+        }
+        false
+    });
+    result.map(|()| options)
+}
+
+fn takes_mut_self(method: &ImplItemFn) -> bool {
+    matches!(
+        method.sig.inputs.first(),
+        Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() && receiver.mutability.is_some()
+    )
+}
+
+// The `.on(...)` call registering a method
+fn registration(method: &ImplItemFn, kind: Option<LitStr>) -> syn::Result<TokenStream2> {
+    let sig = &method.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "async methods can't be action handlers; mark them with #[rstate(skip)]",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "generic methods can't be action handlers; mark them with #[rstate(skip)]",
+        ));
+    }
+
+    let name = &sig.ident;
+    let kind = kind.unwrap_or_else(|| {
+        let name = name.to_string();
+        let name = name.strip_prefix("r#").unwrap_or(&name);
+        LitStr::new(&name.to_uppercase(), sig.ident.span())
+    });
+
+    let mut args = Vec::new();
+    for input in sig.inputs.iter().skip(1) {
+        let FnArg::Typed(input) = input else {
+            continue;
+        };
+        let Pat::Ident(pat) = &*input.pat else {
+            return Err(syn::Error::new(
+                input.pat.span(),
+                "action handler arguments must be plain identifiers",
+            ));
+        };
+        args.push((&pat.ident, &input.ty));
+    }
+
+    // A single argument is the whole payload; several are fields of an object payload
+    let bindings = match args.as_slice() {
+        [] => Vec::new(),
+        [(ident, ty)] => vec![quote! { let #ident: #ty = action.require_payload()?; }],
+        args => args
+            .iter()
+            .map(|(ident, ty)| {
+                let field = camel_case(&ident.to_string());
+                quote! {
+                    let #ident: #ty = ::tauri_plugin_rstate::__payload_field(action, #field)?;
+                }
+            })
+            .collect(),
+    };
+    let idents = args.iter().map(|(ident, _)| ident);
+    let call = quote! { state.#name(#(#idents),*) };
+    // `Result<()>` and `Result<Next>` both convert into `Next`, so follow-ups aren't lost
+    let next = match sig.output {
+        ReturnType::Default => quote! { { #call; ::tauri_plugin_rstate::Next::Done } },
+        ReturnType::Type(..) => quote! { ::core::convert::Into::into(#call?) },
+    };
+
+    Ok(quote! {
+        .on_next(#kind, |state: &mut Self, action: &::tauri_plugin_rstate::Action| {
+            let _ = &action;
+            #(#bindings)*
+            ::core::result::Result::Ok(#next)
+        })
+    })
+}
+
+// Convert a snake_case argument name to camelCase, like Tauri does for command arguments
+fn camel_case(name: &str) -> String {
+    let name = name.strip_prefix("r#").unwrap_or(name);
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' && !camel.is_empty() {
+            upper = true;
+        } else if upper {
+            camel.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camel_case() {
+        assert_eq!(camel_case("value"), "value");
+        assert_eq!(camel_case("due_date"), "dueDate");
+        assert_eq!(camel_case("r#type"), "type");
+    }
+}
@@ -1,4 +1,20 @@
-const COMMANDS: &[&str] = &["get_initial_state", "get_state", "dispatch"];
+const COMMANDS: &[&str] = &[
+    "get_initial_state",
+    "get_changes_since",
+    "get_state",
+    "get_selector",
+    "dispatch",
+    "dispatch_batch",
+    "reset_state",
+    "get_schema",
+    "list_actions",
+    "action_timings",
+    "get_persisted_history",
+    "health_check",
+    "heartbeat",
+    "subscribe",
+    "unsubscribe",
+];
 
 fn main() {
     tauri_plugin::Builder::new(COMMANDS)
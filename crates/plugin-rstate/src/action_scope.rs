@@ -0,0 +1,76 @@
+//! Per-window scopes of the action kinds a window may dispatch.
+
+use serde::{Deserialize, Serialize};
+use tauri::ipc::{CommandScope, ScopeObjectMatch};
+
+use crate::Result;
+
+/// An entry of the scope of the `dispatch` and `dispatch_batch` commands.
+///
+/// These commands read their [scope](https://v2.tauri.app/security/scope/) from the
+/// capabilities of the calling window, so a window can be restricted to an allowlist
+/// of action kinds:
+///
+/// ```json
+/// {
+///   "identifier": "settings",
+///   "windows": ["settings"],
+///   "permissions": [
+///     "rstate:allow-get-initial-state",
+///     { "identifier": "rstate:allow-dispatch", "allow": [{ "action": "settings/*" }] }
+///   ]
+/// }
+/// ```
+///
+/// An `action` ending with `*` matches every kind starting with what precedes it,
+/// others match a single kind. Denied kinds always win; a command without allowed
+/// kinds in its scope may dispatch any kind that isn't denied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActionScope {
+    /// An action kind, or a prefix of action kinds followed by `*`
+    pub action: String,
+}
+
+impl ScopeObjectMatch for ActionScope {
+    type Input = str;
+
+    fn matches(&self, kind: &str) -> bool {
+        match self.action.strip_suffix('*') {
+            Some(prefix) => kind.starts_with(prefix),
+            None => kind == self.action,
+        }
+    }
+}
+
+// Check that the window `label` may dispatch actions of `kind`
+pub(crate) fn check(scope: &CommandScope<ActionScope>, label: &str, kind: &str) -> Result<()> {
+    if scope.matches(kind) {
+        return Ok(());
+    }
+    Err(crate::RstateError::forbidden(format!(
+        "window '{label}' is not allowed to dispatch '{kind}' by its capabilities"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_scopes_match_kinds_and_prefixes() {
+        let settings = ActionScope {
+            action: "settings/*".into(),
+        };
+        assert!(settings.matches("settings/SET_THEME"));
+        assert!(!settings.matches("user/LOGOUT"));
+
+        let logout = ActionScope {
+            action: "user/LOGOUT".into(),
+        };
+        assert!(logout.matches("user/LOGOUT"));
+        assert!(!logout.matches("user/LOGOUT_ALL"));
+
+        let any: ActionScope = serde_json::from_str(r#"{ "action": "*" }"#).unwrap();
+        assert!(any.matches("user/LOGOUT"));
+    }
+}
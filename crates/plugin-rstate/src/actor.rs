@@ -0,0 +1,139 @@
+//! Actor-mode dispatching.
+//!
+//! With [`Builder::actor_mode`](crate::Builder::actor_mode), the app-wide store gets a
+//! dedicated thread applying the actions sent to it over a channel, one at a time and
+//! in the order they were sent. [`Rstate::dispatch_queued`](crate::Rstate::dispatch_queued)
+//! hands an action over and returns right away;
+//! [`Rstate::dispatch_async`](crate::Rstate::dispatch_async) resolves with the new state
+//! once the action is applied. Either way, the caller never waits on the store's lock:
+//! async tasks stay responsive while a slow handler runs, and a handler can queue a
+//! follow-up action without deadlocking on the lock it holds.
+//!
+//! The frontend's dispatches go through the actor too, unless they are batched.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::logging::ACTION_LOG_TARGET;
+use crate::models::{Action, JsonValue};
+use crate::{Result, RstateError};
+
+// An action sent to the actor, along with the channel reporting its outcome if awaited
+type Mail = (Action, Option<oneshot::Sender<Result<JsonValue>>>);
+
+pub(crate) struct Actor {
+    mailbox: mpsc::UnboundedSender<Mail>,
+    // Actions sent and not applied yet
+    pending: Arc<AtomicUsize>,
+}
+
+impl Actor {
+    // Start the actor's thread, applying every action it receives with `dispatch`
+    pub(crate) fn spawn(
+        dispatch: impl Fn(Action) -> Result<JsonValue> + Send + 'static,
+    ) -> Result<Self> {
+        let (mailbox, mut inbox) = mpsc::unbounded_channel::<Mail>();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let applied = pending.clone();
+        std::thread::Builder::new()
+            .name("rstate-actor".to_owned())
+            .spawn(move || {
+                while let Some((action, reply)) = inbox.blocking_recv() {
+                    let kind = action.kind.clone();
+                    let result = dispatch(action);
+                    applied.fetch_sub(1, Ordering::SeqCst);
+                    match reply {
+                        Some(reply) => {
+                            let _ = reply.send(result);
+                        }
+                        None => {
+                            if let Err(err) = result {
+                                log::warn!(target: ACTION_LOG_TARGET, "queued action '{kind}': {err}");
+                            }
+                        }
+                    }
+                }
+            })?;
+        Ok(Self { mailbox, pending })
+    }
+
+    // Queue `action`, without waiting for its outcome
+    pub(crate) fn send(&self, action: Action) -> Result<()> {
+        self.mail(action, None)
+    }
+
+    // Queue `action` right away, resolving with its outcome
+    pub(crate) fn dispatch(
+        &self,
+        action: Action,
+    ) -> impl Future<Output = Result<JsonValue>> + Send + 'static {
+        let (reply, outcome) = oneshot::channel();
+        let mailed = self.mail(action, Some(reply));
+        async move {
+            mailed?;
+            outcome.await.map_err(|_| stopped())?
+        }
+    }
+
+    // Number of actions waiting to be applied, including the one being applied
+    pub(crate) fn len(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    fn mail(
+        &self,
+        action: Action,
+        reply: Option<oneshot::Sender<Result<JsonValue>>>,
+    ) -> Result<()> {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.mailbox.send((action, reply)).map_err(|_| {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            stopped()
+        })
+    }
+}
+
+fn stopped() -> RstateError {
+    RstateError::state("The store's actor stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_actor_applies_actions_in_order() {
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let (release, gate) = channel::<()>();
+        let gate = Mutex::new(gate);
+        let log = applied.clone();
+        let actor = Actor::spawn(move |action| {
+            if action.is("SLOW") {
+                gate.lock().unwrap().recv().unwrap();
+            }
+            if action.is("FAIL") {
+                return Err(RstateError::state("nope"));
+            }
+            let mut log = log.lock().unwrap();
+            log.push(action.kind);
+            Ok(JsonValue::from(log.len()))
+        })
+        .unwrap();
+
+        // Queuing returns while the actor is still busy with a slow handler
+        actor.send(Action::new("SLOW")).unwrap();
+        actor.send(Action::new("FAIL")).unwrap();
+        actor.send(Action::new("A")).unwrap();
+        assert_eq!(actor.len(), 3);
+        release.send(()).unwrap();
+
+        let state = tauri::async_runtime::block_on(actor.dispatch(Action::new("B"))).unwrap();
+        assert_eq!(state, 3);
+        assert_eq!(*applied.lock().unwrap(), ["SLOW", "A", "B"]);
+        assert!(tauri::async_runtime::block_on(actor.dispatch(Action::new("FAIL"))).is_err());
+        assert_eq!(actor.len(), 0);
+    }
+}
@@ -0,0 +1,380 @@
+//! State managers pinned to a thread.
+//!
+//! A [`RstateManager`] must be `Send + Sync`, which rules out managers wrapping
+//! platform handles or other thread-bound resources. Implement [`LocalStateManager`]
+//! instead, and register it through a [`PinnedManager`]: the manager stays on its
+//! thread, and the plugin marshals every call to it and the result back.
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::{PinnedManager, RstateExt};
+//!
+//! tauri::Builder::default()
+//!     .plugin(tauri_plugin_rstate::init_empty())
+//!     .setup(|app| {
+//!         // On a dedicated thread, created there by the factory
+//!         let manager = PinnedManager::spawn("audio-state", || AudioState::open())?;
+//!         app.rstate().register_state_manager(manager)?;
+//!         Ok(())
+//!     })
+//! ```
+//!
+//! Calls made on the manager's thread run directly. Calls from other threads block
+//! until the thread gets to them. Managers can't be pinned to the main thread: calls
+//! are made under the store's lock, and the main thread waiting for that lock would
+//! never get to them.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, ThreadId};
+
+use crate::models::{
+    Action, ActionKinds, AnyAppHandle, DispatchOutcome, Dispatcher, JsonValue, RstateManager,
+};
+
+/// A state manager that isn't `Send` nor `Sync`, run through a [`PinnedManager`].
+///
+/// The methods mirror [`RstateManager`]'s, with the same defaults.
+pub trait LocalStateManager: 'static {
+    /// Get the initial state of the app.
+    fn get_initial_state(&self) -> JsonValue;
+
+    /// See [`RstateManager::dispatch`].
+    fn dispatch(&mut self, action: &Action) -> crate::Result<DispatchOutcome>;
+
+    /// See [`RstateManager::flush`].
+    fn flush(&mut self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// See [`RstateManager::set_dispatcher`].
+    fn set_dispatcher(&mut self, dispatcher: Dispatcher) {
+        let _ = dispatcher;
+    }
+
+    /// See [`RstateManager::set_app`].
+    fn set_app(&mut self, app: AnyAppHandle) {
+        let _ = app;
+    }
+
+    /// See [`RstateManager::set_save_tap`].
+    fn set_save_tap(&mut self, tap: crate::SaveTap) {
+        let _ = tap;
+    }
+
+    /// See [`RstateManager::replace_state`].
+    fn replace_state(&mut self, state: JsonValue) -> crate::Result<()> {
+        let _ = state;
+        Err(crate::RstateError::state(
+            "Replacing the state is not supported by this state manager",
+        ))
+    }
+
+    /// See [`RstateManager::emit_policy`].
+    fn emit_policy(&self, kind: &str) -> Option<crate::EmitPolicy> {
+        let _ = kind;
+        None
+    }
+
+    /// See [`RstateManager::float_comparison`].
+    fn float_comparison(&self) -> crate::FloatComparison {
+        crate::FloatComparison::default()
+    }
+
+    /// See [`RstateManager::last_save`].
+    fn last_save(&self) -> Option<crate::SaveStatus> {
+        None
+    }
+
+    /// See [`RstateManager::schema`].
+    fn schema(&self) -> Option<crate::SchemaFingerprint> {
+        None
+    }
+
+    /// See [`RstateManager::version`].
+    fn version(&self) -> Option<u32> {
+        None
+    }
+
+    /// See [`RstateManager::simulate`].
+    fn simulate(&self, actions: &[Action]) -> crate::Result<JsonValue> {
+        let _ = actions;
+        Err(crate::RstateError::state(
+            "Simulation is not supported by this state manager",
+        ))
+    }
+
+    /// See [`RstateManager::select`].
+    fn select(&self, name: &str) -> crate::Result<JsonValue> {
+        let _ = name;
+        Err(crate::RstateError::state(
+            "Selectors are not supported by this state manager",
+        ))
+    }
+
+    /// See [`RstateManager::selector_stats`].
+    fn selector_stats(&self) -> Vec<crate::SelectorStats> {
+        Vec::new()
+    }
+
+    /// See [`RstateManager::redacted_paths`].
+    fn redacted_paths(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// See [`RstateManager::action_kinds`].
+    fn action_kinds(&self) -> crate::Result<ActionKinds> {
+        Err(crate::RstateError::state(
+            "Listing action kinds is not supported by this state manager",
+        ))
+    }
+
+    /// See [`RstateManager::persisted_history`].
+    fn persisted_history(&self, after: u64, limit: usize) -> crate::Result<crate::HistoryPage> {
+        let _ = (after, limit);
+        Err(crate::RstateError::state(
+            "This state manager has no persisted action log",
+        ))
+    }
+}
+
+// A call marshaled to the manager's thread
+type Job = Box<dyn FnOnce() + Send>;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // The managers pinned to this thread, by id
+    static MANAGERS: RefCell<HashMap<u64, Box<dyn LocalStateManager>>> = RefCell::new(HashMap::new());
+}
+
+// Run `f` with the manager `id` of the current thread
+fn with_manager<T>(id: u64, f: impl FnOnce(&mut dyn LocalStateManager) -> T) -> crate::Result<T> {
+    MANAGERS.with(|managers| {
+        // A manager calling back into its own store would borrow it twice
+        let mut managers = managers
+            .try_borrow_mut()
+            .map_err(|_| crate::RstateError::state("pinned state manager is busy"))?;
+        let manager = managers
+            .get_mut(&id)
+            .ok_or_else(|| crate::RstateError::state("pinned state manager is gone"))?;
+        Ok(f(manager.as_mut()))
+    })
+}
+
+// Move a manager out of the current thread's registry, so it's dropped outside the borrow
+fn remove_manager(id: u64) {
+    let manager = MANAGERS.with(|managers| {
+        managers
+            .try_borrow_mut()
+            .ok()
+            .and_then(|mut managers| managers.remove(&id))
+    });
+    drop(manager);
+}
+
+/// A [`RstateManager`] running a [`LocalStateManager`] on a single thread.
+pub struct PinnedManager {
+    id: u64,
+    thread: ThreadId,
+    jobs: mpsc::Sender<Job>,
+}
+
+impl PinnedManager {
+    /// Run the manager built by `factory` on a new thread named `name`.
+    ///
+    /// The thread stops once the manager is dropped.
+    pub fn spawn<M, F>(name: impl Into<String>, factory: F) -> crate::Result<Self>
+    where
+        M: LocalStateManager,
+        F: FnOnce() -> M + Send + 'static,
+    {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let (sender, jobs) = mpsc::channel::<Job>();
+        let handle = thread::Builder::new().name(name.into()).spawn(move || {
+            MANAGERS.with(|managers| managers.borrow_mut().insert(id, Box::new(factory())));
+            for job in jobs {
+                job();
+            }
+            remove_manager(id);
+        })?;
+
+        Ok(Self {
+            id,
+            thread: handle.thread().id(),
+            jobs: sender,
+        })
+    }
+
+    // Run a job on the manager's thread
+    fn run(&self, job: Job) -> crate::Result<()> {
+        self.jobs
+            .send(job)
+            .map_err(|_| crate::RstateError::state("pinned state manager thread stopped"))
+    }
+
+    // Run `f` with the manager on its thread, and wait for the result
+    fn call<T, F>(&self, f: F) -> crate::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn LocalStateManager) -> T + Send + 'static,
+    {
+        if thread::current().id() == self.thread {
+            return with_manager(self.id, f);
+        }
+
+        let id = self.id;
+        let (sender, result) = mpsc::sync_channel(1);
+        self.run(Box::new(move || {
+            let _ = sender.send(with_manager(id, f));
+        }))?;
+        result
+            .recv()
+            .map_err(|_| crate::RstateError::state("pinned state manager thread stopped"))?
+    }
+}
+
+impl Drop for PinnedManager {
+    fn drop(&mut self) {
+        let id = self.id;
+        if thread::current().id() == self.thread {
+            remove_manager(id);
+        } else {
+            // A stopped thread already dropped its managers
+            let _ = self.run(Box::new(move || remove_manager(id)));
+        }
+    }
+}
+
+// Log the failure of a call that can't report it
+fn or_log<T>(result: crate::Result<T>, fallback: T) -> T {
+    result.unwrap_or_else(|err| {
+        log::warn!(target: crate::ACTION_LOG_TARGET, "pinned state manager: {err}");
+        fallback
+    })
+}
+
+impl RstateManager for PinnedManager {
+    fn get_initial_state(&self) -> JsonValue {
+        or_log(
+            self.call(|manager| manager.get_initial_state()),
+            JsonValue::Null,
+        )
+    }
+
+    fn dispatch(&mut self, action: &Action) -> crate::Result<DispatchOutcome> {
+        let action = action.clone();
+        self.call(move |manager| manager.dispatch(&action))?
+    }
+
+    fn flush(&mut self) -> crate::Result<()> {
+        self.call(|manager| manager.flush())?
+    }
+
+    fn set_dispatcher(&mut self, dispatcher: Dispatcher) {
+        or_log(
+            self.call(move |manager| manager.set_dispatcher(dispatcher)),
+            (),
+        );
+    }
+
+    fn set_app(&mut self, app: AnyAppHandle) {
+        or_log(self.call(move |manager| manager.set_app(app)), ());
+    }
+
+    fn set_save_tap(&mut self, tap: crate::SaveTap) {
+        or_log(self.call(move |manager| manager.set_save_tap(tap)), ());
+    }
+
+    fn replace_state(&mut self, state: JsonValue) -> crate::Result<()> {
+        self.call(move |manager| manager.replace_state(state))?
+    }
+
+    fn emit_policy(&self, kind: &str) -> Option<crate::EmitPolicy> {
+        let kind = kind.to_owned();
+        or_log(self.call(move |manager| manager.emit_policy(&kind)), None)
+    }
+
+    fn float_comparison(&self) -> crate::FloatComparison {
+        or_log(
+            self.call(|manager| manager.float_comparison()),
+            crate::FloatComparison::default(),
+        )
+    }
+
+    fn last_save(&self) -> Option<crate::SaveStatus> {
+        or_log(self.call(|manager| manager.last_save()), None)
+    }
+
+    fn schema(&self) -> Option<crate::SchemaFingerprint> {
+        or_log(self.call(|manager| manager.schema()), None)
+    }
+
+    fn version(&self) -> Option<u32> {
+        or_log(self.call(|manager| manager.version()), None)
+    }
+
+    fn simulate(&self, actions: &[Action]) -> crate::Result<JsonValue> {
+        let actions = actions.to_vec();
+        self.call(move |manager| manager.simulate(&actions))?
+    }
+
+    fn select(&self, name: &str) -> crate::Result<JsonValue> {
+        let name = name.to_owned();
+        self.call(move |manager| manager.select(&name))?
+    }
+
+    fn selector_stats(&self) -> Vec<crate::SelectorStats> {
+        or_log(self.call(|manager| manager.selector_stats()), Vec::new())
+    }
+
+    fn redacted_paths(&self) -> Vec<String> {
+        or_log(self.call(|manager| manager.redacted_paths()), Vec::new())
+    }
+
+    fn action_kinds(&self) -> crate::Result<ActionKinds> {
+        self.call(|manager| manager.action_kinds())?
+    }
+
+    fn persisted_history(&self, after: u64, limit: usize) -> crate::Result<crate::HistoryPage> {
+        self.call(move |manager| manager.persisted_history(after, limit))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    // Not `Send`, thanks to the `Rc`
+    struct Counter(Rc<i64>);
+
+    impl LocalStateManager for Counter {
+        fn get_initial_state(&self) -> JsonValue {
+            serde_json::json!({ "counter": *self.0, "thread": thread::current().name() })
+        }
+
+        fn dispatch(&mut self, action: &Action) -> crate::Result<DispatchOutcome> {
+            let changed = action.is("INCREMENT");
+            if changed {
+                self.0 = Rc::new(*self.0 + 1);
+            }
+            Ok(DispatchOutcome::new(self.get_initial_state(), changed))
+        }
+    }
+
+    #[test]
+    fn test_pinned_manager_runs_on_its_thread() {
+        let mut manager = PinnedManager::spawn("counter-store", || Counter(Rc::new(0))).unwrap();
+
+        let outcome = manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        assert!(outcome.changed);
+        assert_eq!(
+            outcome.state,
+            serde_json::json!({ "counter": 1, "thread": "counter-store" })
+        );
+        assert!(manager.replace_state(JsonValue::Null).is_err());
+        assert_eq!(manager.get_initial_state()["counter"], 1);
+    }
+}
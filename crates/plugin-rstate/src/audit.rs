@@ -0,0 +1,264 @@
+//! Audit log of dispatched actions.
+//!
+//! When enabled with [`Builder::audit_log`](crate::Builder::audit_log), every
+//! dispatched action is appended as one JSON line to a file, so the actions leading
+//! to a user-reported issue can be inspected in production builds, where no logger
+//! may be installed:
+//!
+//! ```rust,ignore
+//! let audit = AuditLog::new(app_log_dir.join("actions.jsonl"))
+//!     .max_size(5 * 1024 * 1024)
+//!     .keep(3);
+//!
+//! tauri_plugin_rstate::Builder::new()
+//!     .state_manager(manager)
+//!     .audit_log(audit)
+//!     .build()
+//! ```
+//!
+//! A record looks like:
+//!
+//! ```json
+//! {"timestamp":1760000000000,"kind":"SET_THEME","payloadHash":"5e1c0b7a","window":"main","changed":true}
+//! ```
+//!
+//! Payloads may hold personal data, so only their CRC32 is recorded (in hex) unless
+//! [`AuditLog::with_payloads`] is set. Once the file reaches its maximum size, it is
+//! rotated to `<path>.1` (and `<path>.1` to `<path>.2`, and so on).
+
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::RstateError;
+use crate::health::unix_millis;
+use crate::logging::ACTION_LOG_TARGET;
+use crate::models::{Action, JsonValue};
+
+/// Configuration of the audit log, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    payloads: bool,
+}
+
+impl AuditLog {
+    /// Append records to the file at `path`, rotated at 10 MiB, keeping 5 rotated
+    /// files.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_size: 10 * 1024 * 1024,
+            keep: 5,
+            payloads: false,
+        }
+    }
+
+    /// Rotate the file once it reaches `bytes`.
+    #[must_use]
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Number of rotated files to keep, older ones are deleted. With `0`, the file
+    /// is truncated when it is full.
+    #[must_use]
+    pub fn keep(mut self, files: usize) -> Self {
+        self.keep = files;
+        self
+    }
+
+    /// Record the payloads of the actions instead of their hash.
+    #[must_use]
+    pub fn with_payloads(mut self) -> Self {
+        self.payloads = true;
+        self
+    }
+
+    // Path of the `index`th rotated file
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(format!(".{index}"));
+        PathBuf::from(path)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditRecord<'a> {
+    timestamp: u64,
+    kind: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<&'a JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// Appends the records to the file, opened on the first record
+pub(crate) struct AuditWriter {
+    config: AuditLog,
+    // Open file and its size
+    file: Mutex<Option<(File, u64)>>,
+}
+
+impl AuditWriter {
+    pub(crate) fn new(config: AuditLog) -> Self {
+        Self {
+            config,
+            file: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn record(&self, action: &Action, result: Result<bool, &RstateError>) {
+        let payload = action.payload.as_ref();
+        let record = AuditRecord {
+            timestamp: unix_millis(),
+            kind: &action.kind,
+            payload: payload.filter(|_| self.config.payloads),
+            payload_hash: payload
+                .filter(|_| !self.config.payloads)
+                .map(|payload| format!("{:08x}", crc32fast::hash(payload.to_string().as_bytes()))),
+            window: action.origin_window(),
+            trace_id: action.trace_id(),
+            changed: result.as_ref().ok().copied(),
+            error: result.err().map(ToString::to_string),
+        };
+        let Ok(mut line) = serde_json::to_vec(&record) else {
+            return;
+        };
+        line.push(b'\n');
+        if let Err(err) = self.append(&line) {
+            log::warn!(
+                target: ACTION_LOG_TARGET,
+                "Failed to write to the audit log {}: {}",
+                self.config.path.display(),
+                err
+            );
+        }
+    }
+
+    fn append(&self, line: &[u8]) -> io::Result<()> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        if let Some((_, size)) = file.as_ref()
+            && *size > 0
+            && *size + line.len() as u64 > self.config.max_size
+        {
+            // Close the file before renaming it
+            *file = None;
+            self.rotate()?;
+        }
+        let (handle, size) = match file.as_mut() {
+            Some(open) => open,
+            None => file.insert(open(&self.config.path)?),
+        };
+        handle.write_all(line)?;
+        *size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        let config = &self.config;
+        if config.keep == 0 {
+            return ignore_missing(std::fs::remove_file(&config.path));
+        }
+        ignore_missing(std::fs::remove_file(config.rotated(config.keep)))?;
+        for index in (1..config.keep).rev() {
+            ignore_missing(std::fs::rename(
+                config.rotated(index),
+                config.rotated(index + 1),
+            ))?;
+        }
+        std::fs::rename(&config.path, config.rotated(1))
+    }
+}
+
+// Open the file at `path` for appending, with its current size
+fn open(path: &Path) -> io::Result<(File, u64)> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+fn ignore_missing(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lines(path: &Path) -> Vec<JsonValue> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_actions_are_appended_and_rotated() {
+        let dir = std::env::temp_dir().join(format!("rstate-audit-{}", std::process::id()));
+        let path = dir.join("actions.jsonl");
+        let writer = AuditWriter::new(AuditLog::new(&path).max_size(200).keep(1));
+
+        let action = Action::with_json("SET_THEME", json!({ "theme": "dark" }));
+        writer.record(&action, Ok(true));
+        writer.record(&Action::new("BOOM"), Err(&RstateError::state("failed")));
+        let records = lines(&path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["kind"], "SET_THEME");
+        assert_eq!(records[0]["changed"], true);
+        assert!(records[0].get("payload").is_none());
+        assert_eq!(
+            records[0]["payloadHash"],
+            format!("{:08x}", crc32fast::hash(br#"{"theme":"dark"}"#))
+        );
+        assert!(records[1]["error"].as_str().unwrap().contains("failed"));
+
+        // The third record doesn't fit anymore
+        writer.record(&action, Ok(false));
+        assert_eq!(lines(&path).len(), 1);
+        assert_eq!(lines(&dir.join("actions.jsonl.1")).len(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_payloads_are_recorded_when_enabled() {
+        let path = std::env::temp_dir().join(format!(
+            "rstate-audit-payloads-{}.jsonl",
+            std::process::id()
+        ));
+        let writer = AuditWriter::new(AuditLog::new(&path).with_payloads());
+        writer.record(&Action::with_json("ADD", json!({ "amount": 2 })), Ok(true));
+        let records = lines(&path);
+        assert_eq!(records[0]["payload"], json!({ "amount": 2 }));
+        assert!(records[0].get("payloadHash").is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+//! Micro-batching of dispatches.
+//!
+//! With a batch window configured through
+//! [`Builder::batch_window`](crate::Builder::batch_window), dispatches made through
+//! [`Rstate::dispatch_batched`](crate::Rstate::dispatch_batched) (including every
+//! dispatch from the frontend) are queued. When the window ends, the queued actions
+//! are applied together in a single lock, followed by a single emit. This smooths
+//! bursts from sources like file watchers without the frontend doing anything.
+//!
+//! With [`Builder::persist_batch_queue`](crate::Builder::persist_batch_queue), the
+//! queue is also saved as actions arrive, and cleared once their batch is applied and
+//! the store flushed. Actions left over by a crash are replayed when the next state
+//! manager is registered, before the store becomes ready.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::Result;
+use crate::logging::ACTION_LOG_TARGET;
+use crate::models::{Action, JsonValue};
+use crate::persistence::StorageBackend;
+
+// A queued action along with the channel reporting its outcome
+pub(crate) type Queued = (Action, oneshot::Sender<Result<JsonValue>>);
+
+pub(crate) struct Batcher {
+    window: Duration,
+    queue: Mutex<Vec<Queued>>,
+    // Where the queue is saved, if persisted
+    storage: Option<Box<dyn StorageBackend>>,
+}
+
+impl Batcher {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            queue: Mutex::new(Vec::new()),
+            storage: None,
+        }
+    }
+
+    // Save the queue in `storage` whenever it changes
+    pub(crate) fn persisted(mut self, storage: Option<Box<dyn StorageBackend>>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub(crate) fn is_persisted(&self) -> bool {
+        self.storage.is_some()
+    }
+
+    pub(crate) fn window(&self) -> Duration {
+        self.window
+    }
+
+    // Queue an action. Returns `true` if it opened a new batch, i.e. the caller
+    // must schedule `take` once the window has passed.
+    pub(crate) fn push(&self, action: Action, outcome: oneshot::Sender<Result<JsonValue>>) -> bool {
+        let Ok(mut queue) = self.queue.lock() else {
+            let _ = outcome.send(Err(crate::RstateError::LockPoisoned(
+                "batch queue".to_owned(),
+            )));
+            return false;
+        };
+        queue.push((action, outcome));
+        self.save(&queue);
+        queue.len() == 1
+    }
+
+    // Queue the actions left over by the last run, if persisted, skipping repeated
+    // dispatch ids. Their outcomes go nowhere. Returns how many were queued.
+    pub(crate) fn restore(&self) -> usize {
+        let Some(storage) = &self.storage else {
+            return 0;
+        };
+        let actions: Vec<Action> = match storage.load() {
+            Ok(Some(saved)) => match serde_json::from_value(saved) {
+                Ok(actions) => actions,
+                Err(err) => {
+                    log::warn!(target: ACTION_LOG_TARGET, "invalid persisted batch queue: {err}");
+                    return 0;
+                }
+            },
+            Ok(None) => return 0,
+            Err(err) => {
+                log::warn!(target: ACTION_LOG_TARGET, "loading the batch queue: {err}");
+                return 0;
+            }
+        };
+        let Ok(mut queue) = self.queue.lock() else {
+            return 0;
+        };
+
+        let mut ids = HashSet::new();
+        let mut restored = 0;
+        for action in actions {
+            if let Some(id) = action.dispatch_id()
+                && !ids.insert(id.to_owned())
+            {
+                continue;
+            }
+            let (outcome, _) = oneshot::channel();
+            queue.push((action, outcome));
+            restored += 1;
+        }
+        restored
+    }
+
+    // Save the current queue, e.g. once a batch is applied
+    pub(crate) fn persist(&self) {
+        if let Ok(queue) = self.queue.lock() {
+            self.save(&queue);
+        }
+    }
+
+    // Save `queue`, if persisted. Failures are logged: the actions are still queued.
+    fn save(&self, queue: &[Queued]) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let result = if queue.is_empty() {
+            storage.clear()
+        } else {
+            serde_json::to_value(queue.iter().map(|(action, _)| action).collect::<Vec<_>>())
+                .map_err(|e| crate::RstateError::serialization(e.to_string()))
+                .and_then(|saved| storage.save(&saved))
+        };
+        if let Err(err) = result {
+            log::warn!(target: ACTION_LOG_TARGET, "saving the batch queue: {err}");
+        }
+    }
+
+    // Number of queued actions
+    pub(crate) fn len(&self) -> usize {
+        self.queue
+            .lock()
+            .map(|queue| queue.len())
+            .unwrap_or_default()
+    }
+
+    // Take the current batch, in dispatch order
+    pub(crate) fn take(&self) -> Vec<Queued> {
+        self.queue
+            .lock()
+            .map(|mut queue| std::mem::take(&mut *queue))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TagFrontend;
+
+    #[test]
+    fn test_batcher_opens_one_batch_per_window() {
+        let batcher = Batcher::new(Duration::from_millis(10));
+
+        let (first, _first_outcome) = oneshot::channel();
+        let (second, _second_outcome) = oneshot::channel();
+        assert!(batcher.push(Action::new("A"), first));
+        assert!(!batcher.push(Action::new("B"), second));
+
+        let batch = batcher.take();
+        assert_eq!(batch.len(), 2);
+        assert!(batch[0].0.is("A"));
+        assert!(batcher.take().is_empty());
+
+        let (third, _third_outcome) = oneshot::channel();
+        assert!(batcher.push(Action::new("C"), third));
+    }
+
+    #[test]
+    fn test_persisted_queue_is_restored_once_per_dispatch_id() {
+        let storage = crate::MemoryBackend::default();
+        let batcher =
+            Batcher::new(Duration::from_millis(10)).persisted(Some(Box::new(storage.clone())));
+
+        let action = Action::new("A").tag_frontend(Some("main"));
+        let (first, _first_outcome) = oneshot::channel();
+        let (second, _second_outcome) = oneshot::channel();
+        batcher.push(action.clone(), first);
+        batcher.push(action, second);
+        assert_eq!(
+            storage.load().unwrap().unwrap().as_array().unwrap().len(),
+            2
+        );
+
+        // The batch is applied: the queue is cleared
+        batcher.take();
+        batcher.persist();
+        assert_eq!(storage.load().unwrap(), None);
+
+        // Left over by a crash
+        let (third, _third_outcome) = oneshot::channel();
+        let (fourth, _fourth_outcome) = oneshot::channel();
+        let action = Action::new("B").tag_frontend(Some("main"));
+        batcher.push(action.clone(), third);
+        batcher.push(action, fourth);
+        let restarted =
+            Batcher::new(Duration::from_millis(10)).persisted(Some(Box::new(storage.clone())));
+        assert_eq!(restarted.restore(), 1);
+        let batch = restarted.take();
+        assert!(batch[0].0.is("B"));
+        assert_eq!(batch[0].0.origin_window(), Some("main"));
+        assert_eq!(Batcher::new(Duration::from_millis(10)).restore(), 0);
+    }
+}
@@ -0,0 +1,130 @@
+//! Declarative bindings from menu items and shortcuts to actions.
+//!
+//! Instead of writing an event handler that matches ids and dispatches actions,
+//! bind the ids directly:
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::{Action, RstateExt};
+//!
+//! app.rstate().bind_menu_item("toggle-mute", Action::new("TOGGLE_MUTE"));
+//! app.rstate().bind_shortcut("CmdOrCtrl+Shift+M", Action::new("TOGGLE_MUTE"));
+//! ```
+//!
+//! Menu items (including tray menus) are handled by the plugin itself. Global
+//! shortcuts are owned by `tauri-plugin-global-shortcut`, so forward them from its
+//! handler with [`Rstate::handle_shortcut`](crate::Rstate::handle_shortcut).
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::models::Action;
+
+#[derive(Default)]
+pub(crate) struct Bindings {
+    menu_items: Mutex<HashMap<String, Action>>,
+    shortcuts: Mutex<HashMap<String, Action>>,
+}
+
+impl Bindings {
+    pub(crate) fn bind_menu_item(&self, id: String, action: Action) {
+        if let Ok(mut menu_items) = self.menu_items.lock() {
+            menu_items.insert(id, action);
+        }
+    }
+
+    pub(crate) fn unbind_menu_item(&self, id: &str) -> bool {
+        self.menu_items
+            .lock()
+            .is_ok_and(|mut menu_items| menu_items.remove(id).is_some())
+    }
+
+    pub(crate) fn menu_item(&self, id: &str) -> Option<Action> {
+        self.menu_items.lock().ok()?.get(id).cloned()
+    }
+
+    pub(crate) fn bind_shortcut(&self, accelerator: &str, action: Action) {
+        if let Ok(mut shortcuts) = self.shortcuts.lock() {
+            shortcuts.insert(normalize_accelerator(accelerator), action);
+        }
+    }
+
+    pub(crate) fn unbind_shortcut(&self, accelerator: &str) -> bool {
+        self.shortcuts.lock().is_ok_and(|mut shortcuts| {
+            shortcuts
+                .remove(&normalize_accelerator(accelerator))
+                .is_some()
+        })
+    }
+
+    pub(crate) fn shortcut(&self, accelerator: &str) -> Option<Action> {
+        self.shortcuts
+            .lock()
+            .ok()?
+            .get(&normalize_accelerator(accelerator))
+            .cloned()
+    }
+}
+
+// Normalize an accelerator so that equivalent spellings match:
+// "CmdOrCtrl+Shift+M", "shift+control+KeyM" and "Ctrl+Shift+m" (off macOS) are the same.
+fn normalize_accelerator(accelerator: &str) -> String {
+    let mut modifiers = Vec::new();
+    let mut key = String::new();
+
+    for token in accelerator.split('+').map(|t| t.trim().to_lowercase()) {
+        let modifier = match token.as_str() {
+            "ctrl" | "control" => "control",
+            "cmd" | "command" | "super" | "meta" => "super",
+            "cmdorctrl" | "commandorcontrol" if cfg!(target_os = "macos") => "super",
+            "cmdorctrl" | "commandorcontrol" => "control",
+            "alt" | "option" => "alt",
+            "shift" => "shift",
+            _ => {
+                // Global shortcut key codes are spelled "KeyM" / "Digit1"
+                key = token
+                    .strip_prefix("key")
+                    .or_else(|| token.strip_prefix("digit"))
+                    .filter(|rest| !rest.is_empty())
+                    .unwrap_or(&token)
+                    .to_owned();
+                continue;
+            }
+        };
+        if !modifiers.contains(&modifier) {
+            modifiers.push(modifier);
+        }
+    }
+
+    modifiers.sort_unstable();
+    modifiers.push(&key);
+    modifiers.join("+")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_accelerator_spellings() {
+        assert_eq!(normalize_accelerator("Ctrl+Shift+M"), "control+shift+m");
+        assert_eq!(
+            normalize_accelerator("shift+control+KeyM"),
+            "control+shift+m"
+        );
+        assert_eq!(normalize_accelerator("Alt+Digit1"), "alt+1");
+        assert_eq!(normalize_accelerator("F12"), "f12");
+    }
+
+    #[test]
+    fn test_shortcut_lookup_uses_normalized_accelerator() {
+        let bindings = Bindings::default();
+        bindings.bind_shortcut("Control+Shift+M", Action::new("TOGGLE_MUTE"));
+
+        let action = bindings.shortcut("shift+control+KeyM").unwrap();
+        assert!(action.is("TOGGLE_MUTE"));
+        assert!(bindings.shortcut("Control+M").is_none());
+
+        assert!(bindings.unbind_shortcut("ctrl+shift+m"));
+        assert!(bindings.shortcut("Control+Shift+M").is_none());
+    }
+}
@@ -0,0 +1,143 @@
+//! Circuit breaker for failing action kinds.
+//!
+//! With [`Builder::circuit_breaker`](crate::Builder::circuit_breaker), an action kind
+//! whose handler fails `threshold` times in a row is rejected for a cooldown, so a
+//! frontend retrying in a loop can't keep hammering a broken reducer. The circuit
+//! opening is logged and emitted as a [`CIRCUIT_OPEN_EVENT`]:
+//!
+//! ```json
+//! { "kind": "SYNC_NOW", "failures": 5, "cooldownMs": 30000, "error": "State error: ..." }
+//! ```
+//!
+//! Once the cooldown has passed, the next dispatch goes through; if it fails again,
+//! the circuit opens right away. A success closes it. Actions rejected by guards don't
+//! count as failures.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::Action;
+
+/// Event emitted when an action kind's circuit opens.
+pub const CIRCUIT_OPEN_EVENT: &str = "rstate://circuit-open";
+
+/// Payload of [`CIRCUIT_OPEN_EVENT`].
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitOpen {
+    /// The action kind rejected from now on
+    pub kind: String,
+    /// Number of failures in a row
+    pub failures: u32,
+    /// How long the kind is rejected, in milliseconds
+    pub cooldown_ms: u64,
+    /// The last error
+    pub error: String,
+}
+
+#[derive(Default)]
+struct Circuit {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+pub(crate) struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            circuits: Mutex::default(),
+        }
+    }
+
+    // Reject `action` if its kind's circuit is open
+    pub(crate) fn check(&self, action: &Action) -> crate::Result<()> {
+        let Ok(mut circuits) = self.circuits.lock() else {
+            return Ok(());
+        };
+        let Some(circuit) = circuits.get_mut(&action.kind) else {
+            return Ok(());
+        };
+        match circuit.open_until {
+            Some(until) if Instant::now() < until => Err(crate::RstateError::rejected(format!(
+                "{} failed {} times in a row; retry in {}ms",
+                action.kind,
+                circuit.failures,
+                until.saturating_duration_since(Instant::now()).as_millis()
+            ))),
+            Some(_) => {
+                // Let a trial through: one more failure opens the circuit again
+                circuit.open_until = None;
+                circuit.failures = self.threshold - 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Count the outcome of a dispatch. Returns the details if the circuit just opened.
+    pub(crate) fn record<T>(
+        &self,
+        action: &Action,
+        result: &crate::Result<T>,
+    ) -> Option<CircuitOpen> {
+        let mut circuits = self.circuits.lock().ok()?;
+        let error = match result {
+            Ok(_) => {
+                circuits.remove(&action.kind);
+                return None;
+            }
+            Err(crate::RstateError::Rejected(_)) => return None,
+            Err(err) => err,
+        };
+
+        let circuit = circuits.entry(action.kind.clone()).or_default();
+        circuit.failures += 1;
+        if circuit.failures < self.threshold || circuit.open_until.is_some() {
+            return None;
+        }
+        circuit.open_until = Some(Instant::now() + self.cooldown);
+        Some(CircuitOpen {
+            kind: action.kind.clone(),
+            failures: circuit.failures,
+            cooldown_ms: self.cooldown.as_millis() as u64,
+            error: error.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_after_failures_in_a_row() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(20));
+        let action = Action::new("SYNC");
+        let failure: crate::Result<()> = Err(crate::RstateError::state("offline"));
+
+        assert_eq!(breaker.record(&action, &failure), None);
+        breaker.record(&action, &Ok(()));
+        assert_eq!(breaker.record(&action, &failure), None);
+        let open = breaker.record(&action, &failure).unwrap();
+        assert_eq!((open.kind.as_str(), open.failures), ("SYNC", 2));
+
+        assert!(breaker.check(&action).is_err());
+        assert!(breaker.check(&Action::new("OTHER")).is_ok());
+        // Its own rejections don't count
+        assert_eq!(breaker.record(&action, &breaker.check(&action)), None);
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.check(&action).is_ok());
+        assert!(breaker.record(&action, &failure).is_some());
+        assert!(breaker.check(&action).is_err());
+    }
+}
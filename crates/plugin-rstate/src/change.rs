@@ -0,0 +1,241 @@
+//! Change detection between state snapshots.
+//!
+//! Besides deciding whether an update needs to be emitted at all, this module can
+//! trim unchanged parts out of an update (see [`Builder::trim_unchanged`](crate::Builder::trim_unchanged)).
+//! Each unchanged top-level key is replaced by a sentinel object:
+//!
+//! ```json
+//! { "todos": { "$unchanged": 41 }, "counter": 42 }
+//! ```
+//!
+//! meaning "`todos` is identical to its value at revision 41". A window that cached
+//! the state of that revision can keep its copy instead of deserializing it again;
+//! any other window should fetch the full state with `get_initial_state`.
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::json;
+
+use crate::models::JsonValue;
+
+pub use tauri_plugin_rstate_core::{StateUpdate, UNCHANGED_KEY};
+
+/// How change detection compares floating-point numbers.
+///
+/// Integers are always compared exactly. Set per store with
+/// [`StateBuilder::float_comparison`](crate::StateBuilder::float_comparison), or by
+/// overriding [`RstateManager::float_comparison`](crate::RstateManager::float_comparison).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatComparison {
+    /// Equal only if identical, like `serde_json` values themselves
+    Exact,
+    /// Equal if the difference is at most the given absolute tolerance.
+    /// The default is `Absolute(f64::EPSILON)`, which only suits values around 1.0.
+    Absolute(f64),
+    /// Equal if the difference is at most the given fraction of the larger magnitude
+    Relative(f64),
+    /// Equal if at most the given number of representable doubles apart
+    Ulps(u64),
+}
+
+impl Default for FloatComparison {
+    fn default() -> Self {
+        Self::Absolute(f64::EPSILON)
+    }
+}
+
+impl FloatComparison {
+    fn equal(self, a: f64, b: f64) -> bool {
+        match self {
+            Self::Exact => a == b,
+            Self::Absolute(tolerance) => (a - b).abs() <= tolerance,
+            Self::Relative(tolerance) => {
+                a == b || (a - b).abs() <= tolerance * a.abs().max(b.abs())
+            }
+            Self::Ulps(max) => a == b || ulps_between(a, b).is_some_and(|ulps| ulps <= max),
+        }
+    }
+}
+
+// Distance between two doubles in units in the last place, or `None` for NaN
+fn ulps_between(a: f64, b: f64) -> Option<u64> {
+    if a.is_nan() || b.is_nan() {
+        return None;
+    }
+    // Map the bit patterns onto a monotonic integer line, with -0.0 and 0.0 adjacent
+    let ordered = |x: f64| {
+        let bits = x.to_bits() as i64;
+        if bits < 0 { i64::MIN - bits } else { bits }
+    };
+    Some(ordered(a).abs_diff(ordered(b)))
+}
+
+// Compare two JSON values for equality (deep comparison).
+// Prevents unnecessary state update events when values haven't changed.
+pub(crate) fn states_are_equal(
+    current: &JsonValue,
+    updated: &JsonValue,
+    floats: FloatComparison,
+) -> bool {
+    match (current, updated) {
+        // Both are null
+        (JsonValue::Null, JsonValue::Null) => true,
+
+        // Both are booleans
+        (JsonValue::Bool(a), JsonValue::Bool(b)) => a == b,
+
+        // Both are numbers - compare as integers first, then as floats
+        (JsonValue::Number(a), JsonValue::Number(b)) => {
+            // Try to compare as integers first, then as floats
+            if let (Some(a_int), Some(b_int)) = (a.as_i64(), b.as_i64()) {
+                a_int == b_int
+            } else if let (Some(a_float), Some(b_float)) = (a.as_f64(), b.as_f64()) {
+                floats.equal(a_float, b_float)
+            } else {
+                false
+            }
+        }
+
+        // Both are strings
+        (JsonValue::String(a), JsonValue::String(b)) => a == b,
+
+        // Both are arrays - compare length and each element
+        (JsonValue::Array(a), JsonValue::Array(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|(a_item, b_item)| states_are_equal(a_item, b_item, floats))
+        }
+
+        // Both are objects - compare all key-value pairs
+        (JsonValue::Object(a), JsonValue::Object(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|(key, a_value)| {
+                    b.get(key)
+                        .is_some_and(|b_value| states_are_equal(a_value, b_value, floats))
+                })
+        }
+
+        // Different types or one is null and the other isn't
+        _ => false,
+    }
+}
+
+// Replace the top-level subtrees of `updated` that are equal in `previous` with
+// an `{"$unchanged": previous_revision}` sentinel.
+// Non-object states can't be trimmed and are returned as is.
+pub(crate) fn trim_unchanged(
+    previous: &JsonValue,
+    updated: &JsonValue,
+    previous_revision: u64,
+    floats: FloatComparison,
+) -> JsonValue {
+    let (JsonValue::Object(previous), JsonValue::Object(updated)) = (previous, updated) else {
+        return updated.clone();
+    };
+
+    let trimmed = updated
+        .iter()
+        .map(|(key, value)| {
+            let unchanged = previous
+                .get(key)
+                .is_some_and(|previous_value| states_are_equal(previous_value, value, floats));
+            let value = if unchanged {
+                json!({ UNCHANGED_KEY: previous_revision })
+            } else {
+                value.clone()
+            };
+            (key.clone(), value)
+        })
+        .collect();
+    JsonValue::Object(trimmed)
+}
+
+// Apply `f` to `state` as a `T`
+pub(crate) fn typed_update<T, F>(state: &mut JsonValue, f: F) -> crate::Result<()>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce(&mut T),
+{
+    let mut typed: T = serde_json::from_value(state.clone())
+        .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+    f(&mut typed);
+    *state = serde_json::to_value(typed)
+        .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+    Ok(())
+}
+
+// Replace the existing value at a dot-notation `key` of `state`
+pub(crate) fn replace_key(state: &mut JsonValue, key: &str, value: JsonValue) -> crate::Result<()> {
+    let target = Some(key)
+        .filter(|key| !key.is_empty())
+        .and_then(|key| state.pointer_mut(&format!("/{}", key.replace('.', "/"))))
+        .ok_or_else(|| crate::RstateError::state(format!("No such key: {key}")))?;
+    *target = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_key_only_replaces_existing_keys() {
+        let mut state = json!({ "settings": { "theme": "light" }, "session": { "user": 1 } });
+        replace_key(&mut state, "settings", json!({ "theme": "dark" })).unwrap();
+        assert_eq!(
+            state,
+            json!({ "settings": { "theme": "dark" }, "session": { "user": 1 } })
+        );
+        assert!(replace_key(&mut state, "setings", json!({})).is_err());
+        assert!(replace_key(&mut state, "", json!({})).is_err());
+    }
+
+    #[test]
+    fn test_states_are_equal_compares_deeply() {
+        let floats = FloatComparison::default();
+        let a = json!({ "counter": 1, "todos": [{ "done": false }] });
+        assert!(states_are_equal(&a, &a.clone(), floats));
+        assert!(!states_are_equal(
+            &a,
+            &json!({ "counter": 1, "todos": [{ "done": true }] }),
+            floats
+        ));
+        assert!(!states_are_equal(&json!(1), &json!("1"), floats));
+        assert!(states_are_equal(&json!(1.5), &json!(1.5), floats));
+    }
+
+    #[test]
+    fn test_float_comparison_strategies() {
+        let equal = |floats, a: f64, b: f64| states_are_equal(&json!(a), &json!(b), floats);
+        let next = |x: f64| f64::from_bits(x.to_bits() + 1);
+
+        // The default absolute epsilon misses changes of tiny values...
+        assert!(equal(FloatComparison::default(), 1e-20, 2e-20));
+        assert!(!equal(FloatComparison::Exact, 1e-20, 2e-20));
+        assert!(!equal(FloatComparison::Relative(1e-9), 1e-20, 2e-20));
+        // ...and treats neighbouring large values as different
+        assert!(!equal(FloatComparison::default(), 1e10, next(1e10)));
+        assert!(equal(FloatComparison::Relative(1e-9), 1e10, next(1e10)));
+        assert!(equal(FloatComparison::Ulps(1), 1e10, next(1e10)));
+        assert!(!equal(FloatComparison::Ulps(1), 1e10, next(next(1e10))));
+        assert!(equal(FloatComparison::Ulps(1), -0.0, 0.0));
+    }
+
+    #[test]
+    fn test_trim_unchanged_replaces_equal_subtrees() {
+        let previous = json!({ "counter": 1, "todos": ["a", "b"], "removed": true });
+        let updated = json!({ "counter": 2, "todos": ["a", "b"], "added": null });
+
+        let trimmed = trim_unchanged(&previous, &updated, 7, FloatComparison::default());
+        assert_eq!(
+            trimmed,
+            json!({ "counter": 2, "todos": { "$unchanged": 7 }, "added": null })
+        );
+
+        // Non-object states are sent as is
+        assert_eq!(
+            trim_unchanged(&json!(1), &json!(2), 7, FloatComparison::default()),
+            json!(2)
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! The [`RstateClient`] of other plugins.
+//!
+//! The plugin registers a [`RstateClientHandle`] during its setup, so plugins
+//! depending only on the small `tauri-plugin-rstate-client` crate can reach the
+//! app-wide store through their `AppHandle`. Their subscriptions go through the same
+//! channels as the frontend's, under a window label of their own.
+
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_rstate_client::{ClientError, Listener, RstateClient, RstateClientHandle};
+
+use crate::RstateExt;
+use crate::models::{Action, JsonValue, StoreScope};
+
+/// Window label the subscriptions of other plugins are filed under.
+pub const CLIENT_WINDOW_LABEL: &str = "@@client";
+
+// Forwards the client calls to the plugin
+struct PluginClient<R: Runtime> {
+    app: AppHandle<R>,
+}
+
+impl<R: Runtime> RstateClient for PluginClient<R> {
+    fn dispatch(
+        &self,
+        kind: &str,
+        payload: Option<JsonValue>,
+    ) -> tauri_plugin_rstate_client::Result<JsonValue> {
+        let action = Action {
+            payload,
+            ..Action::new(kind)
+        };
+        self.app
+            .rstate()
+            .dispatch(action)
+            .map_err(into_client_error)
+    }
+
+    fn get_state(&self, key: &str) -> tauri_plugin_rstate_client::Result<Option<JsonValue>> {
+        self.app.rstate().get_state(key).map_err(into_client_error)
+    }
+
+    fn subscribe(
+        &self,
+        key: Option<&str>,
+        listener: Listener,
+    ) -> tauri_plugin_rstate_client::Result<u64> {
+        self.app
+            .rstate()
+            .subscribe(
+                CLIENT_WINDOW_LABEL,
+                StoreScope::App,
+                key.map(str::to_owned),
+                listener_channel(listener),
+            )
+            .map_err(into_client_error)
+    }
+
+    fn unsubscribe(&self, id: u64) -> bool {
+        self.app.rstate().unsubscribe(id)
+    }
+}
+
+// The client of the plugin of `app`, to be managed by the app
+pub(crate) fn handle<R: Runtime>(app: &AppHandle<R>) -> RstateClientHandle {
+    RstateClientHandle::new(PluginClient { app: app.clone() })
+}
+
+// A channel calling `listener` with the values sent to it
+fn listener_channel(listener: Listener) -> Channel<JsonValue> {
+    Channel::new(move |body| {
+        if let InvokeResponseBody::Json(json) = body {
+            let value = serde_json::from_str(&json)?;
+            listener(&value);
+        }
+        Ok(())
+    })
+}
+
+fn into_client_error(err: crate::RstateError) -> ClientError {
+    ClientError::Rstate(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subscriptions::Subscriptions;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_listener_channel_receives_updates() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let channel = listener_channel(Box::new(move |value| {
+            sink.lock().unwrap().push(value.clone());
+        }));
+
+        let subscriptions = Subscriptions::default();
+        subscriptions
+            .add(
+                "update",
+                CLIENT_WINDOW_LABEL,
+                Some("counter".to_owned()),
+                channel,
+                &json!({ "counter": 0 }),
+            )
+            .unwrap();
+        subscriptions.notify("update", &json!({ "counter": 1 }));
+        assert_eq!(*received.lock().unwrap(), [json!(0), json!(1)]);
+
+        assert_eq!(
+            into_client_error(crate::RstateError::rejected("nope")).to_string(),
+            crate::RstateError::rejected("nope").to_string()
+        );
+    }
+}
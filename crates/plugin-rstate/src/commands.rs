@@ -1,23 +1,336 @@
-use tauri::{AppHandle, Runtime, command};
+use tauri::ipc::{Channel, CommandScope};
+use tauri::{AppHandle, Runtime, Window, command};
 
 use crate::Result;
 use crate::RstateExt;
-use crate::models::{Action, JsonValue};
+use crate::action_scope::{self, ActionScope};
+use crate::event_log::HistoryPage;
+use crate::health::Health;
+use crate::history::Changes;
+use crate::models::{Action, ActionKinds, JsonValue, StoreScope, TagFrontend};
+use crate::schema::SchemaFingerprint;
+use crate::timings::ActionTiming;
+
+// Number of logged actions in a page of `get_persisted_history`, by default and at most
+const DEFAULT_HISTORY_PAGE: usize = 100;
+const MAX_HISTORY_PAGE: usize = 1000;
 
 /// Get the initial/full state.
+///
+/// Waits for a state manager to be registered if a registration timeout is configured.
+/// With [envelope responses](crate::Builder::envelope_responses), resolves to the state
+/// along with its revision.
+///
+/// With the `revision` of the state the window already has, resolves to the
+/// `{"$unchanged": revision}` sentinel if the store is still at that revision.
+///
+/// A window with a [read token](crate::Rstate::mint_read_token) must pass it, and only
+/// gets the parts of the app-wide state the token covers.
 #[command]
-pub(crate) fn get_initial_state<R: Runtime>(app: AppHandle<R>) -> Result<JsonValue> {
-    app.rstate().get_initial_state()
+pub(crate) async fn get_initial_state<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    scope: Option<StoreScope>,
+    revision: Option<u64>,
+    token: Option<String>,
+) -> Result<JsonValue> {
+    let scope = scope.unwrap_or_default();
+    let read_scope = app.rstate().read_scope(window.label(), token.as_deref())?;
+    if let (StoreScope::App, Some(read_scope)) = (scope, read_scope) {
+        app.rstate().wait_for_registration().await?;
+        let mut update = app.rstate().get_state_with_revision()?;
+        update.state = read_scope.filter(&update.state);
+        return app.rstate().respond_with(scope, window.label(), update);
+    }
+    if let Some(known) = revision {
+        let update = match scope {
+            StoreScope::App => {
+                app.rstate().wait_for_registration().await?;
+                app.rstate().get_state_if_modified(known)
+            }
+            StoreScope::Window => app
+                .rstate()
+                .get_window_state_if_modified(window.label(), known),
+        }?;
+        return app.rstate().respond_with(scope, window.label(), update);
+    }
+    let state = match scope {
+        StoreScope::App => {
+            app.rstate().wait_for_registration().await?;
+            app.rstate().get_initial_state()
+        }
+        StoreScope::Window => app.rstate().get_window_initial_state(window.label()),
+    }?;
+    app.rstate().respond(scope, window.label(), state)
+}
+
+/// Get the changes of the app-wide store since the revision `since`: a JSON Patch, or
+/// the full state if the state of that revision is no longer known.
+///
+/// A window with a [read token](crate::Rstate::mint_read_token) must pass it, and
+/// always gets the parts of the state the token covers instead of a patch.
+#[command]
+pub(crate) async fn get_changes_since<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    since: u64,
+    token: Option<String>,
+) -> Result<Changes> {
+    let read_scope = app.rstate().read_scope(window.label(), token.as_deref())?;
+    app.rstate().wait_for_registration().await?;
+    let Some(read_scope) = read_scope else {
+        return app.rstate().get_redacted_changes_since(since);
+    };
+    let update = app.rstate().get_state_with_revision()?;
+    let state = read_scope.filter(&update.state);
+    Ok(Changes {
+        revision: update.revision,
+        patch: None,
+        state: Some(
+            app.rstate()
+                .redact(StoreScope::App, window.label(), "", state)?,
+        ),
+    })
 }
 
 /// Get a specific part of the state by key.
+///
+/// A window with a [read token](crate::Rstate::mint_read_token) must pass it, and
+/// only reads the keys of the app-wide state the token covers.
+/// Paths hidden with [`StateBuilder::redact`](crate::StateBuilder::redact) read as
+/// `"[redacted]"`.
+#[command]
+pub(crate) fn get_state<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    key: &str,
+    scope: Option<StoreScope>,
+    token: Option<String>,
+) -> Result<Option<JsonValue>> {
+    let scope = scope.unwrap_or_default();
+    let read_scope = app.rstate().read_scope(window.label(), token.as_deref())?;
+    let value = match scope {
+        StoreScope::App => {
+            if let Some(read_scope) = read_scope {
+                read_scope.check(Some(key))?;
+            }
+            app.rstate().get_state(key)
+        }
+        StoreScope::Window => app.rstate().get_window_state(window.label(), key),
+    }?;
+    value
+        .map(|value| app.rstate().redact(scope, window.label(), key, value))
+        .transpose()
+}
+
+/// Compute a selector, a value derived from the state.
+///
+/// Waits for a state manager to be registered if a registration timeout is configured.
+/// A window with a [read token](crate::Rstate::mint_read_token) can't compute selectors
+/// of the app-wide store, which may read any part of the state.
+#[command]
+pub(crate) async fn get_selector<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    name: &str,
+    scope: Option<StoreScope>,
+    token: Option<String>,
+) -> Result<JsonValue> {
+    let read_scope = app.rstate().read_scope(window.label(), token.as_deref())?;
+    match scope.unwrap_or_default() {
+        StoreScope::App => {
+            if read_scope.is_some() {
+                return Err(crate::RstateError::rejected(format!(
+                    "read token doesn't cover selector '{name}'"
+                )));
+            }
+            app.rstate().wait_for_registration().await?;
+            app.rstate().select(name)
+        }
+        StoreScope::Window => app.rstate().select_in_window(window.label(), name),
+    }
+}
+
+/// Dispatch several actions in order, with a single state update at the end.
+///
+/// Fails without dispatching any if one is out of the command's
+/// [scope](crate::ActionScope) for the calling window.
+#[command]
+pub(crate) fn dispatch_batch<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    actions: Vec<Action>,
+    scope: Option<StoreScope>,
+    allowed: CommandScope<ActionScope>,
+) -> Result<JsonValue> {
+    actions.iter().try_for_each(|action| {
+        action_scope::check(&allowed, window.label(), &action.kind)?;
+        app.rstate().check_payload_size(action)
+    })?;
+    // Never trust the metadata claimed by the frontend
+    let actions = actions
+        .into_iter()
+        .map(|action| action.tag_frontend(Some(window.label())))
+        .collect();
+    let scope = scope.unwrap_or_default();
+    let state = match scope {
+        StoreScope::App => app.rstate().dispatch_many(actions),
+        StoreScope::Window => app
+            .rstate()
+            .dispatch_many_to_window(window.label(), actions),
+    }?;
+    app.rstate().respond(scope, window.label(), state)
+}
+
+/// Restore the initial state of the app-wide store.
+///
+/// Dispatches [`RESET_ACTION`](crate::RESET_ACTION) as coming from the frontend, so
+/// guards can reject it.
+#[command]
+pub(crate) fn reset_state<R: Runtime>(app: AppHandle<R>, window: Window<R>) -> Result<JsonValue> {
+    let state = app
+        .rstate()
+        .dispatch(Action::new(crate::RESET_ACTION).tag_frontend(Some(window.label())))?;
+    app.rstate().respond(StoreScope::App, window.label(), state)
+}
+
+/// Get the schema fingerprint of the app-wide store's state, if its manager has one.
+///
+/// Compare it with the fingerprint the frontend's types were generated from. Waits for a
+/// state manager to be registered if a registration timeout is configured.
+#[command]
+pub(crate) async fn get_schema<R: Runtime>(app: AppHandle<R>) -> Result<Option<SchemaFingerprint>> {
+    app.rstate().wait_for_registration().await?;
+    app.rstate().schema()
+}
+
+/// List the action kinds the app-wide store and its slices handle, e.g. to validate
+/// dispatches or offer autocompletion. Waits for a state manager to be registered if
+/// a registration timeout is configured.
+#[command]
+pub(crate) async fn list_actions<R: Runtime>(app: AppHandle<R>) -> Result<ActionKinds> {
+    app.rstate().wait_for_registration().await?;
+    app.rstate().action_kinds()
+}
+
+/// Report the handler timings of every action kind, slowest first.
+///
+/// Empty unless enabled with [`Builder::time_actions`](crate::Builder::time_actions).
+#[command]
+pub(crate) fn action_timings<R: Runtime>(app: AppHandle<R>) -> Vec<ActionTiming> {
+    app.rstate().action_timings()
+}
+
+/// Page through the persisted action log of the app-wide store, when it is
+/// [event-sourced](crate::StateBuilder::event_sourced): up to `limit` (default: 100, at
+/// most 1000) of the actions logged after the sequence number `after`, oldest first.
+/// Pass the page's `next` as `after` to get the next one.
+///
+/// Requires the `rstate:persisted-history` permission set. The payloads of stores
+/// that [redact](crate::StateBuilder::redact) paths read as `"[redacted]"`. Rejected
+/// for windows with a [read token](crate::Rstate::mint_read_token).
+#[command]
+pub(crate) async fn get_persisted_history<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    after: Option<u64>,
+    limit: Option<usize>,
+    token: Option<String>,
+) -> Result<HistoryPage> {
+    if app
+        .rstate()
+        .read_scope(window.label(), token.as_deref())?
+        .is_some()
+    {
+        return Err(crate::RstateError::rejected(
+            "read token doesn't cover the action log",
+        ));
+    }
+    app.rstate().wait_for_registration().await?;
+    let limit = limit
+        .unwrap_or(DEFAULT_HISTORY_PAGE)
+        .clamp(1, MAX_HISTORY_PAGE);
+    app.rstate()
+        .get_redacted_persisted_history(after.unwrap_or_default(), limit)
+}
+
+/// Report the health of the app-wide store.
+#[command]
+pub(crate) fn health_check<R: Runtime>(app: AppHandle<R>) -> Health {
+    app.rstate().health_check()
+}
+
+/// Report whether the calling window is listening for state updates.
+///
+/// Call it with `listening: true` when starting to listen and then periodically, and
+/// with `listening: false` when stopping.
+#[command]
+pub(crate) fn heartbeat<R: Runtime>(app: AppHandle<R>, window: Window<R>, listening: bool) {
+    app.rstate().heartbeat(window.label(), listening);
+}
+
+/// Stream the state, or the value at `key`, to `channel`: now, then after every change.
+///
+/// Returns the subscription id, to pass to `unsubscribe`. A window with a
+/// [read token](crate::Rstate::mint_read_token) must pass it, and can only subscribe to
+/// keys of the app-wide state the token covers.
+#[command]
+pub(crate) async fn subscribe<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    key: Option<String>,
+    scope: Option<StoreScope>,
+    channel: Channel<JsonValue>,
+    token: Option<String>,
+) -> Result<u64> {
+    let scope = scope.unwrap_or_default();
+    let read_scope = app.rstate().read_scope(window.label(), token.as_deref())?;
+    if scope == StoreScope::App {
+        if let Some(read_scope) = read_scope {
+            read_scope.check(key.as_deref())?;
+        }
+        app.rstate().wait_for_registration().await?;
+    }
+    app.rstate().subscribe(window.label(), scope, key, channel)
+}
+
+/// End a subscription. Returns `false` if there was none with this id.
 #[command]
-pub(crate) fn get_state<R: Runtime>(app: AppHandle<R>, key: &str) -> Result<Option<JsonValue>> {
-    app.rstate().get_state(key)
+pub(crate) fn unsubscribe<R: Runtime>(app: AppHandle<R>, id: u64) -> bool {
+    app.rstate().unsubscribe(id)
 }
 
 /// Dispatch an action to modify the state.
+///
+/// Fails with [`RstateError::Forbidden`](crate::RstateError::Forbidden) if the kind is
+/// out of the command's [scope](crate::ActionScope) for the calling window.
+/// With `dry_run`, resolves to a [`DryRun`](crate::DryRun) instead, without modifying
+/// the store: the state the action would produce, and the RFC 6902 JSON Patch to it
+/// from the current state.
+/// With [envelope responses](crate::Builder::envelope_responses), resolves to the state
+/// along with its revision.
 #[command]
-pub(crate) fn dispatch<R: Runtime>(app: AppHandle<R>, action: Action) -> Result<JsonValue> {
-    app.rstate().dispatch(action)
+pub(crate) async fn dispatch<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    action: Action,
+    scope: Option<StoreScope>,
+    dry_run: Option<bool>,
+    allowed: CommandScope<ActionScope>,
+) -> Result<JsonValue> {
+    action_scope::check(&allowed, window.label(), &action.kind)?;
+    app.rstate().check_payload_size(&action)?;
+    // Never trust the metadata claimed by the frontend
+    let action = action.tag_frontend(Some(window.label()));
+    let scope = scope.unwrap_or_default();
+    if dry_run.unwrap_or(false) {
+        let preview = app.rstate().dry_run(scope, window.label(), action)?;
+        return serde_json::to_value(preview)
+            .map_err(|e| crate::RstateError::serialization(e.to_string()));
+    }
+    let state = match scope {
+        StoreScope::App => app.rstate().dispatch_batched(action).await,
+        StoreScope::Window => app.rstate().dispatch_to_window(window.label(), action),
+    }?;
+    app.rstate().respond(scope, window.label(), state)
 }
@@ -0,0 +1,144 @@
+//! Values derived from the app-wide state, cached until their dependencies change.
+//!
+//! Backend components often derive values from the state, e.g. a tray tooltip from
+//! the number of unread messages. [`Rstate::computed`](crate::Rstate::computed)
+//! returns a [`Computed`] handle whose [`get`](Computed::get) only recomputes the
+//! value when the state at one of its dependency paths changed.
+//!
+//! ```rust,ignore
+//! let tooltip = app.rstate().computed(["inbox.unread"], |state| {
+//!     format!("{} unread", state["inbox"]["unread"])
+//! });
+//!
+//! // Later, as often as needed
+//! tray.set_tooltip(Some(tooltip.get()?))?;
+//! ```
+
+use std::sync::Mutex;
+use tauri::{AppHandle, Runtime};
+
+use crate::RstateExt;
+use crate::models::{JsonValue, get_state};
+
+// Last computed value, with the revision and dependency values it was computed from
+struct Cached<V> {
+    revision: u64,
+    deps: Vec<Option<JsonValue>>,
+    value: V,
+}
+
+/// A value computed from the app-wide state, see [`Rstate::computed`](crate::Rstate::computed).
+pub struct Computed<R: Runtime, V> {
+    app: AppHandle<R>,
+    deps: Vec<String>,
+    compute: Box<dyn Fn(&JsonValue) -> V + Send + Sync>,
+    cached: Mutex<Option<Cached<V>>>,
+}
+
+impl<R: Runtime, V: Clone> Computed<R, V> {
+    pub(crate) fn new(
+        app: AppHandle<R>,
+        deps: Vec<String>,
+        compute: Box<dyn Fn(&JsonValue) -> V + Send + Sync>,
+    ) -> Self {
+        Self {
+            app,
+            deps,
+            compute,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Get the value, recomputing it if one of its dependencies changed.
+    pub fn get(&self) -> crate::Result<V> {
+        let rstate = self.app.rstate();
+        let mut cached = self
+            .cached
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+        refresh(
+            &mut cached,
+            rstate.revision(),
+            || rstate.get_initial_state(),
+            &self.deps,
+            &self.compute,
+        )
+    }
+}
+
+// Get the cached value, recomputing it if a dependency changed since it was cached
+fn refresh<V: Clone>(
+    cached: &mut Option<Cached<V>>,
+    revision: u64,
+    read_state: impl FnOnce() -> crate::Result<JsonValue>,
+    deps: &[String],
+    compute: &dyn Fn(&JsonValue) -> V,
+) -> crate::Result<V> {
+    // Nothing changed at all since the last call
+    if let Some(cached) = cached.as_ref()
+        && cached.revision == revision
+    {
+        return Ok(cached.value.clone());
+    }
+
+    let state = read_state()?;
+    let dep_values: Vec<_> = deps.iter().map(|path| get_state(&state, path)).collect();
+    if let Some(cached) = cached.as_mut()
+        && cached.deps == dep_values
+    {
+        cached.revision = revision;
+        return Ok(cached.value.clone());
+    }
+
+    let value = compute(&state);
+    *cached = Some(Cached {
+        revision,
+        deps: dep_values,
+        value: value.clone(),
+    });
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_refresh_recomputes_only_on_dependency_changes() {
+        let runs = AtomicUsize::new(0);
+        let compute = |state: &JsonValue| {
+            runs.fetch_add(1, Ordering::SeqCst);
+            state["inbox"]["unread"].as_u64().unwrap_or_default()
+        };
+        let deps = ["inbox.unread".to_owned()];
+        let mut cached = None;
+
+        let state = json!({ "inbox": { "unread": 2 }, "counter": 0 });
+        assert_eq!(
+            refresh(&mut cached, 0, || Ok(state.clone()), &deps, &compute).unwrap(),
+            2
+        );
+        // Same revision: the state isn't even read
+        assert_eq!(
+            refresh(&mut cached, 0, || unreachable!(), &deps, &compute).unwrap(),
+            2
+        );
+
+        // Another part of the state changed
+        let state = json!({ "inbox": { "unread": 2 }, "counter": 1 });
+        assert_eq!(
+            refresh(&mut cached, 1, || Ok(state.clone()), &deps, &compute).unwrap(),
+            2
+        );
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        let state = json!({ "inbox": { "unread": 3 }, "counter": 1 });
+        assert_eq!(
+            refresh(&mut cached, 2, || Ok(state.clone()), &deps, &compute).unwrap(),
+            3
+        );
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
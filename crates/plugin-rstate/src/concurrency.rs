@@ -0,0 +1,111 @@
+//! Concurrency groups.
+//!
+//! Action kinds can be assigned to named groups with
+//! [`Builder::concurrency_group`](crate::Builder::concurrency_group). Actions of the
+//! same group always run one at a time, in dispatch order, even across stores: the
+//! app-wide store, its slices and the window stores each have their own lock. Kinds
+//! not assigned to a group take no group lock, and are only serialized with the other
+//! dispatches to their store.
+//!
+//! ```rust,ignore
+//! tauri_plugin_rstate::Builder::new()
+//!     .concurrency_group("playback", ["PLAY", "PAUSE", "SEEK"])
+//!     .concurrency_group("library", ["IMPORT_TRACKS", "REMOVE_TRACK"])
+//!     .build()
+//! ```
+//!
+//! A group is only held while the store is modified, not while the update is
+//! published, so watchers may dispatch actions of the same group.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::models::Action;
+
+// The concurrency group of every declared kind, along with the group locks
+#[derive(Default)]
+pub(crate) struct ConcurrencyGroups {
+    names: Vec<String>,
+    kinds: HashMap<String, usize>,
+    locks: Vec<Mutex<()>>,
+}
+
+impl ConcurrencyGroups {
+    // Assign `kinds` to the group `name`, creating it if needed. A kind belongs to the
+    // last group it was assigned to.
+    pub(crate) fn declare(&mut self, name: String, kinds: impl IntoIterator<Item = String>) {
+        let index = match self.names.iter().position(|group| *group == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name);
+                self.locks.push(Mutex::new(()));
+                self.names.len() - 1
+            }
+        };
+        for kind in kinds {
+            self.kinds.insert(kind, index);
+        }
+    }
+
+    // The group of `kind`, if it was assigned to one
+    pub(crate) fn group(&self, kind: &str) -> Option<&str> {
+        self.kinds
+            .get(kind)
+            .map(|&index| self.names[index].as_str())
+    }
+
+    // Hold the groups of `actions` until the guards are dropped. Actions not in a group
+    // hold nothing. Groups are always locked in the same order, so overlapping batches
+    // can't deadlock.
+    pub(crate) fn enter(&self, actions: &[Action]) -> crate::Result<Vec<MutexGuard<'_, ()>>> {
+        let mut indices: Vec<usize> = actions
+            .iter()
+            .filter_map(|action| self.kinds.get(&action.kind).copied())
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+            .into_iter()
+            .map(|index| {
+                self.locks[index]
+                    .lock()
+                    .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::TryLockError;
+
+    #[test]
+    fn test_actions_hold_their_groups() {
+        let mut groups = ConcurrencyGroups::default();
+        groups.declare("playback".into(), ["PLAY".into(), "PAUSE".into()]);
+        groups.declare("library".into(), ["IMPORT".into()]);
+        groups.declare("playback".into(), ["SEEK".into()]);
+        assert_eq!(groups.group("SEEK"), Some("playback"));
+        assert_eq!(groups.group("INCREMENT"), None);
+
+        let playing = groups.enter(&[Action::new("PLAY")]).unwrap();
+        assert!(matches!(
+            groups.locks[0].try_lock(),
+            Err(TryLockError::WouldBlock)
+        ));
+        // Other groups are free, and ungrouped kinds hold none
+        let batch = groups
+            .enter(&[Action::new("IMPORT"), Action::new("INCREMENT")])
+            .unwrap();
+        assert_eq!(batch.len(), 1);
+        assert!(
+            groups
+                .enter(&[Action::new("INCREMENT")])
+                .unwrap()
+                .is_empty()
+        );
+        drop(playing);
+        assert!(groups.locks[0].try_lock().is_ok());
+    }
+}
@@ -0,0 +1,54 @@
+//! Plugin configuration from `tauri.conf.json`.
+//!
+//! Some options can be set in the app's config instead of in code, under
+//! `plugins.rstate`:
+//!
+//! ```json
+//! { "plugins": { "rstate": { "coalesceMs": 16 } } }
+//! ```
+//!
+//! Options set on the [`Builder`](crate::Builder) take precedence.
+
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::emit_policy::EmitPolicy;
+
+/// Configuration of the plugin, under `plugins.rstate` in `tauri.conf.json`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    /// Coalesce state updates, emitting at most one per window of this many
+    /// milliseconds (see [`EmitPolicy::coalesced`]). `0` emits every update right away.
+    #[serde(default)]
+    pub coalesce_ms: Option<u64>,
+}
+
+impl Config {
+    // The global emit policy set by the config, if any
+    pub(crate) fn emit_policy(&self) -> Option<EmitPolicy> {
+        self.coalesce_ms.map(|ms| match ms {
+            0 => EmitPolicy::immediate(),
+            ms => EmitPolicy::coalesced(Duration::from_millis(ms)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_sets_the_emit_policy() {
+        let config: Config = serde_json::from_value(json!({ "coalesceMs": 16 })).unwrap();
+        assert_eq!(
+            config.emit_policy(),
+            Some(EmitPolicy::coalesced(Duration::from_millis(16)))
+        );
+        let config: Config = serde_json::from_value(json!({ "coalesceMs": 0 })).unwrap();
+        assert_eq!(config.emit_policy(), Some(EmitPolicy::immediate()));
+        let config: Config = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(config.emit_policy(), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! Support bundles.
+//!
+//! [`Rstate::export_diagnostics`](crate::Rstate::export_diagnostics) writes a zip
+//! archive a user can attach to a bug report, containing:
+//!
+//! - `state.json`: the app-wide state, with the paths configured through
+//!   [`Builder::redact`](crate::Builder::redact), and those hidden from the frontend
+//!   with [`StateBuilder::redact`](crate::StateBuilder::redact) (slices included),
+//!   replaced by `"[redacted]"`
+//! - `actions.json`: the most recent dispatched actions and their outcome
+//! - `stats.json`: dispatch counters, the current revision, the windows listening
+//!   for updates and, if enabled with [`Builder::time_actions`](crate::Builder::time_actions),
+//!   the handler timings of every action kind
+//! - `version.json`: the version of the state set with
+//!   [`StateBuilder::version`](crate::StateBuilder::version) and the plugin version
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::RstateError;
+use crate::models::Action;
+use crate::timings::ActionTiming;
+
+// Number of actions kept for the bundle
+const RECENT_ACTIONS: usize = 100;
+
+// A dispatched action, as listed in `actions.json`
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ActionRecord {
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
+    // Milliseconds since the Unix epoch
+    timestamp: u64,
+    changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// Counters listed in `stats.json`
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Stats {
+    pub(crate) dispatched: u64,
+    pub(crate) changed: u64,
+    pub(crate) failed: u64,
+    pub(crate) revision: u64,
+    pub(crate) listeners: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) timings: Vec<ActionTiming>,
+}
+
+// Keeps the recent actions and counters of every store
+#[derive(Default)]
+pub(crate) struct Recorder {
+    recent: Mutex<VecDeque<ActionRecord>>,
+    stats: Mutex<Stats>,
+}
+
+impl Recorder {
+    pub(crate) fn record(&self, action: &Action, result: Result<bool, &RstateError>) {
+        let record = ActionRecord {
+            kind: action.kind.clone(),
+            trace_id: action.trace_id().map(str::to_owned),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as u64)
+                .unwrap_or_default(),
+            changed: matches!(result, Ok(true)),
+            error: result.err().map(ToString::to_string),
+        };
+
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.dispatched += 1;
+            stats.changed += u64::from(record.changed);
+            stats.failed += u64::from(record.error.is_some());
+        }
+        if let Ok(mut recent) = self.recent.lock() {
+            if recent.len() == RECENT_ACTIONS {
+                recent.pop_front();
+            }
+            recent.push_back(record);
+        }
+    }
+
+    pub(crate) fn recent(&self) -> Vec<ActionRecord> {
+        self.recent
+            .lock()
+            .map(|recent| recent.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        self.stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+}
+
+// Build a zip archive of `entries`, stored without compression
+pub(crate) fn zip(entries: &[(&str, Vec<u8>)]) -> crate::Result<Vec<u8>> {
+    // 1980-01-01 00:00, the earliest date zip can represent
+    const TIME: u16 = 0;
+    const DATE: u16 = (1 << 5) | 1;
+    // Names are UTF-8
+    const FLAGS: u16 = 1 << 11;
+
+    let too_large = || RstateError::state("diagnostics bundle too large");
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (name, data) in entries {
+        let offset = u32::try_from(archive.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let name_len = u16::try_from(name.len()).map_err(|_| too_large())?;
+        let crc = crc32fast::hash(data);
+
+        // Local file header
+        archive.extend(0x0403_4b50u32.to_le_bytes());
+        for field in [20, FLAGS, 0, TIME, DATE] {
+            archive.extend(field.to_le_bytes());
+        }
+        for field in [crc, size, size] {
+            archive.extend(field.to_le_bytes());
+        }
+        archive.extend(name_len.to_le_bytes());
+        archive.extend(0u16.to_le_bytes());
+        archive.extend(name.as_bytes());
+        archive.extend(data);
+
+        // Central directory entry
+        directory.extend(0x0201_4b50u32.to_le_bytes());
+        for field in [20, 20, FLAGS, 0, TIME, DATE] {
+            directory.extend(field.to_le_bytes());
+        }
+        for field in [crc, size, size] {
+            directory.extend(field.to_le_bytes());
+        }
+        for field in [name_len, 0, 0, 0, 0] {
+            directory.extend(field.to_le_bytes());
+        }
+        for field in [0, offset] {
+            directory.extend(field.to_le_bytes());
+        }
+        directory.extend(name.as_bytes());
+    }
+
+    let directory_offset = u32::try_from(archive.len()).map_err(|_| too_large())?;
+    let directory_size = u32::try_from(directory.len()).map_err(|_| too_large())?;
+    let count = u16::try_from(entries.len()).map_err(|_| too_large())?;
+    archive.extend(directory);
+
+    // End of central directory record
+    archive.extend(0x0605_4b50u32.to_le_bytes());
+    for field in [0, 0, count, count] {
+        archive.extend(field.to_le_bytes());
+    }
+    for field in [directory_size, directory_offset] {
+        archive.extend(field.to_le_bytes());
+    }
+    archive.extend(0u16.to_le_bytes());
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redaction::{REDACTED, redact};
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_replaces_paths() {
+        let mut state = json!({ "auth": { "token": "abc", "user": "me" }, "counter": 1 });
+        redact(
+            &mut state,
+            &["auth.token".to_owned(), "missing.path".to_owned()],
+        );
+
+        assert_eq!(
+            state,
+            json!({ "auth": { "token": REDACTED, "user": "me" }, "counter": 1 })
+        );
+    }
+
+    #[test]
+    fn test_recorder_keeps_recent_actions() {
+        let recorder = Recorder::default();
+        let error = RstateError::state("nope");
+        for _ in 0..RECENT_ACTIONS {
+            recorder.record(&Action::new("A"), Ok(true));
+        }
+        recorder.record(&Action::new("B"), Err(&error));
+
+        let recent = recorder.recent();
+        assert_eq!(recent.len(), RECENT_ACTIONS);
+        assert_eq!(recent.last().unwrap().kind, "B");
+        assert_eq!(
+            recent.last().unwrap().error.as_deref(),
+            Some("State error: nope")
+        );
+        assert_eq!(
+            recorder.stats(),
+            Stats {
+                dispatched: RECENT_ACTIONS as u64 + 1,
+                changed: RECENT_ACTIONS as u64,
+                failed: 1,
+                revision: 0,
+                listeners: Vec::new(),
+                timings: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_zip_layout() {
+        let archive = zip(&[("a.json", b"{}".to_vec()), ("b.json", b"[]".to_vec())]).unwrap();
+
+        assert_eq!(&archive[..4], b"PK\x03\x04");
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        // Two entries, the central directory starts right after the second file
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let directory_offset = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        assert_eq!(directory_offset, 2 * (30 + 6 + 2));
+        assert_eq!(
+            &archive[directory_offset..directory_offset + 4],
+            b"PK\x01\x02"
+        );
+        assert_eq!(
+            u32::from_le_bytes(archive[14..18].try_into().unwrap()),
+            crc32fast::hash(b"{}")
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Policies controlling when state updates are emitted.
+//!
+//! By default an update is emitted right after every dispatch that changed the state.
+//! The global policy can be changed with [`Builder::emit_policy`](crate::Builder::emit_policy),
+//! and individual action kinds can override it with
+//! [`StateBuilder::emit_policy`](crate::StateBuilder::emit_policy):
+//!
+//! ```rust,ignore
+//! use std::time::Duration;
+//! use tauri_plugin_rstate::{EmitPolicy, StateBuilder};
+//!
+//! let manager = StateBuilder::new(AppState::default())
+//!     .on("SET_SCROLL_POSITION", |state, action| { /* ... */ Ok(()) })
+//!     .emit_policy("SET_SCROLL_POSITION", EmitPolicy::coalesced(Duration::from_millis(50)))
+//!     .on("SAVE_COMPLETED", |state, action| { /* ... */ Ok(()) })
+//!     .emit_policy("SAVE_COMPLETED", EmitPolicy::immediate().force())
+//!     .build();
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::models::JsonValue;
+
+/// When to emit the state update following a dispatch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmitPolicy {
+    coalesce: Option<Duration>,
+    force: bool,
+}
+
+impl EmitPolicy {
+    /// Emit right after the dispatch (the default).
+    pub const fn immediate() -> Self {
+        Self {
+            coalesce: None,
+            force: false,
+        }
+    }
+
+    /// Emit at most once per `window`, with the latest state.
+    ///
+    /// The first update starts the window; updates dispatched within it replace the
+    /// pending one, which is emitted when the window ends. An immediate update to the
+    /// same store supersedes a pending one. Coalesced updates are never
+    /// [trimmed](crate::Builder::trim_unchanged), as clients may have missed revisions.
+    pub const fn coalesced(window: Duration) -> Self {
+        Self {
+            coalesce: Some(window),
+            force: false,
+        }
+    }
+
+    /// Also emit when the dispatch didn't change the state.
+    #[must_use]
+    pub const fn force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    /// The coalescing window, if updates are coalesced.
+    pub fn coalesce_window(&self) -> Option<Duration> {
+        self.coalesce
+    }
+
+    /// Whether updates are emitted even if the state didn't change.
+    pub fn is_forced(&self) -> bool {
+        self.force
+    }
+}
+
+// Latest pending payload per event name, for coalesced updates
+#[derive(Default)]
+pub(crate) struct Coalescer {
+    pending: Mutex<HashMap<String, JsonValue>>,
+}
+
+impl Coalescer {
+    // Store `payload` as the pending update for `event`.
+    // Returns `true` if nothing was pending, i.e. the caller must schedule a flush.
+    pub(crate) fn defer(&self, event: &str, payload: JsonValue) -> bool {
+        self.pending
+            .lock()
+            .is_ok_and(|mut pending| pending.insert(event.to_owned(), payload).is_none())
+    }
+
+    // Take the pending update for `event`, if any
+    pub(crate) fn take(&self, event: &str) -> Option<JsonValue> {
+        self.pending.lock().ok()?.remove(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_coalescer_keeps_latest_payload() {
+        let coalescer = Coalescer::default();
+
+        assert!(coalescer.defer("rstate://state-update", json!({ "scroll": 1 })));
+        assert!(!coalescer.defer("rstate://state-update", json!({ "scroll": 2 })));
+        assert!(coalescer.defer("rstate://state-update/main", json!({ "scroll": 3 })));
+
+        assert_eq!(
+            coalescer.take("rstate://state-update"),
+            Some(json!({ "scroll": 2 }))
+        );
+        assert_eq!(coalescer.take("rstate://state-update"), None);
+        assert!(coalescer.defer("rstate://state-update", json!({ "scroll": 4 })));
+    }
+}
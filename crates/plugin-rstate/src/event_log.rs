@@ -0,0 +1,337 @@
+//! Event-sourced persistence.
+//!
+//! By default, a persisted store saves its whole state after every dispatch that
+//! changed it. With [`StateBuilder::event_sourced`](crate::StateBuilder::event_sourced),
+//! it appends the dispatched action to a log instead, and only saves the state (a
+//! snapshot) every few actions, truncating the log. When the manager is built, the
+//! snapshot is loaded and the actions logged after it are replayed on top, so a crash
+//! loses no action that was applied:
+//!
+//! ```rust,ignore
+//! let manager = StateBuilder::new(AppState::default())
+//!     .on("ADD_TODO", add_todo)
+//!     .persist_with(FileBackend::new(data_dir.join("state.json")))
+//!     .event_sourced(EventLog::new(data_dir.join("actions.jsonl")).snapshot_every(500))
+//!     .build();
+//! ```
+//!
+//! Replaying only works if handlers are deterministic: they must not read the clock
+//! or random numbers, but get them from the payload. Effects and async handlers don't
+//! run again; their follow-up actions are logged themselves. The completion of an
+//! async handler can't be replayed, so it is persisted with a snapshot right away, as
+//! are states replaced as a whole. Without a storage backend for the snapshots, the
+//! log is never truncated, and these are lost.
+//!
+//! The snapshot records the sequence number of the last action it includes under
+//! [`JOURNAL_KEY`]. Logged actions aren't migrated: after a schema change, actions
+//! that fail to replay are logged and skipped.
+//!
+//! Devtools can page through the log, the actions dispatched since the last snapshot,
+//! with the `get_persisted_history` command (or
+//! [`Rstate::persisted_history`](crate::Rstate::persisted_history)). The command is
+//! only granted by the opt-in `rstate:persisted-history` permission set:
+//!
+//! ```js
+//! let page = await invoke('plugin:rstate|get_persisted_history', { limit: 50 })
+//! while (page.next !== null) {
+//!   page = await invoke('plugin:rstate|get_persisted_history', { after: page.next, limit: 50 })
+//! }
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::logging::ACTION_LOG_TARGET;
+use crate::models::{Action, JsonValue};
+use crate::{Result, RstateError};
+
+/// Key under which a snapshot of an event-sourced state records the sequence number
+/// of the last logged action it includes.
+///
+/// It is only part of the persisted value, never of the state itself.
+pub const JOURNAL_KEY: &str = "@@journal";
+
+/// Configuration of the action log of an event-sourced store, see the
+/// [module docs](self).
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    path: PathBuf,
+    snapshot_every: usize,
+}
+
+impl EventLog {
+    /// Log the actions in the JSONL file at `path`, saving a snapshot every 100
+    /// actions.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            snapshot_every: 100,
+        }
+    }
+
+    /// Save a snapshot (and truncate the log) every `actions` logged actions.
+    #[must_use]
+    pub fn snapshot_every(mut self, actions: usize) -> Self {
+        self.snapshot_every = actions.max(1);
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<A> {
+    seq: u64,
+    action: A,
+}
+
+/// A page of the action log of an event-sourced store, see
+/// [`RstateManager::persisted_history`](crate::RstateManager::persisted_history).
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct HistoryPage {
+    /// The logged actions, oldest first
+    pub entries: Vec<LoggedAction>,
+    /// Sequence number to pass as `after` for the next page, if there is one
+    pub next: Option<u64>,
+}
+
+/// An action of the log, see [`HistoryPage`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct LoggedAction {
+    /// Sequence number of the action in the log
+    pub seq: u64,
+    /// The action
+    pub action: Action,
+}
+
+// The open action log of a store
+pub(crate) struct Journal {
+    config: EventLog,
+    file: Mutex<Option<File>>,
+    // Sequence number of the last logged action
+    seq: AtomicU64,
+    // Actions logged since the last snapshot
+    pending: AtomicUsize,
+}
+
+impl Journal {
+    pub(crate) fn new(config: EventLog) -> Self {
+        Self {
+            config,
+            file: Mutex::new(None),
+            seq: AtomicU64::new(0),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    // The logged actions following the snapshot including `after`. The next
+    // actions are numbered after the last one.
+    pub(crate) fn load(&self, after: u64) -> Result<Vec<Action>> {
+        let content = match std::fs::read_to_string(&self.config.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(RstateError::state(err.to_string())),
+        };
+        if !content.is_empty() && !content.ends_with('\n') {
+            // Terminate an unfinished last line, so the next entry starts on its own
+            self.open()
+                .and_then(|mut file| file.write_all(b"\n"))
+                .map_err(|e| RstateError::state(e.to_string()))?;
+        }
+        let mut last = after;
+        let mut actions = Vec::new();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            // A crash can leave the last line unfinished
+            let entry: Entry<Action> = match serde_json::from_str(line) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    log::warn!(
+                        target: ACTION_LOG_TARGET,
+                        "skipping unreadable entry of {}: {err}",
+                        self.config.path.display()
+                    );
+                    continue;
+                }
+            };
+            last = last.max(entry.seq);
+            if entry.seq > after {
+                actions.push(entry.action);
+            }
+        }
+        self.seq.store(last, Ordering::SeqCst);
+        self.pending.store(actions.len(), Ordering::SeqCst);
+        Ok(actions)
+    }
+
+    // Up to `limit` of the logged actions numbered after `after`, reading the log only
+    // as far as needed. Unreadable entries are skipped.
+    pub(crate) fn page(&self, after: u64, limit: usize) -> Result<HistoryPage> {
+        let file = match File::open(&self.config.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HistoryPage::default()),
+            Err(err) => return Err(RstateError::state(err.to_string())),
+        };
+        let mut page = HistoryPage::default();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| RstateError::state(e.to_string()))?;
+            let Ok(entry) = serde_json::from_str::<Entry<Action>>(&line) else {
+                continue;
+            };
+            if entry.seq <= after {
+                continue;
+            }
+            if page.entries.len() == limit {
+                page.next = page.entries.last().map(|last| last.seq);
+                break;
+            }
+            page.entries.push(LoggedAction {
+                seq: entry.seq,
+                action: entry.action,
+            });
+        }
+        Ok(page)
+    }
+
+    // Append `action`, synced to disk. Returns whether a snapshot is due.
+    pub(crate) fn append(&self, action: &Action) -> Result<bool> {
+        let to_error = |e: io::Error| RstateError::state(e.to_string());
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
+        let file = match file.as_mut() {
+            Some(file) => file,
+            None => file.insert(self.open().map_err(to_error)?),
+        };
+        let seq = self.seq.load(Ordering::SeqCst) + 1;
+        let mut line = serde_json::to_vec(&Entry { seq, action })
+            .map_err(|e| RstateError::serialization(e.to_string()))?;
+        line.push(b'\n');
+        file.write_all(&line).map_err(to_error)?;
+        file.sync_data().map_err(to_error)?;
+        self.seq.store(seq, Ordering::SeqCst);
+        let pending = self.pending.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(pending >= self.config.snapshot_every)
+    }
+
+    // Record the last logged action in a snapshot about to be saved
+    pub(crate) fn stamp(&self, snapshot: &mut JsonValue) {
+        if let JsonValue::Object(fields) = snapshot {
+            fields.insert(
+                JOURNAL_KEY.to_owned(),
+                self.seq.load(Ordering::SeqCst).into(),
+            );
+        }
+    }
+
+    // Empty the log, once a snapshot including its actions is saved
+    pub(crate) fn truncate(&self) -> Result<()> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
+        *file = None;
+        File::create(&self.config.path).map_err(|e| RstateError::state(e.to_string()))?;
+        self.pending.store(0, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn open(&self) -> io::Result<File> {
+        if let Some(parent) = self.config.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.path)
+    }
+}
+
+// Take the sequence number recorded in a loaded snapshot, 0 if there is none
+pub(crate) fn take(persisted: &mut JsonValue) -> u64 {
+    persisted
+        .as_object_mut()
+        .and_then(|fields| fields.remove(JOURNAL_KEY))
+        .and_then(|seq| seq.as_u64())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kinds(actions: &[Action]) -> Vec<&str> {
+        actions.iter().map(|action| action.kind.as_str()).collect()
+    }
+
+    #[test]
+    fn test_actions_after_the_snapshot_are_loaded() {
+        let path =
+            std::env::temp_dir().join(format!("rstate-event-log-{}.jsonl", std::process::id()));
+        let journal = Journal::new(EventLog::new(&path).snapshot_every(2));
+        assert!(journal.load(0).unwrap().is_empty());
+
+        assert!(!journal.append(&Action::new("A")).unwrap());
+        assert!(journal.append(&Action::new("B")).unwrap());
+        let mut snapshot = json!({ "counter": 2 });
+        journal.stamp(&mut snapshot);
+        assert_eq!(snapshot[JOURNAL_KEY], 2);
+        journal.truncate().unwrap();
+        assert!(!journal.append(&Action::new("C")).unwrap());
+        // Unfinished entry
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(br#"{"seq":4,"act"#)
+            .unwrap();
+
+        let reopened = Journal::new(EventLog::new(&path));
+        let actions = reopened.load(take(&mut snapshot)).unwrap();
+        assert_eq!(kinds(&actions), ["C"]);
+        assert_eq!(snapshot, json!({ "counter": 2 }));
+        // Numbered after the last logged action
+        reopened.append(&Action::new("D")).unwrap();
+        let mut snapshot = json!({});
+        reopened.stamp(&mut snapshot);
+        assert_eq!(snapshot[JOURNAL_KEY], 4);
+        assert_eq!(
+            kinds(&Journal::new(EventLog::new(&path)).load(2).unwrap()),
+            ["C", "D"]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_history_is_paged() {
+        let path = std::env::temp_dir().join(format!(
+            "rstate-event-log-pages-{}.jsonl",
+            std::process::id()
+        ));
+        let journal = Journal::new(EventLog::new(&path).snapshot_every(100));
+        assert!(journal.page(0, 10).unwrap().entries.is_empty());
+        for kind in ["A", "B", "C"] {
+            journal.append(&Action::new(kind)).unwrap();
+        }
+
+        let page = journal.page(0, 2).unwrap();
+        let seqs: Vec<_> = page.entries.iter().map(|entry| entry.seq).collect();
+        assert_eq!(seqs, [1, 2]);
+        assert_eq!(page.next, Some(2));
+        let page = journal.page(2, 2).unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert!(page.entries[0].action.is("C"));
+        assert_eq!(page.next, None);
+        // A full last page has no next one
+        assert_eq!(journal.page(1, 2).unwrap().next, None);
+        std::fs::remove_file(path).unwrap();
+    }
+}
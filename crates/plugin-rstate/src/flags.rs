@@ -0,0 +1,141 @@
+//! Feature flags kept in the state.
+//!
+//! Declare flags with [`StateBuilder::flags`](crate::StateBuilder::flags). They are
+//! exposed under the [`FLAGS_KEY`] key of the state, so the frontend sees them like
+//! any other state, are persisted along with it, and are changed with the built-in
+//! [`TOGGLE_FLAG_ACTION`] and [`SET_FLAG_ACTION`] actions:
+//!
+//! ```rust,ignore
+//! let manager = StateBuilder::new(AppState::default())
+//!     .flags(["newEditor", "betaSync"])
+//!     .build();
+//!
+//! // From Rust, or `dispatch("@@rstate/TOGGLE_FLAG", "newEditor")` from the frontend
+//! app.rstate().dispatch(Action::with_payload(TOGGLE_FLAG_ACTION, "newEditor")?)?;
+//! if app.rstate().flag("newEditor")? {
+//!     // ...
+//! }
+//! ```
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::Result;
+use crate::models::{Action, JsonValue};
+
+/// Key of the feature flags in the state.
+pub const FLAGS_KEY: &str = "flags";
+
+/// Kind of the action toggling a feature flag. Its payload is the flag name.
+pub const TOGGLE_FLAG_ACTION: &str = "@@rstate/TOGGLE_FLAG";
+
+/// Kind of the action setting a feature flag. Its payload is `{ "name", "enabled" }`.
+pub const SET_FLAG_ACTION: &str = "@@rstate/SET_FLAG";
+
+#[derive(Deserialize)]
+struct SetFlag {
+    name: String,
+    enabled: bool,
+}
+
+// The declared flags and their values
+#[derive(Default)]
+pub(crate) struct Flags(BTreeMap<String, bool>);
+
+impl Flags {
+    // Declare `names`, disabled
+    pub(crate) fn declare(&mut self, names: impl IntoIterator<Item = String>) {
+        for name in names {
+            self.0.entry(name).or_insert(false);
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Take the values of the declared flags from a full state
+    pub(crate) fn restore(&mut self, state: &JsonValue) {
+        for (name, enabled) in &mut self.0 {
+            if let Some(value) = state[FLAGS_KEY][name.as_str()].as_bool() {
+                *enabled = value;
+            }
+        }
+    }
+
+    // Apply a flag action, or return `None` for any other action
+    pub(crate) fn handle(&mut self, action: &Action) -> Option<Result<()>> {
+        let (name, enabled) = if action.is(TOGGLE_FLAG_ACTION) {
+            match action.require_payload::<String>() {
+                Ok(name) => {
+                    let enabled = self.0.get(&name).is_some_and(|enabled| !enabled);
+                    (name, enabled)
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        } else if action.is(SET_FLAG_ACTION) {
+            match action.require_payload::<SetFlag>() {
+                Ok(SetFlag { name, enabled }) => (name, enabled),
+                Err(err) => return Some(Err(err)),
+            }
+        } else {
+            return None;
+        };
+
+        Some(match self.0.get_mut(&name) {
+            Some(flag) => {
+                *flag = enabled;
+                Ok(())
+            }
+            None => Err(crate::RstateError::state(format!(
+                "Unknown feature flag: {name}"
+            ))),
+        })
+    }
+
+    // Add the flags to a serialized state
+    pub(crate) fn insert_into(&self, state: &mut JsonValue) {
+        if self.0.is_empty() {
+            return;
+        }
+        if let JsonValue::Object(state) = state {
+            let flags = self
+                .0
+                .iter()
+                .map(|(name, enabled)| (name.clone(), JsonValue::Bool(*enabled)))
+                .collect();
+            state.insert(FLAGS_KEY.to_owned(), JsonValue::Object(flags));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_flag_actions() {
+        let mut flags = Flags::default();
+        flags.declare(["newEditor".to_owned(), "betaSync".to_owned()]);
+        flags.restore(&json!({ "flags": { "betaSync": true, "removed": true } }));
+
+        let toggle = Action::with_json(TOGGLE_FLAG_ACTION, "newEditor".into());
+        assert!(flags.handle(&toggle).unwrap().is_ok());
+        let set = Action::with_json(
+            SET_FLAG_ACTION,
+            json!({ "name": "betaSync", "enabled": false }),
+        );
+        assert!(flags.handle(&set).unwrap().is_ok());
+        let unknown = Action::with_json(TOGGLE_FLAG_ACTION, "removed".into());
+        assert!(flags.handle(&unknown).unwrap().is_err());
+        assert!(flags.handle(&Action::new("INCREMENT")).is_none());
+
+        let mut state = json!({ "counter": 1 });
+        flags.insert_into(&mut state);
+        assert_eq!(
+            state,
+            json!({ "counter": 1, "flags": { "betaSync": false, "newEditor": true } })
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Store health reports.
+//!
+//! [`Rstate::health_check`](crate::Rstate::health_check) (and the `health_check`
+//! command) report whether the app-wide store can be locked, how many dispatches wait
+//! in the batch queue, the outcome of the last save, the last failed dispatch and the
+//! time since the last successful one. Poll it from a watchdog, or show it in an
+//! "about" panel:
+//!
+//! ```json
+//! {
+//!   "lock": "free",
+//!   "queueDepth": 0,
+//!   "lastSave": { "status": "saved", "at": 1700000000000 },
+//!   "lastError": { "at": 1699999990000, "kind": "SET_COUNT", "message": "Invalid payload: ..." },
+//!   "sinceLastDispatchMs": 1250
+//! }
+//! ```
+
+use serde::Serialize;
+use std::sync::{Mutex, TryLockError};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ManagedState;
+use crate::models::Action;
+
+// Milliseconds since the Unix epoch
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Whether the store's lock can be taken.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LockStatus {
+    /// The lock is free, or only held by readers
+    Free,
+    /// The lock is held for a change, e.g. by a running dispatch
+    Busy,
+    /// A thread panicked while holding the lock
+    Poisoned,
+    /// No state manager is registered
+    Unregistered,
+}
+
+/// Outcome of the last save of a persisted store.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum SaveStatus {
+    /// The state was saved at `at`, in milliseconds since the Unix epoch
+    Saved { at: u64 },
+    /// Saving the state failed at `at`, in milliseconds since the Unix epoch
+    Failed { at: u64, error: String },
+}
+
+impl SaveStatus {
+    pub(crate) fn of(result: &crate::Result<()>) -> Self {
+        let at = unix_millis();
+        match result {
+            Ok(()) => Self::Saved { at },
+            Err(err) => Self::Failed {
+                at,
+                error: err.to_string(),
+            },
+        }
+    }
+}
+
+/// The last failed dispatch.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LastError {
+    /// When it failed, in milliseconds since the Unix epoch
+    pub at: u64,
+    /// Kind of the action
+    pub kind: String,
+    /// The error message
+    pub message: String,
+}
+
+/// Health report of the app-wide store.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    /// Whether the store's lock can be taken
+    pub lock: LockStatus,
+    /// Number of dispatches waiting in the batch queue, or for the store's actor
+    pub queue_depth: usize,
+    /// Outcome of the last save, if the store is persisted and was saved
+    pub last_save: Option<SaveStatus>,
+    /// The last failed dispatch, if any
+    pub last_error: Option<LastError>,
+    /// Milliseconds since the last successful dispatch, if any
+    pub since_last_dispatch_ms: Option<u64>,
+}
+
+// Outcomes of the dispatches, as reported by health checks
+#[derive(Default)]
+pub(crate) struct Vitals {
+    last_success: Mutex<Option<Instant>>,
+    last_error: Mutex<Option<LastError>>,
+}
+
+impl Vitals {
+    pub(crate) fn record<T>(&self, action: &Action, result: &crate::Result<T>) {
+        match result {
+            Ok(_) => {
+                if let Ok(mut last_success) = self.last_success.lock() {
+                    *last_success = Some(Instant::now());
+                }
+            }
+            Err(err) => {
+                if let Ok(mut last_error) = self.last_error.lock() {
+                    *last_error = Some(LastError {
+                        at: unix_millis(),
+                        kind: action.kind.clone(),
+                        message: err.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    // Report the health of `store`
+    pub(crate) fn check(&self, store: Option<&ManagedState>, queue_depth: usize) -> Health {
+        let (lock, last_save) = match store.map(ManagedState::try_read) {
+            None => (LockStatus::Unregistered, None),
+            Some(Ok(state_guard)) => (LockStatus::Free, state_guard.last_save()),
+            Some(Err(TryLockError::WouldBlock)) => (LockStatus::Busy, None),
+            Some(Err(TryLockError::Poisoned(poisoned))) => {
+                (LockStatus::Poisoned, poisoned.into_inner().last_save())
+            }
+        };
+        Health {
+            lock,
+            queue_depth,
+            last_save,
+            last_error: self.last_error.lock().ok().and_then(|error| error.clone()),
+            since_last_dispatch_ms: self
+                .last_success
+                .lock()
+                .ok()
+                .and_then(|success| *success)
+                .map(|success| success.elapsed().as_millis() as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DispatchOutcome, JsonValue, RstateManager};
+    use std::sync::RwLock;
+
+    struct Failing;
+
+    impl RstateManager for Failing {
+        fn get_initial_state(&self) -> JsonValue {
+            JsonValue::Null
+        }
+
+        fn dispatch(&mut self, _action: &Action) -> crate::Result<DispatchOutcome> {
+            Err(crate::RstateError::state("nope"))
+        }
+
+        fn last_save(&self) -> Option<SaveStatus> {
+            Some(SaveStatus::Saved { at: 1 })
+        }
+    }
+
+    #[test]
+    fn test_health_check() {
+        let vitals = Vitals::default();
+        let store: ManagedState = RwLock::new(Box::new(Failing));
+        let action = Action::new("SET_COUNT");
+        let result = store.write().unwrap().dispatch(&action);
+        vitals.record(&action, &result);
+
+        let health = vitals.check(Some(&store), 2);
+        assert_eq!(health.lock, LockStatus::Free);
+        assert_eq!(health.queue_depth, 2);
+        assert_eq!(health.last_save, Some(SaveStatus::Saved { at: 1 }));
+        assert_eq!(health.last_error.unwrap().message, "State error: nope");
+        assert_eq!(health.since_last_dispatch_ms, None);
+
+        // Readers don't keep the store busy, a change does
+        let reading = store.read().unwrap();
+        assert_eq!(vitals.check(Some(&store), 0).lock, LockStatus::Free);
+        drop(reading);
+        let _guard = store.write().unwrap();
+        assert_eq!(vitals.check(Some(&store), 0).lock, LockStatus::Busy);
+        assert_eq!(vitals.check(None, 0).lock, LockStatus::Unregistered);
+    }
+}
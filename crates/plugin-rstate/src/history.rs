@@ -0,0 +1,134 @@
+//! Resyncing from a known revision.
+//!
+//! A window that reloaded, or missed some updates, still knows the revision of the
+//! state it last saw. The `get_changes_since` command brings it up to date with
+//! [`Changes`]: a JSON Patch from that revision to the current one when the plugin
+//! still has the state of that revision, else the full state:
+//!
+//! ```json
+//! { "revision": 45, "patch": [{ "op": "replace", "path": "/counter", "value": 45 }] }
+//! { "revision": 45, "state": { "counter": 45 } }
+//! ```
+//!
+//! With [`Builder::change_history`](crate::Builder::change_history), the plugin keeps
+//! the states of the app-wide store's most recent revisions; without it, only a window
+//! already at the current revision gets a (empty) patch. Like patch updates, the
+//! changes only cover the app-wide store's manager, not its slices.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tauri_plugin_rstate_core::diff;
+
+pub use tauri_plugin_rstate_core::Changes;
+
+use crate::change::StateUpdate;
+use crate::models::JsonValue;
+
+// The states of a store's most recent revisions, oldest first
+pub(crate) struct History {
+    capacity: usize,
+    states: Mutex<VecDeque<(u64, JsonValue)>>,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            states: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    // Keep the `state` of `revision`, dropping the oldest one if full.
+    // Commits may finish out of order, so revisions are inserted in order.
+    pub(crate) fn record(&self, revision: u64, state: &JsonValue) {
+        let Ok(mut states) = self.states.lock() else {
+            return;
+        };
+        let index = states.partition_point(|(kept, _)| *kept < revision);
+        if states.get(index).is_some_and(|(kept, _)| *kept == revision) {
+            return;
+        }
+        states.insert(index, (revision, state.clone()));
+        while states.len() > self.capacity {
+            states.pop_front();
+        }
+    }
+
+    // The state of `revision`, if still kept
+    fn state(&self, revision: u64) -> Option<JsonValue> {
+        let states = self.states.lock().ok()?;
+        let index = states
+            .binary_search_by_key(&revision, |(kept, _)| *kept)
+            .ok()?;
+        Some(states[index].1.clone())
+    }
+}
+
+// The changes from the state of revision `since` to `current`
+pub(crate) fn changes_since(
+    history: Option<&History>,
+    since: u64,
+    current: StateUpdate,
+) -> Changes {
+    let known = if since == current.revision {
+        Some(current.state.clone())
+    } else {
+        history.and_then(|history| history.state(since))
+    };
+    match known {
+        Some(known) => Changes {
+            revision: current.revision,
+            patch: Some(diff(&known, &current.state)),
+            state: None,
+        },
+        None => Changes {
+            revision: current.revision,
+            patch: None,
+            state: Some(current.state),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn current(revision: u64, counter: u64) -> StateUpdate {
+        StateUpdate {
+            revision,
+            state: json!({ "counter": counter }),
+            trace_id: None,
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn test_changes_since_a_kept_revision() {
+        let history = History::new(2);
+        history.record(1, &json!({ "counter": 1 }));
+        history.record(3, &json!({ "counter": 3 }));
+        history.record(2, &json!({ "counter": 2 }));
+
+        let changes = changes_since(Some(&history), 2, current(3, 3));
+        assert_eq!(
+            serde_json::to_value(changes).unwrap(),
+            json!({ "revision": 3, "patch": [{ "op": "replace", "path": "/counter", "value": 3 }] })
+        );
+        assert_eq!(
+            changes_since(Some(&history), 3, current(3, 3)).patch,
+            Some(Vec::new())
+        );
+
+        // Revision 1 was dropped, and revisions from a previous run are unknown
+        for since in [1, 7] {
+            let changes = changes_since(Some(&history), since, current(3, 3));
+            assert_eq!(changes.state, Some(json!({ "counter": 3 })));
+            assert_eq!(changes.patch, None);
+        }
+        assert_eq!(
+            changes_since(None, 3, current(3, 3)).patch,
+            Some(Vec::new())
+        );
+    }
+}
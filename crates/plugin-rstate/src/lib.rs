@@ -1,28 +1,153 @@
-use std::sync::Mutex;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
 use tauri::{
-    Manager, Runtime,
-    plugin::{Builder, TauriPlugin},
+    AppHandle, Manager, RunEvent, Runtime, WindowEvent,
+    plugin::{Builder as PluginBuilder, TauriPlugin},
+    webview::PageLoadEvent,
 };
 
+// Lets the crate's own tests use the macros, which refer to `::tauri_plugin_rstate`
+#[cfg(test)]
+extern crate self as tauri_plugin_rstate;
+
+mod action_scope;
+mod actor;
+mod affinity;
+mod audit;
+mod batching;
+#[cfg(desktop)]
+mod bindings;
+mod breaker;
+mod computed;
 #[cfg(desktop)]
 mod desktop;
+mod diagnostics;
 #[cfg(mobile)]
 mod mobile;
 
+mod change;
+mod client;
 mod commands;
+mod concurrency;
+mod config;
+mod emit_policy;
 mod error;
+mod event_log;
+mod flags;
+mod health;
+mod history;
+mod listeners;
+mod logging;
+mod macros;
+mod migrations;
 mod models;
+mod namespace;
+mod persistence;
+mod plugin_store;
+mod rate_limit;
+mod redaction;
+mod replay;
+mod retention;
+mod rstate;
+mod schedule;
+mod schema;
+mod selectors;
+mod slices;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 mod state_builder;
+mod store;
+mod subscriptions;
+mod sync;
+mod tap;
+pub mod testing;
+mod timings;
+mod tokens;
+mod transaction;
+mod transport;
+mod trash;
+mod typed;
+mod watchers;
+#[cfg(feature = "websocket")]
+mod websocket;
+mod window_stores;
+
+use crate::breaker::CircuitBreaker;
+use crate::concurrency::ConcurrencyGroups;
+use crate::listeners::DEFAULT_LISTENER_TIMEOUT;
+use crate::rate_limit::{Limit, RateLimits};
 
 // Re-export core types
+pub use crate::action_scope::ActionScope;
+pub use crate::affinity::{LocalStateManager, PinnedManager};
+pub use crate::audit::AuditLog;
+pub use crate::breaker::{CIRCUIT_OPEN_EVENT, CircuitOpen};
+pub use crate::change::{FloatComparison, StateUpdate, UNCHANGED_KEY};
+pub use crate::client::CLIENT_WINDOW_LABEL;
+pub use crate::config::Config;
+pub use crate::emit_policy::EmitPolicy;
 pub use crate::error::{Result, RstateError};
-pub use crate::models::{Action, JsonValue, RstateManager, get_state, state_changed};
-pub use crate::state_builder::{ActionHandler, BuiltStateManager, StateBuilder};
+pub use crate::event_log::{EventLog, HistoryPage, JOURNAL_KEY, LoggedAction};
+pub use crate::flags::{FLAGS_KEY, SET_FLAG_ACTION, TOGGLE_FLAG_ACTION};
+pub use crate::health::{Health, LastError, LockStatus, SaveStatus};
+pub use crate::history::Changes;
+pub use crate::logging::ACTION_LOG_TARGET;
+#[doc(hidden)]
+pub use crate::macros::{__dispatch_command, __payload_field};
+pub use crate::migrations::{MigrationReport, MigrationStep, VERSION_KEY};
+pub use crate::models::{
+    Action, ActionGuard, ActionKinds, ActionMeta, ActionSource, AnyAppHandle, AsAny,
+    DispatchOutcome, Dispatcher, DryRun, JsonValue, RstateManager, StoreScope, get_state,
+    state_changed,
+};
+pub use crate::namespace::Namespace;
+pub use crate::persistence::{
+    ChunkedFileBackend, FileBackend, MemoryBackend, RoutedBackend, SecretBackend, SecretStore,
+    StorageBackend,
+};
+pub use crate::plugin_store::PluginStoreBridge;
+pub use crate::replay::{ReplayFailure, ReplayReport};
+pub use crate::retention::Retention;
+pub use crate::schedule::ScheduleHandle;
+#[cfg(feature = "schema")]
+pub use crate::schema::schema_fingerprint_of;
+pub use crate::schema::{
+    SCHEMA_KEY, STORE_READY_EVENT, SchemaFingerprint, StoreReady, schema_fingerprint,
+};
+pub use crate::selectors::SelectorStats;
+pub use crate::slices::slice_event_name;
+#[cfg(feature = "sqlite")]
+pub use crate::sqlite::SqliteBackend;
+pub use crate::state_builder::{
+    ASYNC_COMPLETE_ACTION, ActionHandler, ActionHandlers, BuiltStateManager, HYDRATE_ACTION, Next,
+    OnDuplicate, RESET_ACTION, StateBuilder, StateModule,
+};
+pub use crate::store::STATE_UPDATE_EVENT;
+pub use crate::sync::{
+    Conflict, ConflictResolver, LocalChange, LocalChangeHook, REMOTE_CHANGE_ACTION, RemoteChange,
+    Resolution,
+};
+pub use crate::tap::{EmitDecision, SaveTap, TapEvent};
+pub use crate::timings::ActionTiming;
+pub use crate::transaction::Transaction;
+pub use crate::transport::{ChannelTransport, Emission, EventTransport, UpdateTransport};
+pub use crate::trash::{RESTORE_ACTION, SOFT_DELETE_ACTION, TRASH_KEY};
+pub use crate::typed::TypedRstate;
+pub use crate::watchers::WatchHandle;
+#[cfg(feature = "websocket")]
+pub use crate::websocket::{DEFAULT_WEBSOCKET_PORT, WebSocketConfig, WebSocketTransport};
+pub use crate::window_stores::window_event_name;
+#[cfg(feature = "schema")]
+pub use schemars;
+pub use tauri_plugin_rstate_client::{ClientError, Listener, RstateClient, RstateClientExt};
+pub use tauri_plugin_rstate_core::{PatchOperation, STATE_PATCH_EVENT, StatePatch};
+#[cfg(feature = "macros")]
+pub use tauri_plugin_rstate_macros::handlers;
 
-#[cfg(desktop)]
-pub use desktop::{Rstate, STATE_UPDATE_EVENT};
-#[cfg(mobile)]
-pub use mobile::{Rstate, STATE_UPDATE_EVENT};
+pub use computed::Computed;
+pub use rstate::Rstate;
 
 /// Extensions to [`tauri::App`], [`tauri::AppHandle`] and [`tauri::Window`] to access the rstate APIs.
 pub trait RstateExt<R: Runtime> {
@@ -35,11 +160,831 @@ impl<R: Runtime, T: Manager<R>> crate::RstateExt<R> for T {
     }
 }
 
-/// Type alias for the managed state.
+/// Type alias for a store's state manager behind its lock.
+///
+/// Reads (e.g. `get_state` and `get_initial_state` calls from many windows) share the
+/// lock; dispatches and other changes take it exclusively.
+pub type ManagedState = RwLock<Box<dyn RstateManager>>;
+
+/// Builder for the rstate plugin.
+///
+/// [`init`] and [`init_empty`] cover the common cases; use the builder when you
+/// need to tweak plugin-level behavior.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use std::time::Duration;
 ///
-/// Note: We don't need `Arc` here because Tauri's `app.manage()` wraps the state
-/// in `Arc` internally. We only need `Mutex` for interior mutability.
-pub type ManagedState = Mutex<Box<dyn RstateManager>>;
+/// tauri::Builder::default()
+///     .plugin(
+///         tauri_plugin_rstate::Builder::new()
+///             .registration_timeout(Duration::from_secs(5))
+///             .build(),
+///     )
+///     .setup(|app| {
+///         let manager = StateBuilder::new(AppState::default()).build();
+///         app.rstate().register_state_manager(manager)?;
+///         Ok(())
+///     })
+///     .run(tauri::generate_context!())
+///     .unwrap();
+/// ```
+pub struct Builder<R: Runtime> {
+    state_manager: Option<Box<dyn RstateManager>>,
+    state_loader: Option<StateLoader>,
+    registration_timeout: Option<Duration>,
+    transports: Vec<Box<dyn UpdateTransport>>,
+    emit_events: bool,
+    log_actions: Option<log::Level>,
+    audit_log: Option<AuditLog>,
+    trim_unchanged: bool,
+    envelope_updates: bool,
+    envelope_responses: bool,
+    emit_patches: bool,
+    emit_policy: Option<EmitPolicy>,
+    guards: Vec<ActionGuard>,
+    on_ready: Option<ReadyHook<R>>,
+    batch_window: Option<Duration>,
+    batch_queue: Option<Box<dyn StorageBackend>>,
+    local_change_hooks: Vec<LocalChangeHook>,
+    on_conflict: Option<ConflictResolver>,
+    redact: Vec<String>,
+    listener_timeout: Duration,
+    skip_idle_windows: bool,
+    skip_dispatching_window: bool,
+    concurrency_groups: ConcurrencyGroups,
+    rate_limits: RateLimits,
+    prime_windows: bool,
+    circuit_breaker: Option<CircuitBreaker>,
+    time_actions: bool,
+    change_history: Option<usize>,
+    max_payload_size: Option<usize>,
+    actor_mode: bool,
+    #[cfg(feature = "websocket")]
+    websocket: Option<WebSocketConfig>,
+}
+
+impl<R: Runtime> Default for Builder<R> {
+    fn default() -> Self {
+        Self {
+            state_manager: None,
+            state_loader: None,
+            registration_timeout: None,
+            transports: Vec::new(),
+            emit_events: true,
+            log_actions: None,
+            audit_log: None,
+            trim_unchanged: false,
+            envelope_updates: false,
+            envelope_responses: false,
+            emit_patches: false,
+            emit_policy: None,
+            guards: Vec::new(),
+            on_ready: None,
+            batch_window: None,
+            batch_queue: None,
+            local_change_hooks: Vec::new(),
+            on_conflict: None,
+            redact: Vec::new(),
+            listener_timeout: DEFAULT_LISTENER_TIMEOUT,
+            skip_idle_windows: false,
+            skip_dispatching_window: false,
+            concurrency_groups: ConcurrencyGroups::default(),
+            rate_limits: RateLimits::default(),
+            prime_windows: false,
+            circuit_breaker: None,
+            time_actions: false,
+            change_history: None,
+            max_payload_size: None,
+            actor_mode: false,
+            #[cfg(feature = "websocket")]
+            websocket: None,
+        }
+    }
+}
+
+impl<R: Runtime> Builder<R> {
+    /// Create a new plugin builder without a state manager.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the state manager to register during plugin setup.
+    ///
+    /// If not set, a manager must be registered later using
+    /// `app.rstate().register_state_manager()`.
+    #[must_use]
+    pub fn state_manager<S: RstateManager>(mut self, state_manager: S) -> Self {
+        self.state_manager = Some(Box::new(state_manager));
+        self.state_loader = None;
+        self
+    }
+
+    /// Build the state manager with `load` on a background thread, started during
+    /// plugin setup, and register it once built.
+    ///
+    /// For large persisted states, so loading the data and running the migrations
+    /// doesn't delay window creation. Until the manager is registered, the frontend's
+    /// `get_initial_state` fails with [`RstateError::Loading`] (after waiting up to the
+    /// [registration timeout](Self::registration_timeout), if any); the frontend
+    /// then waits for the [`STORE_READY_EVENT`]. A failure of `load` is logged, and
+    /// leaves the store unregistered. Replaces [`state_manager`](Self::state_manager).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let plugin = tauri_plugin_rstate::Builder::new()
+    ///     .load_state_manager(move || {
+    ///         Ok(StateBuilder::new(Library::default())
+    ///             .persist(library_path)
+    ///             .migration(1, add_ratings)
+    ///             .build())
+    ///     })
+    ///     .build();
+    /// ```
+    #[must_use]
+    pub fn load_state_manager<S, F>(mut self, load: F) -> Self
+    where
+        S: RstateManager,
+        F: FnOnce() -> Result<S> + Send + 'static,
+    {
+        self.state_loader = Some(boxed_loader(load));
+        self.state_manager = None;
+        self
+    }
+
+    /// Make the `get_initial_state` command wait up to `timeout` for a state
+    /// manager to be registered before failing with [`RstateError::NotRegistered`].
+    ///
+    /// Useful with lazy registration, where a fast-loading frontend may ask for
+    /// the state before `setup` has registered the manager.
+    #[must_use]
+    pub fn registration_timeout(mut self, timeout: Duration) -> Self {
+        self.registration_timeout = Some(timeout);
+        self
+    }
+
+    /// Add a transport that receives every update of the app-wide store and its slices.
+    ///
+    /// Transports are used in addition to the default Tauri event transport,
+    /// unless it is turned off with [`emit_events(false)`](Self::emit_events). They
+    /// don't receive the updates of window stores, which are only emitted to their
+    /// window.
+    #[must_use]
+    pub fn transport<T: UpdateTransport>(mut self, transport: T) -> Self {
+        self.transports.push(Box::new(transport));
+        self
+    }
+
+    /// Whether state updates are emitted as Tauri events (default: `true`).
+    ///
+    /// Turn this off when updates should only go through custom transports. Window
+    /// stores then emit no updates.
+    #[must_use]
+    pub fn emit_events(mut self, emit_events: bool) -> Self {
+        self.emit_events = emit_events;
+        self
+    }
+
+    /// Log every dispatched action at the given level through the [`log`] crate.
+    ///
+    /// Records use the [`ACTION_LOG_TARGET`] target, so they end up wherever
+    /// `tauri-plugin-log` (or any other logger) sends the app's logs.
+    /// Failed actions are logged as warnings.
+    #[must_use]
+    pub fn log_actions(mut self, level: log::Level) -> Self {
+        self.log_actions = Some(level);
+        self
+    }
+
+    /// Append every dispatched action to a rotating JSONL file, for debugging
+    /// user-reported issues in production builds.
+    ///
+    /// Records hold the kind, a hash of the payload (or the payload itself with
+    /// [`AuditLog::with_payloads`]), the dispatching window, the time and the result.
+    #[must_use]
+    pub fn audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Set the global [`EmitPolicy`] (default: [`EmitPolicy::immediate`]).
+    ///
+    /// Action kinds can override it through
+    /// [`RstateManager::emit_policy`], e.g. with [`StateBuilder::emit_policy`]. Takes
+    /// precedence over the `coalesceMs` option of the plugin [`Config`].
+    #[must_use]
+    pub fn emit_policy(mut self, policy: EmitPolicy) -> Self {
+        self.emit_policy = Some(policy);
+        self
+    }
+
+    /// Add a guard that every action must pass before it is dispatched.
+    ///
+    /// Guards run in the order they were added, for every store, and can filter on
+    /// [`Action::source`] (which the plugin sets itself for frontend and remote dispatches).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// Builder::new().guard(|action| {
+    ///     if action.kind.starts_with("RSTATE_RESTORE_") && action.source() != ActionSource::Replay {
+    ///         return Err(RstateError::rejected(format!("{} may only be replayed", action.kind)));
+    ///     }
+    ///     Ok(())
+    /// })
+    /// ```
+    #[must_use]
+    pub fn guard<F>(mut self, guard: F) -> Self
+    where
+        F: Fn(&Action) -> Result<()> + Send + Sync + 'static,
+    {
+        self.guards.push(Box::new(guard));
+        self
+    }
+
+    /// Wrap state update events in a [`StateUpdate`] envelope (default: `false`).
+    ///
+    /// Instead of the bare state, update events then carry the store revision and
+    /// the trace id of the action that caused the update, so the frontend can
+    /// correlate updates with its own dispatches.
+    #[must_use]
+    pub fn envelope_updates(mut self, envelope_updates: bool) -> Self {
+        self.envelope_updates = envelope_updates;
+        self
+    }
+
+    /// Resolve the commands changing a store to a [`StateUpdate`] envelope (default:
+    /// `false`).
+    ///
+    /// `dispatch`, `dispatch_batch`, `reset_state` and the commands generated by
+    /// [`rstate_commands!`](crate::rstate_commands) then resolve to the state of the
+    /// store along with its revision, and `get_initial_state` too, for resyncing. Both
+    /// are read together after the dispatch, so the state includes any dispatch that
+    /// landed in between. Along with [update envelopes](Self::envelope_updates), this
+    /// lets the frontend spot dropped or out-of-order updates: an update whose revision
+    /// isn't the next one means a resync, and one at or below the current revision is
+    /// stale. Dry runs still resolve to the bare simulated state. The revision is the
+    /// store's own: slices have theirs, on their own events.
+    #[must_use]
+    pub fn envelope_responses(mut self, envelope_responses: bool) -> Self {
+        self.envelope_responses = envelope_responses;
+        self
+    }
+
+    /// Emit JSON Patch diffs of the app-wide state instead of the full state (default: `false`).
+    ///
+    /// A dispatch that changed the state then emits a [`StatePatch`] on
+    /// [`STATE_PATCH_EVENT`]. The full state is still emitted on the regular event when a
+    /// patch wouldn't be smaller, and for coalesced or forced updates.
+    #[must_use]
+    pub fn emit_patches(mut self, emit_patches: bool) -> Self {
+        self.emit_patches = emit_patches;
+        self
+    }
+
+    /// Trim unchanged top-level subtrees out of state update events (default: `false`).
+    ///
+    /// When enabled, update events carry a [`StateUpdate`] envelope, and every
+    /// top-level key whose value didn't change is replaced by
+    /// `{"$unchanged": <previous revision>}`. Windows that cached the previous state
+    /// can then skip deserializing large untouched subtrees.
+    #[must_use]
+    pub fn trim_unchanged(mut self, trim_unchanged: bool) -> Self {
+        self.trim_unchanged = trim_unchanged;
+        self
+    }
+
+    /// Batch dispatches arriving within `window` (default: no batching).
+    ///
+    /// Frontend dispatches and [`Rstate::dispatch_batched`] calls are queued and applied
+    /// together when the window ends, producing a single emit. Dispatches through
+    /// [`Rstate::dispatch`] are still applied right away. Only the app-wide store is
+    /// batched.
+    #[must_use]
+    pub fn batch_window(mut self, window: Duration) -> Self {
+        self.batch_window = Some(window);
+        self
+    }
+
+    /// Persist the actions queued by the [batch window](Self::batch_window) in `storage`
+    /// (default: not persisted).
+    ///
+    /// Queued actions are saved as they arrive, and cleared once their batch is applied
+    /// and the app-wide store flushed, so a crash mid-burst doesn't lose user input.
+    /// Actions left over by the last run are replayed when the state manager is
+    /// registered, before the store becomes ready. An action is replayed once per
+    /// [dispatch id](Action::dispatch_id), and keeps it, so handlers can use it as an
+    /// idempotency key: a crash right after flushing the store, before the queue is
+    /// cleared, replays actions that were already applied.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// Builder::new()
+    ///     .batch_window(Duration::from_millis(50))
+    ///     .persist_batch_queue(FileBackend::new(data_dir.join("queue.json")))
+    /// ```
+    #[must_use]
+    pub fn persist_batch_queue(mut self, storage: impl StorageBackend) -> Self {
+        self.batch_queue = Some(Box::new(storage));
+        self
+    }
+
+    /// Call `hook` with every local change of the app-wide store.
+    ///
+    /// Meant for sync layers pushing local changes out. Changes applied with
+    /// [`Rstate::apply_remote_change`] are not surfaced. The hook runs on the
+    /// dispatching thread, after the lock is released; keep it short.
+    #[must_use]
+    pub fn on_local_change<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&LocalChange) + Send + Sync + 'static,
+    {
+        self.local_change_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Resolve conflicts between remote and local changes with `resolver`.
+    ///
+    /// Without a resolver, remote changes win.
+    /// See [`Rstate::apply_remote_change`].
+    #[must_use]
+    pub fn on_conflict<F>(mut self, resolver: F) -> Self
+    where
+        F: Fn(&Conflict) -> Resolution + Send + Sync + 'static,
+    {
+        self.on_conflict = Some(Box::new(resolver));
+        self
+    }
+
+    /// Run `hook` after the state manager is registered, before the store is ready.
+    ///
+    /// Use it to warm up the state (e.g. fetch remote config into it) so the first state
+    /// the frontend sees is already complete: `get_initial_state` calls from the frontend
+    /// wait for the hook, and no update is emitted for the app-wide store until it is done.
+    /// The hook can dispatch as usual. If it fails, the error is logged and the store
+    /// becomes ready anyway.
+    ///
+    /// Note that a [registration timeout](Self::registration_timeout) also bounds how
+    /// long the frontend waits for the hook.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// Builder::new().on_ready(|app| async move {
+    ///     let config = fetch_remote_config().await?;
+    ///     app.rstate().dispatch_with("SET_CONFIG", config)?;
+    ///     Ok(())
+    /// })
+    /// ```
+    #[must_use]
+    pub fn on_ready<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: FnOnce(AppHandle<R>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.on_ready = Some(Box::new(move |app| Box::pin(hook(app))));
+        self
+    }
+
+    /// Serve the state to external WebSocket clients.
+    ///
+    /// See [`WebSocketConfig`] for the protocol and dispatch permissions.
+    #[cfg(feature = "websocket")]
+    #[must_use]
+    pub fn websocket(mut self, config: WebSocketConfig) -> Self {
+        self.websocket = Some(config);
+        self
+    }
+
+    /// Redact the value at `path` (in dot notation) from diagnostics bundles.
+    ///
+    /// Can be called multiple times. See [`Rstate::export_diagnostics`].
+    #[must_use]
+    pub fn redact(mut self, path: impl Into<String>) -> Self {
+        self.redact.push(path.into());
+        self
+    }
+
+    /// Set the time after which a window that stopped sending heartbeats is no longer
+    /// considered listening (default: 30 seconds).
+    ///
+    /// See the `heartbeat` command in [`Rstate::listening_windows`].
+    #[must_use]
+    pub fn listener_timeout(mut self, timeout: Duration) -> Self {
+        self.listener_timeout = timeout;
+        self
+    }
+
+    /// Only emit update events to the windows listening for them (default: `false`).
+    ///
+    /// Windows report their listener with the `heartbeat` command. While no window is
+    /// listening, updates aren't emitted at all, which saves serializing them for
+    /// hidden utility windows. Listeners registered without a target (the default of
+    /// the JS `listen`) can't be told apart by window, and always receive updates.
+    #[must_use]
+    pub fn skip_idle_windows(mut self, skip: bool) -> Self {
+        self.skip_idle_windows = skip;
+        self
+    }
+
+    /// Don't emit the update caused by a frontend dispatch back to the window that
+    /// dispatched it (default: `false`).
+    ///
+    /// The `dispatch` command already resolves to the new state, so the dispatching
+    /// window would otherwise apply it twice. Updates sent as
+    /// [patches](Self::emit_patches) or [coalesced](crate::EmitPolicy) still go to every
+    /// window. As with [`skip_idle_windows`](Self::skip_idle_windows), listeners
+    /// registered without a target can't be told apart by window: listen through the
+    /// window (`getCurrentWebviewWindow().listen`) for them to be skipped.
+    #[must_use]
+    pub fn skip_dispatching_window(mut self, skip: bool) -> Self {
+        self.skip_dispatching_window = skip;
+        self
+    }
+
+    /// Emit the current state to every window once its page has loaded (default:
+    /// `false`), so it doesn't have to wait for `get_initial_state`.
+    ///
+    /// See [`Rstate::prime_window`].
+    #[must_use]
+    pub fn prime_windows(mut self, prime: bool) -> Self {
+        self.prime_windows = prime;
+        self
+    }
+
+    /// Reject an action kind for `cooldown` once its handler failed `threshold` times
+    /// in a row (default: disabled).
+    ///
+    /// Keeps a frontend retrying in a loop from hammering a broken reducer. The circuit
+    /// opening is logged and emitted as a [`CIRCUIT_OPEN_EVENT`]. After the cooldown,
+    /// the next dispatch goes through, and a single failure opens the circuit again.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// tauri_plugin_rstate::Builder::new()
+    ///     .circuit_breaker(5, Duration::from_secs(30))
+    ///     .build()
+    /// ```
+    #[must_use]
+    pub fn circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.circuit_breaker = Some(CircuitBreaker::new(threshold, cooldown));
+        self
+    }
+
+    /// Time how long the state manager takes to handle each action (default: `false`).
+    ///
+    /// The recent timings of every kind are reported, as percentiles, by
+    /// [`Rstate::action_timings`] and the `action_timings` command. Only the handler is
+    /// timed, while the store's lock is held.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// tauri_plugin_rstate::Builder::new()
+    ///     .time_actions(cfg!(debug_assertions))
+    ///     .build()
+    /// ```
+    #[must_use]
+    pub fn time_actions(mut self, time: bool) -> Self {
+        self.time_actions = time;
+        self
+    }
+
+    /// Keep the states of the app-wide store's last `revisions` revisions (default: none).
+    ///
+    /// A window resyncing with the `get_changes_since` command from one of them gets a
+    /// JSON Patch instead of the full state. Every kept state is a full copy, so keep the
+    /// history short for large states.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// tauri_plugin_rstate::Builder::new()
+    ///     .change_history(32)
+    ///     .build()
+    /// ```
+    #[must_use]
+    pub fn change_history(mut self, revisions: usize) -> Self {
+        self.change_history = Some(revisions).filter(|revisions| *revisions > 0);
+        self
+    }
+
+    /// Reject actions from the frontend whose serialized payload is larger than `bytes`
+    /// (default: no limit).
+    ///
+    /// The `dispatch` and `dispatch_batch` commands, and the commands generated by
+    /// [`rstate_commands!`], fail with [`RstateError::PayloadTooLarge`] before any
+    /// guard or handler runs, so an oversized payload never reaches the state, its
+    /// updates or its persisted copy. Actions dispatched from Rust aren't limited.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// tauri_plugin_rstate::Builder::new()
+    ///     .max_payload_size(1024 * 1024)
+    ///     .build()
+    /// ```
+    #[must_use]
+    pub fn max_payload_size(mut self, bytes: usize) -> Self {
+        self.max_payload_size = Some(bytes);
+        self
+    }
+
+    /// Apply the app-wide store's queued dispatches on a dedicated thread (default: off).
+    ///
+    /// [`Rstate::dispatch_queued`] and [`Rstate::dispatch_async`] send the action to
+    /// the store's actor over a channel instead of taking the store's lock themselves,
+    /// and so do the frontend's dispatches unless they are
+    /// [batched](Self::batch_window). The actor applies actions one at a time, in the
+    /// order they were sent. [`Rstate::dispatch`] still applies actions right away.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// tauri_plugin_rstate::Builder::new()
+    ///     .actor_mode(true)
+    ///     .build()
+    /// ```
+    #[must_use]
+    pub fn actor_mode(mut self, actor_mode: bool) -> Self {
+        self.actor_mode = actor_mode;
+        self
+    }
+
+    /// Assign action kinds to the concurrency group `name`.
+    ///
+    /// Actions within a group run one at a time, in dispatch order, even when they go to
+    /// different stores (the app-wide store, its slices and the window stores each have
+    /// their own lock). Kinds not assigned to a group are only serialized with the other
+    /// dispatches to the same store.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// tauri_plugin_rstate::Builder::new()
+    ///     .concurrency_group("playback", ["PLAY", "PAUSE", "SEEK"])
+    ///     .concurrency_group("library", ["IMPORT_TRACKS"])
+    ///     .build()
+    /// ```
+    #[must_use]
+    pub fn concurrency_group<I, K>(mut self, name: impl Into<String>, kinds: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<String>,
+    {
+        self.concurrency_groups
+            .declare(name.into(), kinds.into_iter().map(Into::into));
+        self
+    }
+
+    /// Debounce the actions of `kind`: one only runs once no other was dispatched for
+    /// `wait`, and only the latest one runs.
+    ///
+    /// For typing-driven actions, which would otherwise run the handler and emit an
+    /// update on every keystroke. Every coalesced dispatch resolves to the outcome of
+    /// the action that ran. Applies to the frontend's dispatches and
+    /// [`Rstate::dispatch_batched`], which then skip the batch queue.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// tauri_plugin_rstate::Builder::new()
+    ///     .debounce("SET_SEARCH_QUERY", Duration::from_millis(200))
+    ///     .build()
+    /// ```
+    #[must_use]
+    pub fn debounce(mut self, kind: impl Into<String>, wait: Duration) -> Self {
+        self.rate_limits.set(kind.into(), Limit::Debounce(wait));
+        self
+    }
+
+    /// Throttle the actions of `kind`: the first one runs right away, then at most one
+    /// per `interval`, the latest dispatched during the interval.
+    ///
+    /// Every coalesced dispatch resolves to the outcome of the action that ran. Applies
+    /// to the frontend's dispatches and [`Rstate::dispatch_batched`], which then skip
+    /// the batch queue.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// tauri_plugin_rstate::Builder::new()
+    ///     .throttle("SET_SCROLL_POSITION", Duration::from_millis(100))
+    ///     .build()
+    /// ```
+    #[must_use]
+    pub fn throttle(mut self, kind: impl Into<String>, interval: Duration) -> Self {
+        self.rate_limits.set(kind.into(), Limit::Throttle(interval));
+        self
+    }
+
+    /// Build the plugin.
+    pub fn build(self) -> TauriPlugin<R, Option<Config>> {
+        // Note: No Arc needed - Tauri handles Arc internally when we call app.manage()
+        // We use Option + Mutex to allow taking ownership in the setup closure
+        let state_cell = Mutex::new(self.state_manager);
+        let loader_cell = Mutex::new(self.state_loader);
+        let prime_windows = self.prime_windows;
+        let emit_policy_set = self.emit_policy.is_some();
+        #[cfg(feature = "websocket")]
+        let websocket = Mutex::new(self.websocket);
+        let options = Mutex::new(Some(PluginOptions {
+            registration_timeout: self.registration_timeout,
+            transports: self.transports,
+            emit_events: self.emit_events,
+            log_actions: self.log_actions,
+            audit_log: self.audit_log,
+            trim_unchanged: self.trim_unchanged,
+            envelope_updates: self.envelope_updates,
+            envelope_responses: self.envelope_responses,
+            emit_patches: self.emit_patches,
+            emit_policy: self.emit_policy.unwrap_or_default(),
+            guards: self.guards,
+            on_ready: self.on_ready,
+            batch_window: self.batch_window,
+            batch_queue: self.batch_queue,
+            local_change_hooks: self.local_change_hooks,
+            on_conflict: self.on_conflict,
+            redact: self.redact,
+            listener_timeout: self.listener_timeout,
+            skip_idle_windows: self.skip_idle_windows,
+            skip_dispatching_window: self.skip_dispatching_window,
+            concurrency_groups: self.concurrency_groups,
+            rate_limits: self.rate_limits,
+            circuit_breaker: self.circuit_breaker,
+            time_actions: self.time_actions,
+            change_history: self.change_history,
+            max_payload_size: self.max_payload_size,
+            actor_mode: self.actor_mode,
+        }));
+
+        PluginBuilder::<R, Option<Config>>::new("rstate")
+            .invoke_handler(tauri::generate_handler![
+                commands::get_initial_state,
+                commands::get_changes_since,
+                commands::get_state,
+                commands::get_selector,
+                commands::dispatch,
+                commands::dispatch_batch,
+                commands::reset_state,
+                commands::get_schema,
+                commands::list_actions,
+                commands::action_timings,
+                commands::get_persisted_history,
+                commands::health_check,
+                commands::heartbeat,
+                commands::subscribe,
+                commands::unsubscribe
+            ])
+            .setup(move |app, api| {
+                // Setup is only called once, so the options are always there
+                let mut options = options.lock().unwrap().take().unwrap_or_default();
+                if !emit_policy_set {
+                    if let Some(policy) = api.config().as_ref().and_then(Config::emit_policy) {
+                        options.emit_policy = policy;
+                    }
+                }
+                #[cfg(feature = "websocket")]
+                if let Some(config) = websocket.lock().unwrap().take() {
+                    let transport = websocket::start(app.clone(), config)?;
+                    options.transports.push(Box::new(transport));
+                }
+                #[cfg(mobile)]
+                let rstate = mobile::init(app, api, options)?;
+                #[cfg(desktop)]
+                let rstate = desktop::init(app, api, options)?;
+
+                app.manage(rstate);
+                app.manage(client::handle(app));
+
+                // Take the state out of the Option (setup is only called once)
+                if let Some(state_manager) = state_cell.lock().unwrap().take() {
+                    app.rstate().register_boxed(state_manager)?;
+                }
+                if let Some(load) = loader_cell.lock().unwrap().take() {
+                    app.rstate().load_boxed(load)?;
+                }
+                Ok(())
+            })
+            .on_page_load(move |webview, payload| {
+                if prime_windows && payload.event() == PageLoadEvent::Finished {
+                    if let Err(err) = webview.rstate().prime_window(webview.label()) {
+                        log::warn!(target: ACTION_LOG_TARGET, "priming window '{}': {err}", webview.label());
+                    }
+                }
+            })
+            .on_event(|app, event| {
+                // Flush and drop the store of destroyed windows, so they don't pile up
+                if let RunEvent::WindowEvent {
+                    label,
+                    event: WindowEvent::Destroyed,
+                    ..
+                } = event
+                {
+                    if let Err(err) = app.rstate().remove_window_store(label) {
+                        log::warn!(target: ACTION_LOG_TARGET, "window store '{label}': {err}");
+                    }
+                    app.rstate().heartbeat(label, false);
+                    app.rstate().unsubscribe_window(label);
+                }
+
+                // Flush the app-wide store, so pending saves aren't lost
+                if let RunEvent::Exit = event {
+                    if app.rstate().is_registered() {
+                        if let Err(err) = app.rstate().flush() {
+                            log::warn!(target: ACTION_LOG_TARGET, "flushing state on exit: {err}");
+                        }
+                    }
+                }
+            })
+            .build()
+    }
+}
+
+/// Hook run by [`Builder::on_ready`].
+pub(crate) type ReadyHook<R> =
+    Box<dyn FnOnce(AppHandle<R>) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send>;
+
+/// Builds a state manager in the background, see [`Builder::load_state_manager`].
+pub(crate) type StateLoader = Box<dyn FnOnce() -> Result<Box<dyn RstateManager>> + Send>;
+
+// Box `load`, erasing the type of the manager it builds
+pub(crate) fn boxed_loader<S, F>(load: F) -> StateLoader
+where
+    S: RstateManager,
+    F: FnOnce() -> Result<S> + Send + 'static,
+{
+    Box::new(move || load().map(|state_manager| Box::new(state_manager) as Box<dyn RstateManager>))
+}
+
+/// Plugin-level options collected by [`Builder`].
+pub(crate) struct PluginOptions<R: Runtime> {
+    pub(crate) registration_timeout: Option<Duration>,
+    pub(crate) transports: Vec<Box<dyn UpdateTransport>>,
+    pub(crate) emit_events: bool,
+    pub(crate) log_actions: Option<log::Level>,
+    pub(crate) audit_log: Option<AuditLog>,
+    pub(crate) trim_unchanged: bool,
+    pub(crate) envelope_updates: bool,
+    pub(crate) envelope_responses: bool,
+    pub(crate) emit_patches: bool,
+    pub(crate) emit_policy: EmitPolicy,
+    pub(crate) guards: Vec<ActionGuard>,
+    pub(crate) on_ready: Option<ReadyHook<R>>,
+    pub(crate) batch_window: Option<Duration>,
+    pub(crate) batch_queue: Option<Box<dyn StorageBackend>>,
+    pub(crate) local_change_hooks: Vec<LocalChangeHook>,
+    pub(crate) on_conflict: Option<ConflictResolver>,
+    pub(crate) redact: Vec<String>,
+    pub(crate) listener_timeout: Duration,
+    pub(crate) skip_idle_windows: bool,
+    pub(crate) skip_dispatching_window: bool,
+    pub(crate) concurrency_groups: ConcurrencyGroups,
+    pub(crate) rate_limits: RateLimits,
+    pub(crate) circuit_breaker: Option<CircuitBreaker>,
+    pub(crate) time_actions: bool,
+    pub(crate) change_history: Option<usize>,
+    pub(crate) max_payload_size: Option<usize>,
+    pub(crate) actor_mode: bool,
+}
+
+impl<R: Runtime> Default for PluginOptions<R> {
+    fn default() -> Self {
+        Self {
+            registration_timeout: None,
+            transports: Vec::new(),
+            emit_events: false,
+            log_actions: None,
+            audit_log: None,
+            trim_unchanged: false,
+            envelope_updates: false,
+            envelope_responses: false,
+            emit_patches: false,
+            emit_policy: EmitPolicy::default(),
+            guards: Vec::new(),
+            on_ready: None,
+            batch_window: None,
+            batch_queue: None,
+            local_change_hooks: Vec::new(),
+            on_conflict: None,
+            redact: Vec::new(),
+            listener_timeout: DEFAULT_LISTENER_TIMEOUT,
+            skip_idle_windows: false,
+            skip_dispatching_window: false,
+            concurrency_groups: ConcurrencyGroups::default(),
+            rate_limits: RateLimits::default(),
+            circuit_breaker: None,
+            time_actions: false,
+            change_history: None,
+            max_payload_size: None,
+            actor_mode: false,
+        }
+    }
+}
 
 /// Initializes the plugin with a state manager.
 ///
@@ -57,32 +1002,8 @@ pub type ManagedState = Mutex<Box<dyn RstateManager>>;
 ///     .run(tauri::generate_context!())
 ///     .unwrap();
 /// ```
-pub fn init<R: Runtime, S: RstateManager>(state_manager: S) -> TauriPlugin<R> {
-    // Note: No Arc needed - Tauri handles Arc internally when we call app.manage()
-    // We use Option + Mutex to allow taking ownership in the setup closure
-    let state: ManagedState = Mutex::new(Box::new(state_manager));
-    let state_cell = Mutex::new(Some(state));
-
-    Builder::new("rstate")
-        .invoke_handler(tauri::generate_handler![
-            commands::get_initial_state,
-            commands::get_state,
-            commands::dispatch
-        ])
-        .setup(move |app, api| {
-            #[cfg(mobile)]
-            let rstate = mobile::init(app, api)?;
-            #[cfg(desktop)]
-            let rstate = desktop::init(app, api)?;
-
-            // Take the state out of the Option (setup is only called once)
-            if let Some(managed_state) = state_cell.lock().unwrap().take() {
-                app.manage(managed_state);
-            }
-            app.manage(rstate);
-            Ok(())
-        })
-        .build()
+pub fn init<R: Runtime, S: RstateManager>(state_manager: S) -> TauriPlugin<R, Option<Config>> {
+    Builder::new().state_manager(state_manager).build()
 }
 
 /// Initializes the plugin without a state manager.
@@ -104,22 +1025,6 @@ pub fn init<R: Runtime, S: RstateManager>(state_manager: S) -> TauriPlugin<R> {
 ///     .run(tauri::generate_context!())
 ///     .unwrap();
 /// ```
-pub fn init_empty<R: Runtime>() -> TauriPlugin<R> {
-    Builder::new("rstate")
-        .invoke_handler(tauri::generate_handler![
-            commands::get_initial_state,
-            commands::get_state,
-            commands::dispatch
-        ])
-        .setup(move |app, api| {
-            #[cfg(mobile)]
-            let rstate = mobile::init(app, api)?;
-            #[cfg(desktop)]
-            let rstate = desktop::init(app, api)?;
-
-            // Only register the rstate interface, state manager will be registered later
-            app.manage(rstate);
-            Ok(())
-        })
-        .build()
+pub fn init_empty<R: Runtime>() -> TauriPlugin<R, Option<Config>> {
+    Builder::new().build()
 }
@@ -0,0 +1,79 @@
+//! Tracking of the windows listening for state updates.
+//!
+//! The frontend reports its update listener with the `heartbeat` command: with
+//! `listening: true` when it starts listening and then periodically, well within the
+//! [`Builder::listener_timeout`](crate::Builder::listener_timeout), and with
+//! `listening: false` when it stops. A window that hasn't sent a heartbeat within the
+//! timeout is considered stale, e.g. because it crashed or was reloaded.
+//!
+//! ```js
+//! const unlisten = await listen('rstate://state-update', onUpdate)
+//! const beat = (listening) => invoke('plugin:rstate|heartbeat', { listening })
+//! await beat(true)
+//! const timer = setInterval(() => beat(true), 10_000)
+//! ```
+//!
+//! The active windows are listed by [`Rstate::listening_windows`](crate::Rstate::listening_windows)
+//! and in diagnostics bundles. With [`Builder::skip_idle_windows`](crate::Builder::skip_idle_windows),
+//! updates are only emitted to the listening windows, and not at all while none is.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Time after which a window without heartbeat is considered stale, by default.
+pub(crate) const DEFAULT_LISTENER_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Last heartbeat of every listening window
+pub(crate) struct Listeners {
+    timeout: Duration,
+    windows: Mutex<HashMap<String, Instant>>,
+}
+
+impl Listeners {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Record a heartbeat of the window `label`
+    pub(crate) fn heartbeat(&self, label: &str, listening: bool) {
+        if let Ok(mut windows) = self.windows.lock() {
+            if listening {
+                windows.insert(label.to_owned(), Instant::now());
+            } else {
+                windows.remove(label);
+            }
+        }
+    }
+
+    // Labels of the windows with a recent heartbeat, sorted; stale ones are dropped
+    pub(crate) fn active(&self) -> Vec<String> {
+        let Ok(mut windows) = self.windows.lock() else {
+            return Vec::new();
+        };
+        windows.retain(|_, beat| beat.elapsed() <= self.timeout);
+        let mut labels: Vec<_> = windows.keys().cloned().collect();
+        labels.sort();
+        labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listeners_expire() {
+        let listeners = Listeners::new(Duration::from_millis(20));
+        listeners.heartbeat("main", true);
+        listeners.heartbeat("settings", true);
+        listeners.heartbeat("settings", false);
+        assert_eq!(listeners.active(), ["main"]);
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(listeners.active().is_empty());
+    }
+}
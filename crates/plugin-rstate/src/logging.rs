@@ -0,0 +1,69 @@
+//! Action logging through the [`log`] facade.
+//!
+//! When enabled with [`Builder::log_actions`](crate::Builder::log_actions), every
+//! dispatched action is logged under the [`ACTION_LOG_TARGET`] target. With
+//! `tauri-plugin-log` installed, these records land in the same targets as the rest
+//! of the app's logs (rotating log files, stdout, the webview console); without any
+//! logger installed, they are simply dropped.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! tauri::Builder::default()
+//!     .plugin(tauri_plugin_log::Builder::new().build())
+//!     .plugin(
+//!         tauri_plugin_rstate::Builder::new()
+//!             .state_manager(manager)
+//!             .log_actions(log::Level::Info)
+//!             .build(),
+//!     )
+//!     .run(tauri::generate_context!())
+//!     .unwrap();
+//! ```
+
+use log::Level;
+
+use crate::RstateError;
+use crate::models::Action;
+
+/// Log target used for action records, e.g. for filtering in `tauri-plugin-log`.
+pub const ACTION_LOG_TARGET: &str = "rstate::actions";
+
+// Writes one record per dispatched action.
+// Failed actions are always logged as warnings.
+pub(crate) struct ActionLog {
+    level: Level,
+}
+
+impl ActionLog {
+    pub(crate) fn new(level: Level) -> Self {
+        Self { level }
+    }
+
+    pub(crate) fn record(&self, action: &Action, result: Result<bool, &RstateError>) {
+        // Include the trace id, so records can be matched with frontend logs
+        let trace = action
+            .trace_id()
+            .map(|trace_id| format!(" [trace {trace_id}]"))
+            .unwrap_or_default();
+        match result {
+            Ok(true) => log::log!(
+                target: ACTION_LOG_TARGET,
+                self.level,
+                "{}{}: state changed",
+                action.kind,
+                trace
+            ),
+            Ok(false) => log::log!(
+                target: ACTION_LOG_TARGET,
+                self.level,
+                "{}{}: state unchanged",
+                action.kind,
+                trace
+            ),
+            Err(err) => {
+                log::warn!(target: ACTION_LOG_TARGET, "{}{}: failed: {}", action.kind, trace, err)
+            }
+        }
+    }
+}
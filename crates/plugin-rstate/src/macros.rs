@@ -0,0 +1,90 @@
+/// Generate `#[tauri::command]` wrappers dispatching actions to the app-wide store.
+///
+/// Each entry declares a command dispatching an action kind, without payload, with a
+/// payload taken from a `value` argument, or with a payload taken from a named
+/// argument. The commands tag their actions as coming from the frontend, with a
+/// dispatch id, timestamp and the calling window, like the plugin's `dispatch`
+/// command, and resolve to the new state.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// tauri_plugin_rstate::rstate_commands! {
+///     increment => "INCREMENT",
+///     set_counter(i32) => "SET_COUNTER",
+///     add_todo(text: String) => "ADD_TODO",
+/// }
+///
+/// tauri::Builder::default()
+///     .invoke_handler(tauri::generate_handler![increment, set_counter, add_todo])
+/// ```
+///
+/// ```js
+/// await invoke('set_counter', { value: 42 })
+/// await invoke('add_todo', { text: 'Write docs' })
+/// ```
+#[macro_export]
+macro_rules! rstate_commands {
+    () => {};
+    ($name:ident => $kind:expr $(, $($rest:tt)*)?) => {
+        #[::tauri::command]
+        async fn $name<R: ::tauri::Runtime>(
+            app: ::tauri::AppHandle<R>,
+            window: ::tauri::Window<R>,
+        ) -> $crate::Result<$crate::JsonValue> {
+            let action = $crate::Action::new($kind);
+            $crate::__dispatch_command(&app, &window, action)
+        }
+        $($crate::rstate_commands! { $($rest)* })?
+    };
+    ($name:ident($ty:ty) => $kind:expr $(, $($rest:tt)*)?) => {
+        $crate::rstate_commands! { $name(value: $ty) => $kind $(, $($rest)*)? }
+    };
+    ($name:ident($arg:ident: $ty:ty) => $kind:expr $(, $($rest:tt)*)?) => {
+        #[::tauri::command]
+        async fn $name<R: ::tauri::Runtime>(
+            app: ::tauri::AppHandle<R>,
+            window: ::tauri::Window<R>,
+            $arg: $ty,
+        ) -> $crate::Result<$crate::JsonValue> {
+            let action = $crate::Action::with_payload($kind, $arg)?;
+            $crate::__dispatch_command(&app, &window, action)
+        }
+        $($crate::rstate_commands! { $($rest)* })?
+    };
+}
+
+// Deserialize the field `name` of an action's object payload, for handlers generated
+// by `#[handlers]`. A missing field is `null`, so it can be an `Option`.
+#[doc(hidden)]
+pub fn __payload_field<T: serde::de::DeserializeOwned>(
+    action: &crate::Action,
+    name: &str,
+) -> crate::Result<T> {
+    let payload = action
+        .payload
+        .as_ref()
+        .ok_or_else(|| crate::RstateError::missing_payload(&action.kind))?;
+    let value = payload.get(name).cloned().unwrap_or_default();
+    serde_json::from_value(value)
+        .map_err(|e| crate::RstateError::invalid_payload(format!("{name}: {e}")))
+}
+
+// Dispatch an action received through a command generated by `rstate_commands!`
+#[doc(hidden)]
+pub fn __dispatch_command<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    window: &tauri::Window<R>,
+    action: crate::Action,
+) -> crate::Result<crate::JsonValue> {
+    use crate::RstateExt;
+    use crate::models::TagFrontend;
+
+    app.rstate().check_payload_size(&action)?;
+    // Never trust the metadata claimed by the frontend
+    let state = app
+        .rstate()
+        .dispatch(action.tag_frontend(Some(window.label())))?;
+    app.rstate()
+        .respond(crate::StoreScope::App, window.label(), state)
+}
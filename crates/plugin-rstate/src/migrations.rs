@@ -0,0 +1,321 @@
+//! Versioned persisted state.
+//!
+//! Changing the shape of a persisted state type breaks loading the state saved by
+//! older app versions. With [`StateBuilder::version`](crate::StateBuilder::version),
+//! the persisted state records the version of its shape under [`VERSION_KEY`], and
+//! the migrations registered with [`StateBuilder::migration`](crate::StateBuilder::migration)
+//! bring older states up to date when the manager is built:
+//!
+//! ```rust,ignore
+//! let manager = StateBuilder::new(AppState::default())
+//!     .persist(data_dir.join("state.json"))
+//!     .version(3)
+//!     // v1 had a single `name`
+//!     .migration(1, |mut state| {
+//!         let name = state["name"].take();
+//!         state["user"] = serde_json::json!({ "name": name });
+//!         state
+//!     })
+//!     // v2 stored the theme as a boolean
+//!     .migration(2, |mut state| {
+//!         let dark = state["darkMode"].take().as_bool().unwrap_or_default();
+//!         state["theme"] = if dark { "dark".into() } else { "light".into() };
+//!         state
+//!     })
+//!     .build();
+//! ```
+//!
+//! The migration registered for version `n` turns a version `n` state into a version
+//! `n + 1` state; they run in order, from the persisted version up to the current one.
+//! A state persisted before versioning was enabled counts as version 1.
+//!
+//! A state that can't be migrated (saved by a newer version, with a missing or
+//! panicking migration, or not fitting the state once migrated) makes
+//! [`StateBuilder::try_build`](crate::StateBuilder::try_build) fail. With
+//! [`build`](crate::StateBuilder::build), the manager starts from the initial state
+//! with persistence disabled instead, so the state is still there for a fixed build.
+//!
+//! Before shipping an upgrade (or from a pre-flight check screen), run the migrations
+//! on a copy of the persisted state with
+//! [`StateBuilder::dry_run_migrations`](crate::StateBuilder::dry_run_migrations): it
+//! reports the steps that would run, the keys each touches, and whether the result
+//! would load, without writing anything.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tauri_plugin_rstate_core::diff;
+
+use crate::error::catch_panic;
+use crate::models::JsonValue;
+use crate::{Result, RstateError};
+
+/// Key under which the version of the persisted state is stored, next to its fields.
+///
+/// It is only part of the persisted value, never of the state itself.
+pub const VERSION_KEY: &str = "@@version";
+
+// Version of a state persisted before versioning was enabled
+const UNVERSIONED: u32 = 1;
+
+// Turns a state of some version into a state of the next one
+type Migration = Box<dyn Fn(JsonValue) -> JsonValue + Send + Sync>;
+
+/// Outcome of [`StateBuilder::dry_run_migrations`](crate::StateBuilder::dry_run_migrations).
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct MigrationReport {
+    /// Version of the persisted state, if there is one
+    pub persisted_version: Option<u32>,
+    /// Version the state would be migrated to, if versioning is enabled
+    pub version: Option<u32>,
+    /// The migrations that would run, in order, up to the first failing one
+    pub steps: Vec<MigrationStep>,
+    /// Why the persisted state wouldn't load: a newer or invalid version, a missing or
+    /// panicking migration, or a result that doesn't fit the state. Empty if it would.
+    pub errors: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Whether the persisted state would load.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// A migration that would run, see [`MigrationReport`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct MigrationStep {
+    /// Version the migration starts from; it produces a state of version `from + 1`
+    pub from: u32,
+    /// Keys it adds, changes or removes, in dot notation
+    pub touched: Vec<String>,
+}
+
+// The current version of a persisted state, and the migrations leading to it
+#[derive(Default)]
+pub(crate) struct Migrations {
+    version: Option<u32>,
+    steps: BTreeMap<u32, Migration>,
+}
+
+impl Migrations {
+    pub(crate) fn set_version(&mut self, version: u32) {
+        self.version = Some(version);
+    }
+
+    pub(crate) fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    pub(crate) fn add(&mut self, from: u32, migration: Migration) {
+        self.steps.insert(from, migration);
+    }
+
+    // Whether a loaded state needs migrating to the current version
+    pub(crate) fn outdated(&self, persisted: &JsonValue) -> bool {
+        self.version
+            .is_some_and(|version| stored_version(persisted).is_ok_and(|stored| stored < version))
+    }
+
+    // Bring a loaded state up to the current version, removing its version key
+    pub(crate) fn migrate(&self, mut persisted: JsonValue) -> Result<JsonValue> {
+        let stored = stored_version(&persisted)?;
+        if let Some(fields) = persisted.as_object_mut() {
+            fields.remove(VERSION_KEY);
+        }
+        let Some(version) = self.version else {
+            return Ok(persisted);
+        };
+        if stored > version {
+            return Err(RstateError::state(format!(
+                "persisted state has version {stored}, newer than {version}"
+            )));
+        }
+
+        for from in stored..version {
+            let migration = self
+                .steps
+                .get(&from)
+                .ok_or_else(|| RstateError::state(format!("no migration from version {from}")))?;
+            let kind = format!("migration from version {from}");
+            persisted = catch_panic(&kind, || Ok(migration(persisted)))?;
+        }
+        Ok(persisted)
+    }
+
+    // Migrate a copy of a loaded state like `migrate`, recording every step. Returns the
+    // report, along with the migrated state if every step succeeded.
+    pub(crate) fn dry_run(&self, mut persisted: JsonValue) -> (MigrationReport, Option<JsonValue>) {
+        let mut report = MigrationReport {
+            version: self.version,
+            ..MigrationReport::default()
+        };
+        let stored = match stored_version(&persisted) {
+            Ok(stored) => stored,
+            Err(err) => {
+                report.errors.push(err.to_string());
+                return (report, None);
+            }
+        };
+        report.persisted_version = Some(stored);
+        if let Some(fields) = persisted.as_object_mut() {
+            fields.remove(VERSION_KEY);
+        }
+        let Some(version) = self.version else {
+            return (report, Some(persisted));
+        };
+        if stored > version {
+            report.errors.push(format!(
+                "persisted state has version {stored}, newer than {version}"
+            ));
+            return (report, None);
+        }
+
+        for from in stored..version {
+            let Some(migration) = self.steps.get(&from) else {
+                report
+                    .errors
+                    .push(format!("no migration from version {from}"));
+                return (report, None);
+            };
+            let before = persisted.clone();
+            let kind = format!("migration from version {from}");
+            match catch_panic(&kind, || Ok(migration(before.clone()))) {
+                Ok(migrated) => persisted = migrated,
+                Err(err) => {
+                    report.errors.push(err.to_string());
+                    return (report, None);
+                }
+            }
+            let mut touched: Vec<String> = diff(&before, &persisted)
+                .iter()
+                .map(|operation| operation.path().trim_start_matches('/').replace('/', "."))
+                .collect();
+            touched.sort();
+            touched.dedup();
+            report.steps.push(MigrationStep { from, touched });
+        }
+        (report, Some(persisted))
+    }
+}
+
+// The version recorded in a loaded state
+fn stored_version(persisted: &JsonValue) -> Result<u32> {
+    match persisted.get(VERSION_KEY) {
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| RstateError::state(format!("invalid persisted version: {version}"))),
+        None => Ok(UNVERSIONED),
+    }
+}
+
+// Record `version` in a state about to be persisted
+pub(crate) fn stamp(state: &mut JsonValue, version: u32) {
+    if let JsonValue::Object(fields) = state {
+        fields.insert(VERSION_KEY.to_owned(), version.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrations_run_from_the_persisted_version() {
+        let mut migrations = Migrations::default();
+        migrations.set_version(3);
+        migrations.add(
+            1,
+            Box::new(|mut state| {
+                let name = state.as_object_mut().unwrap().remove("name");
+                state["user"] = json!({ "name": name });
+                state
+            }),
+        );
+        migrations.add(
+            2,
+            Box::new(|mut state| {
+                state["theme"] = json!("dark");
+                state
+            }),
+        );
+
+        // Unversioned states are version 1
+        assert_eq!(
+            migrations.migrate(json!({ "name": "ada" })).unwrap(),
+            json!({ "user": { "name": "ada" }, "theme": "dark" })
+        );
+        assert_eq!(
+            migrations
+                .migrate(json!({ "@@version": 2, "theme": "light" }))
+                .unwrap(),
+            json!({ "theme": "dark" })
+        );
+        assert_eq!(
+            migrations
+                .migrate(json!({ "@@version": 3, "a": 1 }))
+                .unwrap(),
+            json!({ "a": 1 })
+        );
+        assert!(migrations.migrate(json!({ "@@version": 4 })).is_err());
+        assert!(migrations.outdated(&json!({ "@@version": 2 })));
+        assert!(!migrations.outdated(&json!({ "@@version": 3 })));
+
+        let mut state = json!({ "a": 1 });
+        stamp(&mut state, 3);
+        assert_eq!(migrations.migrate(state).unwrap(), json!({ "a": 1 }));
+    }
+
+    #[test]
+    fn test_dry_run_reports_steps_and_failures() {
+        let mut migrations = Migrations::default();
+        migrations.set_version(4);
+        migrations.add(
+            1,
+            Box::new(|mut state| {
+                let name = state.as_object_mut().unwrap().remove("name");
+                state["user"] = json!({ "name": name });
+                state
+            }),
+        );
+        migrations.add(2, Box::new(|state| state));
+
+        let (report, migrated) = migrations.dry_run(json!({ "name": "ada", "a": 1 }));
+        assert_eq!(report.persisted_version, Some(1));
+        assert_eq!(report.version, Some(4));
+        let steps: Vec<_> = report
+            .steps
+            .iter()
+            .map(|step| (step.from, step.touched.clone()))
+            .collect();
+        assert_eq!(
+            steps,
+            [(1, vec!["name".to_owned(), "user".to_owned()]), (2, vec![])]
+        );
+        assert_eq!(report.errors, ["no migration from version 3"]);
+        assert!(!report.is_ok());
+        assert!(migrated.is_none());
+
+        migrations.add(3, Box::new(|_| panic!("boom")));
+        let (report, _) = migrations.dry_run(json!({ "@@version": 3 }));
+        assert!(report.steps.is_empty());
+        assert_eq!(
+            report.errors,
+            ["Handler panicked: migration from version 3: boom"]
+        );
+
+        let (report, migrated) = migrations.dry_run(json!({ "@@version": 4, "a": 1 }));
+        assert!(report.is_ok());
+        assert_eq!(migrated, Some(json!({ "a": 1 })));
+        let (report, _) = migrations.dry_run(json!({ "@@version": 5 }));
+        assert_eq!(
+            report.errors,
+            ["persisted state has version 5, newer than 4"]
+        );
+    }
+}
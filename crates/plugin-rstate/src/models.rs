@@ -1,135 +1,156 @@
-use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::sync::Arc;
 
-pub use serde_json::Value as JsonValue;
+pub use tauri_plugin_rstate_core::{
+    Action, ActionMeta, ActionSource, DryRun, JsonValue, get_state, state_changed,
+};
 
-/// An action to be dispatched to the state manager.
+/// A guard deciding whether an action may be dispatched.
 ///
-/// Actions are the primary way to modify state. Each action has a `kind` (type)
-/// and an optional `payload` containing data for the action.
-///
-/// # Example
-///
-/// ```rust,ignore
-/// use tauri_plugin_rstate::Action;
+/// Return an error (typically [`RstateError::rejected`](crate::RstateError::rejected))
+/// to reject the action before any handler runs.
+pub type ActionGuard = Box<dyn Fn(&Action) -> crate::Result<()> + Send + Sync>;
+
+// Run every guard against `action`, stopping at the first rejection
+pub(crate) fn check_guards(guards: &[ActionGuard], action: &Action) -> crate::Result<()> {
+    guards.iter().try_for_each(|guard| guard(action))
+}
+
+// Reject `action` if its serialized payload is larger than `limit` bytes
+pub(crate) fn check_payload_size(action: &Action, limit: Option<usize>) -> crate::Result<()> {
+    let (Some(limit), Some(payload)) = (limit, &action.payload) else {
+        return Ok(());
+    };
+    // Count the bytes without building the serialized string
+    let mut counter = ByteCounter(0);
+    serde_json::to_writer(&mut counter, payload)
+        .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+    if counter.0 > limit {
+        return Err(crate::RstateError::PayloadTooLarge {
+            kind: action.kind.clone(),
+            size: counter.0,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+struct ByteCounter(usize);
+
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Tag an action received from the frontend, with a fresh dispatch id, the current
+// time and the calling window. Whatever the frontend claimed is overwritten.
+pub(crate) trait TagFrontend {
+    fn tag_frontend(self, window: Option<&str>) -> Self;
+}
+
+impl TagFrontend for Action {
+    fn tag_frontend(mut self, window: Option<&str>) -> Self {
+        let meta = self.meta.get_or_insert_with(ActionMeta::default);
+        meta.source = ActionSource::Frontend;
+        meta.id = Some(uuid::Uuid::new_v4().to_string());
+        meta.timestamp = Some(crate::health::unix_millis());
+        meta.window = window.map(str::to_owned);
+        self
+    }
+}
+
+/// The store targeted by a frontend command.
 ///
-/// // Create a simple action
-/// let action = Action::new("INCREMENT");
+/// Defaults to the app-wide store; `Window` targets the store created for the
+/// calling window with [`Rstate::create_window_store`](crate::Rstate::create_window_store).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum StoreScope {
+    /// The app-wide store
+    #[default]
+    App,
+    /// The store of the calling window
+    Window,
+}
+
+/// Downcasting access to a state manager, implemented for every type.
 ///
-/// // Create an action with a payload
-/// let action = Action::with_payload("ADD_TODO", "Buy groceries")?;
+/// A supertrait of [`RstateManager`], so code holding a registered manager (e.g. in
+/// [`Rstate::with_state_manager`](crate::Rstate::with_state_manager)) can get back to
+/// the concrete manager, e.g. to use the typed API of a
+/// [`BuiltStateManager`](crate::BuiltStateManager). Prefer
+/// [`downcast_ref`](dyn RstateManager::downcast_ref) on the manager: calling `as_any`
+/// on a `Box` or a lock guard returns the box or the guard itself.
 ///
-/// // In a handler, extract the payload
-/// let text: String = action.require_payload()?;
+/// ```rust,ignore
+/// let counter = app.rstate().with_state_manager(|manager| {
+///     manager
+///         .downcast_ref::<BuiltStateManager<AppState>>()
+///         .map(|manager| manager.with_state(|state| state.counter))
+/// })?;
 /// ```
-#[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct Action {
-    /// A string label for the action (e.g., "INCREMENT")
-    pub kind: String,
-    /// An optional payload for the action
-    pub payload: Option<JsonValue>,
+pub trait AsAny {
+    /// The manager as [`Any`].
+    fn as_any(&self) -> &dyn Any;
+
+    /// The manager as a mutable [`Any`].
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
 
-impl Action {
-    /// Create a new action with a kind and no payload
-    ///
-    /// # Example
-    ///
-    /// ```rust,ignore
-    /// let action = Action::new("INCREMENT");
-    /// ```
-    pub fn new(kind: impl Into<String>) -> Self {
-        Self {
-            kind: kind.into(),
-            payload: None,
-        }
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 
-    /// Create a new action with a kind and typed payload
-    ///
-    /// # Example
-    ///
-    /// ```rust,ignore
-    /// let action = Action::with_payload("SET_COUNT", 42)?;
-    /// let action = Action::with_payload("ADD_TODO", TodoItem { text: "Buy milk".into() })?;
-    /// ```
-    pub fn with_payload<T: Serialize>(kind: impl Into<String>, payload: T) -> crate::Result<Self> {
-        Ok(Self {
-            kind: kind.into(),
-            payload: Some(
-                serde_json::to_value(payload)
-                    .map_err(|e| crate::RstateError::serialization(e.to_string()))?,
-            ),
-        })
-    }
-
-    /// Create a new action with a kind and raw JSON payload
-    ///
-    /// # Example
-    ///
-    /// ```rust,ignore
-    /// use serde_json::json;
-    /// let action = Action::with_json("SET_CONFIG", json!({"theme": "dark"}));
-    /// ```
-    pub fn with_json(kind: impl Into<String>, payload: JsonValue) -> Self {
-        Self {
-            kind: kind.into(),
-            payload: Some(payload),
-        }
-    }
-
-    /// Get the payload as a specific type, returning None if missing or invalid
-    ///
-    /// # Example
-    ///
-    /// ```rust,ignore
-    /// if let Some(count) = action.payload_as::<i32>()? {
-    ///     println!("Count: {}", count);
-    /// }
-    /// ```
-    pub fn payload_as<T: DeserializeOwned>(&self) -> crate::Result<Option<T>> {
-        match &self.payload {
-            Some(value) => serde_json::from_value(value.clone())
-                .map(Some)
-                .map_err(|e| crate::RstateError::invalid_payload(e.to_string())),
-            None => Ok(None),
-        }
-    }
-
-    /// Get the payload as a specific type, returning an error if missing
-    ///
-    /// This is the most common way to extract a payload in action handlers.
-    ///
-    /// # Example
-    ///
-    /// ```rust,ignore
-    /// // In an action handler:
-    /// let todo: TodoItem = action.require_payload()?;
-    /// let count: i32 = action.require_payload()?;
-    /// ```
-    pub fn require_payload<T: DeserializeOwned>(&self) -> crate::Result<T> {
-        match &self.payload {
-            Some(value) => serde_json::from_value(value.clone())
-                .map_err(|e| crate::RstateError::invalid_payload(e.to_string())),
-            None => Err(crate::RstateError::missing_payload(&self.kind)),
-        }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The action kinds a state manager handles, see [`RstateManager::action_kinds`].
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ActionKinds {
+    /// The kinds with a handler, including the built-in ones the manager accepts,
+    /// sorted
+    pub kinds: Vec<String>,
+    /// Slices with a default handler, handling any kind prefixed with `"{slice}/"`
+    pub default_slices: Vec<String>,
+    /// Whether a default handler handles the other kinds
+    pub default_handler: bool,
+}
+
+/// The outcome of [`RstateManager::dispatch`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DispatchOutcome {
+    /// The state after the dispatch
+    pub state: JsonValue,
+    /// Whether the dispatch changed the state
+    pub changed: bool,
+}
+
+impl DispatchOutcome {
+    /// Create an outcome from the new state and whether it changed.
+    pub fn new(state: JsonValue, changed: bool) -> Self {
+        Self { state, changed }
     }
 
-    /// Check if the action has a payload
-    pub fn has_payload(&self) -> bool {
-        self.payload.is_some()
+    /// The outcome of a dispatch that changed the state to `state`.
+    pub fn changed(state: JsonValue) -> Self {
+        Self::new(state, true)
     }
 
-    /// Check if the action matches a specific kind
-    ///
-    /// # Example
-    ///
-    /// ```rust,ignore
-    /// if action.is("INCREMENT") {
-    ///     // handle increment
-    /// }
-    /// ```
-    pub fn is(&self, kind: &str) -> bool {
-        self.kind == kind
+    /// The outcome of a dispatch that left the state as `state`.
+    pub fn unchanged(state: JsonValue) -> Self {
+        Self::new(state, false)
     }
 }
 
@@ -138,12 +159,16 @@ impl Action {
 /// Implement this trait to define your state management logic.
 /// For simpler use cases, consider using [`StateBuilder`] instead.
 ///
+/// The plugin keeps the manager behind a [`ManagedState`](crate::ManagedState) lock:
+/// the `&self` methods may run on several threads at once, while the `&mut self` ones
+/// run alone.
+///
 /// # Example
 ///
 /// ```rust,ignore
 /// use serde::{Deserialize, Serialize};
 /// use std::sync::Mutex;
-/// use tauri_plugin_rstate::{Action, JsonValue, RstateManager, Result, RstateError};
+/// use tauri_plugin_rstate::{Action, DispatchOutcome, JsonValue, RstateManager, Result, RstateError};
 ///
 /// #[derive(Serialize, Deserialize, Default)]
 /// struct AppState {
@@ -163,82 +188,208 @@ impl Action {
 ///             .unwrap_or(JsonValue::Null)
 ///     }
 ///
-///     fn dispatch(&mut self, action: &Action) -> Result<JsonValue> {
+///     fn dispatch(&mut self, action: &Action) -> Result<DispatchOutcome> {
 ///         let mut state = self.state.lock()
 ///             .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
 ///
-///         match action.kind.as_str() {
-///             "INCREMENT" => state.counter += 1,
-///             "SET_COUNT" => state.counter = action.require_payload()?,
-///             _ => {}
-///         }
+///         let changed = match action.kind.as_str() {
+///             "INCREMENT" => {
+///                 state.counter += 1;
+///                 true
+///             }
+///             "SET_COUNT" => {
+///                 let count = action.require_payload()?;
+///                 std::mem::replace(&mut state.counter, count) != count
+///             }
+///             _ => false,
+///         };
 ///
-///         serde_json::to_value(&*state)
-///             .map_err(|e| RstateError::serialization(e.to_string()))
+///         let state = serde_json::to_value(&*state)
+///             .map_err(|e| RstateError::serialization(e.to_string()))?;
+///         Ok(DispatchOutcome::new(state, changed))
 ///     }
 /// }
 /// ```
-pub trait RstateManager: Send + Sync + 'static {
+pub trait RstateManager: AsAny + Send + Sync + 'static {
     /// Get the initial state of the app.
     fn get_initial_state(&self) -> JsonValue;
 
-    /// Apply an action to the state and return the new state.
-    fn dispatch(&mut self, action: &Action) -> crate::Result<JsonValue>;
-}
+    /// Apply an action to the state and return the new state, and whether it changed.
+    ///
+    /// The plugin trusts `changed` to decide whether to emit and persist the update,
+    /// without comparing the states, so the state is only serialized once per
+    /// dispatch. Reporting a change when unsure is always safe.
+    ///
+    /// A panicking dispatch is reported as a [`HandlerPanic`](crate::RstateError::HandlerPanic)
+    /// error; the manager's state must be left consistent, as
+    /// [`StateBuilder`](crate::StateBuilder)'s managers do by rolling back.
+    fn dispatch(&mut self, action: &Action) -> crate::Result<DispatchOutcome>;
 
-/// Helper function to get a specific part of the state by key (supports dot notation).
-///
-/// # Example
-///
-/// ```rust,ignore
-/// use serde_json::json;
-/// use tauri_plugin_rstate::get_state;
-///
-/// let state = json!({
-///     "user": {
-///         "profile": {
-///             "name": "John"
-///         }
-///     }
-/// });
-///
-/// let name = get_state(&state, "user.profile.name");
-/// assert_eq!(name, Some(json!("John")));
-///
-/// // Empty key returns the full state
-/// let full = get_state(&state, "");
-/// assert_eq!(full, Some(state.clone()));
-/// ```
-pub fn get_state(state: &JsonValue, key: &str) -> Option<JsonValue> {
-    if key.is_empty() {
-        return Some(state.clone());
+    /// Flush pending work (e.g. unsaved changes) before the store is dropped.
+    ///
+    /// Called when a window store is removed, including when its window is destroyed.
+    /// The default implementation does nothing.
+    fn flush(&mut self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Receive a [`Dispatcher`] for the store this manager was registered as.
+    ///
+    /// Called by the plugin when the manager is registered (including as a window
+    /// store), so work finishing in the background (e.g. async handlers) can dispatch
+    /// follow-up actions. The default implementation ignores it.
+    fn set_dispatcher(&mut self, dispatcher: Dispatcher) {
+        let _ = dispatcher;
     }
 
-    // Convert the key to a JSON pointer path (e.g., "theme.is_dark" -> "/theme/is_dark")
-    let pointer_path = format!("/{}", key.replace('.', "/"));
+    /// Receive the [`AppHandle`](tauri::AppHandle) the manager was registered with, as
+    /// an [`AnyAppHandle`].
+    ///
+    /// Called by the plugin when the manager is registered (including as a window
+    /// store or a slice), for handlers needing the app, such as
+    /// [`StateBuilder::on_with_app`](crate::StateBuilder::on_with_app)'s. The default
+    /// implementation ignores it.
+    fn set_app(&mut self, app: AnyAppHandle) {
+        let _ = app;
+    }
 
-    // Use the built-in JSON pointer to get the value (more efficient)
-    state.pointer(&pointer_path).cloned()
+    /// Receive a [`SaveTap`](crate::SaveTap) to report every write of the persisted
+    /// state to.
+    ///
+    /// Called by the plugin when the manager is registered, so the writes show up in
+    /// [`Rstate::tap`](crate::Rstate::tap). The default implementation ignores it.
+    fn set_save_tap(&mut self, tap: crate::SaveTap) {
+        let _ = tap;
+    }
+
+    /// Replace the whole state, bypassing action handlers.
+    ///
+    /// Used by [`Rstate::update`](crate::Rstate::update). The default implementation
+    /// returns an error.
+    fn replace_state(&mut self, state: JsonValue) -> crate::Result<()> {
+        let _ = state;
+        Err(crate::RstateError::state(
+            "Replacing the state is not supported by this state manager",
+        ))
+    }
+
+    /// The [`EmitPolicy`](crate::EmitPolicy) for actions of `kind`, overriding the
+    /// global one. The default implementation has no overrides.
+    fn emit_policy(&self, kind: &str) -> Option<crate::EmitPolicy> {
+        let _ = kind;
+        None
+    }
+
+    /// How change detection compares floating-point numbers in this store's state.
+    /// The default implementation uses [`FloatComparison::default`](crate::FloatComparison).
+    fn float_comparison(&self) -> crate::FloatComparison {
+        crate::FloatComparison::default()
+    }
+
+    /// Outcome of the last save, for stores that persist their state. Reported by
+    /// [`Rstate::health_check`](crate::Rstate::health_check). The default
+    /// implementation returns `None`.
+    fn last_save(&self) -> Option<crate::SaveStatus> {
+        None
+    }
+
+    /// Schema fingerprint of the state, if known. Sent to the frontend when the store
+    /// is ready, and returned by [`Rstate::schema`](crate::Rstate::schema). The
+    /// default implementation returns `None`.
+    fn schema(&self) -> Option<crate::SchemaFingerprint> {
+        None
+    }
+
+    /// Version of the state's shape, if persisted states are migrated. Recorded in
+    /// diagnostics bundles by [`Rstate::export_diagnostics`](crate::Rstate::export_diagnostics).
+    /// The default implementation returns `None`.
+    fn version(&self) -> Option<u32> {
+        None
+    }
+
+    /// Apply actions to a copy of the state and return the resulting state.
+    ///
+    /// The real state must not be modified. Used for "what would happen" previews.
+    /// The default implementation returns an error, as a generic manager has no way
+    /// to copy its state.
+    fn simulate(&self, actions: &[Action]) -> crate::Result<JsonValue> {
+        let _ = actions;
+        Err(crate::RstateError::state(
+            "Simulation is not supported by this state manager",
+        ))
+    }
+
+    /// Compute the selector `name`: a value derived from the state, e.g. a count, so
+    /// readers don't need to pull the whole state and derive it themselves.
+    ///
+    /// Used by [`Rstate::select`](crate::Rstate::select) and the `get_selector`
+    /// command. The default implementation returns an error.
+    fn select(&self, name: &str) -> crate::Result<JsonValue> {
+        let _ = name;
+        Err(crate::RstateError::state(
+            "Selectors are not supported by this state manager",
+        ))
+    }
+
+    /// Cache metrics of the selectors, for managers caching their values. Reported by
+    /// [`Rstate::selector_stats`](crate::Rstate::selector_stats). The default
+    /// implementation returns none.
+    fn selector_stats(&self) -> Vec<crate::SelectorStats> {
+        Vec::new()
+    }
+
+    /// Paths (in dot notation) of the state hidden from the frontend, see
+    /// [`StateBuilder::redact`](crate::StateBuilder::redact). The default
+    /// implementation hides none.
+    fn redacted_paths(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The action kinds the manager handles, so frontends and devtools can validate
+    /// dispatches and offer autocompletion.
+    ///
+    /// Used by [`Rstate::action_kinds`](crate::Rstate::action_kinds) and the
+    /// `list_actions` command. The default implementation returns an error.
+    fn action_kinds(&self) -> crate::Result<ActionKinds> {
+        Err(crate::RstateError::state(
+            "Listing action kinds is not supported by this state manager",
+        ))
+    }
+
+    /// Up to `limit` of the actions in the manager's persisted action log numbered after
+    /// `after`, oldest first, for managers logging them (see
+    /// [`StateBuilder::event_sourced`](crate::StateBuilder::event_sourced)).
+    ///
+    /// Used by [`Rstate::persisted_history`](crate::Rstate::persisted_history) and the
+    /// `get_persisted_history` command. The default implementation returns an error.
+    fn persisted_history(&self, after: u64, limit: usize) -> crate::Result<crate::HistoryPage> {
+        let _ = (after, limit);
+        Err(crate::RstateError::state(
+            "This state manager has no persisted action log",
+        ))
+    }
 }
 
-/// Helper function to check if a specific part of the state has changed.
-///
-/// This can be used for targeted updates when you only care about specific fields.
-///
-/// # Example
-///
-/// ```rust,ignore
-/// if state_changed(&old_state, &new_state, "user.settings.theme") {
-///     // Theme changed, update UI
-/// }
-/// ```
-pub fn state_changed(old_state: &JsonValue, new_state: &JsonValue, key: &str) -> bool {
-    let old_value = get_state(old_state, key);
-    let new_value = get_state(new_state, key);
+impl dyn RstateManager {
+    /// The manager as a `M`, if it is one.
+    pub fn downcast_ref<M: RstateManager>(&self) -> Option<&M> {
+        self.as_any().downcast_ref()
+    }
 
-    match (old_value, new_value) {
-        (Some(old), Some(new)) => old != new,
-        (None, None) => false,
-        _ => true, // One exists and the other doesn't, so it changed
+    /// The manager as a mutable `M`, if it is one.
+    pub fn downcast_mut<M: RstateManager>(&mut self) -> Option<&mut M> {
+        self.as_any_mut().downcast_mut()
     }
 }
+
+/// Dispatches actions to the store a manager is registered as.
+///
+/// See [`RstateManager::set_dispatcher`].
+pub type Dispatcher = Arc<dyn Fn(Action) -> crate::Result<JsonValue> + Send + Sync>;
+
+/// The [`AppHandle<R>`](tauri::AppHandle) a manager is registered with, with its
+/// runtime erased, so [`RstateManager`] isn't generic over it. Downcast it with
+/// `app.downcast_ref::<AppHandle<R>>()`.
+///
+/// See [`RstateManager::set_app`].
+pub type AnyAppHandle = Arc<dyn Any + Send + Sync>;
@@ -0,0 +1,63 @@
+//! Action kind naming policies.
+//!
+//! In apps with many actions, a [`Namespace`] keeps the action catalog organized by
+//! requiring every kind to share a prefix. Set it with
+//! [`StateBuilder::namespace`](crate::StateBuilder::namespace): registered kinds are
+//! checked when the manager is built, and dispatched kinds when they are handled.
+//!
+//! ```rust,ignore
+//! let manager = StateBuilder::new(AppState::default())
+//!     .namespace(Namespace::Required("app/"))
+//!     .on("app/INCREMENT", |state, _| { state.counter += 1; Ok(()) })
+//!     .build();
+//! ```
+
+use crate::{Result, RstateError};
+
+// Prefix of the actions made by rstate itself, which are exempt from naming policies
+const INTERNAL_PREFIX: &str = "@@rstate/";
+
+/// A naming policy for action kinds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Namespace {
+    /// Any kind is allowed (the default)
+    #[default]
+    Any,
+    /// Kinds must start with the given prefix, e.g. `"app/"`
+    Required(&'static str),
+}
+
+impl Namespace {
+    /// Check that `kind` follows the policy.
+    pub fn check(&self, kind: &str) -> Result<()> {
+        match self {
+            Self::Required(prefix)
+                if !kind.starts_with(prefix) && !kind.starts_with(INTERNAL_PREFIX) =>
+            {
+                Err(RstateError::invalid_action_kind(format!(
+                    "'{kind}' is outside the required namespace '{prefix}'"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_namespace() {
+        let namespace = Namespace::Required("app/");
+
+        assert!(namespace.check("app/INCREMENT").is_ok());
+        assert!(namespace.check("@@rstate/REMOTE_CHANGE").is_ok());
+        let err = namespace.check("INCREMENT").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid action kind: 'INCREMENT' is outside the required namespace 'app/'"
+        );
+        assert!(Namespace::Any.check("INCREMENT").is_ok());
+    }
+}
@@ -0,0 +1,826 @@
+//! Persisting state across app restarts.
+//!
+//! A [`StorageBackend`] loads and saves a JSON value. Besides the built-in
+//! [`FileBackend`], [`ChunkedFileBackend`] and [`MemoryBackend`] (and `SqliteBackend`
+//! with the `sqlite` feature), implement it to keep state in the OS keyring or cloud
+//! storage. Attach one to a state
+//! manager with [`StateBuilder::persist_with`](crate::StateBuilder::persist_with):
+//! the persisted state is loaded when the manager is built, and saved after every
+//! dispatch that changed the state and when the store is flushed. For the common case
+//! of a single JSON file, [`StateBuilder::persist`](crate::StateBuilder::persist)
+//! also debounces saves. When the shape of the state changes between app versions,
+//! version it with [`StateBuilder::version`](crate::StateBuilder::version) and migrate
+//! older persisted states with [`StateBuilder::migration`](crate::StateBuilder::migration).
+//!
+//! Different parts of the state can live in different backends. A [`RoutedBackend`]
+//! routes paths (in the dot notation of [`get_state`](crate::get_state)) to their
+//! own backend and composes loads and saves:
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::{
+//!     ChunkedFileBackend, FileBackend, RoutedBackend, SecretBackend, StateBuilder,
+//! };
+//!
+//! let storage = RoutedBackend::new()
+//!     .route("settings", FileBackend::new(config_dir.join("settings.json")))
+//!     .route("cache", ChunkedFileBackend::new(cache_dir.join("cache")))
+//!     .route("secrets", SecretBackend::new("state", Keyring::new("my-app")))
+//!     .rest(FileBackend::new(data_dir.join("state.json")));
+//!
+//! let manager = StateBuilder::new(AppState::default())
+//!     .persist_with(storage)
+//!     .build();
+//! ```
+//!
+//! A [`SecretBackend`] keeps its part of the state in a [`SecretStore`], such as the OS
+//! keyring or a Stronghold vault, instead of a file. Implement [`SecretStore`] over
+//! the crate of your choice:
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::{RstateError, SecretStore};
+//!
+//! struct Keyring(String);
+//!
+//! impl SecretStore for Keyring {
+//!     fn get(&self, name: &str) -> tauri_plugin_rstate::Result<Option<Vec<u8>>> {
+//!         match keyring::Entry::new(&self.0, name).and_then(|entry| entry.get_secret()) {
+//!             Ok(secret) => Ok(Some(secret)),
+//!             Err(keyring::Error::NoEntry) => Ok(None),
+//!             Err(e) => Err(RstateError::state(e.to_string())),
+//!         }
+//!     }
+//!
+//!     fn set(&self, name: &str, secret: &[u8]) -> tauri_plugin_rstate::Result<()> {
+//!         keyring::Entry::new(&self.0, name)
+//!             .and_then(|entry| entry.set_secret(secret))
+//!             .map_err(|e| RstateError::state(e.to_string()))
+//!     }
+//!
+//!     fn delete(&self, name: &str) -> tauri_plugin_rstate::Result<()> {
+//!         match keyring::Entry::new(&self.0, name).and_then(|entry| entry.delete_credential()) {
+//!             Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+//!             Err(e) => Err(RstateError::state(e.to_string())),
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Secrets persisted this way still reach the frontend with the rest of the state,
+//! unless hidden with [`StateBuilder::redact`](crate::StateBuilder::redact).
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::Result;
+use crate::health::SaveStatus;
+use crate::models::{JsonValue, get_state};
+use crate::tap::SaveTap;
+use crate::{migrations, schema};
+
+/// A place to persist state.
+pub trait StorageBackend: Send + Sync + 'static {
+    /// Load the persisted value, or `None` if nothing was persisted yet.
+    fn load(&self) -> Result<Option<JsonValue>>;
+
+    /// Persist `value`, replacing what was persisted before.
+    fn save(&self, value: &JsonValue) -> Result<()>;
+
+    /// Remove the persisted value.
+    fn clear(&self) -> Result<()>;
+}
+
+/// A backend storing the value as a JSON file.
+///
+/// Saves go through a temporary file that is renamed over the target, so a crash
+/// mid-write never leaves a truncated file behind.
+pub struct FileBackend {
+    path: PathBuf,
+}
+
+impl FileBackend {
+    /// Create a backend for the file at `path`. Parent directories are created on save.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn load(&self) -> Result<Option<JsonValue>> {
+        match fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| crate::RstateError::serialization(e.to_string())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self, value: &JsonValue) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let bytes = serde_json::to_vec_pretty(value)
+            .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, bytes)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A backend storing large states as one JSON file per top-level key.
+///
+/// Small states are stored in a single `state.json` file in the directory. Once the
+/// serialized state exceeds the [threshold](Self::threshold), every top-level key is
+/// stored in its own file under `chunks/`, listed in a `manifest.json`, and saves only
+/// rewrite the chunks whose subtree changed. States that aren't objects are always
+/// stored in a single file.
+pub struct ChunkedFileBackend {
+    dir: PathBuf,
+    threshold: usize,
+    // Hash of every chunk as last written or loaded, by key
+    written: Mutex<HashMap<String, u64>>,
+}
+
+impl ChunkedFileBackend {
+    /// Create a backend storing files in the directory `dir`, with a threshold of 1 MiB.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            threshold: 1024 * 1024,
+            written: Mutex::default(),
+        }
+    }
+
+    /// Split the state into chunks once it exceeds `bytes` serialized.
+    #[must_use]
+    pub fn threshold(mut self, bytes: usize) -> Self {
+        self.threshold = bytes;
+        self
+    }
+
+    fn single(&self) -> FileBackend {
+        FileBackend::new(self.dir.join("state.json"))
+    }
+
+    fn manifest(&self) -> FileBackend {
+        FileBackend::new(self.dir.join("manifest.json"))
+    }
+
+    fn chunk(&self, file: &str) -> FileBackend {
+        FileBackend::new(self.dir.join("chunks").join(file))
+    }
+
+    // Save to the single file, then drop the chunks (the manifest takes precedence)
+    fn save_single(&self, value: &JsonValue) -> Result<()> {
+        self.single().save(value)?;
+        self.remove_chunks()
+    }
+
+    fn remove_chunks(&self) -> Result<()> {
+        self.written()?.clear();
+        self.manifest().clear()?;
+        match fs::remove_dir_all(self.dir.join("chunks")) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn written(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, u64>>> {
+        self.written
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))
+    }
+}
+
+impl StorageBackend for ChunkedFileBackend {
+    fn load(&self) -> Result<Option<JsonValue>> {
+        let Some(manifest) = self.manifest().load()? else {
+            return self.single().load();
+        };
+
+        let mut written = self.written()?;
+        let mut state = serde_json::Map::new();
+        for key in manifest_keys(&manifest) {
+            if let Some(value) = self.chunk(&chunk_file(&key)).load()? {
+                written.insert(key.clone(), hash_chunk(&value));
+                state.insert(key, value);
+            }
+        }
+        Ok(Some(JsonValue::Object(state)))
+    }
+
+    fn save(&self, value: &JsonValue) -> Result<()> {
+        let JsonValue::Object(state) = value else {
+            return self.save_single(value);
+        };
+        let size = serde_json::to_vec(value)
+            .map_err(|e| crate::RstateError::serialization(e.to_string()))?
+            .len();
+        if size <= self.threshold {
+            return self.save_single(value);
+        }
+
+        let mut written = self.written()?;
+        for (key, subtree) in state {
+            let hash = hash_chunk(subtree);
+            if written.get(key) != Some(&hash) {
+                self.chunk(&chunk_file(key)).save(subtree)?;
+                written.insert(key.clone(), hash);
+            }
+        }
+
+        // Drop the chunks of removed keys, after the manifest stopped listing them
+        let removed: Vec<_> = written
+            .keys()
+            .filter(|key| !state.contains_key(*key))
+            .cloned()
+            .collect();
+        let keys: Vec<_> = state.keys().collect();
+        self.manifest().save(&serde_json::json!({ "keys": keys }))?;
+        for key in removed {
+            self.chunk(&chunk_file(&key)).clear()?;
+            written.remove(&key);
+        }
+        self.single().clear()
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.remove_chunks()?;
+        self.single().clear()
+    }
+}
+
+fn manifest_keys(manifest: &JsonValue) -> Vec<String> {
+    manifest["keys"]
+        .as_array()
+        .map(|keys| {
+            keys.iter()
+                .filter_map(|key| key.as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// File name of the chunk of `key`, with anything but ASCII alphanumerics, `-` and `_`
+// percent-encoded
+fn chunk_file(key: &str) -> String {
+    let mut file = String::with_capacity(key.len() + 5);
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            file.push(byte as char);
+        } else {
+            file.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    file.push_str(".json");
+    file
+}
+
+fn hash_chunk(value: &JsonValue) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A backend keeping the value in memory, e.g. for tests.
+///
+/// Clones share the same value, so a clone can be kept to inspect what was saved.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    value: Arc<Mutex<Option<JsonValue>>>,
+}
+
+impl MemoryBackend {
+    /// Create an empty backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a backend with `value` already persisted.
+    pub fn with_value(value: JsonValue) -> Self {
+        Self {
+            value: Arc::new(Mutex::new(Some(value))),
+        }
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn load(&self) -> Result<Option<JsonValue>> {
+        self.value
+            .lock()
+            .map(|value| value.clone())
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))
+    }
+
+    fn save(&self, value: &JsonValue) -> Result<()> {
+        *self
+            .value
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))? = Some(value.clone());
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        *self
+            .value
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))? = None;
+        Ok(())
+    }
+}
+
+/// A store of secrets by name, such as the OS keyring or a Stronghold vault, for a
+/// [`SecretBackend`].
+pub trait SecretStore: Send + Sync + 'static {
+    /// The secret `name`, or `None` if there is none.
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Store `secret` as `name`, replacing the previous one.
+    fn set(&self, name: &str, secret: &[u8]) -> Result<()>;
+
+    /// Remove the secret `name`. Succeeds if there is none.
+    fn delete(&self, name: &str) -> Result<()>;
+}
+
+/// A backend keeping the value in a [`SecretStore`], as a single JSON secret.
+///
+/// Meant for the sensitive part of the state (e.g. `secrets`), routed to it with a
+/// [`RoutedBackend`] while the rest is persisted as usual.
+pub struct SecretBackend {
+    name: String,
+    store: Box<dyn SecretStore>,
+}
+
+impl SecretBackend {
+    /// Create a backend keeping the value as the secret `name` of `store`.
+    pub fn new(name: impl Into<String>, store: impl SecretStore) -> Self {
+        Self {
+            name: name.into(),
+            store: Box::new(store),
+        }
+    }
+}
+
+impl StorageBackend for SecretBackend {
+    fn load(&self) -> Result<Option<JsonValue>> {
+        self.store
+            .get(&self.name)?
+            .map(|secret| {
+                serde_json::from_slice(&secret)
+                    .map_err(|e| crate::RstateError::serialization(e.to_string()))
+            })
+            .transpose()
+    }
+
+    fn save(&self, value: &JsonValue) -> Result<()> {
+        let secret = serde_json::to_vec(value)
+            .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+        self.store.set(&self.name, &secret)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.store.delete(&self.name)
+    }
+}
+
+/// A backend routing parts of the state to different backends.
+///
+/// Each routed path is saved to and loaded from its own backend. Everything else
+/// goes to the [`rest`](Self::rest) backend, or isn't persisted if there is none.
+#[derive(Default)]
+pub struct RoutedBackend {
+    routes: Vec<(String, Box<dyn StorageBackend>)>,
+    rest: Option<Box<dyn StorageBackend>>,
+}
+
+impl RoutedBackend {
+    /// Create a backend without any routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Persist the subtree at `path` (e.g. `"settings"` or `"cache.images"`) in `backend`.
+    #[must_use]
+    pub fn route(mut self, path: impl Into<String>, backend: impl StorageBackend) -> Self {
+        self.routes.push((path.into(), Box::new(backend)));
+        self
+    }
+
+    /// Persist everything that isn't routed elsewhere in `backend`.
+    #[must_use]
+    pub fn rest(mut self, backend: impl StorageBackend) -> Self {
+        self.rest = Some(Box::new(backend));
+        self
+    }
+}
+
+impl StorageBackend for RoutedBackend {
+    fn load(&self) -> Result<Option<JsonValue>> {
+        let mut state = match &self.rest {
+            Some(rest) => rest.load()?,
+            None => None,
+        };
+
+        for (path, backend) in &self.routes {
+            if let Some(value) = backend.load()? {
+                set_path(
+                    state.get_or_insert_with(|| JsonValue::Object(Default::default())),
+                    path,
+                    value,
+                );
+            }
+        }
+
+        Ok(state)
+    }
+
+    fn save(&self, value: &JsonValue) -> Result<()> {
+        // Try every backend, so one failing doesn't hold back the others
+        let mut result = Ok(());
+        for (path, backend) in &self.routes {
+            if let Some(subtree) = get_state(value, path) {
+                result = result.and(backend.save(&subtree));
+            }
+        }
+
+        if let Some(rest) = &self.rest {
+            let mut remaining = value.clone();
+            for (path, _) in &self.routes {
+                remove_path(&mut remaining, path);
+            }
+            result = result.and(rest.save(&remaining));
+        }
+
+        result
+    }
+
+    fn clear(&self) -> Result<()> {
+        let mut result = Ok(());
+        for backend in self
+            .routes
+            .iter()
+            .map(|(_, backend)| backend)
+            .chain(&self.rest)
+        {
+            result = result.and(backend.clear());
+        }
+        result
+    }
+}
+
+// Saves the state of a manager to its backend, right away or debounced.
+// With a delay, the first change opens a window and the latest state is saved when
+// it ends, so bursts of dispatches result in a single write.
+pub(crate) struct Persister {
+    storage: Arc<dyn StorageBackend>,
+    delay: Option<Duration>,
+    pending: Arc<Mutex<Option<JsonValue>>>,
+    last: Arc<Mutex<Option<SaveStatus>>>,
+    // Reports every write, once the manager is registered and a tap is open
+    tap: Arc<Mutex<Option<SaveTap>>>,
+    // Version recorded in the saved state, if versioned
+    version: Option<u32>,
+    // Schema fingerprint recorded in the saved state, if any
+    fingerprint: Option<String>,
+}
+
+impl Persister {
+    pub(crate) fn new(storage: Box<dyn StorageBackend>, delay: Option<Duration>) -> Self {
+        Self {
+            storage: Arc::from(storage),
+            delay,
+            pending: Arc::default(),
+            last: Arc::default(),
+            tap: Arc::default(),
+            version: None,
+            fingerprint: None,
+        }
+    }
+
+    // Record `version` in every saved state
+    pub(crate) fn versioned(mut self, version: Option<u32>) -> Self {
+        self.version = version;
+        self
+    }
+
+    // Record `fingerprint` in every saved state
+    pub(crate) fn fingerprinted(mut self, fingerprint: Option<String>) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    // Record the version and schema fingerprint, if any
+    fn stamp(&self, state: &mut JsonValue) {
+        if let Some(version) = self.version {
+            migrations::stamp(state, version);
+        }
+        if let Some(fingerprint) = &self.fingerprint {
+            schema::stamp(state, fingerprint);
+        }
+    }
+
+    pub(crate) fn set_tap(&self, tap: SaveTap) {
+        if let Ok(mut current) = self.tap.lock() {
+            *current = Some(tap);
+        }
+    }
+
+    // Outcome of the last save
+    pub(crate) fn last(&self) -> Option<SaveStatus> {
+        self.last.lock().ok().and_then(|last| last.clone())
+    }
+
+    pub(crate) fn save(&self, mut state: JsonValue) {
+        self.stamp(&mut state);
+        let Some(delay) = self.delay else {
+            log_failure(self.write(&state));
+            return;
+        };
+
+        let Ok(mut pending) = self.pending.lock() else {
+            return;
+        };
+        if pending.replace(state).is_some() {
+            // A save is already scheduled and will pick up this state
+            return;
+        }
+        let writer = self.writer();
+        let pending = self.pending.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let state = pending.lock().ok().and_then(|mut pending| pending.take());
+            if let Some(state) = state {
+                log_failure(writer.write(&state));
+            }
+        });
+    }
+
+    // Save `state` right away, superseding any scheduled save
+    pub(crate) fn flush(&self, state: &JsonValue) -> Result<()> {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.take();
+        }
+        if self.version.is_some() || self.fingerprint.is_some() {
+            let mut state = state.clone();
+            self.stamp(&mut state);
+            self.write(&state)
+        } else {
+            self.write(state)
+        }
+    }
+
+    fn write(&self, state: &JsonValue) -> Result<()> {
+        self.writer().write(state)
+    }
+
+    // What a scheduled save needs to write the state
+    fn writer(&self) -> Writer {
+        Writer {
+            storage: self.storage.clone(),
+            last: self.last.clone(),
+            tap: self.tap.clone(),
+        }
+    }
+}
+
+// Writes states to a backend
+struct Writer {
+    storage: Arc<dyn StorageBackend>,
+    last: Arc<Mutex<Option<SaveStatus>>>,
+    tap: Arc<Mutex<Option<SaveTap>>>,
+}
+
+impl Writer {
+    // Write `state`, keeping the outcome for health checks and reporting it to the tap
+    fn write(&self, state: &JsonValue) -> Result<()> {
+        let started = Instant::now();
+        let result = self.storage.save(state);
+        let status = SaveStatus::of(&result);
+        if let Some(tap) = self.tap.lock().ok().and_then(|tap| tap.clone()) {
+            tap(&status, started.elapsed());
+        }
+        if let Ok(mut last) = self.last.lock() {
+            *last = Some(status);
+        }
+        result
+    }
+}
+
+// Failures of background saves are logged rather than failing the dispatch, which has
+// already been applied
+fn log_failure(result: Result<()>) {
+    if let Err(err) = result {
+        log::warn!("failed to persist state: {err}");
+    }
+}
+
+/// Overlay `persisted` onto `state`: objects are merged key by key, anything else
+/// is replaced. Keys missing from `persisted` keep their value from `state`, so fields
+/// added in a newer app version get their defaults.
+pub(crate) fn merge_persisted(state: &mut JsonValue, persisted: JsonValue) {
+    match (state, persisted) {
+        (JsonValue::Object(state), JsonValue::Object(persisted)) => {
+            for (key, value) in persisted {
+                match state.get_mut(&key) {
+                    Some(existing) => merge_persisted(existing, value),
+                    None => {
+                        state.insert(key, value);
+                    }
+                }
+            }
+        }
+        (state, persisted) => *state = persisted,
+    }
+}
+
+// Set the value at a dot-notation `path`, creating intermediate objects as needed
+pub(crate) fn set_path(state: &mut JsonValue, path: &str, value: JsonValue) {
+    let mut current = state;
+    for segment in path.split('.') {
+        if !current.is_object() {
+            *current = JsonValue::Object(Default::default());
+        }
+        current = current
+            .as_object_mut()
+            .expect("just made sure this is an object")
+            .entry(segment)
+            .or_insert(JsonValue::Null);
+    }
+    *current = value;
+}
+
+// Remove the value at a dot-notation `path`, if it exists
+pub(crate) fn remove_path(state: &mut JsonValue, path: &str) {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (
+            state.pointer_mut(&format!("/{}", parent.replace('.', "/"))),
+            key,
+        ),
+        None => (Some(state), path),
+    };
+    if let Some(JsonValue::Object(parent)) = parent {
+        parent.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_routed_backend_splits_and_composes() {
+        let settings = MemoryBackend::new();
+        let token = MemoryBackend::new();
+        let rest = MemoryBackend::new();
+        let backend = RoutedBackend::new()
+            .route("settings", settings.clone())
+            .route("secrets.token", token.clone())
+            .rest(rest.clone());
+
+        let state = json!({
+            "settings": { "theme": "dark" },
+            "secrets": { "token": "abc", "user": "me" },
+            "counter": 1
+        });
+        backend.save(&state).unwrap();
+
+        assert_eq!(settings.load().unwrap(), Some(json!({ "theme": "dark" })));
+        assert_eq!(token.load().unwrap(), Some(json!("abc")));
+        assert_eq!(
+            rest.load().unwrap(),
+            Some(json!({ "secrets": { "user": "me" }, "counter": 1 }))
+        );
+        assert_eq!(backend.load().unwrap(), Some(state));
+
+        backend.clear().unwrap();
+        assert_eq!(backend.load().unwrap(), None);
+    }
+
+    #[derive(Clone, Default)]
+    struct Vault(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+    impl SecretStore for Vault {
+        fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+            Ok(self.0.lock().unwrap().get(name).cloned())
+        }
+
+        fn set(&self, name: &str, secret: &[u8]) -> Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(name.to_owned(), secret.to_vec());
+            Ok(())
+        }
+
+        fn delete(&self, name: &str) -> Result<()> {
+            self.0.lock().unwrap().remove(name);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_secrets_are_kept_in_their_store() {
+        let vault = Vault::default();
+        let rest = MemoryBackend::new();
+        let backend = RoutedBackend::new()
+            .route("secrets", SecretBackend::new("state", vault.clone()))
+            .rest(rest.clone());
+
+        let state = json!({ "secrets": { "token": "abc" }, "counter": 1 });
+        backend.save(&state).unwrap();
+        assert_eq!(
+            vault.get("state").unwrap(),
+            Some(br#"{"token":"abc"}"#.to_vec())
+        );
+        assert_eq!(rest.load().unwrap(), Some(json!({ "counter": 1 })));
+        assert_eq!(backend.load().unwrap(), Some(state));
+
+        backend.clear().unwrap();
+        assert_eq!(vault.get("state").unwrap(), None);
+    }
+
+    #[test]
+    fn test_merge_persisted_keeps_new_fields() {
+        let mut state = json!({ "settings": { "theme": "light", "fontSize": 12 }, "counter": 0 });
+        merge_persisted(
+            &mut state,
+            json!({ "settings": { "theme": "dark" }, "counter": 5 }),
+        );
+
+        assert_eq!(
+            state,
+            json!({ "settings": { "theme": "dark", "fontSize": 12 }, "counter": 5 })
+        );
+    }
+
+    #[test]
+    fn test_chunked_backend_rewrites_changed_chunks_only() {
+        let dir = std::env::temp_dir().join(format!("rstate-chunked-{}", std::process::id()));
+        let backend = ChunkedFileBackend::new(&dir).threshold(16);
+        let modified = |file: &str| {
+            fs::metadata(dir.join("chunks").join(file))
+                .and_then(|metadata| metadata.modified())
+                .unwrap()
+        };
+
+        // Small states go to a single file
+        backend.save(&json!({ "a": 1 })).unwrap();
+        assert!(dir.join("state.json").exists());
+        assert_eq!(backend.load().unwrap(), Some(json!({ "a": 1 })));
+
+        let state = json!({ "todos": ["a", "b"], "a/b": { "x": 1 }, "gone": true });
+        backend.save(&state).unwrap();
+        assert!(!dir.join("state.json").exists());
+        assert!(dir.join("chunks").join("a%2Fb.json").exists());
+        let todos_modified = modified("todos.json");
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let state = json!({ "todos": ["a", "b"], "a/b": { "x": 2 } });
+        backend.save(&state).unwrap();
+        assert_eq!(modified("todos.json"), todos_modified);
+        assert!(!dir.join("chunks").join("gone.json").exists());
+
+        // A fresh backend composes the chunks
+        let reloaded = ChunkedFileBackend::new(&dir).threshold(16);
+        assert_eq!(reloaded.load().unwrap(), Some(state));
+
+        reloaded.clear().unwrap();
+        assert_eq!(reloaded.load().unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_backend_round_trip() {
+        let dir = std::env::temp_dir().join(format!("rstate-test-{}", std::process::id()));
+        let backend = FileBackend::new(dir.join("nested").join("state.json"));
+
+        assert_eq!(backend.load().unwrap(), None);
+        backend.save(&json!({ "counter": 3 })).unwrap();
+        assert_eq!(backend.load().unwrap(), Some(json!({ "counter": 3 })));
+        backend.clear().unwrap();
+        assert_eq!(backend.load().unwrap(), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//! Interop with `tauri-plugin-store`.
+//!
+//! Apps migrating from `tauri-plugin-store` keep their settings in a `.store`/`.json`
+//! file, a JSON object of keys to values. A [`PluginStoreBridge`] wraps the backend
+//! persisting the state and mirrors selected paths of the state into such a file: on
+//! load, the values found in the file are hydrated into the state, and every save
+//! writes them back under their key. Code still reading the file through
+//! `tauri-plugin-store` sees the values the state holds, so it can move to rstate
+//! incrementally:
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::{FileBackend, PluginStoreBridge, StateBuilder};
+//!
+//! let storage = PluginStoreBridge::new(
+//!     FileBackend::new(data_dir.join("state.json")),
+//!     data_dir.join("settings.json"),
+//! )
+//! .mirror("settings.theme", "theme")
+//! .mirror("settings.language", "lang");
+//!
+//! let manager = StateBuilder::new(AppState::default())
+//!     .persist_with(storage)
+//!     .build();
+//! ```
+//!
+//! Keys of the file that aren't mirrored are left untouched. `tauri-plugin-store`
+//! caches the file in memory, so the mirrored keys must not be written through it
+//! anymore, or its next save would overwrite the state's values.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::Result;
+use crate::models::{JsonValue, get_state};
+use crate::persistence::{FileBackend, StorageBackend, set_path};
+
+type Object = serde_json::Map<String, JsonValue>;
+
+/// A backend mirroring paths of the state into a `tauri-plugin-store` file, see the
+/// [`PluginStoreBridge::mirror`] method.
+pub struct PluginStoreBridge {
+    inner: Box<dyn StorageBackend>,
+    file: FileBackend,
+    // State path and store key of every mirrored value
+    mirrors: Vec<(String, String)>,
+    // The mirrored values last written, to skip saves not changing them
+    written: Mutex<Option<Object>>,
+}
+
+impl PluginStoreBridge {
+    /// Create a bridge persisting the state in `inner`, and mirroring it into the
+    /// store file at `path`.
+    pub fn new(inner: impl StorageBackend, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner: Box::new(inner),
+            file: FileBackend::new(path),
+            mirrors: Vec::new(),
+            written: Mutex::new(None),
+        }
+    }
+
+    /// Mirror the value at `path` of the state (in dot notation) into the store key
+    /// `key`. The value of the file wins when the state is loaded.
+    #[must_use]
+    pub fn mirror(mut self, path: impl Into<String>, key: impl Into<String>) -> Self {
+        self.mirrors.push((path.into(), key.into()));
+        self
+    }
+
+    // The content of the store file, empty if there is none
+    fn read_file(&self) -> Result<Object> {
+        match self.file.load()? {
+            Some(JsonValue::Object(object)) => Ok(object),
+            Some(_) => Err(crate::RstateError::serialization(format!(
+                "{} is not a store file",
+                self.file.path().display()
+            ))),
+            None => Ok(Object::new()),
+        }
+    }
+}
+
+impl StorageBackend for PluginStoreBridge {
+    fn load(&self) -> Result<Option<JsonValue>> {
+        let mut state = self.inner.load()?;
+        let file = self.read_file()?;
+        for (path, key) in &self.mirrors {
+            if let Some(value) = file.get(key) {
+                set_path(
+                    state.get_or_insert_with(|| JsonValue::Object(Object::new())),
+                    path,
+                    value.clone(),
+                );
+            }
+        }
+        Ok(state)
+    }
+
+    fn save(&self, value: &JsonValue) -> Result<()> {
+        self.inner.save(value)?;
+
+        let mirrored: Object = self
+            .mirrors
+            .iter()
+            .filter_map(|(path, key)| Some((key.clone(), get_state(value, path)?)))
+            .collect();
+        let mut written = self
+            .written
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+        if written.as_ref() == Some(&mirrored) {
+            return Ok(());
+        }
+        let mut file = self.read_file()?;
+        file.extend(mirrored.clone());
+        self.file.save(&JsonValue::Object(file))?;
+        *written = Some(mirrored);
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.inner.clear()?;
+        let mut file = self.read_file()?;
+        for (_, key) in &self.mirrors {
+            file.remove(key);
+        }
+        self.file.save(&JsonValue::Object(file))?;
+        if let Ok(mut written) = self.written.lock() {
+            *written = None;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryBackend;
+    use serde_json::json;
+
+    #[test]
+    fn test_mirrored_paths_are_kept_in_the_store_file() {
+        let path =
+            std::env::temp_dir().join(format!("rstate-plugin-store-{}.json", std::process::id()));
+        let file = FileBackend::new(&path);
+        file.save(&json!({ "theme": "dark", "window": { "width": 800 } }))
+            .unwrap();
+        let inner = MemoryBackend::with_value(json!({ "settings": { "theme": "light" } }));
+        let bridge = PluginStoreBridge::new(inner.clone(), &path)
+            .mirror("settings.theme", "theme")
+            .mirror("settings.language", "lang");
+
+        // Hydrated from the store file
+        assert_eq!(
+            bridge.load().unwrap(),
+            Some(json!({ "settings": { "theme": "dark" } }))
+        );
+
+        bridge
+            .save(&json!({ "settings": { "theme": "light", "language": "fr" }, "counter": 1 }))
+            .unwrap();
+        assert_eq!(
+            file.load().unwrap(),
+            Some(json!({ "theme": "light", "lang": "fr", "window": { "width": 800 } }))
+        );
+        assert_eq!(inner.load().unwrap().unwrap()["counter"], 1);
+
+        bridge.clear().unwrap();
+        assert_eq!(
+            file.load().unwrap(),
+            Some(json!({ "window": { "width": 800 } }))
+        );
+        assert_eq!(inner.load().unwrap(), None);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+//! Per-kind debouncing and throttling.
+//!
+//! Typing-driven actions hammer the store and the event bus: every keystroke runs the
+//! handler and emits an update. Kinds marked with
+//! [`Builder::debounce`](crate::Builder::debounce) or
+//! [`Builder::throttle`](crate::Builder::throttle) are coalesced before running:
+//!
+//! - a debounced kind only runs once no other action of that kind was dispatched
+//!   for the configured wait, with the latest action;
+//! - a throttled kind runs right away, then at most once per interval, with the
+//!   latest action dispatched during the interval.
+//!
+//! ```rust,ignore
+//! tauri_plugin_rstate::Builder::new()
+//!     .debounce("SET_SEARCH_QUERY", Duration::from_millis(200))
+//!     .throttle("SET_SCROLL_POSITION", Duration::from_millis(100))
+//!     .build()
+//! ```
+//!
+//! The coalesced dispatches all resolve to the outcome of the action that ran.
+//! Only the frontend's dispatches and [`Rstate::dispatch_batched`](crate::Rstate::dispatch_batched)
+//! are coalesced, and they skip the batch queue; [`Rstate::dispatch`](crate::Rstate::dispatch)
+//! always runs right away.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Runtime};
+use tokio::sync::oneshot;
+
+use crate::models::{Action, JsonValue};
+use crate::{Result, RstateError, RstateExt};
+
+// Reports the outcome of a coalesced dispatch
+pub(crate) type Waiter = oneshot::Sender<Result<JsonValue>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Limit {
+    Debounce(Duration),
+    Throttle(Duration),
+}
+
+// The latest action of a kind waiting to run, along with every dispatch it stands for
+pub(crate) struct Pending {
+    pub(crate) action: Action,
+    waiters: Vec<Waiter>,
+}
+
+impl Pending {
+    // Report the outcome of the action to every coalesced dispatch
+    pub(crate) fn settle(self, result: &Result<JsonValue>) {
+        for waiter in self.waiters {
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    pending: Option<Pending>,
+    // Bumped by every debounced dispatch, so only the last one's timer runs it
+    generation: u64,
+    // Whether a throttle interval is running
+    open: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct RateLimits {
+    limits: HashMap<String, Limit>,
+    slots: Mutex<HashMap<String, Slot>>,
+}
+
+impl RateLimits {
+    pub(crate) fn set(&mut self, kind: String, limit: Limit) {
+        self.limits.insert(kind, limit);
+    }
+
+    pub(crate) fn limit(&self, kind: &str) -> Option<Limit> {
+        self.limits.get(kind).copied()
+    }
+
+    // Make `action` the latest of its kind, to run later. Returns the generation the
+    // debounce timer started for it must find to run it.
+    pub(crate) fn defer(&self, action: Action, waiter: Waiter) -> u64 {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = slots.entry(action.kind.clone()).or_default();
+        let mut waiters = slot
+            .pending
+            .take()
+            .map(|pending| pending.waiters)
+            .unwrap_or_default();
+        waiters.push(waiter);
+        slot.pending = Some(Pending { action, waiters });
+        slot.generation += 1;
+        slot.generation
+    }
+
+    // The pending action of `kind`, if no other was deferred since `generation`
+    pub(crate) fn take_settled(&self, kind: &str, generation: u64) -> Option<Pending> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = slots
+            .get_mut(kind)
+            .filter(|slot| slot.generation == generation)?;
+        slot.pending.take()
+    }
+
+    // Start a throttle interval for `kind`. Returns `false` if one is running already.
+    pub(crate) fn open(&self, kind: &str) -> bool {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = slots.entry(kind.to_owned()).or_default();
+        !std::mem::replace(&mut slot.open, true)
+    }
+
+    // At the end of a throttle interval: the action of `kind` to run, starting another
+    // interval, or `None` if there is none, ending them
+    pub(crate) fn take_or_close(&self, kind: &str) -> Option<Pending> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = slots.get_mut(kind)?;
+        let pending = slot.pending.take();
+        slot.open = pending.is_some();
+        pending
+    }
+}
+
+// Dispatch `action`, of a kind limited by `limit`, once coalesced
+pub(crate) async fn dispatch_limited<R: Runtime>(
+    app: &AppHandle<R>,
+    limit: Limit,
+    action: Action,
+) -> Result<JsonValue> {
+    let rstate = app.rstate();
+    let limits = rstate.rate_limits();
+    let kind = action.kind.clone();
+    let app = app.clone();
+    let outcome = match limit {
+        // The first action of a throttle interval runs right away
+        Limit::Throttle(interval) if limits.open(&kind) => {
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let rstate = app.rstate();
+                    let Some(pending) = rstate.rate_limits().take_or_close(&kind) else {
+                        break;
+                    };
+                    run(&app, pending).await;
+                }
+            });
+            return rstate.dispatch_async(action).await;
+        }
+        Limit::Throttle(_) => {
+            let (waiter, outcome) = oneshot::channel();
+            limits.defer(action, waiter);
+            outcome
+        }
+        Limit::Debounce(wait) => {
+            let (waiter, outcome) = oneshot::channel();
+            let generation = limits.defer(action, waiter);
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(wait).await;
+                let pending = app.rstate().rate_limits().take_settled(&kind, generation);
+                if let Some(pending) = pending {
+                    run(&app, pending).await;
+                }
+            });
+            outcome
+        }
+    };
+    outcome
+        .await
+        .map_err(|_| RstateError::state("Coalesced dispatch was dropped"))?
+}
+
+// Run a coalesced action, reporting its outcome to every dispatch it stands for
+async fn run<R: Runtime>(app: &AppHandle<R>, pending: Pending) {
+    let result = app.rstate().dispatch_async(pending.action.clone()).await;
+    pending.settle(&result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(query: &str) -> Action {
+        Action::with_payload("SET_SEARCH_QUERY", query).unwrap()
+    }
+
+    #[test]
+    fn test_debounced_actions_coalesce_into_the_latest() {
+        let limits = RateLimits::default();
+        let (first, first_outcome) = oneshot::channel();
+        let (second, second_outcome) = oneshot::channel();
+        let stale = limits.defer(search("a"), first);
+        let latest = limits.defer(search("ab"), second);
+
+        // Only the timer of the latest dispatch runs the action
+        assert!(limits.take_settled("SET_SEARCH_QUERY", stale).is_none());
+        let pending = limits.take_settled("SET_SEARCH_QUERY", latest).unwrap();
+        assert_eq!(pending.action.payload, Some("ab".into()));
+        pending.settle(&Ok(JsonValue::from(2)));
+        assert_eq!(first_outcome.blocking_recv().unwrap().unwrap(), 2);
+        assert_eq!(second_outcome.blocking_recv().unwrap().unwrap(), 2);
+        assert!(limits.take_settled("SET_SEARCH_QUERY", latest).is_none());
+    }
+
+    #[test]
+    fn test_coalesced_dispatches_share_the_error() {
+        let limits = RateLimits::default();
+        let (first, first_outcome) = oneshot::channel();
+        let (second, second_outcome) = oneshot::channel();
+        limits.defer(search("a"), first);
+        let latest = limits.defer(search("ab"), second);
+
+        let pending = limits.take_settled("SET_SEARCH_QUERY", latest).unwrap();
+        pending.settle(&Err(RstateError::rejected("query too short")));
+        for outcome in [first_outcome, second_outcome] {
+            let err = outcome.blocking_recv().unwrap().unwrap_err();
+            assert!(matches!(err, RstateError::Rejected(msg) if msg == "query too short"));
+        }
+    }
+
+    #[test]
+    fn test_throttle_intervals_run_while_actions_arrive() {
+        let limits = RateLimits::default();
+        assert!(limits.open("SCROLL"));
+        assert!(!limits.open("SCROLL"));
+
+        let (waiter, _outcome) = oneshot::channel();
+        limits.defer(Action::new("SCROLL"), waiter);
+        assert!(limits.take_or_close("SCROLL").is_some());
+        // The interval of the trailing action is running
+        assert!(!limits.open("SCROLL"));
+        assert!(limits.take_or_close("SCROLL").is_none());
+        assert!(limits.open("SCROLL"));
+    }
+}
@@ -0,0 +1,118 @@
+//! Paths of the state hidden from the frontend.
+//!
+//! [`StateBuilder::redact`](crate::StateBuilder::redact) marks paths of a store's
+//! state, such as an auth token, whose value must not leave the backend. Their value
+//! is replaced by `"[redacted]"` in the updates, patches and subscriptions sent to the
+//! frontend, and in the state returned by commands, while the store keeps it intact:
+//! handlers, backend reads such as [`Rstate::get_state`](crate::Rstate::get_state) and
+//! [watchers](crate::Rstate::watch) see the actual value.
+
+use tauri_plugin_rstate_core::PatchOperation;
+
+use crate::models::JsonValue;
+
+/// Value replacing redacted parts of the state.
+pub(crate) const REDACTED: &str = "[redacted]";
+
+// Replace the value at every dot-notation path with `REDACTED`
+pub(crate) fn redact(state: &mut JsonValue, paths: &[String]) {
+    for path in paths {
+        redact_path(state, path);
+    }
+}
+
+fn redact_path(state: &mut JsonValue, path: &str) {
+    let pointer = format!("/{}", path.replace('.', "/"));
+    if let Some(value) = state.pointer_mut(&pointer) {
+        *value = JsonValue::String(REDACTED.to_owned());
+    }
+}
+
+// Redact `value`, read at the dot-notation `key` of a state redacting `paths`
+pub(crate) fn redact_at(value: &mut JsonValue, key: &str, paths: &[String]) {
+    if key.is_empty() {
+        return redact(value, paths);
+    }
+    for path in paths {
+        if path == key || key.starts_with(&format!("{path}.")) {
+            *value = JsonValue::String(REDACTED.to_owned());
+            return;
+        }
+        if let Some(rest) = path
+            .strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix('.'))
+        {
+            redact_path(value, rest);
+        }
+    }
+}
+
+// Redact the values set by the operations of a patch of a state redacting `paths`
+pub(crate) fn redact_patch(patch: &mut [PatchOperation], paths: &[String]) {
+    for operation in patch {
+        if let PatchOperation::Add { path, value } | PatchOperation::Replace { path, value } =
+            operation
+        {
+            let key = path.trim_start_matches('/').replace('/', ".");
+            redact_at(value, &key, paths);
+        }
+    }
+}
+
+// The redacted `paths` of the store mounted at `key`, relative to the state it's
+// mounted in
+pub(crate) fn prefixed<'a>(key: &'a str, paths: &'a [String]) -> impl Iterator<Item = String> + 'a {
+    paths.iter().map(move |path| format!("{key}.{path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_values_are_redacted_wherever_they_are_read() {
+        let paths = ["auth.token".to_owned()];
+        let mut state = json!({ "auth": { "token": "abc", "user": "me" }, "counter": 1 });
+        redact(&mut state, &paths);
+        assert_eq!(
+            state,
+            json!({ "auth": { "token": REDACTED, "user": "me" }, "counter": 1 })
+        );
+
+        let mut auth = json!({ "token": "abc", "user": "me" });
+        redact_at(&mut auth, "auth", &paths);
+        assert_eq!(auth, json!({ "token": REDACTED, "user": "me" }));
+        let mut token = json!("abc");
+        redact_at(&mut token, "auth.token", &paths);
+        assert_eq!(token, REDACTED);
+        let mut counter = json!(1);
+        redact_at(&mut counter, "counter", &paths);
+        assert_eq!(counter, 1);
+
+        let mut patch = vec![
+            PatchOperation::Replace {
+                path: "/auth".into(),
+                value: json!({ "token": "def", "user": "you" }),
+            },
+            PatchOperation::Add {
+                path: "/auth/token".into(),
+                value: json!("ghi"),
+            },
+        ];
+        redact_patch(&mut patch, &paths);
+        assert_eq!(
+            patch,
+            [
+                PatchOperation::Replace {
+                    path: "/auth".into(),
+                    value: json!({ "token": REDACTED, "user": "you" }),
+                },
+                PatchOperation::Add {
+                    path: "/auth/token".into(),
+                    value: json!(REDACTED),
+                },
+            ]
+        );
+    }
+}
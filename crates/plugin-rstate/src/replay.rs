@@ -0,0 +1,143 @@
+//! Replaying recorded actions.
+//!
+//! [`Rstate::replay`](crate::Rstate::replay) feeds a recorded action log through the
+//! current reducers, to reproduce a bug report from the log a user submitted. The log
+//! is read as JSON lines, each one either:
+//!
+//! - a serialized [`Action`]
+//! - an entry of an [`EventLog`](crate::EventLog)
+//! - a record of an [`AuditLog`](crate::AuditLog) written
+//!   [`with_payloads`](crate::AuditLog::with_payloads); records holding a payload
+//!   hash can't be replayed
+//!
+//! ```rust,ignore
+//! let log = std::fs::File::open("actions.jsonl")?;
+//! let report = app.rstate().replay(std::io::BufReader::new(log), true)?;
+//! for failure in &report.failures {
+//!     eprintln!("line {}: {}", failure.line, failure.error);
+//! }
+//! ```
+//!
+//! Replayed actions are tagged with [`ActionSource::Replay`], so guards can tell
+//! them apart. They are dispatched to the app-wide store, or to the slice their kind
+//! is prefixed with.
+
+use std::io::BufRead;
+
+use serde::Serialize;
+
+use crate::models::{Action, ActionSource, JsonValue};
+use crate::{Result, RstateError};
+
+/// Outcome of [`Rstate::replay`](crate::Rstate::replay).
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ReplayReport {
+    /// Number of actions dispatched successfully
+    pub replayed: usize,
+    /// Actions that couldn't be read or whose dispatch failed, in log order
+    pub failures: Vec<ReplayFailure>,
+}
+
+/// A line of the log that failed to replay, see [`ReplayReport`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ReplayFailure {
+    /// Line of the log, starting at 1
+    pub line: usize,
+    /// Kind of the action, if it could be read
+    pub kind: Option<String>,
+    /// Why it failed
+    pub error: String,
+}
+
+// Dispatch every action read from `reader` with `dispatch`. Fails only if the log
+// can't be read.
+pub(crate) fn run(
+    reader: impl BufRead,
+    mut dispatch: impl FnMut(Action) -> Result<JsonValue>,
+) -> Result<ReplayReport> {
+    let mut report = ReplayReport::default();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| RstateError::state(e.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let failure = |kind: Option<String>, error: &RstateError| ReplayFailure {
+            line: index + 1,
+            kind,
+            error: error.to_string(),
+        };
+        let action = match parse(&line) {
+            Ok(action) => action.with_source(ActionSource::Replay),
+            Err(err) => {
+                report.failures.push(failure(None, &err));
+                continue;
+            }
+        };
+        let kind = action.kind.clone();
+        match dispatch(action) {
+            Ok(_) => report.replayed += 1,
+            Err(err) => report.failures.push(failure(Some(kind), &err)),
+        }
+    }
+    Ok(report)
+}
+
+// Read the action of a line of the log
+fn parse(line: &str) -> Result<Action> {
+    let mut value: JsonValue =
+        serde_json::from_str(line).map_err(|e| RstateError::serialization(e.to_string()))?;
+    // An entry of an event log
+    if let Some(action) = value.get_mut("action") {
+        value = action.take();
+    }
+    if value.get("payloadHash").is_some() && value.get("payload").is_none() {
+        return Err(RstateError::invalid_payload(
+            "the action was recorded without its payload",
+        ));
+    }
+    serde_json::from_value(value).map_err(|e| RstateError::serialization(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_recorded_actions_are_replayed() {
+        let log = [
+            r#"{"kind":"ADD","payload":1}"#,
+            "",
+            r#"{"seq":7,"action":{"kind":"ADD","payload":2}}"#,
+            r#"{"timestamp":1760000000000,"kind":"ADD","payload":3,"window":"main","changed":true}"#,
+            r#"{"timestamp":1760000000000,"kind":"ADD","payloadHash":"5e1c0b7a"}"#,
+            r#"{"kind":"ADD","payload":"#,
+            r#"{"kind":"FAIL"}"#,
+        ]
+        .join("\n");
+
+        let mut total = 0;
+        let report = run(log.as_bytes(), |action| {
+            assert_eq!(action.source(), ActionSource::Replay);
+            if action.is("FAIL") {
+                return Err(RstateError::rejected("nope"));
+            }
+            total += action.require_payload::<i64>()?;
+            Ok(json!(total))
+        })
+        .unwrap();
+
+        assert_eq!(total, 6);
+        assert_eq!(report.replayed, 3);
+        let failed: Vec<_> = report
+            .failures
+            .iter()
+            .map(|failure| (failure.line, failure.kind.as_deref()))
+            .collect();
+        assert_eq!(failed, [(5, None), (6, None), (7, Some("FAIL"))]);
+    }
+}
@@ -0,0 +1,122 @@
+//! Retention policies for collections.
+//!
+//! Append-heavy collections, like logs or recent-files lists, grow without bound
+//! unless every handler trims them. Declare their bound once with
+//! [`StateBuilder::retain`](crate::StateBuilder::retain) instead, on arrays or maps
+//! of the state by dot-notation path:
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::Retention::{Newest, Oldest};
+//!
+//! let manager = StateBuilder::new(AppState::default())
+//!     .retain("logs", Newest(500))
+//!     .retain("settings.recentFiles", Newest(10))
+//!     .retain("onboarding.steps", Oldest(5))
+//!     .build();
+//! ```
+//!
+//! The collections are trimmed after every dispatch that left them over their bound,
+//! before the update is emitted and saved. Arrays keep their order; map entries are
+//! ordered by key, so maps keyed by timestamp or sequential id keep their newest or
+//! oldest entries.
+
+use std::collections::BTreeMap;
+
+use crate::models::JsonValue;
+
+/// How many items of a collection to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retention {
+    /// Keep the last `n` items (the most recently appended)
+    Newest(usize),
+    /// Keep the first `n` items
+    Oldest(usize),
+}
+
+impl Retention {
+    // Number of leading items to drop from a collection of `len` items, and how many
+    // to keep after them
+    fn bounds(self, len: usize) -> (usize, usize) {
+        match self {
+            Retention::Newest(n) => (len.saturating_sub(n), n),
+            Retention::Oldest(n) => (0, n),
+        }
+    }
+}
+
+// The retention policies of a store's collections, by path
+#[derive(Default)]
+pub(crate) struct Retentions(BTreeMap<String, Retention>);
+
+impl Retentions {
+    pub(crate) fn declare(&mut self, path: String, retention: Retention) {
+        self.0.insert(path, retention);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Trim the collections of `state` over their bound.
+    // Returns `true` if any was trimmed.
+    pub(crate) fn apply(&self, state: &mut JsonValue) -> bool {
+        let mut trimmed = false;
+        for (path, retention) in &self.0 {
+            let pointer = format!("/{}", path.replace('.', "/"));
+            match state.pointer_mut(&pointer) {
+                Some(JsonValue::Array(items)) => {
+                    let (skip, keep) = retention.bounds(items.len());
+                    if items.len() > keep {
+                        items.drain(..skip);
+                        items.truncate(keep);
+                        trimmed = true;
+                    }
+                }
+                Some(JsonValue::Object(entries)) => {
+                    let (skip, keep) = retention.bounds(entries.len());
+                    if entries.len() > keep {
+                        *entries = std::mem::take(entries)
+                            .into_iter()
+                            .skip(skip)
+                            .take(keep)
+                            .collect();
+                        trimmed = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        trimmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_retention_trims_collections() {
+        let mut retentions = Retentions::default();
+        retentions.declare("logs".into(), Retention::Newest(2));
+        retentions.declare("files.recent".into(), Retention::Oldest(1));
+        retentions.declare("missing".into(), Retention::Newest(1));
+        retentions.declare("counter".into(), Retention::Newest(0));
+
+        let mut state = json!({
+            "logs": [1, 2, 3],
+            "files": { "recent": { "b": 2, "a": 1 } },
+            "counter": 3
+        });
+        assert!(retentions.apply(&mut state));
+        assert_eq!(
+            state,
+            json!({ "logs": [2, 3], "files": { "recent": { "a": 1 } }, "counter": 3 })
+        );
+        assert!(!retentions.apply(&mut state));
+
+        retentions.declare("logs".into(), Retention::Newest(5));
+        state["logs"] = json!([1, 2, 3]);
+        assert!(!retentions.apply(&mut state));
+    }
+}
@@ -0,0 +1,2183 @@
+//! The plugin's state, shared by every platform.
+//!
+//! [`Rstate`] implements the plugin's APIs the same way on desktop and mobile. The
+//! `desktop` and `mobile` modules only hold what differs: the platform's part of the
+//! state, the APIs only available there, and how the plugin is set up.
+
+use serde::{Serialize, de::DeserializeOwned};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Runtime, ipc::Channel};
+use tauri_plugin_rstate_core::diff;
+use tokio::sync::watch;
+
+use crate::RstateExt;
+use crate::actor::Actor;
+use crate::audit::AuditWriter;
+use crate::batching::Batcher;
+use crate::breaker::{CircuitBreaker, CircuitOpen};
+use crate::change::{StateUpdate, replace_key, states_are_equal, typed_update};
+use crate::computed::Computed;
+use crate::concurrency::ConcurrencyGroups;
+use crate::diagnostics::{Recorder, zip};
+use crate::event_log::HistoryPage;
+use crate::health::{Health, Vitals};
+use crate::history::{self, Changes, History};
+use crate::listeners::Listeners;
+use crate::logging::{ACTION_LOG_TARGET, ActionLog};
+use crate::models::{
+    Action, ActionGuard, ActionKinds, ActionSource, DispatchOutcome, Dispatcher, DryRun, JsonValue,
+    RstateManager, StoreScope, check_guards, check_payload_size,
+};
+use crate::persistence::set_path;
+use crate::rate_limit::{RateLimits, dispatch_limited};
+use crate::redaction::{REDACTED, redact, redact_at, redact_patch};
+use crate::replay::{self, ReplayReport};
+use crate::schedule::{ScheduleHandle, schedule, schedule_every};
+use crate::schema::{STORE_READY_EVENT, SchemaFingerprint, StoreReady};
+use crate::selectors::SelectorStats;
+use crate::slices::{SliceStores, slice_dispatcher};
+use crate::store::{self, AppStore, Merged, Publisher, STATE_UPDATE_EVENT, read_state, simulate};
+use crate::sync::{
+    ConflictResolver, LocalChange, LocalChangeHook, REMOTE_CHANGE_ACTION, RemoteChange, resolve,
+};
+use crate::tap::{self, EmitDecision, TapEvent, Taps};
+use crate::timings::{ActionTiming, Timings};
+use crate::tokens::{ReadScope, ReadTokens};
+use crate::transaction::{HeldEvents, Store, Transaction};
+use crate::typed::TypedRstate;
+use crate::watchers::WatchHandle;
+use crate::window_stores::{WindowStores, window_event_name};
+use crate::{ManagedState, PluginOptions, ReadyHook, StateLoader};
+
+#[cfg(desktop)]
+use crate::desktop::Platform;
+#[cfg(mobile)]
+use crate::mobile::Platform;
+
+// Marks a `batched` scope for its lifetime, so panics don't leave emits suppressed
+struct BatchedScope<'a>(&'a AtomicUsize);
+
+impl<'a> BatchedScope<'a> {
+    fn enter(scopes: &'a AtomicUsize) -> Self {
+        scopes.fetch_add(1, Ordering::SeqCst);
+        Self(scopes)
+    }
+}
+
+impl Drop for BatchedScope<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Build the plugin's state from the builder's options, with the platform's own part
+pub(crate) fn init<R: Runtime>(
+    app: &AppHandle<R>,
+    mut options: PluginOptions<R>,
+    platform: Platform<R>,
+) -> crate::Result<Rstate<R>> {
+    let listeners = Arc::new(Listeners::new(options.listener_timeout));
+    let publisher = Arc::new(Publisher::new(app, &mut options, &listeners));
+
+    // The actor dispatches through the plugin's state, which is managed by the time
+    // anything is queued
+    let actor = options
+        .actor_mode
+        .then(|| {
+            let app = app.clone();
+            Actor::spawn(move |action| app.rstate().dispatch(action))
+        })
+        .transpose()?;
+
+    Ok(Rstate {
+        app: app.clone(),
+        registration_timeout: options.registration_timeout,
+        ready: watch::Sender::new(false),
+        loading: AtomicBool::new(false),
+        on_ready: Mutex::new(options.on_ready),
+        publisher,
+        action_log: options.log_actions.map(ActionLog::new),
+        audit: options.audit_log.map(AuditWriter::new),
+        platform,
+        window_stores: WindowStores::default(),
+        slices: SliceStores::default(),
+        app_store: AppStore::default(),
+        revision: AtomicU64::new(0),
+        envelope_responses: options.envelope_responses,
+        history: options.change_history.map(History::new),
+        max_payload_size: options.max_payload_size,
+        batched_scopes: AtomicUsize::new(0),
+        held_events: HeldEvents::default(),
+        taps: Taps::default(),
+        guards: options.guards,
+        concurrency_groups: options.concurrency_groups,
+        rate_limits: options.rate_limits,
+        breaker: options.circuit_breaker,
+        read_tokens: ReadTokens::default(),
+        timings: options.time_actions.then(Timings::default),
+        batcher: options
+            .batch_window
+            .map(|window| Batcher::new(window).persisted(options.batch_queue)),
+        actor,
+        local_change_hooks: options.local_change_hooks,
+        on_conflict: options.on_conflict,
+        recorder: Recorder::default(),
+        vitals: Vitals::default(),
+        redact: options.redact,
+        listeners,
+    })
+}
+
+/// Access to the rstate APIs.
+///
+/// This struct provides synchronous methods for state management.
+/// Access it via the [`RstateExt`](crate::RstateExt) trait.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use tauri_plugin_rstate::RstateExt;
+///
+/// #[tauri::command]
+/// fn increment(app: tauri::AppHandle) -> Result<(), String> {
+///     app.rstate()
+///         .dispatch_kind("INCREMENT")
+///         .map_err(|e| e.to_string())?;
+///     Ok(())
+/// }
+/// ```
+pub struct Rstate<R: Runtime> {
+    app: AppHandle<R>,
+    registration_timeout: Option<Duration>,
+    ready: watch::Sender<bool>,
+    loading: AtomicBool,
+    on_ready: Mutex<Option<ReadyHook<R>>>,
+    publisher: Arc<Publisher>,
+    action_log: Option<ActionLog>,
+    audit: Option<AuditWriter>,
+    pub(crate) platform: Platform<R>,
+    window_stores: WindowStores,
+    slices: SliceStores,
+    app_store: AppStore,
+    revision: AtomicU64,
+    envelope_responses: bool,
+    batched_scopes: AtomicUsize,
+    held_events: HeldEvents,
+    taps: Taps,
+    history: Option<History>,
+    max_payload_size: Option<usize>,
+    guards: Vec<ActionGuard>,
+    concurrency_groups: ConcurrencyGroups,
+    rate_limits: RateLimits,
+    breaker: Option<CircuitBreaker>,
+    read_tokens: ReadTokens,
+    timings: Option<Timings>,
+    batcher: Option<Batcher>,
+    actor: Option<Actor>,
+    local_change_hooks: Vec<LocalChangeHook>,
+    on_conflict: Option<ConflictResolver>,
+    recorder: Recorder,
+    vitals: Vitals,
+    redact: Vec<String>,
+    listeners: Arc<Listeners>,
+}
+
+impl<R: Runtime> Rstate<R> {
+    /// Get the event name used for state updates.
+    #[inline]
+    pub fn get_event_name(&self) -> &'static str {
+        STATE_UPDATE_EVENT
+    }
+
+    /// Get the current revision of the app-wide store.
+    ///
+    /// The revision starts at 0 and is incremented every time a dispatch changes the state.
+    #[inline]
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+
+    /// Derive a value from the app-wide state, recomputed only when the state at one
+    /// of the `deps` paths (supports dot notation) changes.
+    ///
+    /// `compute` receives the full state. The returned [`Computed`] handle caches the
+    /// value; call [`Computed::get`] whenever the value is needed.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let tooltip = app.rstate().computed(["inbox.unread"], |state| {
+    ///     format!("{} unread", state["inbox"]["unread"])
+    /// });
+    /// tray.set_tooltip(Some(tooltip.get()?))?;
+    /// ```
+    pub fn computed<V, F>(
+        &self,
+        deps: impl IntoIterator<Item = impl Into<String>>,
+        compute: F,
+    ) -> Computed<R, V>
+    where
+        V: Clone,
+        F: Fn(&JsonValue) -> V + Send + Sync + 'static,
+    {
+        Computed::new(
+            self.app.clone(),
+            deps.into_iter().map(Into::into).collect(),
+            Box::new(compute),
+        )
+    }
+
+    /// Write a diagnostics bundle for support to `path`, as a zip archive.
+    ///
+    /// The bundle contains the app-wide state with the paths set through
+    /// [`Builder::redact`](crate::Builder::redact) and
+    /// [`StateBuilder::redact`](crate::StateBuilder::redact) redacted, the last 100
+    /// dispatched actions, dispatch stats, and the version of the state set through
+    /// [`StateBuilder::version`](crate::StateBuilder::version).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// #[tauri::command]
+    /// fn send_report(app: tauri::AppHandle, path: PathBuf) -> Result<(), String> {
+    ///     app.rstate().export_diagnostics(&path).map_err(|e| e.to_string())
+    /// }
+    /// ```
+    pub fn export_diagnostics(&self, path: impl AsRef<Path>) -> crate::Result<()> {
+        let mut state = self.get_initial_state()?;
+        redact(&mut state, &self.redact);
+        redact(&mut state, &self.redacted_paths(StoreScope::App, "")?);
+        let mut stats = self.recorder.stats();
+        stats.revision = self.revision();
+        stats.listeners = self.listening_windows();
+        stats.timings = self.action_timings();
+        let version = serde_json::json!({
+            "schemaVersion": store::version(&*self.state_manager()?)?,
+            "pluginVersion": env!("CARGO_PKG_VERSION"),
+        });
+
+        let to_json = |value: JsonValue| {
+            serde_json::to_vec_pretty(&value)
+                .map_err(|e| crate::RstateError::serialization(e.to_string()))
+        };
+        let entries = [
+            ("state.json", to_json(state)?),
+            (
+                "actions.json",
+                to_json(serde_json::json!(self.recorder.recent()))?,
+            ),
+            ("stats.json", to_json(serde_json::json!(stats))?),
+            ("version.json", to_json(version)?),
+        ];
+        std::fs::write(path, zip(&entries)?)?;
+        Ok(())
+    }
+
+    /// Labels of the windows listening for state updates, sorted.
+    ///
+    /// Windows report their listener with the `heartbeat` command: with
+    /// `listening: true` when they start listening and then periodically, and with
+    /// `listening: false` when they stop. Windows that haven't sent a heartbeat within
+    /// the [`listener_timeout`](crate::Builder::listener_timeout) are left out.
+    ///
+    /// # Example
+    ///
+    /// ```js
+    /// await listen('rstate://state-update', onUpdate)
+    /// await invoke('plugin:rstate|heartbeat', { listening: true })
+    /// setInterval(() => invoke('plugin:rstate|heartbeat', { listening: true }), 10_000)
+    /// ```
+    pub fn listening_windows(&self) -> Vec<String> {
+        self.listeners.active()
+    }
+
+    // Record a heartbeat of the window `label`
+    pub(crate) fn heartbeat(&self, label: &str, listening: bool) {
+        self.listeners.heartbeat(label, listening);
+    }
+
+    // Stream the updates of the `scope` store of the window `label` to `channel`,
+    // starting with the current state
+    pub(crate) fn subscribe(
+        &self,
+        label: &str,
+        scope: StoreScope,
+        key: Option<String>,
+        channel: Channel<JsonValue>,
+    ) -> crate::Result<u64> {
+        let subscriptions = self.publisher.subscriptions();
+        // Subscribe under the store's lock, so no update is missed in between
+        match scope {
+            StoreScope::App => {
+                if let Some((slice, rest)) = key.as_deref().and_then(|key| self.slices.resolve(key))
+                {
+                    let key = Some(rest.to_owned()).filter(|rest| !rest.is_empty());
+                    let state = store::read_redacted(&slice.state)?;
+                    return subscriptions.add(&slice.event(), label, key, channel, &state);
+                }
+                let state_manager = self.state_manager()?;
+                let state = store::read_redacted(&state_manager)?;
+                subscriptions.add(STATE_UPDATE_EVENT, label, key, channel, &state)
+            }
+            StoreScope::Window => {
+                let window_store = self.window_stores.get(label)?;
+                let state = store::read_redacted(&window_store.state)?;
+                let event = window_event_name(label);
+                subscriptions.add(&event, label, key, channel, &state)
+            }
+        }
+    }
+
+    // End a subscription. Returns `false` if there was none with this id.
+    pub(crate) fn unsubscribe(&self, id: u64) -> bool {
+        self.publisher.subscriptions().remove(id)
+    }
+
+    // End the subscriptions of the window `label`
+    pub(crate) fn unsubscribe_window(&self, label: &str) {
+        self.publisher.subscriptions().remove_window(label);
+    }
+
+    /// Call `f` with the old and new value at `path` (dot notation, empty for the
+    /// whole state) of the app-wide state whenever it changes, e.g. to update a tray
+    /// or a menu. A missing value is seen as `null`; a path into a slice watches the
+    /// slice's store.
+    ///
+    /// `f` is called once the update is published, outside the store's lock, so it
+    /// may read the state or dispatch actions. Changes held by the emit policy are
+    /// seen when their update is sent.
+    ///
+    /// ```rust,ignore
+    /// app.rstate().watch("user.settings.theme", |_old, new| {
+    ///     log::info!("theme is now {new}");
+    /// })?;
+    /// ```
+    pub fn watch<F>(&self, path: &str, f: F) -> crate::Result<WatchHandle>
+    where
+        F: Fn(&JsonValue, &JsonValue) + Send + Sync + 'static,
+    {
+        let watchers = self.publisher.watchers();
+        // Watch under the store's lock, so no change is missed in between
+        if let Some((slice, rest)) = self.slices.resolve(path) {
+            let store = store::read(&slice.state)?;
+            return watchers.add(
+                &slice.event(),
+                rest,
+                &store.get_initial_state(),
+                Arc::new(f),
+            );
+        }
+        let state_manager = self.state_manager()?;
+        let store = store::read(&state_manager)?;
+        watchers.add(
+            STATE_UPDATE_EVENT,
+            path,
+            &store.get_initial_state(),
+            Arc::new(f),
+        )
+    }
+
+    /// Open a tap receiving a [`TapEvent`] for every dispatch, commit (with the decision
+    /// to emit its update or not) and write of a persisted state, from now on.
+    ///
+    /// Meant for end-to-end tests asserting on the plugin's observable behavior, e.g.
+    /// that a no-op action emitted nothing. The tap closes when the receiver is
+    /// dropped; nothing is recorded while none is open. Persisted writes are reported
+    /// for managers supporting [`set_save_tap`](RstateManager::set_save_tap), such as
+    /// [`StateBuilder`](crate::StateBuilder)'s.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let tap = app.rstate().tap();
+    /// app.rstate().dispatch(Action::new("NOOP"))?;
+    /// assert!(tap.try_iter().any(|event| matches!(
+    ///     event,
+    ///     TapEvent::Commit { emit: EmitDecision::Skipped, .. }
+    /// )));
+    /// ```
+    pub fn tap(&self) -> Receiver<TapEvent> {
+        self.taps.open()
+    }
+
+    /// Report the health of the app-wide store.
+    ///
+    /// See [`Health`](crate::Health). Never blocks: a held lock is reported as busy.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let health = app.rstate().health_check();
+    /// if health.lock == LockStatus::Poisoned {
+    ///     // ...
+    /// }
+    /// ```
+    pub fn health_check(&self) -> Health {
+        let store = self.app_store.get().ok();
+        let queue_depth = self.batcher.as_ref().map_or(0, Batcher::len)
+            + self.actor.as_ref().map_or(0, Actor::len);
+        self.vitals.check(store.as_deref(), queue_depth)
+    }
+
+    /// Report the handler timings of every action kind dispatched so far, slowest first.
+    ///
+    /// Empty unless enabled with [`Builder::time_actions`](crate::Builder::time_actions).
+    /// See [`ActionTiming`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// for timing in app.rstate().action_timings().iter().take(5) {
+    ///     println!("{}: p99 {}µs over {} actions", timing.kind, timing.p99_us, timing.count);
+    /// }
+    /// ```
+    pub fn action_timings(&self) -> Vec<ActionTiming> {
+        self.timings
+            .as_ref()
+            .map(Timings::report)
+            .unwrap_or_default()
+    }
+
+    /// Get the concurrency group of an action kind, if it was assigned to one with
+    /// [`Builder::concurrency_group`](crate::Builder::concurrency_group).
+    pub fn concurrency_group(&self, kind: &str) -> Option<&str> {
+        self.concurrency_groups.group(kind)
+    }
+
+    /// Check if a state manager is registered.
+    ///
+    /// Returns `true` if a state manager has been registered, `false` otherwise.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.app_store.is_registered()
+    }
+
+    /// Check if a state manager is being loaded in the background, see
+    /// [`load_state_manager`](Self::load_state_manager).
+    #[inline]
+    pub fn is_loading(&self) -> bool {
+        self.loading.load(Ordering::SeqCst)
+    }
+
+    /// Check if the app-wide store is ready.
+    ///
+    /// The store becomes ready once a state manager is registered and the
+    /// [`on_ready`](crate::Builder::on_ready) hook, if any, has completed.
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Wait until the app-wide store is ready.
+    ///
+    /// Returns immediately if the store is already ready. Otherwise waits up to
+    /// the timeout configured with [`Builder::registration_timeout`](crate::Builder::registration_timeout),
+    /// failing with [`RstateError::NotRegistered`](crate::RstateError::NotRegistered)
+    /// if it isn't ready in time, or [`RstateError::Loading`](crate::RstateError::Loading)
+    /// if its manager is still [loading](Self::load_state_manager). Without a configured timeout, this only waits for
+    /// the `on_ready` hook of an already registered manager.
+    pub async fn wait_for_registration(&self) -> crate::Result<()> {
+        // Subscribe before checking, so a registration in between isn't missed
+        let mut ready = self.ready.subscribe();
+        if self.is_ready() {
+            return Ok(());
+        }
+        let ready = ready.wait_for(|ready| *ready);
+
+        match self.registration_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, ready).await {
+                Ok(Ok(_)) => Ok(()),
+                _ => Err(self.not_ready()),
+            },
+            // Registered, but the `on_ready` hook is still running
+            None if self.is_registered() => ready
+                .await
+                .map(|_| ())
+                .map_err(|_| crate::RstateError::NotRegistered),
+            None => Err(self.not_ready()),
+        }
+    }
+
+    // The error for a store that isn't ready in time
+    fn not_ready(&self) -> crate::RstateError {
+        if self.is_loading() {
+            crate::RstateError::Loading
+        } else {
+            crate::RstateError::NotRegistered
+        }
+    }
+
+    // Replay the batch queue left over by the last run, then run the `on_ready` hook,
+    // if any, and mark the store ready
+    pub(crate) fn finish_registration(&self) {
+        if self
+            .batcher
+            .as_ref()
+            .is_some_and(|batcher| batcher.restore() > 0)
+        {
+            self.apply_batch();
+        }
+
+        let hook = self.on_ready.lock().ok().and_then(|mut hook| hook.take());
+        let Some(hook) = hook else {
+            self.mark_ready();
+            return;
+        };
+
+        let app = self.app.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(err) = hook(app.clone()).await {
+                log::warn!(target: ACTION_LOG_TARGET, "on_ready hook failed: {err}");
+            }
+            app.rstate().mark_ready();
+        });
+    }
+
+    // Wake up anyone waiting in `wait_for_registration`, and tell the frontend
+    fn mark_ready(&self) {
+        self.ready.send_replace(true);
+        let ready = StoreReady {
+            schema: self.schema().ok().flatten(),
+        };
+        if let Err(err) = self.app.emit(STORE_READY_EVENT, &ready) {
+            log::warn!(target: ACTION_LOG_TARGET, "ready event: {err}");
+        }
+    }
+
+    /// Get the schema fingerprint of the app-wide store's state, if its manager has one.
+    ///
+    /// See [`StateBuilder::schema_fingerprint`](crate::StateBuilder::schema_fingerprint).
+    pub fn schema(&self) -> crate::Result<Option<SchemaFingerprint>> {
+        store::schema(&*self.state_manager()?)
+    }
+
+    // The app-wide store's state manager
+    #[inline]
+    fn state_manager(&self) -> crate::Result<Arc<ManagedState>> {
+        self.app_store.get()
+    }
+
+    /// Flush the app-wide store, e.g. to save its persisted state right away.
+    ///
+    /// This happens automatically when the app exits.
+    pub fn flush(&self) -> crate::Result<()> {
+        self.state_manager()?
+            .write()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?
+            .flush()?;
+        self.slices.flush()
+    }
+
+    /// Get the initial state from the state manager.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let state = app.rstate().get_initial_state()?;
+    /// ```
+    pub fn get_initial_state(&self) -> crate::Result<JsonValue> {
+        let mut state = read_state(&*self.state_manager()?)?;
+        self.slices.insert_into(&mut state)?;
+        Ok(state)
+    }
+
+    /// Get the full state of the app-wide store along with its revision.
+    ///
+    /// Both are read under the store's lock, so the revision is exactly the state's: a
+    /// frontend resyncing after a missed update can carry on from it.
+    pub fn get_state_with_revision(&self) -> crate::Result<StateUpdate> {
+        self.read_revision(None)
+    }
+
+    /// Get the full state of the app-wide store along with its revision, unless the store
+    /// is still at revision `known`.
+    ///
+    /// The state is then the [`UNCHANGED_KEY`](crate::UNCHANGED_KEY) sentinel
+    /// `{"$unchanged": known}`, so a window resuming with the state of that revision
+    /// doesn't transfer it again. With slices, the full state is always returned, as
+    /// they have their own revisions.
+    pub fn get_state_if_modified(&self, known: u64) -> crate::Result<StateUpdate> {
+        self.read_revision(Some(known))
+    }
+
+    /// Get the changes of the app-wide store since revision `since`: a JSON Patch from
+    /// the state of that revision to the current one when it is still known (see
+    /// [`Builder::change_history`](crate::Builder::change_history)), else the full state.
+    pub fn get_changes_since(&self, since: u64) -> crate::Result<Changes> {
+        let current = store::read_revision(&*self.state_manager()?, &self.revision, None)?;
+        let mut changes = history::changes_since(self.history.as_ref(), since, current);
+        if let Some(state) = &mut changes.state {
+            self.slices.insert_into(state)?;
+        }
+        Ok(changes)
+    }
+
+    fn read_revision(&self, known: Option<u64>) -> crate::Result<StateUpdate> {
+        let known = known.filter(|_| self.slices.is_empty());
+        let mut update = store::read_revision(&*self.state_manager()?, &self.revision, known)?;
+        if known != Some(update.revision) {
+            self.slices.insert_into(&mut update.state)?;
+        }
+        Ok(update)
+    }
+
+    // The response of a command that changed the store `scope`: `state`, or with
+    // envelope responses, the store's state along with its revision
+    pub(crate) fn respond(
+        &self,
+        scope: StoreScope,
+        label: &str,
+        state: JsonValue,
+    ) -> crate::Result<JsonValue> {
+        if !self.envelope_responses {
+            return self.redact(scope, label, "", state);
+        }
+        let update = match scope {
+            StoreScope::App => self.get_state_with_revision()?,
+            StoreScope::Window => self.get_window_state_with_revision(label)?,
+        };
+        self.respond_with(scope, label, update)
+    }
+
+    // The response carrying `update` of the store `scope`: its state, or with envelope
+    // responses, the envelope
+    pub(crate) fn respond_with(
+        &self,
+        scope: StoreScope,
+        label: &str,
+        mut update: StateUpdate,
+    ) -> crate::Result<JsonValue> {
+        update.state = self.redact(scope, label, "", update.state)?;
+        if !self.envelope_responses {
+            return Ok(update.state);
+        }
+        serde_json::to_value(update).map_err(|e| crate::RstateError::serialization(e.to_string()))
+    }
+
+    // Preview `action` on the `scope` store of the window `label` for the frontend: the
+    // state it would produce and the patch to it from the current one, read under the
+    // same lock, both redacted
+    pub(crate) fn dry_run(
+        &self,
+        scope: StoreScope,
+        label: &str,
+        action: Action,
+    ) -> crate::Result<DryRun> {
+        let actions = [action];
+        self.check_guards(&actions)?;
+        let (current, state) = match scope {
+            StoreScope::App => store::preview(&*self.state_manager()?, &actions)?,
+            StoreScope::Window => store::preview(&self.window_stores.get(label)?.state, &actions)?,
+        };
+        let current = self.redact(scope, label, "", current)?;
+        let state = self.redact(scope, label, "", state)?;
+        Ok(DryRun {
+            diff: diff(&current, &state),
+            state,
+        })
+    }
+
+    // The paths of the `scope` store of the window `label` hidden from the frontend.
+    // Those of the app-wide store include its slices'.
+    pub(crate) fn redacted_paths(
+        &self,
+        scope: StoreScope,
+        label: &str,
+    ) -> crate::Result<Vec<String>> {
+        match scope {
+            StoreScope::App => {
+                let mut paths = store::redacted_paths(&*self.state_manager()?)?;
+                paths.extend(self.slices.redacted_paths()?);
+                Ok(paths)
+            }
+            StoreScope::Window => store::redacted_paths(&self.window_stores.get(label)?.state),
+        }
+    }
+
+    // Hide the redacted paths from `value`, read at `key` of the `scope` store of the
+    // window `label`, before sending it to the frontend
+    pub(crate) fn redact(
+        &self,
+        scope: StoreScope,
+        label: &str,
+        key: &str,
+        mut value: JsonValue,
+    ) -> crate::Result<JsonValue> {
+        redact_at(&mut value, key, &self.redacted_paths(scope, label)?);
+        Ok(value)
+    }
+
+    // A page of the action log of the app-wide store, as sent to the frontend. Redacted
+    // paths are paths of the state, not of payloads: if the store has any, no payload
+    // is sent.
+    pub(crate) fn get_redacted_persisted_history(
+        &self,
+        after: u64,
+        limit: usize,
+    ) -> crate::Result<HistoryPage> {
+        let mut page = self.persisted_history(after, limit)?;
+        if !self.redacted_paths(StoreScope::App, "")?.is_empty() {
+            for entry in &mut page.entries {
+                if entry.action.payload.is_some() {
+                    entry.action.payload = Some(JsonValue::String(REDACTED.to_owned()));
+                }
+            }
+        }
+        Ok(page)
+    }
+
+    // The changes of the app-wide store since revision `since`, as sent to the frontend
+    pub(crate) fn get_redacted_changes_since(&self, since: u64) -> crate::Result<Changes> {
+        let redacted = self.redacted_paths(StoreScope::App, "")?;
+        let mut changes = self.get_changes_since(since)?;
+        if let Some(patch) = &mut changes.patch {
+            redact_patch(patch, &redacted);
+        }
+        if let Some(state) = &mut changes.state {
+            redact(state, &redacted);
+        }
+        Ok(changes)
+    }
+
+    /// Get a specific part of the state by key (supports dot notation).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // Get nested state
+    /// let user_name = app.rstate().get_state("user.profile.name")?;
+    /// ```
+    pub fn get_state(&self, key: &str) -> crate::Result<Option<JsonValue>> {
+        if let Some((slice, rest)) = self.slices.resolve(key) {
+            return Ok(crate::models::get_state(&read_state(&slice.state)?, rest));
+        }
+        let full_state = read_state(&*self.state_manager()?)?;
+        Ok(crate::models::get_state(&full_state, key))
+    }
+
+    /// Compute the selector `name` of the app-wide store, a value derived from its
+    /// state. The selectors of a slice registered with
+    /// [`register_slice`](Self::register_slice) are named `slice/name`.
+    ///
+    /// See [`StateBuilder::selector`](crate::StateBuilder::selector).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let completed: usize = serde_json::from_value(app.rstate().select("completed_count")?)?;
+    /// ```
+    pub fn select(&self, name: &str) -> crate::Result<JsonValue> {
+        if let Some((slice, selector)) = self.slices.resolve_selector(name) {
+            return store::select(&slice.state, selector);
+        }
+        store::select(&*self.state_manager()?, name)
+    }
+
+    /// Report how often the selectors of the app-wide store were served from their
+    /// cache, by name, to find the ones recomputed on almost every read.
+    ///
+    /// See [`StateBuilder::selector`](crate::StateBuilder::selector).
+    pub fn selector_stats(&self) -> crate::Result<Vec<SelectorStats>> {
+        store::selector_stats(&*self.state_manager()?)
+    }
+
+    /// List the action kinds the app-wide store handles, along with those of the
+    /// slices registered with [`register_slice`](Self::register_slice), prefixed with
+    /// their key.
+    ///
+    /// See [`RstateManager::action_kinds`](crate::RstateManager::action_kinds).
+    pub fn action_kinds(&self) -> crate::Result<ActionKinds> {
+        let mut kinds = store::action_kinds(&*self.state_manager()?)?;
+        self.slices.add_action_kinds(&mut kinds)?;
+        Ok(kinds)
+    }
+
+    /// Page through the persisted action log of the app-wide store, when it is
+    /// [event-sourced](crate::StateBuilder::event_sourced): up to `limit` of the actions
+    /// logged after the sequence number `after` (0 for the first page), oldest first.
+    ///
+    /// The log only holds the actions dispatched since the last snapshot. See
+    /// [`RstateManager::persisted_history`](crate::RstateManager::persisted_history).
+    pub fn persisted_history(&self, after: u64, limit: usize) -> crate::Result<HistoryPage> {
+        store::persisted_history(&*self.state_manager()?, after, limit)
+    }
+
+    /// Mint a token restricting the reads of the window `label` to the keys of the
+    /// app-wide state under `prefixes` (dot notation).
+    ///
+    /// Hand it to an embedded or untrusted webview: from then on, the window's read
+    /// commands are rejected unless they carry the token, and only serve keys under the
+    /// prefixes. Revoke it with
+    /// [`revoke_read_token`](Self::revoke_read_token).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let token = app.rstate().mint_read_token("panel", ["player.track", "theme"]);
+    /// panel.eval(&format!("window.__RSTATE_TOKEN__ = {token:?}"))?;
+    /// ```
+    pub fn mint_read_token(
+        &self,
+        label: impl Into<String>,
+        prefixes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> String {
+        self.read_tokens
+            .mint(label.into(), prefixes.into_iter().map(Into::into).collect())
+    }
+
+    /// Revoke a token minted with [`mint_read_token`](Self::mint_read_token). Returns
+    /// `false` if it was unknown.
+    pub fn revoke_read_token(&self, token: &str) -> bool {
+        self.read_tokens.revoke(token)
+    }
+
+    // What the window `label` may read of the app-wide state, presenting `token`
+    pub(crate) fn read_scope(
+        &self,
+        label: &str,
+        token: Option<&str>,
+    ) -> crate::Result<Option<ReadScope>> {
+        self.read_tokens.scope(label, token)
+    }
+
+    /// Access the app-wide store with its state as a `T`, instead of JSON.
+    ///
+    /// Fails if no state manager is registered, or if the state isn't a `T`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let store = app.rstate().typed::<AppState>()?;
+    /// let state: AppState = store.dispatch(Action::with_payload("SET_COUNT", 42)?)?;
+    /// let todos = store.with_state(|state| state.todos.len())?;
+    /// ```
+    pub fn typed<T: DeserializeOwned>(&self) -> crate::Result<TypedRstate<'_, R, T>> {
+        TypedRstate::new(self)
+    }
+
+    /// Whether a feature flag declared with [`StateBuilder::flags`](crate::StateBuilder::flags)
+    /// is enabled. Unknown flags are disabled.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// if app.rstate().flag("newEditor")? {
+    ///     // ...
+    /// }
+    /// ```
+    pub fn flag(&self, name: &str) -> crate::Result<bool> {
+        let state = self.get_initial_state()?;
+        Ok(state[crate::FLAGS_KEY][name].as_bool().unwrap_or(false))
+    }
+
+    /// Dispatch an action to the state manager.
+    ///
+    /// Emits a state update event only if the state actually changed, unless the
+    /// [`EmitPolicy`](crate::EmitPolicy) for the action kind says otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use tauri_plugin_rstate::Action;
+    ///
+    /// let action = Action::with_payload("SET_COUNT", 42)?;
+    /// let new_state = app.rstate().dispatch(action)?;
+    /// ```
+    pub fn dispatch(&self, action: Action) -> crate::Result<JsonValue> {
+        if let Some((slice, inner)) = self.slices.route(&action) {
+            let result = self.apply(&slice.state, &slice.revision, &slice.event(), &inner);
+            self.record(&action, &result);
+            return result.map(|(state, _)| state);
+        }
+        let result = self.state_manager().and_then(|state_manager| {
+            self.apply(&state_manager, &self.revision, STATE_UPDATE_EVENT, &action)
+        });
+        self.record(&action, &result);
+        let (state, changed) = result?;
+        if changed {
+            self.notify_local_change(Some(&action), &state);
+        }
+        Ok(state)
+    }
+
+    /// Modify the state directly, without an action.
+    ///
+    /// For backend code where inventing an action kind for every internal mutation is
+    /// pure ceremony. `f` receives the current state as JSON; the result goes through the
+    /// same change detection and emission as a dispatch, under the global
+    /// [`EmitPolicy`](crate::EmitPolicy). Requires a state manager supporting
+    /// [`replace_state`](RstateManager::replace_state), such as [`StateBuilder`](crate::StateBuilder)'s.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().update(|state| state["lastSync"] = json!(now))?;
+    /// ```
+    pub fn update<F: FnOnce(&mut JsonValue)>(&self, f: F) -> crate::Result<JsonValue> {
+        let (state, changed) = self.replace_with(None, f)?;
+        if changed {
+            self.notify_local_change(None, &state);
+        }
+        Ok(state)
+    }
+
+    /// Apply a change made elsewhere, e.g. pulled by a sync layer.
+    ///
+    /// The change is applied as a merge dispatch of kind
+    /// [`REMOTE_CHANGE_ACTION`](crate::REMOTE_CHANGE_ACTION) with source
+    /// [`ActionSource::Remote`](crate::ActionSource::Remote), so guards and the action log
+    /// see it, but it isn't surfaced to [`on_local_change`](crate::Builder::on_local_change)
+    /// hooks. Conflicts are resolved by the [`on_conflict`](crate::Builder::on_conflict)
+    /// callback. Like [`update`](Self::update), this requires
+    /// [`replace_state`](RstateManager::replace_state) support.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().apply_remote_change(
+    ///     RemoteChange::new("settings.theme", json!("dark")).with_base(json!("light")),
+    /// )?;
+    /// ```
+    pub fn apply_remote_change(&self, change: RemoteChange) -> crate::Result<JsonValue> {
+        let action =
+            Action::with_payload(REMOTE_CHANGE_ACTION, &change)?.with_source(ActionSource::Remote);
+        let result = check_guards(&self.guards, &action).and_then(|()| {
+            self.replace_with(Some(&action), |state| {
+                if let Some(value) = resolve(state, &change, self.on_conflict.as_ref()) {
+                    set_path(state, &change.path, value);
+                }
+            })
+        });
+        self.record(&action, &result);
+        result.map(|(state, _)| state)
+    }
+
+    // Modify the app-wide store's state as JSON with `f` and replace it
+    fn replace_with<F: FnOnce(&mut JsonValue)>(
+        &self,
+        action: Option<&Action>,
+        f: F,
+    ) -> crate::Result<(JsonValue, bool)> {
+        let state_manager = self.state_manager()?;
+        self.commit(
+            &state_manager,
+            &self.revision,
+            STATE_UPDATE_EVENT,
+            action,
+            &[],
+            |state_manager| {
+                let current = state_manager.get_initial_state();
+                let mut state = current.clone();
+                f(&mut state);
+                if states_are_equal(&current, &state, state_manager.float_comparison()) {
+                    return Ok(DispatchOutcome::unchanged(state));
+                }
+                state_manager.replace_state(state)?;
+                Ok(DispatchOutcome::changed(state_manager.get_initial_state()))
+            },
+        )
+    }
+
+    // Surface a local change of the app-wide store to the sync hooks
+    fn notify_local_change(&self, action: Option<&Action>, state: &JsonValue) {
+        if self.local_change_hooks.is_empty() {
+            return;
+        }
+        let change = LocalChange {
+            action: action.cloned(),
+            revision: self.revision(),
+            state: state.clone(),
+        };
+        for hook in &self.local_change_hooks {
+            hook(&change);
+        }
+    }
+
+    /// Modify the state directly as a `T`, without an action.
+    ///
+    /// Like [`update`](Self::update), with the state deserialized into `T` and serialized
+    /// back afterwards. This is the typed way to mutate the state of a registered
+    /// [`BuiltStateManager`](crate::BuiltStateManager) and emit it:
+    /// [`with_state_mut`](crate::BuiltStateManager::with_state_mut) emits nothing.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().update_as(|state: &mut AppState| state.last_sync = now)?;
+    /// ```
+    pub fn update_as<T, F>(&self, f: F) -> crate::Result<JsonValue>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(&mut T),
+    {
+        let mut result = Ok(());
+        let state = self.update(|state| {
+            result = typed_update(state, f);
+        });
+        result.and(state)
+    }
+
+    /// Replace the value at `key` (in dot notation), leaving the rest of the state alone.
+    ///
+    /// For importing part of a state, e.g. just the settings, without clobbering live
+    /// session state. The key must already exist, and the resulting state is validated by
+    /// [`replace_state`](RstateManager::replace_state), which [`StateBuilder`](crate::StateBuilder)'s
+    /// manager does by deserializing it into its state type. Like [`update`](Self::update),
+    /// nothing is emitted if the value didn't change; with
+    /// [`emit_patches`](crate::Builder::emit_patches) or
+    /// [`trim_unchanged`](crate::Builder::trim_unchanged), the update only carries the subtree.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let imported: JsonValue = serde_json::from_str(&fs::read_to_string(path)?)?;
+    /// app.rstate().hydrate_key("settings", imported["settings"].clone())?;
+    /// ```
+    pub fn hydrate_key(&self, key: &str, value: JsonValue) -> crate::Result<JsonValue> {
+        let mut result = Ok(());
+        let state = self.update(|state| {
+            result = replace_key(state, key, value);
+        });
+        result.and(state)
+    }
+
+    /// Dispatch several actions in order, under a single lock acquisition, with a single
+    /// state update event at the end.
+    ///
+    /// The guards run against every action first, so a rejected action leaves the store
+    /// untouched. The actions then apply in order until one fails: the ones before it
+    /// stay applied and are emitted, and its error is returned. The update follows the
+    /// global [`EmitPolicy`](crate::EmitPolicy).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().dispatch_many(vec![
+    ///     Action::new("CLEAR_TODOS"),
+    ///     Action::with_payload("ADD_TODO", "Write docs")?,
+    /// ])?;
+    /// ```
+    pub fn dispatch_many(&self, actions: Vec<Action>) -> crate::Result<JsonValue> {
+        if let Some((slice, inner)) = self.slices.route_many(&actions)? {
+            return self.apply_many(&slice.state, &slice.revision, &slice.event(), &inner);
+        }
+        let state_manager = self.state_manager()?;
+        self.apply_many(&state_manager, &self.revision, STATE_UPDATE_EVENT, &actions)
+    }
+
+    /// Queue an action for the store's actor, and return without waiting for it.
+    ///
+    /// With [actor mode](crate::Builder::actor_mode), the action is applied on the
+    /// actor's thread after the actions queued before it; a failure is logged. Safe to
+    /// call from an action handler, e.g. to queue a follow-up action. Without actor
+    /// mode, this is the same as [`dispatch`](Self::dispatch), minus the new state.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().dispatch_queued(Action::with_payload("TRACK_PLAYED", id)?)?;
+    /// ```
+    pub fn dispatch_queued(&self, action: Action) -> crate::Result<()> {
+        match &self.actor {
+            Some(actor) => actor.send(action),
+            None => self.dispatch(action).map(drop),
+        }
+    }
+
+    /// Dispatch an action without blocking the calling task, resolving to the new state.
+    ///
+    /// The dispatch runs on the async runtime's blocking pool, so async commands and
+    /// background tasks never wait on the store's lock. With
+    /// [actor mode](crate::Builder::actor_mode), the action is sent to the store's
+    /// actor instead, and applied after the actions queued before it. The future
+    /// doesn't borrow `self`, so it can be spawned.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// #[tauri::command]
+    /// async fn import_library(app: tauri::AppHandle) -> Result<JsonValue, String> {
+    ///     app.rstate()
+    ///         .dispatch_async(Action::new("IMPORT_LIBRARY"))
+    ///         .await
+    ///         .map_err(|e| e.to_string())
+    /// }
+    /// ```
+    pub fn dispatch_async(
+        &self,
+        action: Action,
+    ) -> impl Future<Output = crate::Result<JsonValue>> + Send + 'static {
+        let queued = match &self.actor {
+            Some(actor) => Ok(actor.dispatch(action)),
+            None => Err(action),
+        };
+        let app = self.app.clone();
+        async move {
+            match queued {
+                Ok(outcome) => outcome.await,
+                Err(action) => {
+                    tauri::async_runtime::spawn_blocking(move || app.rstate().dispatch(action))
+                        .await
+                        .map_err(|e| crate::RstateError::state(format!("dispatch task: {e}")))?
+                }
+            }
+        }
+    }
+
+    /// Dispatch an action once `delay` has passed, on the async runtime.
+    ///
+    /// For timeouts kept in the state, e.g. dismissing a notification or expiring a
+    /// session. The action goes through [`dispatch_async`](Self::dispatch_async); a
+    /// failure is logged. The returned handle cancels it; dropping it doesn't.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let dismiss = app.rstate().dispatch_after(
+    ///     Duration::from_secs(5),
+    ///     Action::with_payload("DISMISS_NOTIFICATION", id)?,
+    /// );
+    /// // The user closed it first
+    /// dismiss.cancel();
+    /// ```
+    pub fn dispatch_after(&self, delay: Duration, action: Action) -> ScheduleHandle {
+        let app = self.app.clone();
+        schedule(delay, async move {
+            let kind = action.kind.clone();
+            if let Err(err) = app.rstate().dispatch_async(action).await {
+                log::warn!(target: ACTION_LOG_TARGET, "scheduled action '{kind}': {err}");
+            }
+        })
+    }
+
+    /// Dispatch the action built by `action` every `period`, on the async runtime,
+    /// starting one period from now.
+    ///
+    /// For polling and refresh loops, modeled as periodic dispatches instead of a
+    /// hand-rolled thread. The action goes through
+    /// [`dispatch_async`](Self::dispatch_async); a failure is logged, and the ticker
+    /// keeps going. A slow dispatch delays the next ticks rather than piling them up.
+    /// The returned handle stops the ticker; dropping it doesn't. A period shorter
+    /// than a millisecond, zero included, is raised to one.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let poll = app.rstate().dispatch_every(Duration::from_secs(30), || Action::new("POLL_INBOX"));
+    /// // On logout
+    /// poll.cancel();
+    /// ```
+    pub fn dispatch_every<F>(&self, period: Duration, action: F) -> ScheduleHandle
+    where
+        F: Fn() -> Action + Send + 'static,
+    {
+        let app = self.app.clone();
+        schedule_every(period, move || {
+            let action = action();
+            let app = app.clone();
+            async move {
+                let kind = action.kind.clone();
+                if let Err(err) = app.rstate().dispatch_async(action).await {
+                    log::warn!(target: ACTION_LOG_TARGET, "recurring action '{kind}': {err}");
+                }
+            }
+        })
+    }
+
+    /// Dispatch an action as part of a batch.
+    ///
+    /// With a [batch window](crate::Builder::batch_window), the action is queued with
+    /// the others dispatched within the window, and they are applied together once it
+    /// ends, with a single emit. Resolves to the state after the whole batch, or to the
+    /// action's own error (other actions of the batch are still applied).
+    /// Without a batch window, this is the same as [`dispatch_async`](Self::dispatch_async).
+    ///
+    /// Actions of a [debounced](crate::Builder::debounce) or
+    /// [throttled](crate::Builder::throttle) kind skip the batch, and are coalesced
+    /// instead.
+    ///
+    /// The frontend `dispatch` command goes through this method.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // In a file watcher callback
+    /// tauri::async_runtime::spawn(async move {
+    ///     app.rstate().dispatch_batched(Action::with_payload("FILE_CHANGED", path)?).await
+    /// });
+    /// ```
+    pub async fn dispatch_batched(&self, action: Action) -> crate::Result<JsonValue> {
+        if let Some(limit) = self.rate_limits.limit(&action.kind) {
+            return dispatch_limited(&self.app, limit, action).await;
+        }
+        // Slices have their own store, outside the batch
+        let Some(batcher) = self
+            .batcher
+            .as_ref()
+            .filter(|_| self.slices.route(&action).is_none())
+        else {
+            return self.dispatch_async(action).await;
+        };
+
+        let (outcome, result) = tokio::sync::oneshot::channel();
+        if batcher.push(action, outcome) {
+            let app = self.app.clone();
+            let window = batcher.window();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(window).await;
+                app.rstate().apply_batch();
+            });
+        }
+
+        result
+            .await
+            .map_err(|_| crate::RstateError::state("Batch was dropped"))?
+    }
+
+    /// Run `f` without emitting intermediate updates of the app-wide store, then emit
+    /// a single update with the final state if it changed.
+    ///
+    /// Useful for Rust code paths performing several related dispatches in sequence.
+    /// Updates caused by other threads while `f` runs are held back as well. Scopes can
+    /// be nested; only the outermost one emits. Like coalesced updates, the final
+    /// update is never trimmed nor sent as a patch.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().batched(|| {
+    ///     app.rstate().dispatch_kind("CLEAR_TODOS")?;
+    ///     for todo in imported {
+    ///         app.rstate().dispatch_with("ADD_TODO", todo)?;
+    ///     }
+    ///     Ok::<_, tauri_plugin_rstate::RstateError>(())
+    /// })??;
+    /// ```
+    pub fn batched<T>(&self, f: impl FnOnce() -> T) -> crate::Result<T> {
+        let start_revision = self.revision();
+        let scope = BatchedScope::enter(&self.batched_scopes);
+        let output = f();
+        drop(scope);
+
+        if self.batched_scopes.load(Ordering::SeqCst) == 0
+            && self.revision() != start_revision
+            && self.is_ready()
+        {
+            let state = self.get_initial_state()?;
+            let redacted = self.redacted_paths(StoreScope::App, "")?;
+            self.publisher.publish_full(
+                STATE_UPDATE_EVENT,
+                self.revision(),
+                state,
+                &redacted,
+                None,
+            )?;
+        }
+        Ok(output)
+    }
+
+    /// Run `f` as a transaction over several stores, emitting the updates of every
+    /// store it changed together once it ends.
+    ///
+    /// The stores dispatched to through the [`Transaction`] (the app-wide store, slices
+    /// and window stores) emit nothing while `f` runs. When it ends, whether or not it
+    /// failed, every store that changed emits a single update with its full state, in
+    /// the order they were first touched and sharing the transaction's trace id, so the
+    /// frontend never renders the stores out of step. The dispatches are not rolled
+    /// back if `f` fails. The trace id is only sent with
+    /// [`Builder::envelope_updates`](crate::Builder::envelope_updates).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().transaction(|tx| {
+    ///     tx.dispatch(Action::with_payload("import/FINISH", &report)?)?;
+    ///     tx.dispatch(Action::with_payload("ADD_RECENT", &report.path)?)
+    /// })?;
+    /// ```
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&Transaction<'_, R>) -> crate::Result<T>,
+    ) -> crate::Result<T> {
+        let transaction = Transaction::new(self);
+        let output = f(&transaction);
+        let emitted = transaction.finish();
+        let output = output?;
+        emitted.map(|()| output)
+    }
+
+    /// Replay a recorded action log through the current reducers, e.g. to reproduce
+    /// a bug report from the log a user submitted.
+    ///
+    /// `log` is read as JSON lines, each a serialized [`Action`], an entry of an
+    /// [`EventLog`](crate::EventLog) or a record of an [`AuditLog`](crate::AuditLog)
+    /// written [`with_payloads`](crate::AuditLog::with_payloads). The actions are
+    /// tagged with [`ActionSource::Replay`](crate::ActionSource::Replay). With
+    /// `hold_updates`, nothing is emitted until the whole log is replayed, as in a
+    /// [`transaction`](Self::transaction); otherwise every action emits its update.
+    /// Actions that fail are listed in the [`ReplayReport`] without stopping the replay.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the log can't be read.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let log = std::io::BufReader::new(std::fs::File::open("actions.jsonl")?);
+    /// let report = app.rstate().replay(log, true)?;
+    /// ```
+    pub fn replay(
+        &self,
+        log: impl std::io::BufRead,
+        hold_updates: bool,
+    ) -> crate::Result<ReplayReport> {
+        if hold_updates {
+            self.transaction(|tx| replay::run(log, |action| tx.dispatch(action)))
+        } else {
+            replay::run(log, |action| self.dispatch(action))
+        }
+    }
+
+    // The events held back by running transactions
+    pub(crate) fn held_events(&self) -> &HeldEvents {
+        &self.held_events
+    }
+
+    pub(crate) fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limits
+    }
+
+    // The store `action` is dispatched to by `dispatch`
+    pub(crate) fn store_of(&self, action: &Action) -> Store {
+        match self.slices.route(action) {
+            Some((slice, _)) => Store::Slice(slice.key.clone()),
+            None => Store::App,
+        }
+    }
+
+    // The current revision of `store`
+    pub(crate) fn store_revision(&self, store: &Store) -> crate::Result<u64> {
+        Ok(match store {
+            Store::App => self.revision(),
+            Store::Slice(key) => self.slices.get(key)?.revision.load(Ordering::SeqCst),
+            Store::Window(label) => self
+                .window_stores
+                .get(label)?
+                .revision
+                .load(Ordering::SeqCst),
+        })
+    }
+
+    // Emit the full state of `store`, as part of the transaction `trace_id`
+    pub(crate) fn publish_store(&self, store: &Store, trace_id: &str) -> crate::Result<()> {
+        let (revision, state, redacted) = match store {
+            // Left to the registration or `batched` scope, which emit it when they end
+            Store::App if !self.is_ready() || self.batched_scopes.load(Ordering::SeqCst) > 0 => {
+                return Ok(());
+            }
+            Store::App => (
+                self.revision(),
+                self.get_initial_state()?,
+                self.redacted_paths(StoreScope::App, "")?,
+            ),
+            Store::Slice(key) => {
+                let slice = self.slices.get(key)?;
+                (
+                    slice.revision.load(Ordering::SeqCst),
+                    read_state(&slice.state)?,
+                    store::redacted_paths(&slice.state)?,
+                )
+            }
+            Store::Window(label) => {
+                let store = self.window_stores.get(label)?;
+                (
+                    store.revision.load(Ordering::SeqCst),
+                    read_state(&store.state)?,
+                    store::redacted_paths(&store.state)?,
+                )
+            }
+        };
+        self.publisher
+            .publish_full(&store.event(), revision, state, &redacted, Some(trace_id))
+    }
+
+    // Apply the queued batch to the app-wide store, with a single emit
+    fn apply_batch(&self) {
+        let Some(batcher) = &self.batcher else {
+            return;
+        };
+        let batch = batcher.take();
+        if batch.is_empty() {
+            return;
+        }
+
+        let mut outcomes = Vec::with_capacity(batch.len());
+        let result = self.state_manager().and_then(|state_manager| {
+            self.commit(
+                &state_manager,
+                &self.revision,
+                STATE_UPDATE_EVENT,
+                None,
+                &[],
+                |state_manager| {
+                    let mut merged = Merged::default();
+                    for (action, _) in &batch {
+                        let outcome = self
+                            .check_circuit(action)
+                            .and_then(|()| check_guards(&self.guards, action))
+                            .and_then(|()| self.run(state_manager, action))
+                            .map(|outcome| merged.add(outcome));
+                        outcomes.push(outcome);
+                    }
+                    Ok(merged.finish(state_manager))
+                },
+            )
+        });
+
+        // Only forget the persisted actions once their changes are saved
+        if batcher.is_persisted() {
+            let flushed = self.state_manager().and_then(|state_manager| {
+                state_manager
+                    .write()
+                    .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?
+                    .flush()
+            });
+            match flushed {
+                Ok(()) => batcher.persist(),
+                Err(err) => log::warn!(target: ACTION_LOG_TARGET, "flushing a batch: {err}"),
+            }
+        }
+
+        // Report the state after the whole batch to every action that succeeded
+        let mut outcomes = outcomes.into_iter();
+        for (action, sender) in batch {
+            let outcome = match (&result, outcomes.next()) {
+                (_, Some(Err(err))) => Err(err),
+                (Ok((state, changed)), _) => Ok((state.clone(), *changed)),
+                (Err(err), _) => Err(err.clone()),
+            };
+            self.record(&action, &outcome);
+            if let Ok((state, true)) = &outcome {
+                self.notify_local_change(Some(&action), state);
+            }
+            let _ = sender.send(outcome.map(|(state, _)| state));
+        }
+    }
+
+    /// Preview the state that dispatching `actions` in order would produce.
+    ///
+    /// The actions run against a copy of the current state: the store is not modified,
+    /// no update is emitted and nothing is logged. Handlers still run, so keep side
+    /// effects out of them when using previews.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let preview = app.rstate().simulate(&[Action::with_payload("ADD_ITEM", item)?])?;
+    /// let total = get_state(&preview, "cart.total");
+    /// ```
+    pub fn simulate(&self, actions: &[Action]) -> crate::Result<JsonValue> {
+        self.check_guards(actions)?;
+        simulate(&*self.state_manager()?, actions)
+    }
+
+    /// Emit the current state to the window `label` only, as a regular update.
+    ///
+    /// New windows otherwise stay blank until their frontend calls
+    /// `get_initial_state`. Call this once the window's listeners are registered, or
+    /// let the plugin do it when its page has loaded with
+    /// [`Builder::prime_windows`](crate::Builder::prime_windows). The app-wide state is
+    /// emitted if a state manager is registered, and the window's own store if it has
+    /// one. Other transports don't receive these updates.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let window = WebviewWindowBuilder::new(app, "settings", url).build()?;
+    /// app.rstate().prime_window(window.label())?;
+    /// ```
+    pub fn prime_window(&self, label: &str) -> crate::Result<()> {
+        if self.is_registered() {
+            let revision = self.revision();
+            let state = self.get_initial_state()?;
+            self.emit_to_window(label, STATE_UPDATE_EVENT, revision, state)?;
+        }
+        if let Ok(window_store) = self.window_stores.get(label) {
+            let state = read_state(&window_store.state)?;
+            let revision = window_store.revision.load(Ordering::SeqCst);
+            self.emit_to_window(label, &window_event_name(label), revision, state)?;
+        }
+        Ok(())
+    }
+
+    // Emit the full `state` of `revision` under `event` to the window `label` only
+    fn emit_to_window(
+        &self,
+        label: &str,
+        event: &str,
+        revision: u64,
+        state: JsonValue,
+    ) -> crate::Result<()> {
+        let payload = self.publisher.full_payload(revision, state)?;
+        self.app
+            .emit_to(label, event, payload)
+            .map_err(|err| crate::RstateError::Emit(err.to_string()))
+    }
+
+    /// Register `state_manager` as the slice `key` of the app-wide state, with its own
+    /// lock.
+    ///
+    /// Actions prefixed with `"{key}/"` are dispatched to it without the prefix, reads
+    /// of `key` are served by it, and its updates are emitted under
+    /// [`slice_event_name(key)`](crate::slice_event_name). A slow reducer in the slice
+    /// then doesn't block the rest of the state. The full state from
+    /// [`get_initial_state`](Self::get_initial_state) includes the slice under `key`.
+    ///
+    /// Fails if the slice is already registered, or if `key` contains a `.` or `/`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().register_slice("import", StateBuilder::new(ImportState::default()).build())?;
+    /// app.rstate().dispatch_with("import/RUN", files)?;
+    /// ```
+    pub fn register_slice<S: RstateManager>(
+        &self,
+        key: impl Into<String>,
+        state_manager: S,
+    ) -> crate::Result<()> {
+        let key = key.into();
+        let mut state_manager: Box<dyn RstateManager> = Box::new(state_manager);
+        state_manager.set_dispatcher(slice_dispatcher(&key, self.dispatcher(None)));
+        state_manager.set_app(Arc::new(self.app.clone()));
+        state_manager.set_save_tap(self.taps.saves_of(crate::slice_event_name(&key)));
+        self.slices.insert(key, state_manager)
+    }
+
+    /// Create a store scoped to the window `label`.
+    ///
+    /// The store is independent of the app-wide store. Its updates are emitted under
+    /// [`window_event_name(label)`](crate::window_event_name), and the window's frontend
+    /// reaches it by passing `scope: "window"` to the plugin commands.
+    ///
+    /// Fails if the window already has a store.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let manager = StateBuilder::new(Document::default()).build();
+    /// app.rstate().create_window_store("doc-1", manager)?;
+    /// ```
+    pub fn create_window_store<S: RstateManager>(
+        &self,
+        label: impl Into<String>,
+        state_manager: S,
+    ) -> crate::Result<()> {
+        let label = label.into();
+        let mut state_manager: Box<dyn RstateManager> = Box::new(state_manager);
+        state_manager.set_dispatcher(self.dispatcher(Some(label.clone())));
+        state_manager.set_app(Arc::new(self.app.clone()));
+        state_manager.set_save_tap(self.taps.saves_of(window_event_name(&label)));
+        self.window_stores.insert(label, state_manager)
+    }
+
+    /// Flush and remove the store of the window `label`.
+    ///
+    /// This happens automatically when the window is destroyed. Returns `true` if
+    /// the window had a store.
+    pub fn remove_window_store(&self, label: &str) -> crate::Result<bool> {
+        self.window_stores.close(label)
+    }
+
+    /// Check if the window `label` has a store.
+    pub fn has_window_store(&self, label: &str) -> bool {
+        self.window_stores.contains(label)
+    }
+
+    /// Get the full state of the window `label`'s store.
+    pub fn get_window_initial_state(&self, label: &str) -> crate::Result<JsonValue> {
+        read_state(&self.window_stores.get(label)?.state)
+    }
+
+    /// Get the full state of the window `label`'s store along with its revision.
+    pub fn get_window_state_with_revision(&self, label: &str) -> crate::Result<StateUpdate> {
+        let store = self.window_stores.get(label)?;
+        store::read_revision(&store.state, &store.revision, None)
+    }
+
+    /// Get the full state of the window `label`'s store along with its revision, unless
+    /// the store is still at revision `known`. See
+    /// [`get_state_if_modified`](Self::get_state_if_modified).
+    pub fn get_window_state_if_modified(
+        &self,
+        label: &str,
+        known: u64,
+    ) -> crate::Result<StateUpdate> {
+        let store = self.window_stores.get(label)?;
+        store::read_revision(&store.state, &store.revision, Some(known))
+    }
+
+    /// Get a specific part of the window `label`'s state by key (supports dot notation).
+    pub fn get_window_state(&self, label: &str, key: &str) -> crate::Result<Option<JsonValue>> {
+        let full_state = self.get_window_initial_state(label)?;
+        Ok(crate::models::get_state(&full_state, key))
+    }
+
+    /// Compute the selector `name` of the window `label`'s store. See
+    /// [`select`](Self::select).
+    pub fn select_in_window(&self, label: &str, name: &str) -> crate::Result<JsonValue> {
+        store::select(&self.window_stores.get(label)?.state, name)
+    }
+
+    /// Dispatch an action to the window `label`'s store.
+    ///
+    /// Emits a [`window_event_name(label)`](crate::window_event_name) event only if
+    /// the state actually changed.
+    pub fn dispatch_to_window(&self, label: &str, action: Action) -> crate::Result<JsonValue> {
+        let event = crate::window_event_name(label);
+        let result = self
+            .window_stores
+            .get(label)
+            .and_then(|store| self.apply(&store.state, &store.revision, &event, &action));
+        self.record(&action, &result);
+        result.map(|(state, _)| state)
+    }
+
+    /// Dispatch several actions to the window `label`'s store, with a single emit.
+    /// See [`dispatch_many`](Self::dispatch_many).
+    pub fn dispatch_many_to_window(
+        &self,
+        label: &str,
+        actions: Vec<Action>,
+    ) -> crate::Result<JsonValue> {
+        let event = crate::window_event_name(label);
+        let store = self.window_stores.get(label)?;
+        self.apply_many(&store.state, &store.revision, &event, &actions)
+    }
+
+    /// Preview the state that dispatching `actions` to the window `label`'s store
+    /// would produce. See [`simulate`](Self::simulate).
+    pub fn simulate_in_window(&self, label: &str, actions: &[Action]) -> crate::Result<JsonValue> {
+        self.check_guards(actions)?;
+        simulate(&self.window_stores.get(label)?.state, actions)
+    }
+
+    // Run the action through a store and emit the update under `event` if needed.
+    // Returns the updated state and whether it changed.
+    fn apply(
+        &self,
+        store: &ManagedState,
+        revision: &AtomicU64,
+        event: &str,
+        action: &Action,
+    ) -> crate::Result<(JsonValue, bool)> {
+        self.check_circuit(action)?;
+        check_guards(&self.guards, action)?;
+        let actions = std::slice::from_ref(action);
+        self.commit(
+            store,
+            revision,
+            event,
+            Some(action),
+            actions,
+            |state_manager| self.run(state_manager, action),
+        )
+    }
+
+    // Run the actions through a store in order, stopping at the first failure, and
+    // emit a single update under `event` if needed. Every action run is recorded.
+    fn apply_many(
+        &self,
+        store: &ManagedState,
+        revision: &AtomicU64,
+        event: &str,
+        actions: &[Action],
+    ) -> crate::Result<JsonValue> {
+        actions
+            .iter()
+            .try_for_each(|action| self.check_circuit(action))?;
+        self.check_guards(actions)?;
+        let mut failure = None;
+        let mut applied = 0;
+        let outcome = self.commit(store, revision, event, None, actions, |state_manager| {
+            let mut merged = Merged::default();
+            for action in actions {
+                match self.run(state_manager, action) {
+                    Ok(outcome) => merged.add(outcome),
+                    Err(err) => {
+                        failure = Some(err);
+                        break;
+                    }
+                }
+                applied += 1;
+            }
+            Ok(merged.finish(state_manager))
+        });
+
+        for action in &actions[..applied] {
+            self.record(action, &outcome);
+            if let (STATE_UPDATE_EVENT, Ok((state, true))) = (event, &outcome) {
+                self.notify_local_change(Some(action), state);
+            }
+        }
+        if let Some(err) = failure {
+            let failed = Err(err);
+            self.record(&actions[applied], &failed);
+            return failed.map(|(state, _)| state);
+        }
+        outcome.map(|(state, _)| state)
+    }
+
+    // Modify a store with `mutate` and emit the update under `event` if needed.
+    // `action` is the action being applied, if any. The concurrency groups of `grouped`
+    // are held while the store is modified, but not while publishing: watchers may
+    // dispatch actions of the same groups.
+    // Returns the updated state and whether it changed.
+    fn commit<F>(
+        &self,
+        store: &ManagedState,
+        revision: &AtomicU64,
+        event: &str,
+        action: Option<&Action>,
+        grouped: &[Action],
+        mutate: F,
+    ) -> crate::Result<(JsonValue, bool)>
+    where
+        F: FnOnce(&mut dyn RstateManager) -> crate::Result<DispatchOutcome>,
+    {
+        let started = Instant::now();
+        let groups = self.concurrency_groups.enter(grouped)?;
+        let commit = store::commit(
+            store,
+            revision,
+            self.publisher.emit_policy(),
+            action,
+            self.publisher.keeps_previous(),
+            mutate,
+        );
+        drop(groups);
+        let commit = commit?;
+        let elapsed = started.elapsed();
+        if let (STATE_UPDATE_EVENT, Some(history)) = (event, &self.history) {
+            if let Some((revision, state)) = commit.change() {
+                history.record(revision, state);
+            }
+        }
+
+        let emit = self.emit_decision(event, commit.should_emit());
+        self.taps.send(|| TapEvent::Commit {
+            store: event.to_owned(),
+            revision: commit.revision(),
+            changed: commit.changed(),
+            emit,
+            elapsed,
+        });
+        if emit == EmitDecision::Emitted {
+            self.publisher.publish(event, &commit, action)?;
+        }
+        Ok(commit.into_state())
+    }
+
+    // Whether to emit the update of a commit under `event`
+    fn emit_decision(&self, event: &str, should_emit: bool) -> EmitDecision {
+        if !should_emit {
+            return EmitDecision::Skipped;
+        }
+        // Stores held by a transaction emit when it ends
+        if self.held_events.contains(event) {
+            return EmitDecision::Held;
+        }
+        // Nothing is emitted for the app-wide store until it is ready, and inside
+        // `batched` scopes, which emit once when they end
+        if event != STATE_UPDATE_EVENT {
+            EmitDecision::Emitted
+        } else if !self.is_ready() {
+            EmitDecision::NotReady
+        } else if self.batched_scopes.load(Ordering::SeqCst) > 0 {
+            EmitDecision::Batched
+        } else {
+            EmitDecision::Emitted
+        }
+    }
+
+    // Run the action's handler, timing it if enabled
+    fn run(
+        &self,
+        state_manager: &mut dyn RstateManager,
+        action: &Action,
+    ) -> crate::Result<DispatchOutcome> {
+        match &self.timings {
+            Some(timings) => timings.time(action, || state_manager.dispatch(action)),
+            None => state_manager.dispatch(action),
+        }
+    }
+
+    // Run the guards against every action
+    fn check_guards(&self, actions: &[Action]) -> crate::Result<()> {
+        actions
+            .iter()
+            .try_for_each(|action| check_guards(&self.guards, action))
+    }
+
+    // Reject an action received from the frontend if its payload is over the size limit
+    pub(crate) fn check_payload_size(&self, action: &Action) -> crate::Result<()> {
+        check_payload_size(action, self.max_payload_size)
+    }
+
+    // Reject the action if its kind's circuit is open
+    fn check_circuit(&self, action: &Action) -> crate::Result<()> {
+        match &self.breaker {
+            Some(breaker) => breaker.check(action),
+            None => Ok(()),
+        }
+    }
+
+    // Report a circuit that just opened
+    fn circuit_opened(&self, open: CircuitOpen) {
+        log::warn!(
+            target: ACTION_LOG_TARGET,
+            "{} failed {} times in a row, rejecting it for {}ms: {}",
+            open.kind, open.failures, open.cooldown_ms, open.error
+        );
+        if let Err(err) = self.app.emit(crate::CIRCUIT_OPEN_EVENT, &open) {
+            log::warn!(target: ACTION_LOG_TARGET, "circuit open event: {err}");
+        }
+    }
+
+    // Write the action log record for a dispatch, if enabled
+    fn record(&self, action: &Action, result: &crate::Result<(JsonValue, bool)>) {
+        self.vitals.record(action, result);
+        if let Some(open) = self
+            .breaker
+            .as_ref()
+            .and_then(|breaker| breaker.record(action, result))
+        {
+            self.circuit_opened(open);
+        }
+        let result = result.as_ref().map(|(_, changed)| *changed);
+        self.taps.send(|| tap::dispatched(action, result));
+        self.recorder.record(action, result);
+        if let Some(action_log) = &self.action_log {
+            action_log.record(action, result);
+        }
+        if let Some(audit) = &self.audit {
+            audit.record(action, result);
+        }
+    }
+
+    /// Dispatch an action with just a kind (no payload).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().dispatch_kind("INCREMENT")?;
+    /// ```
+    #[inline]
+    pub fn dispatch_kind(&self, kind: impl Into<String>) -> crate::Result<JsonValue> {
+        self.dispatch(Action::new(kind))
+    }
+
+    /// Restore the initial state of the app-wide store by dispatching
+    /// [`RESET_ACTION`](crate::RESET_ACTION), e.g. on logout.
+    ///
+    /// The update is emitted like for any other action, and the reset state is saved
+    /// if the store is persisted.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().reset()?;
+    /// ```
+    #[inline]
+    pub fn reset(&self) -> crate::Result<JsonValue> {
+        self.dispatch_kind(crate::RESET_ACTION)
+    }
+
+    /// Dispatch an action with a typed payload.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().dispatch_with("SET_COUNT", 42)?;
+    /// app.rstate().dispatch_with("ADD_TODO", &todo_item)?;
+    /// ```
+    #[inline]
+    pub fn dispatch_with<T: serde::Serialize>(
+        &self,
+        kind: impl Into<String>,
+        payload: T,
+    ) -> crate::Result<JsonValue> {
+        self.dispatch(Action::with_payload(kind, payload)?)
+    }
+
+    /// Register a state manager.
+    ///
+    /// Use this with [`init_empty`](crate::init_empty) for lazy initialization.
+    /// Fails with [`RstateError::AlreadyRegistered`](crate::RstateError::AlreadyRegistered)
+    /// if a manager is already registered; use
+    /// [`replace_state_manager`](Self::replace_state_manager) to swap it.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let manager = StateBuilder::new(AppState::default()).build();
+    /// app.rstate().register_state_manager(manager)?;
+    /// ```
+    pub fn register_state_manager<S: RstateManager>(&self, state_manager: S) -> crate::Result<()> {
+        self.register_boxed(Box::new(state_manager))
+    }
+
+    /// Build a state manager with `load` on a background thread, and register it once
+    /// built.
+    ///
+    /// Meanwhile, [`is_loading`](Self::is_loading) is `true` and the frontend's
+    /// `get_initial_state` fails with [`RstateError::Loading`](crate::RstateError::Loading).
+    /// The store becomes ready, and emits the
+    /// [`STORE_READY_EVENT`](crate::STORE_READY_EVENT), once the manager is registered.
+    /// A failure or panic of `load` is logged, and another manager can be loaded then.
+    /// Fails if a manager is already registered or loading. See [`Builder::load_state_manager`](crate::Builder::load_state_manager).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// app.rstate().load_state_manager(move || Ok(load_library(&library_path)?.build()))?;
+    /// ```
+    pub fn load_state_manager<S, F>(&self, load: F) -> crate::Result<()>
+    where
+        S: RstateManager,
+        F: FnOnce() -> crate::Result<S> + Send + 'static,
+    {
+        self.load_boxed(crate::boxed_loader(load))
+    }
+
+    // Build and register a boxed state manager, see `load_state_manager`
+    pub(crate) fn load_boxed(&self, load: StateLoader) -> crate::Result<()> {
+        if self.is_registered() || self.loading.swap(true, Ordering::SeqCst) {
+            return Err(crate::RstateError::AlreadyRegistered);
+        }
+        let app = self.app.clone();
+        let spawned = std::thread::Builder::new()
+            .name("rstate-loader".to_owned())
+            .spawn(move || {
+                let rstate = app.rstate();
+                // A panicking loader must not leave the store loading forever
+                let registered = crate::error::catch_panic("state manager loader", || {
+                    load().and_then(|state_manager| rstate.register_boxed(state_manager))
+                });
+                rstate.loading.store(false, Ordering::SeqCst);
+                if let Err(err) = registered {
+                    log::warn!(target: ACTION_LOG_TARGET, "loading the state manager: {err}");
+                }
+            });
+        if let Err(err) = spawned {
+            self.loading.store(false, Ordering::SeqCst);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    // Register a boxed state manager, see `register_state_manager`
+    pub(crate) fn register_boxed(
+        &self,
+        mut state_manager: Box<dyn RstateManager>,
+    ) -> crate::Result<()> {
+        state_manager.set_dispatcher(self.dispatcher(None));
+        state_manager.set_app(Arc::new(self.app.clone()));
+        state_manager.set_save_tap(self.taps.saves_of(STATE_UPDATE_EVENT.to_owned()));
+        self.app_store.register(state_manager)?;
+        self.finish_registration();
+        Ok(())
+    }
+
+    /// Replace the registered state manager, e.g. to switch to another user's store
+    /// on login.
+    ///
+    /// Dispatches already running finish against the previous manager, which is then
+    /// flushed and dropped; its flush error, if any, is returned, with the new manager
+    /// in place. Windows receive the new manager's full state. Registers the manager
+    /// if none is registered.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let manager = StateBuilder::new(UserState::default())
+    ///     .persist(user_dir.join("state.json"))
+    ///     .build();
+    /// app.rstate().replace_state_manager(manager)?;
+    /// ```
+    pub fn replace_state_manager<S: RstateManager>(&self, state_manager: S) -> crate::Result<()> {
+        let mut state_manager: Box<dyn RstateManager> = Box::new(state_manager);
+        state_manager.set_dispatcher(self.dispatcher(None));
+        state_manager.set_app(Arc::new(self.app.clone()));
+        state_manager.set_save_tap(self.taps.saves_of(STATE_UPDATE_EVENT.to_owned()));
+        let Some(previous) = self.app_store.replace(Some(state_manager))? else {
+            self.finish_registration();
+            return Ok(());
+        };
+
+        let flushed = store::lock(&previous).and_then(|mut previous| previous.flush());
+        if self.is_ready() {
+            let revision = self.revision.fetch_add(1, Ordering::SeqCst) + 1;
+            let state = self.get_initial_state()?;
+            let redacted = self.redacted_paths(StoreScope::App, "")?;
+            self.publisher
+                .publish_full(STATE_UPDATE_EVENT, revision, state, &redacted, None)?;
+        }
+        flushed
+    }
+
+    /// Unregister the state manager, e.g. on logout.
+    ///
+    /// The manager is flushed and dropped once dispatches already running finish. Until
+    /// another manager is registered, the app-wide store is not ready: dispatches fail
+    /// with [`RstateError::NotRegistered`](crate::RstateError::NotRegistered), and
+    /// windows keep the last state they received.
+    pub fn unregister_state_manager(&self) -> crate::Result<()> {
+        let previous = self
+            .app_store
+            .replace(None)?
+            .ok_or(crate::RstateError::NotRegistered)?;
+        self.ready.send_replace(false);
+        store::lock(&previous)?.flush()
+    }
+
+    /// Run `f` with the registered state manager, holding the store's lock.
+    ///
+    /// Use it to reach the concrete manager, e.g. the typed API of a
+    /// [`BuiltStateManager`](crate::BuiltStateManager). Changes made this way are
+    /// not emitted.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let counter = app.rstate().with_state_manager(|manager| {
+    ///     manager
+    ///         .downcast_ref::<BuiltStateManager<AppState>>()
+    ///         .map(|manager| manager.with_state(|state| state.counter))
+    /// })?;
+    /// ```
+    pub fn with_state_manager<T>(
+        &self,
+        f: impl FnOnce(&mut dyn RstateManager) -> T,
+    ) -> crate::Result<T> {
+        let state_manager = self.state_manager()?;
+        let mut state_manager = store::lock(&state_manager)?;
+        Ok(f(state_manager.as_mut()))
+    }
+
+    // Dispatcher handing actions back to the app-wide store, or to a window's store
+    pub(crate) fn dispatcher(&self, window: Option<String>) -> Dispatcher {
+        let app = self.app.clone();
+        Arc::new(move |action| match &window {
+            Some(label) => app.rstate().dispatch_to_window(label, action),
+            None => app.rstate().dispatch(action),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "test"))]
+mod tests {
+    use super::*;
+    use crate::StateBuilder;
+    use crate::testing::{assert_state_eq, mock_app};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Serialize, Deserialize, Default)]
+    struct Counter {
+        counter: i64,
+    }
+
+    #[test]
+    fn test_watchers_dispatch_actions_of_the_same_group() {
+        let manager = StateBuilder::new(Counter::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .build();
+        let app = mock_app(
+            crate::Builder::new()
+                .state_manager(manager)
+                .concurrency_group("counter", ["INCREMENT"]),
+        );
+        let handle = app.handle().clone();
+        app.rstate()
+            .watch("counter", move |_old, new| {
+                if *new == json!(1) {
+                    handle.rstate().dispatch(Action::new("INCREMENT")).unwrap();
+                }
+            })
+            .unwrap();
+
+        // A deadlock would never report back
+        let (done, finished) = std::sync::mpsc::channel();
+        let handle = app.handle().clone();
+        std::thread::spawn(move || {
+            let _ = done.send(handle.rstate().dispatch(Action::new("INCREMENT")).is_ok());
+        });
+        assert_eq!(finished.recv_timeout(Duration::from_secs(5)), Ok(true));
+        assert_state_eq(&app, "counter", json!(2));
+    }
+
+    #[test]
+    fn test_panicking_loaders_stop_loading() {
+        let app = mock_app(crate::Builder::new());
+        let wait_loaded = || {
+            let started = Instant::now();
+            while app.rstate().is_loading() {
+                assert!(started.elapsed() < Duration::from_secs(5), "still loading");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        app.rstate()
+            .load_state_manager(|| -> crate::Result<crate::BuiltStateManager<Counter>> {
+                panic!("corrupt library")
+            })
+            .unwrap();
+        wait_loaded();
+        assert!(!app.rstate().is_registered());
+
+        app.rstate()
+            .load_state_manager(|| Ok(StateBuilder::new(Counter { counter: 3 }).build()))
+            .unwrap();
+        wait_loaded();
+        assert_state_eq(&app, "counter", json!(3));
+    }
+
+    #[test]
+    fn test_persisted_history_hides_payloads_of_redacting_stores() {
+        #[derive(Serialize, Deserialize, Default)]
+        struct Session {
+            token: String,
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("rstate-redacted-log-{}.jsonl", std::process::id()));
+        let manager = StateBuilder::new(Session::default())
+            .on("LOGIN", |state, action| {
+                state.token = action.require_payload()?;
+                Ok(())
+            })
+            .redact("token")
+            .event_sourced(crate::EventLog::new(&path))
+            .build();
+        let app = mock_app(crate::Builder::new().state_manager(manager));
+        app.rstate()
+            .dispatch(Action::with_payload("LOGIN", "secret").unwrap())
+            .unwrap();
+
+        let logged = app.rstate().persisted_history(0, 10).unwrap();
+        assert_eq!(logged.entries[0].action.payload, Some(json!("secret")));
+        let sent = app.rstate().get_redacted_persisted_history(0, 10).unwrap();
+        assert_eq!(sent.entries[0].action.payload, Some(json!(REDACTED)));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! Scheduled (delayed and recurring) actions.
+//!
+//! [`Rstate::dispatch_after`](crate::Rstate::dispatch_after) dispatches an action once
+//! a delay has passed, on the Tauri async runtime, e.g. to dismiss a notification or
+//! expire a session kept in the state.
+//! [`Rstate::dispatch_every`](crate::Rstate::dispatch_every) dispatches an action
+//! periodically, e.g. to poll a server or refresh a clock, instead of a hand-rolled
+//! thread. The returned [`ScheduleHandle`] cancels them.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tauri::async_runtime::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+
+// Shortest period of a recurring action; shorter ones, zero included, are raised to it
+pub(crate) const MIN_PERIOD: Duration = Duration::from_millis(1);
+
+/// Handle to an action scheduled with [`Rstate::dispatch_after`](crate::Rstate::dispatch_after)
+/// or [`Rstate::dispatch_every`](crate::Rstate::dispatch_every).
+///
+/// Dropping the handle doesn't cancel the action.
+#[derive(Debug)]
+pub struct ScheduleHandle {
+    task: JoinHandle<()>,
+    // Set by whichever comes first: the delay passing, or the cancellation. Recurring
+    // actions only settle when cancelled.
+    settled: Arc<AtomicBool>,
+}
+
+impl ScheduleHandle {
+    /// Cancel the action, or stop the ticker of a recurring one. Returns `false` if a
+    /// delayed action was already dispatched (or being so), or if it was already
+    /// cancelled.
+    pub fn cancel(&self) -> bool {
+        if self.settled.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        self.task.abort();
+        true
+    }
+
+    /// Whether the action is still waiting for its delay to pass, or still recurring.
+    pub fn is_pending(&self) -> bool {
+        !self.settled.load(Ordering::SeqCst)
+    }
+}
+
+// Run `fire` once `delay` has passed, unless cancelled first
+pub(crate) fn schedule<F>(delay: Duration, fire: F) -> ScheduleHandle
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let settled = Arc::new(AtomicBool::new(false));
+    let due = settled.clone();
+    let task = tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(delay).await;
+        if !due.swap(true, Ordering::SeqCst) {
+            fire.await;
+        }
+    });
+    ScheduleHandle { task, settled }
+}
+
+// Run `tick` every `period`, starting one period from now, until cancelled. A tick
+// running late delays the next ones rather than piling them up. `period` is at least
+// `MIN_PERIOD`, tokio panics on a zero one.
+pub(crate) fn schedule_every<F, T>(period: Duration, mut tick: F) -> ScheduleHandle
+where
+    F: FnMut() -> T + Send + 'static,
+    T: Future<Output = ()> + Send + 'static,
+{
+    let period = period.max(MIN_PERIOD);
+    let settled = Arc::new(AtomicBool::new(false));
+    let stopped = settled.clone();
+    let task = tauri::async_runtime::spawn(async move {
+        let mut ticks = tokio::time::interval_at(Instant::now() + period, period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            tick().await;
+        }
+    });
+    ScheduleHandle { task, settled }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_scheduled_actions_fire_unless_cancelled() {
+        let (sender, mut fired) = mpsc::unbounded_channel();
+        let schedule_send = |delay, value| {
+            let sender = sender.clone();
+            schedule(Duration::from_millis(delay), async move {
+                sender.send(value).unwrap();
+            })
+        };
+
+        let cancelled = schedule_send(20, "cancelled");
+        let kept = schedule_send(10, "kept");
+        assert!(cancelled.is_pending());
+        assert!(cancelled.cancel());
+        assert!(!cancelled.is_pending());
+
+        let value = tauri::async_runtime::block_on(fired.recv());
+        assert_eq!(value, Some("kept"));
+        assert!(!kept.is_pending());
+        assert!(!kept.cancel());
+        tauri::async_runtime::block_on(async {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+        });
+        assert!(fired.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_recurring_actions_tick_until_cancelled() {
+        let (sender, mut ticks) = mpsc::unbounded_channel();
+        let mut count = 0;
+        let ticker = schedule_every(Duration::from_millis(5), move || {
+            count += 1;
+            let sender = sender.clone();
+            async move {
+                let _ = sender.send(count);
+            }
+        });
+
+        let first = tauri::async_runtime::block_on(async {
+            (ticks.recv().await, ticks.recv().await, ticks.recv().await)
+        });
+        assert_eq!(first, (Some(1), Some(2), Some(3)));
+        assert!(ticker.is_pending());
+        assert!(ticker.cancel());
+        assert!(!ticker.cancel());
+
+        // The sender is dropped along with the stopped ticker
+        let rest = tauri::async_runtime::block_on(async {
+            let mut rest = Vec::new();
+            while let Some(tick) = ticks.recv().await {
+                rest.push(tick);
+            }
+            rest
+        });
+        assert!(rest.len() <= 1);
+    }
+
+    #[test]
+    fn test_zero_period_is_clamped() {
+        let (sender, mut ticks) = mpsc::unbounded_channel();
+        let ticker = schedule_every(Duration::ZERO, move || {
+            let sender = sender.clone();
+            async move {
+                let _ = sender.send(());
+            }
+        });
+
+        let ticked = tauri::async_runtime::block_on(async {
+            ticks.recv().await.is_some() && ticks.recv().await.is_some()
+        });
+        assert!(ticked);
+        assert!(ticker.cancel());
+    }
+}
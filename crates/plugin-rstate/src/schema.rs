@@ -0,0 +1,133 @@
+//! State schema fingerprints.
+//!
+//! A persisted state, the backend's state type and the frontend's generated types
+//! can drift apart: a field is renamed without a migration, or the frontend bundle is
+//! older than the backend. With [`StateBuilder::schema`](crate::StateBuilder::schema)
+//! (`schema` feature, for types deriving `JsonSchema`) or
+//! [`StateBuilder::schema_fingerprint`](crate::StateBuilder::schema_fingerprint), the
+//! manager knows a fingerprint of its state's schema:
+//!
+//! - it is saved with the persisted state, under [`SCHEMA_KEY`], and a persisted state
+//!   saved with another fingerprint (and not migrated since) is reported at startup;
+//! - it is sent to the frontend in the [`STORE_READY_EVENT`], and returned by the
+//!   `get_schema` command, to compare with the fingerprint its types were generated
+//!   from.
+//!
+//! ```json
+//! { "schema": { "fingerprint": "9f3c2a1b", "persisted": "04d2e8f7" } }
+//! ```
+
+use serde::Serialize;
+
+use crate::models::JsonValue;
+
+/// Key under which the schema fingerprint of the persisted state is stored, next to
+/// its fields.
+///
+/// It is only part of the persisted value, never of the state itself.
+pub const SCHEMA_KEY: &str = "@@schema";
+
+/// Event emitted when the app-wide store becomes ready, with a [`StoreReady`] payload.
+pub const STORE_READY_EVENT: &str = "rstate://ready";
+
+/// Payload of the [`STORE_READY_EVENT`].
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreReady {
+    /// Schema fingerprint of the state, if the manager has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<SchemaFingerprint>,
+}
+
+/// Schema fingerprint of a store's state.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaFingerprint {
+    /// Fingerprint of the state type's schema
+    pub fingerprint: String,
+    /// Fingerprint recorded in the persisted state, if it doesn't match and the
+    /// persisted state wasn't migrated from an older version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persisted: Option<String>,
+}
+
+/// Fingerprint of a JSON schema: a checksum of its canonical form, so object key
+/// order doesn't matter.
+pub fn schema_fingerprint(schema: &JsonValue) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    hash_canonical(schema, &mut hasher);
+    format!("{:08x}", hasher.finalize())
+}
+
+// Feed `value` to the hasher with object keys sorted
+fn hash_canonical(value: &JsonValue, hasher: &mut crc32fast::Hasher) {
+    match value {
+        JsonValue::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort_unstable();
+            hasher.update(b"{");
+            for key in keys {
+                hasher.update(JsonValue::from(key.as_str()).to_string().as_bytes());
+                hasher.update(b":");
+                hash_canonical(&fields[key], hasher);
+                hasher.update(b",");
+            }
+            hasher.update(b"}");
+        }
+        JsonValue::Array(items) => {
+            hasher.update(b"[");
+            for item in items {
+                hash_canonical(item, hasher);
+                hasher.update(b",");
+            }
+            hasher.update(b"]");
+        }
+        scalar => hasher.update(scalar.to_string().as_bytes()),
+    }
+}
+
+/// Fingerprint of `T`'s JSON schema.
+#[cfg(feature = "schema")]
+pub fn schema_fingerprint_of<T: schemars::JsonSchema>() -> String {
+    let schema = schemars::schema_for!(T);
+    schema_fingerprint(&serde_json::to_value(schema).unwrap_or_default())
+}
+
+// Take the fingerprint recorded in a loaded state, if any
+pub(crate) fn take(persisted: &mut JsonValue) -> Option<String> {
+    match persisted.as_object_mut()?.remove(SCHEMA_KEY)? {
+        JsonValue::String(fingerprint) => Some(fingerprint),
+        _ => None,
+    }
+}
+
+// Record `fingerprint` in a state about to be persisted
+pub(crate) fn stamp(state: &mut JsonValue, fingerprint: &str) {
+    if let JsonValue::Object(fields) = state {
+        fields.insert(SCHEMA_KEY.to_owned(), fingerprint.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_fingerprint_ignores_key_order() {
+        let schema = json!({ "type": "object", "properties": { "a": 1, "b": [true, null] } });
+        let reordered = json!({ "properties": { "b": [true, null], "a": 1 }, "type": "object" });
+        assert_eq!(schema_fingerprint(&schema), schema_fingerprint(&reordered));
+        assert_eq!(schema_fingerprint(&schema).len(), 8);
+        assert_ne!(
+            schema_fingerprint(&schema),
+            schema_fingerprint(&json!({ "type": "object", "properties": { "a": 1 } }))
+        );
+
+        let mut state = json!({ "a": 1 });
+        stamp(&mut state, "abc");
+        assert_eq!(take(&mut state).as_deref(), Some("abc"));
+        assert_eq!(state, json!({ "a": 1 }));
+        assert_eq!(take(&mut state), None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Memoized selectors.
+//!
+//! A selector registered with [`StateBuilder::selector`](crate::StateBuilder::selector)
+//! is only computed once per version of the state: until the next change, repeated
+//! [`Rstate::select`](crate::Rstate::select) calls and `get_selector` commands get the
+//! cached value back, without recomputing or serializing it again.
+//! [`Rstate::selector_stats`](crate::Rstate::selector_stats) reports how often each
+//! selector hit its cache, to find the ones recomputed too often (e.g. derived from
+//! a part of the state changing on every dispatch).
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Result;
+use crate::models::JsonValue;
+
+/// Cache metrics of a selector, see [`Rstate::selector_stats`](crate::Rstate::selector_stats).
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectorStats {
+    /// The selector's name
+    pub name: String,
+    /// Number of reads served from the cache
+    pub hits: u64,
+    /// Number of reads computing the value
+    pub misses: u64,
+}
+
+#[derive(Default)]
+struct Entry {
+    // The value, and the version of the state it was computed from
+    cached: Option<(u64, JsonValue)>,
+    hits: u64,
+    misses: u64,
+}
+
+// The last value of every selector, for the version of the state it was computed from
+#[derive(Default)]
+pub(crate) struct SelectorCache {
+    // Bumped by every change of the state
+    version: AtomicU64,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl SelectorCache {
+    // Drop every cached value, as the state changed
+    pub(crate) fn invalidate(&self) {
+        self.version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // The value of the selector `name`, computed with `compute` unless cached for the
+    // current version. The state must not change until this returns.
+    pub(crate) fn get_or_compute(
+        &self,
+        name: &str,
+        compute: impl FnOnce() -> Result<JsonValue>,
+    ) -> Result<JsonValue> {
+        let version = self.version.load(Ordering::SeqCst);
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+        let entry = entries.entry(name.to_owned()).or_default();
+        if let Some((cached, value)) = &entry.cached
+            && *cached == version
+        {
+            entry.hits += 1;
+            return Ok(value.clone());
+        }
+        entry.misses += 1;
+        let value = compute()?;
+        entry.cached = Some((version, value.clone()));
+        Ok(value)
+    }
+
+    // The metrics of every selector read so far, by name
+    pub(crate) fn stats(&self) -> Vec<SelectorStats> {
+        let Ok(entries) = self.entries.lock() else {
+            return Vec::new();
+        };
+        let mut stats: Vec<_> = entries
+            .iter()
+            .map(|(name, entry)| SelectorStats {
+                name: name.clone(),
+                hits: entry.hits,
+                misses: entry.misses,
+            })
+            .collect();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selectors_are_computed_once_per_version() {
+        let cache = SelectorCache::default();
+        let mut computed = 0;
+        let mut read = |cache: &SelectorCache| {
+            cache
+                .get_or_compute("count", || {
+                    computed += 1;
+                    Ok(JsonValue::from(computed))
+                })
+                .unwrap()
+        };
+
+        assert_eq!(read(&cache), 1);
+        assert_eq!(read(&cache), 1);
+        cache.invalidate();
+        assert_eq!(read(&cache), 2);
+        assert_eq!(
+            cache.stats(),
+            [SelectorStats {
+                name: "count".into(),
+                hits: 1,
+                misses: 2,
+            }]
+        );
+
+        // Failures aren't cached
+        assert!(
+            cache
+                .get_or_compute("broken", || Err(crate::RstateError::state("nope")))
+                .is_err()
+        );
+        assert_eq!(cache.stats()[0].misses, 1);
+    }
+}
@@ -0,0 +1,279 @@
+//! Slices of the app-wide state with their own lock.
+//!
+//! Slices mounted with [`StateBuilder::slice`](crate::StateBuilder::slice) live in the
+//! app-wide store, behind its single lock: a slow reducer in one slice holds up every
+//! other dispatch and read. A slice registered with
+//! [`Rstate::register_slice`](crate::Rstate::register_slice) is a separate store
+//! instead, with its own lock and revision, so it doesn't block the rest of the state:
+//!
+//! ```rust,ignore
+//! let import = StateBuilder::new(ImportState::default())
+//!     .on("RUN", |state, action| run_import(state, action.require_payload()?));
+//! app.rstate().register_slice("import", import.build())?;
+//!
+//! // Runs in the slice's store, as `RUN`; reads of "ui.theme" don't wait for it
+//! app.rstate().dispatch_with("import/RUN", files)?;
+//! ```
+//!
+//! Actions whose kind is prefixed with `"{key}/"` go to the slice, without the prefix,
+//! and reads of `key` (or a key under it) are served by it. The full state (from
+//! `get_initial_state`) includes every slice under its key. A slice's updates are
+//! emitted under [`slice_event_name`] rather than with the app-wide state.
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, RwLock};
+
+use crate::models::{Action, ActionKinds, Dispatcher, JsonValue, RstateManager};
+use crate::{ManagedState, Result, RstateError};
+
+/// Event name used for state updates of the slice registered under `key`.
+pub fn slice_event_name(key: &str) -> String {
+    format!("rstate://slice-update/{key}")
+}
+
+// A slice's store along with its revision counter
+pub(crate) struct SliceStore {
+    pub(crate) key: String,
+    pub(crate) state: ManagedState,
+    pub(crate) revision: AtomicU64,
+}
+
+impl SliceStore {
+    pub(crate) fn event(&self) -> String {
+        slice_event_name(&self.key)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct SliceStores {
+    stores: RwLock<HashMap<String, Arc<SliceStore>>>,
+}
+
+impl SliceStores {
+    pub(crate) fn insert(&self, key: String, state_manager: Box<dyn RstateManager>) -> Result<()> {
+        if key.is_empty() || key.contains(['.', '/']) {
+            return Err(RstateError::state(format!("invalid slice key: '{key}'")));
+        }
+        let mut stores = self
+            .stores
+            .write()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
+        if stores.contains_key(&key) {
+            return Err(RstateError::state(format!(
+                "slice '{key}' is already registered"
+            )));
+        }
+        let store = SliceStore {
+            key: key.clone(),
+            state: RwLock::new(state_manager),
+            revision: AtomicU64::new(0),
+        };
+        stores.insert(key, Arc::new(store));
+        Ok(())
+    }
+
+    fn find(&self, key: &str) -> Option<Arc<SliceStore>> {
+        self.stores.read().ok()?.get(key).cloned()
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Result<Arc<SliceStore>> {
+        self.find(key)
+            .ok_or_else(|| RstateError::state(format!("slice '{key}' is not registered")))
+    }
+
+    // The slice handling `action`, with the action as the slice sees it
+    pub(crate) fn route(&self, action: &Action) -> Option<(Arc<SliceStore>, Action)> {
+        let (key, kind) = action.kind.split_once('/')?;
+        let store = self.find(key)?;
+        let mut action = action.clone();
+        action.kind = kind.to_owned();
+        Some((store, action))
+    }
+
+    // The slice serving the dot-notation `path`, with the path inside the slice
+    pub(crate) fn resolve<'a>(&self, path: &'a str) -> Option<(Arc<SliceStore>, &'a str)> {
+        let (key, rest) = path.split_once('.').unwrap_or((path, ""));
+        Some((self.find(key)?, rest))
+    }
+
+    // The slice computing the selector `name`, named `key/selector`, with the
+    // selector's name inside the slice
+    pub(crate) fn resolve_selector<'a>(&self, name: &'a str) -> Option<(Arc<SliceStore>, &'a str)> {
+        let (key, selector) = name.split_once('/')?;
+        Some((self.find(key)?, selector))
+    }
+
+    // The slice handling `actions`, if they are all its actions. A batch can't span
+    // several stores.
+    pub(crate) fn route_many(
+        &self,
+        actions: &[Action],
+    ) -> Result<Option<(Arc<SliceStore>, Vec<Action>)>> {
+        let mut routed = actions.iter().map(|action| self.route(action));
+        let Some(Some((store, first))) = routed.next() else {
+            if actions.iter().any(|action| self.route(action).is_some()) {
+                return Err(RstateError::state("a batch can't span several slices"));
+            }
+            return Ok(None);
+        };
+        let mut inner = vec![first];
+        for route in routed {
+            match route {
+                Some((other, action)) if Arc::ptr_eq(&store, &other) => inner.push(action),
+                _ => return Err(RstateError::state("a batch can't span several slices")),
+            }
+        }
+        Ok(Some((store, inner)))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.stores.read().is_ok_and(|stores| stores.is_empty())
+    }
+
+    // Add the state of every slice to the app-wide `state`
+    pub(crate) fn insert_into(&self, state: &mut JsonValue) -> Result<()> {
+        let stores: Vec<_> = match self.stores.read() {
+            Ok(stores) if !stores.is_empty() => stores.values().cloned().collect(),
+            _ => return Ok(()),
+        };
+        if let JsonValue::Object(state) = state {
+            for store in stores {
+                let slice = crate::store::read_state(&store.state)?;
+                state.insert(store.key.clone(), slice);
+            }
+        }
+        Ok(())
+    }
+
+    // The redacted paths of every slice, relative to the app-wide state
+    pub(crate) fn redacted_paths(&self) -> Result<Vec<String>> {
+        let stores: Vec<_> = match self.stores.read() {
+            Ok(stores) => stores.values().cloned().collect(),
+            Err(e) => return Err(RstateError::LockPoisoned(e.to_string())),
+        };
+        let mut paths = Vec::new();
+        for store in stores {
+            let redacted = crate::store::redacted_paths(&store.state)?;
+            paths.extend(crate::redaction::prefixed(&store.key, &redacted));
+        }
+        Ok(paths)
+    }
+
+    // Add the action kinds of every slice to `kinds`, prefixed with its key. A slice
+    // whose manager can't list them handles any kind under its key.
+    pub(crate) fn add_action_kinds(&self, kinds: &mut ActionKinds) -> Result<()> {
+        let stores: Vec<_> = match self.stores.read() {
+            Ok(stores) => stores.values().cloned().collect(),
+            Err(e) => return Err(RstateError::LockPoisoned(e.to_string())),
+        };
+        for store in stores {
+            let Ok(slice) = crate::store::action_kinds(&store.state) else {
+                kinds.default_slices.push(store.key.clone());
+                continue;
+            };
+            let key = &store.key;
+            kinds
+                .kinds
+                .extend(slice.kinds.iter().map(|kind| format!("{key}/{kind}")));
+            kinds.default_slices.extend(
+                slice
+                    .default_slices
+                    .iter()
+                    .map(|inner| format!("{key}/{inner}")),
+            );
+            if slice.default_handler {
+                kinds.default_slices.push(key.clone());
+            }
+        }
+        kinds.kinds.sort();
+        kinds.default_slices.sort();
+        Ok(())
+    }
+
+    // Flush every slice
+    pub(crate) fn flush(&self) -> Result<()> {
+        let stores: Vec<_> = match self.stores.read() {
+            Ok(stores) => stores.values().cloned().collect(),
+            Err(e) => return Err(RstateError::LockPoisoned(e.to_string())),
+        };
+        stores
+            .iter()
+            .try_for_each(|store| crate::store::lock(&store.state)?.flush())
+    }
+}
+
+// Dispatcher handing the slice `key`'s actions back through `dispatcher`, prefixed
+pub(crate) fn slice_dispatcher(key: &str, dispatcher: Dispatcher) -> Dispatcher {
+    let prefix = format!("{key}/");
+    Arc::new(move |mut action| {
+        action.kind.insert_str(0, &prefix);
+        dispatcher(action)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::DispatchOutcome;
+    use serde_json::json;
+
+    struct Value(JsonValue);
+
+    impl RstateManager for Value {
+        fn get_initial_state(&self) -> JsonValue {
+            self.0.clone()
+        }
+
+        fn dispatch(&mut self, _action: &Action) -> Result<DispatchOutcome> {
+            Ok(DispatchOutcome::unchanged(self.0.clone()))
+        }
+    }
+
+    #[test]
+    fn test_slices_route_actions_and_paths() {
+        let slices = SliceStores::default();
+        slices
+            .insert("import".into(), Box::new(Value(json!({ "progress": 3 }))))
+            .unwrap();
+        assert!(
+            slices
+                .insert("import".into(), Box::new(Value(json!({}))))
+                .is_err()
+        );
+        assert!(
+            slices
+                .insert("ui.theme".into(), Box::new(Value(json!({}))))
+                .is_err()
+        );
+
+        let (store, action) = slices.route(&Action::new("import/RUN")).unwrap();
+        assert_eq!(
+            (store.key.as_str(), action.kind.as_str()),
+            ("import", "RUN")
+        );
+        assert!(slices.route(&Action::new("todos/ADD")).is_none());
+        assert!(slices.route(&Action::new("INCREMENT")).is_none());
+        let (_, batch) = slices
+            .route_many(&[Action::new("import/RUN"), Action::new("import/STOP")])
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch[1].kind, "STOP");
+        assert!(
+            slices
+                .route_many(&[Action::new("INCREMENT"), Action::new("import/RUN")])
+                .is_err()
+        );
+
+        let (_, rest) = slices.resolve("import.progress").unwrap();
+        assert_eq!(rest, "progress");
+        assert!(slices.resolve("ui.theme").is_none());
+
+        let mut state = json!({ "ui": { "theme": "dark" } });
+        slices.insert_into(&mut state).unwrap();
+        assert_eq!(
+            state,
+            json!({ "ui": { "theme": "dark" }, "import": { "progress": 3 } })
+        );
+    }
+}
@@ -0,0 +1,446 @@
+//! SQLite storage backend.
+//!
+//! [`SqliteBackend`] keeps a state in a table of a SQLite database, one row per
+//! top-level key. Saves only rewrite the rows whose subtree changed, all in one
+//! transaction, so a crash mid-save leaves the previous state intact. Single keys can
+//! be loaded on their own with [`SqliteBackend::load_key`], without reading the rest
+//! of a large state:
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::{SqliteBackend, StateBuilder};
+//!
+//! let manager = StateBuilder::new(AppState::default())
+//!     .persist_with(SqliteBackend::new(data_dir.join("state.db")).table("app"))
+//!     .build();
+//! ```
+//!
+//! The backend links the system SQLite library (`libsqlite3`), and is enabled with
+//! the `sqlite` feature. The action log of an event-sourced store stays in its own
+//! file, see [`EventLog`](crate::EventLog).
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::{CStr, CString, c_char, c_int, c_uchar};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::models::JsonValue;
+use crate::persistence::StorageBackend;
+use crate::{Result, RstateError};
+
+// Row of a state that isn't an object, stored whole
+const VALUE_KEY: &str = "@@value";
+
+// How long to wait for another connection to release the database
+const BUSY_TIMEOUT_MS: c_int = 5000;
+
+/// A backend storing the state in a SQLite database, see the [module docs](self).
+pub struct SqliteBackend {
+    path: PathBuf,
+    table: String,
+    // Opened on first use, so creating the backend never fails
+    connection: Mutex<Option<Connection>>,
+    // Hash of every row as last written or loaded, by key
+    written: Mutex<HashMap<String, u64>>,
+}
+
+impl SqliteBackend {
+    /// Create a backend for the database at `path`, storing the state in the table
+    /// `state`. The database and its parent directories are created on first use.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            table: "state".to_owned(),
+            connection: Mutex::default(),
+            written: Mutex::default(),
+        }
+    }
+
+    /// Store the state in the table `name`, e.g. to keep several stores in one
+    /// database.
+    #[must_use]
+    pub fn table(mut self, name: impl Into<String>) -> Self {
+        self.table = name.into();
+        self
+    }
+
+    /// The path of the database.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the persisted value of the top-level key `key` alone, or `None` if it
+    /// wasn't persisted.
+    pub fn load_key(&self, key: &str) -> Result<Option<JsonValue>> {
+        let sql = format!("SELECT value FROM {} WHERE key = ?1", self.quoted_table());
+        let mut rows = Vec::new();
+        self.with_connection(|connection| {
+            connection.query(&sql, &[key], |row| rows.push(row.text(0)))
+        })?;
+        rows.pop().map(|value| parse(&value)).transpose()
+    }
+
+    fn quoted_table(&self) -> String {
+        format!("\"{}\"", self.table.replace('"', "\"\""))
+    }
+
+    // Run `f` on the connection, opening the database and creating the table first
+    // if needed
+    fn with_connection<T>(&self, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        let mut connection = self
+            .connection
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
+        if connection.is_none() {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let opened = Connection::open(&self.path)?;
+            opened.execute("PRAGMA journal_mode = WAL", &[])?;
+            opened.execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+                    self.quoted_table()
+                ),
+                &[],
+            )?;
+            *connection = Some(opened);
+        }
+        f(connection.as_ref().expect("the connection was just opened"))
+    }
+
+    fn written(&self) -> Result<MutexGuard<'_, HashMap<String, u64>>> {
+        self.written
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn load(&self) -> Result<Option<JsonValue>> {
+        let sql = format!("SELECT key, value FROM {}", self.quoted_table());
+        let mut rows = Vec::new();
+        self.with_connection(|connection| {
+            connection.query(&sql, &[], |row| rows.push((row.text(0), row.text(1))))
+        })?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        if let Some((_, value)) = rows.iter().find(|(key, _)| key == VALUE_KEY) {
+            return parse(value).map(Some);
+        }
+
+        let mut written = self.written()?;
+        let mut state = serde_json::Map::new();
+        for (key, value) in rows {
+            written.insert(key.clone(), hash_row(&value));
+            state.insert(key, parse(&value)?);
+        }
+        Ok(Some(JsonValue::Object(state)))
+    }
+
+    fn save(&self, value: &JsonValue) -> Result<()> {
+        let rows = match value {
+            JsonValue::Object(state) => state
+                .iter()
+                .map(|(key, subtree)| (key.clone(), subtree.to_string()))
+                .collect(),
+            _ => vec![(VALUE_KEY.to_owned(), value.to_string())],
+        };
+        let table = self.quoted_table();
+        let upsert = format!(
+            "INSERT INTO {table} (key, value) VALUES (?1, ?2) \
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value"
+        );
+
+        let mut written = self.written()?;
+        let changed: Vec<_> = rows
+            .iter()
+            .filter(|(key, value)| written.get(key) != Some(&hash_row(value)))
+            .collect();
+        self.with_connection(|connection| {
+            connection.transaction(|| {
+                let mut stored = Vec::new();
+                connection.query(&format!("SELECT key FROM {table}"), &[], |row| {
+                    stored.push(row.text(0))
+                })?;
+                for key in stored {
+                    if !rows.iter().any(|(kept, _)| *kept == key) {
+                        connection
+                            .execute(&format!("DELETE FROM {table} WHERE key = ?1"), &[&key])?;
+                    }
+                }
+                for (key, value) in &changed {
+                    connection.execute(&upsert, &[key, value])?;
+                }
+                Ok(())
+            })
+        })?;
+
+        // Only remember what was committed
+        *written = rows
+            .iter()
+            .map(|(key, value)| (key.clone(), hash_row(value)))
+            .collect();
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        let sql = format!("DELETE FROM {}", self.quoted_table());
+        self.with_connection(|connection| connection.execute(&sql, &[]))?;
+        self.written()?.clear();
+        Ok(())
+    }
+}
+
+fn parse(value: &str) -> Result<JsonValue> {
+    serde_json::from_str(value).map_err(|e| RstateError::serialization(e.to_string()))
+}
+
+fn hash_row(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// An open database. SQLite is opened in serialized mode, so it can move between
+// threads; the backend only uses it under its mutex anyway.
+struct Connection(*mut ffi::Sqlite3);
+
+// SAFETY: the connection is opened with `SQLITE_OPEN_FULLMUTEX`
+unsafe impl Send for Connection {}
+
+impl Connection {
+    fn open(path: &Path) -> Result<Self> {
+        let path = CString::new(path.to_string_lossy().into_owned())
+            .map_err(|e| RstateError::state(e.to_string()))?;
+        let mut db = ptr::null_mut();
+        let flags =
+            ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE | ffi::SQLITE_OPEN_FULLMUTEX;
+        // SAFETY: `path` is NUL-terminated and `db` is a valid out pointer
+        let code = unsafe { ffi::sqlite3_open_v2(path.as_ptr(), &mut db, flags, ptr::null()) };
+        // Even a failed open allocates a handle (unless out of memory), closed on drop
+        let connection = Self(db);
+        if code != ffi::SQLITE_OK {
+            return Err(connection.error());
+        }
+        // SAFETY: `db` is an open connection
+        unsafe { ffi::sqlite3_busy_timeout(db, BUSY_TIMEOUT_MS) };
+        Ok(connection)
+    }
+
+    // Run `sql`, binding `params` to `?1`, `?2`...
+    fn execute(&self, sql: &str, params: &[&str]) -> Result<()> {
+        self.query(sql, params, |_| {})
+    }
+
+    // Run `sql`, binding `params` to `?1`, `?2`..., and pass every resulting row to `f`
+    fn query(&self, sql: &str, params: &[&str], mut f: impl FnMut(&Row<'_>)) -> Result<()> {
+        let statement = Statement::prepare(self, sql)?;
+        for (index, param) in (1..).zip(params) {
+            statement.bind(index, param)?;
+        }
+        while statement.step()? {
+            f(&Row(&statement));
+        }
+        Ok(())
+    }
+
+    // Run `f` in a transaction, committed if it succeeds and rolled back otherwise
+    fn transaction(&self, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        self.execute("BEGIN IMMEDIATE", &[])?;
+        match f().and_then(|()| self.execute("COMMIT", &[])) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let _ = self.execute("ROLLBACK", &[]);
+                Err(err)
+            }
+        }
+    }
+
+    fn error(&self) -> RstateError {
+        if self.0.is_null() {
+            return RstateError::state("sqlite: out of memory");
+        }
+        // SAFETY: the handle is valid, and the message is a NUL-terminated string
+        // owned by SQLite, copied before any other call
+        let message = unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.0)) };
+        RstateError::state(format!("sqlite: {}", message.to_string_lossy()))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: every statement is finalized before its connection can be dropped.
+        // Closing a null handle is a no-op.
+        unsafe { ffi::sqlite3_close(self.0) };
+    }
+}
+
+struct Statement<'c> {
+    connection: &'c Connection,
+    raw: *mut ffi::Sqlite3Stmt,
+}
+
+impl<'c> Statement<'c> {
+    fn prepare(connection: &'c Connection, sql: &str) -> Result<Self> {
+        let mut raw = ptr::null_mut();
+        let length = c_int::try_from(sql.len()).map_err(|e| RstateError::state(e.to_string()))?;
+        // SAFETY: `sql` is valid for `length` bytes and `raw` is a valid out pointer
+        let code = unsafe {
+            ffi::sqlite3_prepare_v2(
+                connection.0,
+                sql.as_ptr().cast(),
+                length,
+                &mut raw,
+                ptr::null_mut(),
+            )
+        };
+        if code != ffi::SQLITE_OK {
+            return Err(connection.error());
+        }
+        Ok(Self { connection, raw })
+    }
+
+    fn bind(&self, index: c_int, text: &str) -> Result<()> {
+        let length = c_int::try_from(text.len()).map_err(|e| RstateError::state(e.to_string()))?;
+        // SAFETY: `text` is valid for `length` bytes, and SQLite copies it
+        let code = unsafe {
+            ffi::sqlite3_bind_text(
+                self.raw,
+                index,
+                text.as_ptr().cast(),
+                length,
+                ffi::SQLITE_TRANSIENT,
+            )
+        };
+        if code != ffi::SQLITE_OK {
+            return Err(self.connection.error());
+        }
+        Ok(())
+    }
+
+    // Advance to the next row, `false` once there are none left
+    fn step(&self) -> Result<bool> {
+        // SAFETY: the statement is prepared and not finalized
+        match unsafe { ffi::sqlite3_step(self.raw) } {
+            ffi::SQLITE_ROW => Ok(true),
+            ffi::SQLITE_DONE => Ok(false),
+            _ => Err(self.connection.error()),
+        }
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the statement is prepared, and finalized only here
+        unsafe { ffi::sqlite3_finalize(self.raw) };
+    }
+}
+
+// The current row of a statement
+struct Row<'s>(&'s Statement<'s>);
+
+impl Row<'_> {
+    // The text of the column `index`, empty if it is NULL
+    fn text(&self, index: c_int) -> String {
+        // SAFETY: the statement points at a row. The text is read before the length,
+        // as SQLite documents, and stays valid until the next step.
+        unsafe {
+            let text = ffi::sqlite3_column_text(self.0.raw, index);
+            if text.is_null() {
+                return String::new();
+            }
+            let length = ffi::sqlite3_column_bytes(self.0.raw, index);
+            let bytes = std::slice::from_raw_parts(text, usize::try_from(length).unwrap_or(0));
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+    }
+}
+
+// The parts of the SQLite C API the backend uses
+mod ffi {
+    use super::{c_char, c_int, c_uchar};
+
+    pub(super) enum Sqlite3 {}
+    pub(super) enum Sqlite3Stmt {}
+
+    pub(super) const SQLITE_OK: c_int = 0;
+    pub(super) const SQLITE_ROW: c_int = 100;
+    pub(super) const SQLITE_DONE: c_int = 101;
+    pub(super) const SQLITE_OPEN_READWRITE: c_int = 0x0000_0002;
+    pub(super) const SQLITE_OPEN_CREATE: c_int = 0x0000_0004;
+    pub(super) const SQLITE_OPEN_FULLMUTEX: c_int = 0x0001_0000;
+    // Destructor telling SQLite to copy bound values
+    pub(super) const SQLITE_TRANSIENT: isize = -1;
+
+    #[link(name = "sqlite3")]
+    unsafe extern "C" {
+        pub(super) fn sqlite3_open_v2(
+            filename: *const c_char,
+            db: *mut *mut Sqlite3,
+            flags: c_int,
+            vfs: *const c_char,
+        ) -> c_int;
+        pub(super) fn sqlite3_close(db: *mut Sqlite3) -> c_int;
+        pub(super) fn sqlite3_busy_timeout(db: *mut Sqlite3, ms: c_int) -> c_int;
+        pub(super) fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+        pub(super) fn sqlite3_prepare_v2(
+            db: *mut Sqlite3,
+            sql: *const c_char,
+            bytes: c_int,
+            statement: *mut *mut Sqlite3Stmt,
+            tail: *mut *const c_char,
+        ) -> c_int;
+        pub(super) fn sqlite3_bind_text(
+            statement: *mut Sqlite3Stmt,
+            index: c_int,
+            text: *const c_char,
+            bytes: c_int,
+            destructor: isize,
+        ) -> c_int;
+        pub(super) fn sqlite3_step(statement: *mut Sqlite3Stmt) -> c_int;
+        pub(super) fn sqlite3_column_text(
+            statement: *mut Sqlite3Stmt,
+            column: c_int,
+        ) -> *const c_uchar;
+        pub(super) fn sqlite3_column_bytes(statement: *mut Sqlite3Stmt, column: c_int) -> c_int;
+        pub(super) fn sqlite3_finalize(statement: *mut Sqlite3Stmt) -> c_int;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_sqlite_backend_round_trip() {
+        let dir = std::env::temp_dir().join(format!("rstate-sqlite-{}", std::process::id()));
+        let backend = SqliteBackend::new(dir.join("state.db")).table("app");
+
+        assert_eq!(backend.load().unwrap(), None);
+        let state = json!({ "todos": [{ "title": "write" }], "filter": "all" });
+        backend.save(&state).unwrap();
+        assert_eq!(backend.load_key("filter").unwrap(), Some(json!("all")));
+        assert_eq!(backend.load_key("missing").unwrap(), None);
+
+        // Removed keys are deleted, and a fresh backend reads what was committed
+        backend.save(&json!({ "filter": "done" })).unwrap();
+        let reloaded = SqliteBackend::new(dir.join("state.db")).table("app");
+        assert_eq!(reloaded.load().unwrap(), Some(json!({ "filter": "done" })));
+        assert_eq!(reloaded.load_key("todos").unwrap(), None);
+
+        // States that aren't objects are stored whole
+        reloaded.save(&json!([1, 2])).unwrap();
+        assert_eq!(reloaded.load().unwrap(), Some(json!([1, 2])));
+
+        reloaded.clear().unwrap();
+        assert_eq!(reloaded.load().unwrap(), None);
+        drop((backend, reloaded));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -43,17 +43,208 @@
 //! ```
 
 use serde::{Serialize, de::DeserializeOwned};
-use std::collections::HashMap;
-use std::sync::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Runtime};
 
 use crate::Result;
-use crate::models::{Action, JsonValue, RstateManager};
+use crate::change::{FloatComparison, states_are_equal};
+use crate::emit_policy::EmitPolicy;
+use crate::error::catch_panic;
+use crate::event_log::{EventLog, Journal};
+use crate::flags::{Flags, SET_FLAG_ACTION, TOGGLE_FLAG_ACTION};
+use crate::health::{SaveStatus, unix_millis};
+use crate::logging::ACTION_LOG_TARGET;
+use crate::migrations::{MigrationReport, Migrations};
+use crate::models::{
+    Action, ActionKinds, AnyAppHandle, DispatchOutcome, Dispatcher, JsonValue, RstateManager,
+    get_state,
+};
+use crate::namespace::Namespace;
+use crate::persistence::{
+    FileBackend, Persister, StorageBackend, merge_persisted, remove_path, set_path,
+};
+use crate::retention::{Retention, Retentions};
+use crate::schema::SchemaFingerprint;
+use crate::selectors::SelectorCache;
+use crate::trash::{RESTORE_ACTION, SOFT_DELETE_ACTION, Trash};
 
 /// A handler function type for processing actions.
 ///
 /// The handler receives a mutable reference to the state and the action,
 /// and should return `Ok(())` on success or an error if the action failed.
-pub type ActionHandler<T> = Box<dyn Fn(&mut T, &Action) -> Result<()> + Send + Sync>;
+// A watcher of a slice of the state, called with its old and new value
+type Watcher = (String, Box<dyn Fn(&JsonValue, &JsonValue) + Send + Sync>);
+
+// Save debounce used by `StateBuilder::persist`
+const DEFAULT_SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub type ActionHandler<T> = Box<dyn Fn(&mut T, &Action) -> Result<Next> + Send + Sync>;
+
+/// What to do once an action handler registered with [`StateBuilder::on_next`]
+/// succeeded.
+///
+/// A handler returning `Ok(Next::Done)` is done, like the handlers registered with
+/// [`StateBuilder::on`]. A handler returning `Ok(Next::Dispatch(actions))` chains
+/// follow-up actions, e.g. `LOGIN_SUCCESS` → `LOAD_PREFERENCES`: they are handled
+/// right after it, in order and within the same dispatch, so the plugin emits a
+/// single update once the whole chain is applied, and they trigger their
+/// [effects](StateBuilder::effect) and async handlers as usual.
+///
+/// Follow-up actions carry the action's trace id unless they have their own, go to
+/// the handlers only (not to the plugin's guards or the built-in actions), and fail
+/// the dispatch if they fail. A chain is limited to 64 actions, to stop handlers
+/// dispatching each other forever.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// builder.on_next("LOGIN_SUCCESS", |state, action| {
+///     state.user = Some(action.require_payload()?);
+///     Ok(Next::Dispatch(vec![Action::new("LOAD_PREFERENCES")]))
+/// })
+/// ```
+#[derive(Debug, Clone, Default)]
+pub enum Next {
+    /// Nothing follows the action
+    #[default]
+    Done,
+    /// Handle these actions next
+    Dispatch(Vec<Action>),
+}
+
+impl From<()> for Next {
+    fn from((): ()) -> Self {
+        Self::Done
+    }
+}
+
+// Longest chain of actions a dispatch handles, see `Next`
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// A state type whose action handlers are its methods.
+///
+/// Implemented by the [`handlers`](crate::handlers) attribute macro, and registered
+/// with [`StateBuilder::handlers`].
+pub trait ActionHandlers: Serialize + DeserializeOwned + Send + Sync + Sized + 'static {
+    /// Register the handlers on `builder`.
+    fn register(builder: StateBuilder<Self>) -> StateBuilder<Self>;
+}
+
+/// What [`StateBuilder::build`] does when several handlers were registered for the
+/// same action kind with [`StateBuilder::on`] (or [`on_async`](StateBuilder::on_async)).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// The last handler registered wins (the default)
+    #[default]
+    Overwrite,
+    /// The last handler registered wins, and the duplicates are logged
+    Warn,
+    /// Building fails with [`RstateError::DuplicateHandlers`](crate::RstateError::DuplicateHandlers)
+    Error,
+}
+
+/// A reusable part of a store, registered with [`StateBuilder::module`].
+///
+/// Lets a feature crate contribute its handlers, async handlers, emit policies and
+/// flags to the app's store, and test them on their own, without knowing how the rest
+/// of the store is built. Closures taking and returning the builder are modules too.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// // In the `sync` crate
+/// pub struct SyncModule {
+///     pub endpoint: Url,
+/// }
+///
+/// impl<T: HasSyncState> StateModule<T> for SyncModule {
+///     fn register(self, builder: StateBuilder<T>) -> StateBuilder<T> {
+///         builder
+///             .on("sync/STARTED", |state, _| { state.sync_mut().running = true; Ok(()) })
+///             .on_async("sync/STARTED", move |_| run_sync(self.endpoint.clone()), apply_sync)
+///             .emit_policy("sync/PROGRESS", EmitPolicy::coalesced(Duration::from_millis(100)))
+///     }
+/// }
+///
+/// // In the app
+/// let manager = StateBuilder::new(AppState::default())
+///     .module(SyncModule { endpoint })
+///     .module(EditorModule::default())
+///     .build();
+/// ```
+pub trait StateModule<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Register the module on `builder`.
+    fn register(self, builder: StateBuilder<T>) -> StateBuilder<T>;
+}
+
+impl<T, F> StateModule<T> for F
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    F: FnOnce(StateBuilder<T>) -> StateBuilder<T>,
+{
+    fn register(self, builder: StateBuilder<T>) -> StateBuilder<T> {
+        self(builder)
+    }
+}
+
+/// Kind of the action applying the result of an async handler.
+///
+/// Dispatched by the plugin when a handler registered with [`StateBuilder::on_async`]
+/// completes. Its payload holds the original `kind` and an internal `id`.
+pub const ASYNC_COMPLETE_ACTION: &str = "@@rstate/ASYNC_COMPLETE";
+
+/// Kind of the built-in action restoring the initial state.
+///
+/// A [`BuiltStateManager`] goes back to the initial state given to
+/// [`StateBuilder::new`], ignoring the persisted state, with its flags disabled and its
+/// trash emptied. Dispatch it with [`Rstate::reset`](crate::Rstate::reset) or the
+/// `reset_state` command. Custom [`RstateManager`]s can handle it too.
+pub const RESET_ACTION: &str = "@@rstate/RESET";
+
+/// Kind of the built-in action replacing the whole state with its payload.
+///
+/// Only handled by a [`BuiltStateManager`] built with
+/// [`StateBuilder::hydratable`]; rejected otherwise. The payload must deserialize as
+/// the state type, or the action fails and the state is left untouched.
+pub const HYDRATE_ACTION: &str = "@@rstate/HYDRATE";
+
+// The state mutation an async handler resolved to
+type Completion<T> = Box<dyn FnOnce(&mut T) -> Result<()> + Send>;
+
+// An async handler, running the I/O part of an action
+type AsyncHandler<T> = Box<
+    dyn Fn(&Action) -> Pin<Box<dyn Future<Output = Result<Completion<T>>> + Send>> + Send + Sync,
+>;
+
+// A side effect of an action, built from the state committed by the action. Fails if
+// the snapshot doesn't deserialize.
+type Effect = Box<
+    dyn Fn(
+            Dispatcher,
+            &JsonValue,
+            &Action,
+        ) -> Result<Pin<Box<dyn Future<Output = Result<()>> + Send>>>
+        + Send
+        + Sync,
+>;
+
+// A value derived from the state, serialized
+type Selector<T> = Box<dyn Fn(&T) -> serde_json::Result<JsonValue> + Send + Sync>;
+
+// A field derived from the state, serialized
+type Derived<T> = Box<dyn Fn(&T) -> serde_json::Result<JsonValue> + Send + Sync>;
+
+// Hands the app the manager is registered with to a handler needing it
+type AppBinder = Box<dyn Fn(&AnyAppHandle) + Send + Sync>;
 
 /// A builder for creating state managers with a fluent API.
 ///
@@ -93,6 +284,29 @@ where
     initial_state: T,
     handlers: HashMap<String, ActionHandler<T>>,
     default_handler: Option<ActionHandler<T>>,
+    slice_defaults: HashMap<String, ActionHandler<T>>,
+    async_handlers: HashMap<String, AsyncHandler<T>>,
+    effects: HashMap<String, Vec<Effect>>,
+    app_binders: Vec<AppBinder>,
+    selectors: HashMap<String, Selector<T>>,
+    // By path, in registration order
+    derived: Vec<(String, Derived<T>)>,
+    redacted: Vec<String>,
+    emit_policies: HashMap<String, EmitPolicy>,
+    float_comparison: FloatComparison,
+    flags: Flags,
+    trash: Trash,
+    retentions: Retentions,
+    storage: Option<Box<dyn StorageBackend>>,
+    save_debounce: Option<Duration>,
+    event_log: Option<EventLog>,
+    migrations: Migrations,
+    schema_fingerprint: Option<String>,
+    namespace: Namespace,
+    hydratable: bool,
+    on_duplicate: OnDuplicate,
+    // Kinds registered more than once, in registration order
+    duplicates: Vec<String>,
 }
 
 impl<T> StateBuilder<T>
@@ -111,6 +325,27 @@ where
             initial_state,
             handlers: HashMap::new(),
             default_handler: None,
+            slice_defaults: HashMap::new(),
+            async_handlers: HashMap::new(),
+            effects: HashMap::new(),
+            app_binders: Vec::new(),
+            selectors: HashMap::new(),
+            derived: Vec::new(),
+            redacted: Vec::new(),
+            emit_policies: HashMap::new(),
+            float_comparison: FloatComparison::default(),
+            flags: Flags::default(),
+            trash: Trash::default(),
+            retentions: Retentions::default(),
+            storage: None,
+            save_debounce: None,
+            event_log: None,
+            migrations: Migrations::default(),
+            schema_fingerprint: None,
+            namespace: Namespace::Any,
+            hydratable: false,
+            on_duplicate: OnDuplicate::default(),
+            duplicates: Vec::new(),
         }
     }
 
@@ -133,14 +368,146 @@ where
     ///     })
     /// ```
     #[must_use]
-    pub fn on<F>(mut self, action_kind: impl Into<String>, handler: F) -> Self
+    pub fn on<F>(self, action_kind: impl Into<String>, handler: F) -> Self
     where
         F: Fn(&mut T, &Action) -> Result<()> + Send + Sync + 'static,
     {
-        self.handlers.insert(action_kind.into(), Box::new(handler));
+        self.on_next(action_kind, move |state, action| {
+            handler(state, action).map(Next::from)
+        })
+    }
+
+    /// Register an action handler chaining follow-up actions.
+    ///
+    /// Like [`on`](Self::on), for handlers returning what to do next: a handler
+    /// returning [`Next::Dispatch`] has its follow-up actions handled right after it,
+    /// within the same dispatch, and the plugin emits a single update for the whole
+    /// chain. If any action of the chain fails, the dispatch fails and the changes of
+    /// the whole chain are rolled back. See [`Next`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder
+    ///     .on_next("LOGIN_SUCCESS", |state, action| {
+    ///         state.user = Some(action.require_payload()?);
+    ///         Ok(Next::Dispatch(vec![Action::new("LOAD_PREFERENCES")]))
+    ///     })
+    ///     .on("LOAD_PREFERENCES", |state, _| {
+    ///         state.preferences = load_preferences(state.user.as_ref())?;
+    ///         Ok(())
+    ///     })
+    /// ```
+    #[must_use]
+    pub fn on_next<F>(mut self, action_kind: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(&mut T, &Action) -> Result<Next> + Send + Sync + 'static,
+    {
+        let kind = action_kind.into();
+        if self
+            .handlers
+            .insert(kind.clone(), Box::new(handler))
+            .is_some()
+        {
+            self.duplicates.push(kind);
+        }
         self
     }
 
+    /// Register the handlers generated from the state type's methods by the
+    /// [`handlers`](crate::handlers) attribute macro.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// #[tauri_plugin_rstate::handlers]
+    /// impl AppState {
+    ///     fn increment(&mut self) {
+    ///         self.counter += 1;
+    ///     }
+    /// }
+    ///
+    /// let manager = StateBuilder::new(AppState::default()).handlers().build();
+    /// ```
+    #[must_use]
+    pub fn handlers(self) -> Self
+    where
+        T: ActionHandlers,
+    {
+        T::register(self)
+    }
+
+    /// Register a [`StateModule`], e.g. one provided by a feature crate.
+    ///
+    /// Modules are registered in order, like the builder calls they make; combine with
+    /// [`on_duplicate`](Self::on_duplicate) to catch modules handling the same kinds.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let manager = StateBuilder::new(AppState::default())
+    ///     .module(auth::module())
+    ///     .module(|builder: StateBuilder<AppState>| builder.flags(["betaEditor"]))
+    ///     .build();
+    /// ```
+    #[must_use]
+    pub fn module(self, module: impl StateModule<T>) -> Self {
+        module.register(self)
+    }
+
+    /// Register an action handler needing the app, e.g. to reach its windows, its paths
+    /// or other plugins, or to emit events.
+    ///
+    /// Like [`on`](Self::on), with the [`AppHandle`] the manager is registered with
+    /// (including as a window store or a slice). The handler runs while the store is
+    /// locked: dispatching to the same store from it would deadlock, use an
+    /// [`effect`](Self::effect) instead. Actions fail until the manager is registered
+    /// with the plugin.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder.on_with_app("SET_TITLE", |app: &AppHandle, state, action| {
+    ///     state.title = action.require_payload()?;
+    ///     if let Some(window) = app.get_webview_window("main") {
+    ///         let _ = window.set_title(&state.title);
+    ///     }
+    ///     Ok(())
+    /// })
+    /// ```
+    #[must_use]
+    pub fn on_with_app<R, F>(self, action_kind: impl Into<String>, handler: F) -> Self
+    where
+        R: Runtime,
+        F: Fn(&AppHandle<R>, &mut T, &Action) -> Result<()> + Send + Sync + 'static,
+    {
+        self.on_with::<AppHandle<R>, F>(action_kind, handler)
+    }
+
+    // Register a handler needing the app, as an `A`
+    fn on_with<A, F>(mut self, action_kind: impl Into<String>, handler: F) -> Self
+    where
+        A: Clone + Send + Sync + 'static,
+        F: Fn(&A, &mut T, &Action) -> Result<()> + Send + Sync + 'static,
+    {
+        let app: Arc<OnceLock<A>> = Arc::default();
+        let bound = app.clone();
+        self.app_binders.push(Box::new(move |any| {
+            if let Some(app) = any.downcast_ref::<A>() {
+                let _ = bound.set(app.clone());
+            }
+        }));
+        self.on(action_kind, move |state, action| {
+            let app = app.get().ok_or_else(|| {
+                crate::RstateError::state(format!(
+                    "{}: the handler needs the state manager to be registered with the plugin",
+                    action.kind
+                ))
+            })?;
+            handler(app, state, action)
+        })
+    }
+
     /// Register a default handler for unknown actions.
     ///
     /// This handler is called when no specific handler is found for an action.
@@ -165,164 +532,2263 @@ where
     where
         F: Fn(&mut T, &Action) -> Result<()> + Send + Sync + 'static,
     {
-        self.default_handler = Some(Box::new(handler));
+        self.default_handler = Some(Box::new(move |state, action| {
+            handler(state, action).map(Next::from)
+        }));
         self
     }
 
-    /// Build the state manager.
+    /// Mount the handlers of `slice` on the field `key` of the state.
     ///
-    /// Returns a [`BuiltStateManager`] that implements [`RstateManager`]
-    /// and can be passed to [`crate::init`].
+    /// Every action kind registered on `slice` is registered here prefixed with
+    /// `"{key}/"`, e.g. `ADD` becomes `todos/ADD`, and its handler receives the `key`
+    /// field of the state (deserialized as `S`) instead of the whole state. The slice's
+    /// default handler covers unknown kinds under the prefix. Async handlers and emit
+    /// policies are mounted the same way; the slice's other settings (initial state,
+    /// persistence, ...) are ignored. Slices can be nested.
+    ///
+    /// The state goes through JSON to reach the slice, so prefer
+    /// [`on`](Self::on) with a direct field access for hot paths.
     ///
     /// # Example
     ///
     /// ```rust,ignore
-    /// let state_manager = StateBuilder::new(AppState::default())
-    ///     .on("INCREMENT", |state, _| { state.counter += 1; Ok(()) })
-    ///     .build();
+    /// let todos = StateBuilder::new(Vec::<Todo>::new())
+    ///     .on("ADD", |todos, action| { todos.push(action.require_payload()?); Ok(()) })
+    ///     .on("CLEAR", |todos, _| { todos.clear(); Ok(()) });
     ///
-    /// tauri::Builder::default()
-    ///     .plugin(tauri_plugin_rstate::init(state_manager))
-    ///     .run(tauri::generate_context!())
-    ///     .unwrap();
+    /// let manager = StateBuilder::new(AppState::default())
+    ///     .slice("todos", todos) // handles `todos/ADD` and `todos/CLEAR`
+    ///     .build();
     /// ```
-    pub fn build(self) -> BuiltStateManager<T> {
-        BuiltStateManager {
-            state: Mutex::new(self.initial_state),
-            handlers: self.handlers,
-            default_handler: self.default_handler,
+    #[must_use]
+    pub fn slice<S>(mut self, key: impl Into<String>, slice: StateBuilder<S>) -> Self
+    where
+        S: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let key: Arc<str> = key.into().into();
+        let mount = |handler: ActionHandler<S>| -> ActionHandler<T> {
+            let key = key.clone();
+            Box::new(move |state, action| {
+                with_slice(state, &key, |slice: &mut S| handler(slice, action))
+            })
+        };
+
+        for (kind, handler) in slice.handlers {
+            let kind = format!("{key}/{kind}");
+            if self.handlers.insert(kind.clone(), mount(handler)).is_some() {
+                self.duplicates.push(kind);
+            }
+        }
+        if let Some(handler) = slice.default_handler {
+            self.slice_defaults.insert(key.to_string(), mount(handler));
+        }
+        for (inner, handler) in slice.slice_defaults {
+            self.slice_defaults
+                .insert(format!("{key}/{inner}"), mount(handler));
+        }
+        for (kind, handler) in slice.async_handlers {
+            let slice_key = key.clone();
+            let handler: AsyncHandler<T> = Box::new(move |action| {
+                let future = handler(action);
+                let slice_key = slice_key.clone();
+                Box::pin(async move {
+                    let completion = future.await?;
+                    let completion: Completion<T> =
+                        Box::new(move |state| with_slice(state, &slice_key, completion));
+                    Ok(completion)
+                })
+            });
+            let kind = format!("{key}/{kind}");
+            if self.async_handlers.insert(kind.clone(), handler).is_some() {
+                self.duplicates.push(kind);
+            }
+        }
+        self.app_binders.extend(slice.app_binders);
+        for (name, selector) in slice.selectors {
+            let slice_key = key.clone();
+            let mounted: Selector<T> = Box::new(move |state| {
+                let slice: S = serde_json::from_value(
+                    serde_json::to_value(state)?
+                        .get_mut(&*slice_key)
+                        .map(JsonValue::take)
+                        .unwrap_or_default(),
+                )?;
+                selector(&slice)
+            });
+            self.selectors.insert(format!("{key}/{name}"), mounted);
+        }
+        for (path, derive) in slice.derived {
+            let slice_key = key.clone();
+            let mounted: Derived<T> = Box::new(move |state| {
+                let slice: S = serde_json::from_value(
+                    serde_json::to_value(state)?
+                        .get_mut(&*slice_key)
+                        .map(JsonValue::take)
+                        .unwrap_or_default(),
+                )?;
+                derive(&slice)
+            });
+            self.derive_at(format!("{key}.{path}"), mounted);
+        }
+        self.redacted
+            .extend(crate::redaction::prefixed(&key, &slice.redacted));
+        for (kind, effects) in slice.effects {
+            let mounted = effects.into_iter().map(|effect| -> Effect {
+                let key = key.clone();
+                Box::new(move |dispatcher, snapshot, action| {
+                    let snapshot = snapshot.get(&*key).unwrap_or(&JsonValue::Null);
+                    effect(dispatcher, snapshot, action)
+                })
+            });
+            self.effects
+                .entry(format!("{key}/{kind}"))
+                .or_default()
+                .extend(mounted);
         }
+        self.duplicates.extend(
+            slice
+                .duplicates
+                .into_iter()
+                .map(|kind| format!("{key}/{kind}")),
+        );
+        for (kind, policy) in slice.emit_policies {
+            self.emit_policies.insert(format!("{key}/{kind}"), policy);
+        }
+        self
     }
-}
 
-impl<T> Default for StateBuilder<T>
-where
-    T: Serialize + DeserializeOwned + Send + Sync + Default + 'static,
-{
-    fn default() -> Self {
-        Self::new(T::default())
+    /// Register an async handler for a specific action kind.
+    ///
+    /// When an action of `action_kind` is dispatched, `run` is spawned on
+    /// `tauri::async_runtime` with a copy of the action, outside the store lock, so it
+    /// can do I/O (HTTP, DB). Once it completes, `apply` commits its result to the state
+    /// through an [`ASYNC_COMPLETE_ACTION`] dispatch, which emits as usual. A sync
+    /// handler registered with [`on`](Self::on) for the same kind still runs right away,
+    /// e.g. to set a loading flag.
+    ///
+    /// Failures of `run` are logged. Async handlers need the manager to be registered
+    /// with the plugin, which provides the [`Dispatcher`] for the completion.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder
+    ///     .on("FETCH_USER", |state, _| { state.loading = true; Ok(()) })
+    ///     .on_async(
+    ///         "FETCH_USER",
+    ///         |action| async move { api::fetch_user(action.require_payload()?).await },
+    ///         |state, user| {
+    ///             state.loading = false;
+    ///             state.user = Some(user);
+    ///             Ok(())
+    ///         },
+    ///     )
+    /// ```
+    #[must_use]
+    pub fn on_async<F, Fut, V, A>(
+        mut self,
+        action_kind: impl Into<String>,
+        run: F,
+        apply: A,
+    ) -> Self
+    where
+        F: Fn(Action) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<V>> + Send + 'static,
+        V: Send + 'static,
+        A: Fn(&mut T, V) -> Result<()> + Send + Sync + 'static,
+    {
+        let apply = Arc::new(apply);
+        let handler: AsyncHandler<T> = Box::new(move |action| {
+            let apply = apply.clone();
+            let future = run(action.clone());
+            Box::pin(async move {
+                let value = future.await?;
+                let completion: Completion<T> = Box::new(move |state| apply(state, value));
+                Ok(completion)
+            })
+        });
+        let kind = action_kind.into();
+        if self.async_handlers.insert(kind.clone(), handler).is_some() {
+            self.duplicates.push(kind);
+        }
+        self
     }
-}
 
-/// A state manager built from [`StateBuilder`].
-///
-/// This struct implements [`RstateManager`] and handles:
-/// - Thread-safe state access via internal [`Mutex`]
-/// - Action routing to registered handlers
-/// - Automatic serialization of state to JSON
-///
-/// You typically don't create this directly; use [`StateBuilder::build`] instead.
-pub struct BuiltStateManager<T>
-where
-    T: Serialize + DeserializeOwned + Send + Sync + 'static,
-{
-    state: Mutex<T>,
-    handlers: HashMap<String, ActionHandler<T>>,
-    default_handler: Option<ActionHandler<T>>,
-}
-
-impl<T> BuiltStateManager<T>
-where
-    T: Serialize + DeserializeOwned + Send + Sync + 'static,
-{
-    /// Execute a function with a reference to the current state.
+    /// Register a side effect for a specific action kind.
     ///
-    /// This locks the internal mutex for the duration of the function call.
+    /// Once an action of `action_kind` is handled and its change committed, `effect` is
+    /// spawned on `tauri::async_runtime` with a [`Dispatcher`] for the store, a
+    /// snapshot of the new state and a copy of the action. Effects keep the I/O (saving
+    /// to an API, notifying) out of the handlers, which stay pure; they can't modify
+    /// the state, but may dispatch follow-up actions, which carry the action's trace
+    /// id unless they have their own. Follow-up actions go to the whole store, so
+    /// those of a [slice](Self::slice)'s effects need the slice's prefix.
+    ///
+    /// Several effects can be registered for the same kind, and they all run. They
+    /// don't run if the action fails. Failures of `effect` are logged. Effects need the
+    /// manager to be registered with the plugin, which provides the [`Dispatcher`]:
+    /// until then, dispatching the action fails without changing the state, and effects
+    /// of its follow-up actions are skipped with a warning.
     ///
     /// # Example
     ///
     /// ```rust,ignore
-    /// let count = manager.with_state(|state| state.counter)?;
+    /// builder
+    ///     .on("ADD_TODO", |state, action| {
+    ///         state.todos.push(action.require_payload()?);
+    ///         Ok(())
+    ///     })
+    ///     .effect("ADD_TODO", |dispatch, state: AppState, _action| async move {
+    ///         api::save_todos(&state.todos).await?;
+    ///         dispatch(Action::new("TODOS_SAVED"))?;
+    ///         Ok(())
+    ///     })
     /// ```
-    pub fn with_state<R, F: FnOnce(&T) -> R>(&self, f: F) -> Result<R> {
-        let state = self
-            .state
-            .lock()
-            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
-        Ok(f(&state))
+    #[must_use]
+    pub fn effect<F, Fut>(mut self, action_kind: impl Into<String>, effect: F) -> Self
+    where
+        F: Fn(Dispatcher, T, Action) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let effect: Effect = Box::new(move |dispatcher, snapshot, action| {
+            let snapshot: T = serde_json::from_value(snapshot.clone())
+                .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+            Ok(Box::pin(effect(dispatcher, snapshot, action.clone())))
+        });
+        self.effects
+            .entry(action_kind.into())
+            .or_default()
+            .push(effect);
+        self
     }
 
-    /// Execute a function with a mutable reference to the current state.
+    /// Register a selector: a named value derived from the state.
     ///
-    /// This locks the internal mutex for the duration of the function call.
+    /// Read with [`Rstate::select`](crate::Rstate::select), or the `get_selector`
+    /// command from the frontend, which then doesn't need to pull the whole state and
+    /// derive the value itself. The value is cached until the state changes, see
+    /// [`Rstate::selector_stats`](crate::Rstate::selector_stats). The selectors of a
+    /// [slice](Self::slice) are named `slice/name`. Registering a name again replaces
+    /// its selector.
     ///
     /// # Example
     ///
     /// ```rust,ignore
-    /// manager.with_state_mut(|state| {
-    ///     state.counter += 1;
-    /// })?;
+    /// builder.selector("completed_count", |state| {
+    ///     state.todos.iter().filter(|todo| todo.completed).count()
+    /// })
     /// ```
-    pub fn with_state_mut<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Result<R> {
-        let mut state = self
-            .state
-            .lock()
-            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
-        Ok(f(&mut state))
+    #[must_use]
+    pub fn selector<F, V>(mut self, name: impl Into<String>, selector: F) -> Self
+    where
+        F: Fn(&T) -> V + Send + Sync + 'static,
+        V: Serialize,
+    {
+        self.selectors.insert(
+            name.into(),
+            Box::new(move |state| serde_json::to_value(selector(state))),
+        );
+        self
     }
 
-    /// Get a clone of the current state.
+    /// Declare a field derived from the state, at a `path` (dot notation) of the
+    /// serialized state.
     ///
-    /// This is useful when you need to read the state outside of a handler.
+    /// The field is recomputed whenever the state is serialized after a change, so
+    /// updates sent to the frontend, [`Rstate::get_state`](crate::Rstate::get_state)
+    /// and [`simulate`](RstateManager::simulate) include it, while `T` doesn't
+    /// duplicate the data. Derived fields aren't persisted, and are ignored when the
+    /// state is read back (e.g. by a hydration), unless `T` denies unknown fields. The
+    /// fields of a [slice](Self::slice) are nested under its key. Declaring a path
+    /// again replaces its field.
     ///
     /// # Example
     ///
     /// ```rust,ignore
-    /// let current_state: AppState = manager.get_state_clone()?;
+    /// builder.derive("stats.total", |state| state.todos.len())
     /// ```
-    pub fn get_state_clone(&self) -> Result<T>
+    #[must_use]
+    pub fn derive<F, V>(mut self, path: impl Into<String>, derive: F) -> Self
     where
-        T: Clone,
+        F: Fn(&T) -> V + Send + Sync + 'static,
+        V: Serialize,
     {
-        self.with_state(|s| s.clone())
+        self.derive_at(
+            path.into(),
+            Box::new(move |state| serde_json::to_value(derive(state))),
+        );
+        self
     }
-}
 
-impl<T> RstateManager for BuiltStateManager<T>
-where
-    T: Serialize + DeserializeOwned + Send + Sync + 'static,
-{
-    fn get_initial_state(&self) -> JsonValue {
-        // Safe: if lock is poisoned, return Null rather than panic
-        self.state
-            .lock()
-            .ok()
-            .and_then(|state| serde_json::to_value(&*state).ok())
-            .unwrap_or(JsonValue::Null)
+    // Add a derived field, replacing the one at the same path
+    fn derive_at(&mut self, path: String, derive: Derived<T>) {
+        self.derived.retain(|(existing, _)| *existing != path);
+        self.derived.push((path, derive));
     }
 
-    fn dispatch(&mut self, action: &Action) -> Result<JsonValue> {
-        let mut state = self
-            .state
-            .lock()
-            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
-
-        // Find and execute the handler
-        if let Some(handler) = self.handlers.get(&action.kind) {
-            handler(&mut state, action)?;
-        } else if let Some(ref default_handler) = self.default_handler {
-            default_handler(&mut state, action)?;
-        }
-        // If no handler found and no default, silently ignore (state unchanged)
+    /// Hide the value at `path` (in dot notation) from the frontend.
+    ///
+    /// The value is replaced by `"[redacted]"` in the updates, patches and
+    /// subscriptions the plugin sends, and in the state its commands return, but stays
+    /// intact in the store: handlers, backend reads and [watchers](crate::Rstate::watch)
+    /// see it. It is persisted as usual. Can be called multiple times; the paths of a
+    /// [slice](Self::slice) are nested under its key.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder.redact("auth.token")
+    /// ```
+    #[must_use]
+    pub fn redact(mut self, path: impl Into<String>) -> Self {
+        self.redacted.push(path.into());
+        self
+    }
 
-        // Return updated state
-        serde_json::to_value(&*state).map_err(|e| crate::RstateError::serialization(e.to_string()))
+    /// Override the emit policy for actions of a specific kind.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder
+    ///     .emit_policy("SET_SCROLL_POSITION", EmitPolicy::coalesced(Duration::from_millis(50)))
+    ///     .emit_policy("SAVE_COMPLETED", EmitPolicy::immediate().force())
+    /// ```
+    #[must_use]
+    pub fn emit_policy(mut self, action_kind: impl Into<String>, policy: EmitPolicy) -> Self {
+        self.emit_policies.insert(action_kind.into(), policy);
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Set how change detection compares floating-point numbers in this store
+    /// (default: [`FloatComparison::Absolute`] with `f64::EPSILON`).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// // Coordinates in the millions: treat sub-micrometer jitter as unchanged
+    /// builder.float_comparison(FloatComparison::Relative(1e-12))
+    /// ```
+    #[must_use]
+    pub fn float_comparison(mut self, comparison: FloatComparison) -> Self {
+        self.float_comparison = comparison;
+        self
+    }
+
+    /// Declare feature flags, disabled by default.
+    ///
+    /// The flags are exposed in the state under [`FLAGS_KEY`](crate::FLAGS_KEY) (the
+    /// state must not have a field of that name), persisted along with it, and changed
+    /// with the built-in [`TOGGLE_FLAG_ACTION`](crate::TOGGLE_FLAG_ACTION) and
+    /// [`SET_FLAG_ACTION`](crate::SET_FLAG_ACTION) actions. Read them from Rust with
+    /// [`Rstate::flag`](crate::Rstate::flag).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder.flags(["newEditor", "betaSync"])
+    /// ```
+    #[must_use]
+    pub fn flags(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.flags.declare(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Enable soft-deletes on the collection at `path` (an array of items with an `id`,
+    /// in dot notation), keeping deleted items for `ttl`.
+    ///
+    /// Items are deleted and restored with the built-in
+    /// [`SOFT_DELETE_ACTION`](crate::SOFT_DELETE_ACTION) and
+    /// [`RESTORE_ACTION`](crate::RESTORE_ACTION) actions. Deleted items are exposed in
+    /// the state under [`TRASH_KEY`](crate::TRASH_KEY) (the state must not have a field
+    /// of that name), persisted along with it, and purged on the first dispatch after
+    /// their TTL.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder.soft_delete("todos", Duration::from_secs(7 * 24 * 60 * 60))
+    /// ```
+    #[must_use]
+    pub fn soft_delete(mut self, path: impl Into<String>, ttl: Duration) -> Self {
+        self.trash.declare(path.into(), ttl);
+        self
+    }
+
+    /// Bound the array or map at `path` (dot notation) to the items kept by `retention`.
+    ///
+    /// The collection is trimmed after every dispatch leaving it over its bound, so
+    /// handlers can append to it freely. Map entries are ordered by key. Declaring a
+    /// path again replaces its retention.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder
+    ///     .on("LOG", |state, action| { state.logs.push(action.require_payload()?); Ok(()) })
+    ///     .retain("logs", Retention::Newest(500))
+    /// ```
+    #[must_use]
+    pub fn retain(mut self, path: impl Into<String>, retention: Retention) -> Self {
+        self.retentions.declare(path.into(), retention);
+        self
+    }
+
+    /// Require action kinds to follow a naming policy (default: [`Namespace::Any`]).
+    ///
+    /// Registered kinds are checked by [`build`](Self::build), and dispatching an
+    /// action outside the namespace fails with
+    /// [`RstateError::InvalidActionKind`](crate::RstateError::InvalidActionKind).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder
+    ///     .namespace(Namespace::Required("app/"))
+    ///     .on("app/INCREMENT", |state, _| { state.counter += 1; Ok(()) })
+    /// ```
+    #[must_use]
+    pub fn namespace(mut self, namespace: Namespace) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    /// Set what [`build`](Self::build) does when several handlers were registered for
+    /// the same action kind (default: [`OnDuplicate::Overwrite`]).
+    ///
+    /// Only the last handler registered for a kind runs, which is easy to miss in
+    /// large builders. With [`OnDuplicate::Warn`] or [`OnDuplicate::Error`], every
+    /// duplicated kind is reported at once. A sync and an async handler for the same
+    /// kind are not duplicates.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let manager = StateBuilder::new(AppState::default())
+    ///     .on_duplicate(OnDuplicate::Error)
+    ///     .on("INCREMENT", |state, _| { state.counter += 1; Ok(()) })
+    ///     .on("INCREMENT", |state, _| { state.counter += 2; Ok(()) })
+    ///     .try_build(); // Err(DuplicateHandlers("INCREMENT"))
+    /// ```
+    #[must_use]
+    pub fn on_duplicate(mut self, on_duplicate: OnDuplicate) -> Self {
+        self.on_duplicate = on_duplicate;
+        self
+    }
+
+    /// Accept [`HYDRATE_ACTION`], replacing the whole state with the action's payload
+    /// (default: `false`).
+    ///
+    /// Lets the frontend restore a snapshot it kept, or hand over a state it rendered
+    /// with. The payload is validated by deserializing it as the state type. Anything
+    /// allowed to dispatch can replace the whole state, so add a
+    /// [guard](crate::Builder::guard) if only some sources should.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let manager = StateBuilder::new(AppState::default()).hydratable(true).build();
+    /// ```
+    ///
+    /// ```js
+    /// await invoke('plugin:rstate|dispatch', {
+    ///   action: { kind: '@@rstate/HYDRATE', payload: snapshot },
+    /// })
+    /// ```
+    #[must_use]
+    pub fn hydratable(mut self, hydratable: bool) -> Self {
+        self.hydratable = hydratable;
+        self
+    }
+
+    /// Persist the state in `storage`.
+    ///
+    /// The persisted state is loaded on [`build`](Self::build), on top of the initial
+    /// state, so fields missing from it keep their initial values. The state is saved
+    /// after every dispatch that changed it, and when the store is flushed.
+    /// Use [`FileBackend`], [`MemoryBackend`](crate::MemoryBackend), or implement
+    /// [`StorageBackend`] for other storage. See [`RoutedBackend`](crate::RoutedBackend)
+    /// to split the state across backends.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder.persist_with(FileBackend::new(data_dir.join("state.json")))
+    /// ```
+    #[must_use]
+    pub fn persist_with(mut self, storage: impl StorageBackend) -> Self {
+        self.storage = Some(Box::new(storage));
+        self
+    }
+
+    /// Persist the state as a JSON file at `path`, with autosave.
+    ///
+    /// The file is loaded on [`build`](Self::build) like with
+    /// [`persist_with`](Self::persist_with). Saves are debounced by 500ms (see
+    /// [`save_debounce`](Self::save_debounce)), and the state is saved when the app exits.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder.persist(app.path().app_data_dir()?.join("state.json"))
+    /// ```
+    #[must_use]
+    pub fn persist(self, path: impl Into<PathBuf>) -> Self {
+        self.persist_with(FileBackend::new(path))
+            .save_debounce(DEFAULT_SAVE_DEBOUNCE)
+    }
+
+    /// Debounce saves of the persisted state by `delay` (default: none for
+    /// [`persist_with`](Self::persist_with), 500ms for [`persist`](Self::persist)).
+    ///
+    /// The state is saved once `delay` has passed since the first unsaved change, so
+    /// bursts of dispatches result in a single write. Flushing the store, which happens
+    /// when the app exits, saves right away.
+    #[must_use]
+    pub fn save_debounce(mut self, delay: Duration) -> Self {
+        self.save_debounce = Some(delay);
+        self
+    }
+
+    /// Persist the dispatched actions in an append-only log instead of the state.
+    ///
+    /// The state is saved as a snapshot in the storage of
+    /// [`persist_with`](Self::persist_with) every few actions and when the store is
+    /// flushed, truncating the log. On [`build`](Self::build), the actions logged after
+    /// the snapshot are replayed, so the state is recovered up to the last action even
+    /// after a crash. Handlers must be deterministic, see the
+    /// [`EventLog`](crate::EventLog) docs.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder
+    ///     .persist_with(FileBackend::new(data_dir.join("state.json")))
+    ///     .event_sourced(EventLog::new(data_dir.join("actions.jsonl")))
+    /// ```
+    #[must_use]
+    pub fn event_sourced(mut self, log: EventLog) -> Self {
+        self.event_log = Some(log);
+        self
+    }
+
+    /// Version the persisted state, starting at 1.
+    ///
+    /// Bump the version whenever the shape of the persisted state changes, and register
+    /// a [`migration`](Self::migration) from the previous version. The version is saved
+    /// with the state (under [`VERSION_KEY`](crate::VERSION_KEY)), and states persisted
+    /// by older versions, or before versioning was enabled (version 1), are migrated
+    /// when the manager is built.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// builder
+    ///     .persist(data_dir.join("state.json"))
+    ///     .version(2)
+    ///     .migration(1, |mut state| {
+    ///         state["theme"] = if state["darkMode"] == true { "dark" } else { "light" }.into();
+    ///         state
+    ///     })
+    /// ```
+    #[must_use]
+    pub fn version(mut self, version: u32) -> Self {
+        self.migrations.set_version(version);
+        self
+    }
+
+    /// Register the migration turning a persisted state of version `from` into one of
+    /// version `from + 1`. See [`version`](Self::version).
+    ///
+    /// Migrations receive and return the persisted JSON, before it is merged onto the
+    /// initial state.
+    #[must_use]
+    pub fn migration<F>(mut self, from: u32, migrate: F) -> Self
+    where
+        F: Fn(JsonValue) -> JsonValue + Send + Sync + 'static,
+    {
+        self.migrations.add(from, Box::new(migrate));
+        self
+    }
+
+    /// Run the [migrations](Self::migration) on a copy of the persisted state, and
+    /// report what building the manager would do with it, without writing anything.
+    ///
+    /// The report lists the migrations that would run and the keys each adds, changes
+    /// or removes, along with the reasons the persisted state wouldn't load: it can't
+    /// be read, its version is newer than [`version`](Self::version), a migration is
+    /// missing or panics, or the migrated state isn't a valid `T` (the manager would
+    /// then start from the initial state).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let builder = app_state_builder(&data_dir);
+    /// let report = builder.dry_run_migrations();
+    /// if !report.is_ok() {
+    ///     return Err(format!("can't upgrade: {:?}", report.errors));
+    /// }
+    /// let manager = builder.build();
+    /// ```
+    pub fn dry_run_migrations(&self) -> MigrationReport {
+        let mut persisted = match self.storage.as_deref().map(StorageBackend::load) {
+            Some(Ok(Some(persisted))) => persisted,
+            Some(Err(err)) => {
+                return MigrationReport {
+                    version: self.migrations.version(),
+                    errors: vec![format!("failed to load persisted state: {err}")],
+                    ..MigrationReport::default()
+                };
+            }
+            Some(Ok(None)) | None => {
+                return MigrationReport {
+                    version: self.migrations.version(),
+                    ..MigrationReport::default()
+                };
+            }
+        };
+        crate::event_log::take(&mut persisted);
+        crate::schema::take(&mut persisted);
+        let (mut report, migrated) = self.migrations.dry_run(persisted);
+        if let Some(migrated) = migrated {
+            let mut state = match serde_json::to_value(&self.initial_state) {
+                Ok(state) => state,
+                Err(err) => {
+                    report.errors.push(err.to_string());
+                    return report;
+                }
+            };
+            merge_persisted(&mut state, migrated);
+            if let Err(err) = serde_json::from_value::<T>(state) {
+                report
+                    .errors
+                    .push(format!("migrated state doesn't fit the state: {err}"));
+            }
+        }
+        report
+    }
+
+    /// Fingerprint the state's schema from `T`'s `JsonSchema` derive.
+    ///
+    /// See [`schema_fingerprint`](Self::schema_fingerprint). Derive with the
+    /// re-exported [`schemars`](crate::schemars), so the versions match:
+    ///
+    /// ```rust,ignore
+    /// use tauri_plugin_rstate::schemars::{self, JsonSchema};
+    ///
+    /// #[derive(Serialize, Deserialize, JsonSchema, Default)]
+    /// #[schemars(crate = "tauri_plugin_rstate::schemars")]
+    /// struct AppState { counter: i32 }
+    ///
+    /// let manager = StateBuilder::new(AppState::default()).schema().build();
+    /// ```
+    #[cfg(feature = "schema")]
+    #[must_use]
+    pub fn schema(self) -> Self
+    where
+        T: schemars::JsonSchema,
+    {
+        self.schema_fingerprint(crate::schema::schema_fingerprint_of::<T>())
+    }
+
+    /// Set the fingerprint of the state's schema, e.g. one computed by a build script
+    /// from the same source as the frontend's types, or with
+    /// [`schema_fingerprint`](crate::schema_fingerprint()).
+    ///
+    /// The fingerprint is saved with the persisted state (under
+    /// [`SCHEMA_KEY`](crate::SCHEMA_KEY)), and sent to the frontend when the store is
+    /// ready. A persisted state saved with another fingerprint, without a
+    /// [`version`](Self::version) bump migrating it, is logged as a warning when the
+    /// manager is built and reported to the frontend.
+    #[must_use]
+    pub fn schema_fingerprint(mut self, fingerprint: impl Into<String>) -> Self {
+        self.schema_fingerprint = Some(fingerprint.into());
+        self
+    }
+
+    /// Build the state manager.
+    ///
+    /// Returns a [`BuiltStateManager`] that implements [`RstateManager`]
+    /// and can be passed to [`crate::init`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let state_manager = StateBuilder::new(AppState::default())
+    ///     .on("INCREMENT", |state, _| { state.counter += 1; Ok(()) })
+    ///     .build();
+    ///
+    /// tauri::Builder::default()
+    ///     .plugin(tauri_plugin_rstate::init(state_manager))
+    ///     .run(tauri::generate_context!())
+    ///     .unwrap();
+    /// ```
+    ///
+    /// If the persisted state can't be loaded (see [`try_build`](Self::try_build)), the
+    /// error is logged and the manager starts from the initial state, with persistence
+    /// disabled so the persisted state isn't overwritten.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a registered action kind violates the [`namespace`](Self::namespace),
+    /// or has several handlers with [`OnDuplicate::Error`]. Use
+    /// [`try_build`](Self::try_build) to handle this as an error.
+    pub fn build(self) -> BuiltStateManager<T> {
+        match self.build_with(false) {
+            Ok(manager) => manager,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Build the state manager, failing if a registered action kind violates the
+    /// [`namespace`](Self::namespace), or has several handlers with
+    /// [`OnDuplicate::Error`], or if the persisted state can't be loaded: it can't be
+    /// read, it was saved by a newer version, a migration is missing or panics, or the
+    /// migrated state doesn't deserialize. The persisted state is left untouched then.
+    pub fn try_build(self) -> Result<BuiltStateManager<T>> {
+        self.build_with(true)
+    }
+
+    // Build the manager. Unless `strict`, a persisted state that can't be loaded is
+    // logged and left alone, and the manager starts from the initial state without
+    // persistence.
+    fn build_with(self, strict: bool) -> Result<BuiltStateManager<T>> {
+        self.check_duplicates()?;
+        for kind in self
+            .handlers
+            .keys()
+            .chain(self.async_handlers.keys())
+            .chain(self.effects.keys())
+            .chain(self.emit_policies.keys())
+        {
+            self.namespace.check(kind)?;
+        }
+
+        let mut flags = self.flags;
+        let mut trash = self.trash;
+        // Captured before anything is restored, for `RESET_ACTION`
+        let mut initial = serde_json::to_value(&self.initial_state)
+            .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+        flags.insert_into(&mut initial);
+        trash.insert_into(&mut initial);
+        let mut schema = self
+            .schema_fingerprint
+            .clone()
+            .map(|fingerprint| SchemaFingerprint {
+                fingerprint,
+                persisted: None,
+            });
+        let loaded = match self.storage.as_deref() {
+            Some(storage) => {
+                load_persisted(storage, &self.migrations, &self.initial_state, &mut schema)
+            }
+            None => Ok(None),
+        };
+        // The sequence number of the last logged action included in the persisted state
+        let (state, snapshot_seq, persistent) = match loaded {
+            Ok(Some(Loaded {
+                persisted,
+                state,
+                seq,
+            })) => {
+                flags.restore(&persisted);
+                trash.restore(&persisted);
+                (state, seq, true)
+            }
+            Ok(None) => (self.initial_state, 0, true),
+            Err(err) if !strict => {
+                log::error!(
+                    target: ACTION_LOG_TARGET,
+                    "can't load the persisted state, starting from the initial state without persistence: {err}"
+                );
+                (self.initial_state, 0, false)
+            }
+            Err(err) => return Err(err),
+        };
+        let storage = self.storage.filter(|_| persistent);
+
+        let journal = self.event_log.filter(|_| persistent).map(Journal::new);
+        let replayed = match &journal {
+            Some(journal) => journal.load(snapshot_seq)?,
+            None => Vec::new(),
+        };
+
+        let mut manager = BuiltStateManager {
+            state: RwLock::new(state),
+            last_snapshot: RwLock::new(None),
+            initial,
+            handlers: self.handlers,
+            default_handler: self.default_handler,
+            slice_defaults: self.slice_defaults,
+            async_handlers: self.async_handlers,
+            effects: self.effects,
+            app_binders: self.app_binders,
+            selectors: self.selectors,
+            selector_cache: SelectorCache::default(),
+            derived: self.derived,
+            redacted: self.redacted,
+            emit_policies: self.emit_policies,
+            float_comparison: self.float_comparison,
+            flags,
+            trash,
+            retentions: self.retentions,
+            version: self.migrations.version(),
+            storage: storage.map(|storage| {
+                Persister::new(storage, self.save_debounce)
+                    .versioned(self.migrations.version())
+                    .fingerprinted(self.schema_fingerprint)
+            }),
+            journal,
+            replaying: true,
+            schema,
+            watchers: Vec::new(),
+            namespace: self.namespace,
+            hydratable: self.hydratable,
+            dispatcher: None,
+            completions: Arc::default(),
+            next_completion: AtomicU64::new(0),
+        };
+        for action in &replayed {
+            if let Err(err) = manager.dispatch(action) {
+                log::warn!(
+                    target: ACTION_LOG_TARGET,
+                    "{}: failed to replay logged action: {err}",
+                    action.kind
+                );
+            }
+        }
+        manager.replaying = false;
+        Ok(manager)
+    }
+}
+
+impl<T> StateBuilder<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    // Report the kinds registered more than once, according to `on_duplicate`
+    fn check_duplicates(&self) -> Result<()> {
+        let mut kinds: Vec<&str> = Vec::new();
+        for kind in &self.duplicates {
+            if !kinds.contains(&kind.as_str()) {
+                kinds.push(kind);
+            }
+        }
+        if kinds.is_empty() {
+            return Ok(());
+        }
+
+        let kinds = kinds.join(", ");
+        match self.on_duplicate {
+            OnDuplicate::Overwrite => Ok(()),
+            OnDuplicate::Warn => {
+                log::warn!(
+                    target: ACTION_LOG_TARGET,
+                    "several handlers registered for {kinds}; only the last ones run"
+                );
+                Ok(())
+            }
+            OnDuplicate::Error => Err(crate::RstateError::DuplicateHandlers(kinds)),
+        }
+    }
+}
+
+impl<T> Default for StateBuilder<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + Default + 'static,
+{
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// A state manager built from [`StateBuilder`].
+///
+/// This struct implements [`RstateManager`] and handles:
+/// - Thread-safe state access via an internal [`RwLock`], so readers don't wait for each other
+/// - Action routing to registered handlers
+/// - Automatic serialization of state to JSON
+/// - Optional persistence (see [`StateBuilder::persist_with`])
+///
+/// You typically don't create this directly; use [`StateBuilder::build`] instead.
+pub struct BuiltStateManager<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    state: RwLock<T>,
+    // The state as last serialized, while it is unchanged, so a dispatch only
+    // serializes the state once
+    last_snapshot: RwLock<Option<JsonValue>>,
+    // The initial state, with the flags and trash, for `RESET_ACTION`
+    initial: JsonValue,
+    handlers: HashMap<String, ActionHandler<T>>,
+    default_handler: Option<ActionHandler<T>>,
+    slice_defaults: HashMap<String, ActionHandler<T>>,
+    async_handlers: HashMap<String, AsyncHandler<T>>,
+    effects: HashMap<String, Vec<Effect>>,
+    app_binders: Vec<AppBinder>,
+    selectors: HashMap<String, Selector<T>>,
+    selector_cache: SelectorCache,
+    derived: Vec<(String, Derived<T>)>,
+    redacted: Vec<String>,
+    emit_policies: HashMap<String, EmitPolicy>,
+    float_comparison: FloatComparison,
+    flags: Flags,
+    trash: Trash,
+    retentions: Retentions,
+    // Version of the state's shape, if persisted states are migrated
+    version: Option<u32>,
+    storage: Option<Persister>,
+    // The action log, if event sourced
+    journal: Option<Journal>,
+    // While the logged actions are replayed on build
+    replaying: bool,
+    schema: Option<SchemaFingerprint>,
+    watchers: Vec<Watcher>,
+    namespace: Namespace,
+    hydratable: bool,
+    dispatcher: Option<Dispatcher>,
+    completions: Arc<Mutex<HashMap<u64, Completion<T>>>>,
+    next_completion: AtomicU64,
+}
+
+impl<T> BuiltStateManager<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Execute a function with a reference to the current state.
+    ///
+    /// This holds the internal lock for reading for the duration of the function
+    /// call: other readers aren't blocked, changes are.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let count = manager.with_state(|state| state.counter)?;
+    /// ```
+    pub fn with_state<R, F: FnOnce(&T) -> R>(&self, f: F) -> Result<R> {
+        let state = self
+            .state
+            .read()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+        Ok(f(&state))
+    }
+
+    /// Execute a function with a mutable reference to the current state.
+    ///
+    /// This holds the internal lock for writing for the duration of the function call.
+    /// The change isn't emitted nor persisted: once the manager is registered, mutate
+    /// the state with [`Rstate::update_as`](crate::Rstate::update_as) instead, which
+    /// runs the change detection, saves and emits under the store's lock.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// manager.with_state_mut(|state| {
+    ///     state.counter += 1;
+    /// })?;
+    /// ```
+    pub fn with_state_mut<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Result<R> {
+        let mut state = self
+            .state
+            .write()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+        self.forget_snapshot();
+        Ok(f(&mut state))
+    }
+
+    /// Get a clone of the current state.
+    ///
+    /// This is useful when you need to read the state outside of a handler.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let current_state: AppState = manager.get_state_clone()?;
+    /// ```
+    pub fn get_state_clone(&self) -> Result<T>
+    where
+        T: Clone,
+    {
+        self.with_state(|s| s.clone())
+    }
+
+    /// The action kinds the manager handles: those with a handler or an async handler,
+    /// and the built-in ones it accepts ([`RESET_ACTION`], and the actions of
+    /// hydration, flags and soft deletes when enabled).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let kinds = manager.action_kinds();
+    /// assert!(kinds.kinds.contains(&"INCREMENT".to_string()));
+    /// ```
+    pub fn action_kinds(&self) -> ActionKinds {
+        let mut kinds: Vec<String> = self
+            .handlers
+            .keys()
+            .chain(self.async_handlers.keys())
+            .cloned()
+            .collect();
+        kinds.push(RESET_ACTION.to_owned());
+        if self.hydratable {
+            kinds.push(HYDRATE_ACTION.to_owned());
+        }
+        if !self.flags.is_empty() {
+            kinds.extend([SET_FLAG_ACTION.to_owned(), TOGGLE_FLAG_ACTION.to_owned()]);
+        }
+        if !self.trash.is_empty() {
+            kinds.extend([SOFT_DELETE_ACTION.to_owned(), RESTORE_ACTION.to_owned()]);
+        }
+        kinds.sort();
+        kinds.dedup();
+        let mut default_slices: Vec<String> = self.slice_defaults.keys().cloned().collect();
+        default_slices.sort();
+        ActionKinds {
+            kinds,
+            default_slices,
+            default_handler: self.default_handler.is_some(),
+        }
+    }
+
+    /// Watch the slice of the state at `path` (supports dot notation) as a `V`.
+    ///
+    /// `callback` is called with the old and new value whenever a dispatch changes the
+    /// slice. Only the slice is deserialized. It runs while the state is locked, so it
+    /// must not dispatch to this store; changes where either value doesn't deserialize
+    /// as a `V` (e.g. the slice was added or removed) are logged and skipped.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let manager = StateBuilder::new(AppState::default())
+    ///     .on("SET_THEME", set_theme)
+    ///     .build()
+    ///     .watch_typed("settings", |old: &Settings, new: &Settings| {
+    ///         if old.theme != new.theme {
+    ///             apply_theme(&new.theme);
+    ///         }
+    ///     });
+    /// ```
+    #[must_use]
+    pub fn watch_typed<V, F>(mut self, path: impl Into<String>, callback: F) -> Self
+    where
+        V: DeserializeOwned,
+        F: Fn(&V, &V) + Send + Sync + 'static,
+    {
+        let path = path.into();
+        let watched = path.clone();
+        let watcher = move |old: &JsonValue, new: &JsonValue| match (
+            V::deserialize(old),
+            V::deserialize(new),
+        ) {
+            (Ok(old), Ok(new)) => callback(&old, &new),
+            (Err(err), _) | (_, Err(err)) => {
+                log::warn!("watcher of '{watched}': {err}");
+            }
+        };
+        self.watchers.push((path, Box::new(watcher)));
+        self
+    }
+}
+
+impl<T> RstateManager for BuiltStateManager<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn get_initial_state(&self) -> JsonValue {
+        // Safe: if lock is poisoned, return Null rather than panic
+        let Ok(state) = self.state.read() else {
+            return JsonValue::Null;
+        };
+        if let Some(snapshot) = self.last_snapshot.read().ok().and_then(|last| last.clone()) {
+            return snapshot;
+        }
+        let Ok(snapshot) = self.snapshot(&state) else {
+            return JsonValue::Null;
+        };
+        // Cache it for the next readers: the state can't change while it is read
+        if let Ok(mut last) = self.last_snapshot.write() {
+            *last = Some(snapshot.clone());
+        }
+        snapshot
+    }
+
+    fn dispatch(&mut self, action: &Action) -> Result<DispatchOutcome> {
+        // Fail before changing anything, rather than after committing
+        self.check_dispatcher(action)?;
+        let mut state = self
+            .state
+            .write()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+
+        // Taken, so a failing dispatch doesn't leave a stale snapshot behind
+        let last = self
+            .last_snapshot
+            .write()
+            .ok()
+            .and_then(|mut last| last.take());
+        let previous = match last {
+            Some(previous) => previous,
+            None => self
+                .snapshot(&state)
+                .map_err(|e| crate::RstateError::serialization(e.to_string()))?,
+        };
+
+        let now = unix_millis();
+        self.trash.purge(now);
+        // The actions handled, the dispatched one along with its follow-ups, which
+        // trigger the effects
+        let mut chain = Vec::new();
+
+        if action.is(ASYNC_COMPLETE_ACTION) {
+            self.rollback_on_error(&mut state, &previous, |state| self.complete(state, action))?;
+        } else if action.is(RESET_ACTION) {
+            *state = serde_json::from_value(self.initial.clone())
+                .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+            self.flags.restore(&self.initial);
+            self.trash.restore(&self.initial);
+        } else if action.is(HYDRATE_ACTION) {
+            if !self.hydratable {
+                return Err(crate::RstateError::rejected(
+                    "hydration is not enabled for this store",
+                ));
+            }
+            let payload: JsonValue = action.require_payload()?;
+            *state = serde_json::from_value(payload.clone())
+                .map_err(|e| crate::RstateError::invalid_payload(e.to_string()))?;
+            self.flags.restore(&payload);
+            self.trash.restore(&payload);
+        } else if let Some(result) = self.flags.handle(action) {
+            result?;
+        } else if Trash::handles(action) {
+            let to_error = |e: serde_json::Error| crate::RstateError::serialization(e.to_string());
+            let mut json = serde_json::to_value(&*state).map_err(to_error)?;
+            self.trash.handle(&mut json, action, now)?;
+            *state = serde_json::from_value(json).map_err(to_error)?;
+        } else {
+            self.rollback_on_error(&mut state, &previous, |state| {
+                self.handle_chain(state, action, |handled| chain.push(handled.clone()))
+            })?;
+        }
+        self.apply_retentions(&mut state)?;
+
+        // Return updated state
+        let updated = self
+            .snapshot(&state)
+            .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+        if previous != updated {
+            self.selector_cache.invalidate();
+            self.notify_watchers(&previous, &updated);
+            self.persist(action, updated.clone());
+        }
+        if let Ok(mut last) = self.last_snapshot.write() {
+            *last = Some(updated.clone());
+        }
+        // The state is committed: a follow-up whose async handler or effects can't run
+        // can only be reported
+        for handled in &chain {
+            if let Err(err) = self
+                .spawn_async(handled)
+                .and_then(|()| self.spawn_effects(handled, &updated))
+            {
+                log::warn!(target: ACTION_LOG_TARGET, "{err}");
+            }
+        }
+        let changed = !states_are_equal(&previous, &updated, self.float_comparison);
+        Ok(DispatchOutcome::new(updated, changed))
+    }
+
+    fn set_dispatcher(&mut self, dispatcher: Dispatcher) {
+        self.dispatcher = Some(dispatcher);
+    }
+
+    fn set_app(&mut self, app: AnyAppHandle) {
+        for bind in &self.app_binders {
+            bind(&app);
+        }
+    }
+
+    fn set_save_tap(&mut self, tap: crate::SaveTap) {
+        if let Some(storage) = &self.storage {
+            storage.set_tap(tap);
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.journal.is_some() {
+            return self.snapshot_journal(self.get_initial_state());
+        }
+        match &self.storage {
+            Some(storage) => storage.flush(&self.without_derived(self.get_initial_state())),
+            None => Ok(()),
+        }
+    }
+
+    fn replace_state(&mut self, state: JsonValue) -> Result<()> {
+        let typed: T = serde_json::from_value(state.clone())
+            .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+        let previous = self.get_initial_state();
+        self.with_state_mut(|current| *current = typed)?;
+        self.flags.restore(&state);
+        self.trash.restore(&state);
+        if !self.watchers.is_empty() {
+            self.notify_watchers(&previous, &state);
+        }
+        if self.journal.is_some() {
+            return self.snapshot_journal(state);
+        }
+        self.save(state);
+        Ok(())
+    }
+
+    fn emit_policy(&self, kind: &str) -> Option<EmitPolicy> {
+        self.emit_policies.get(kind).copied()
+    }
+
+    fn last_save(&self) -> Option<SaveStatus> {
+        self.storage.as_ref().and_then(Persister::last)
+    }
+
+    fn schema(&self) -> Option<SchemaFingerprint> {
+        self.schema.clone()
+    }
+
+    fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    fn float_comparison(&self) -> FloatComparison {
+        self.float_comparison
+    }
+
+    fn simulate(&self, actions: &[Action]) -> Result<JsonValue> {
+        // Copy the state through JSON, so `T` doesn't need to be `Clone`
+        let snapshot = self.with_state(|state| serde_json::to_value(state))?;
+        let mut state: T = snapshot
+            .and_then(serde_json::from_value)
+            .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+
+        for action in actions {
+            self.handle_chain(&mut state, action, |_| ())?;
+        }
+
+        self.snapshot(&state)
+            .map_err(|e| crate::RstateError::serialization(e.to_string()))
+    }
+
+    fn select(&self, name: &str) -> Result<JsonValue> {
+        let selector = self
+            .selectors
+            .get(name)
+            .ok_or_else(|| crate::RstateError::state(format!("No selector '{name}'")))?;
+        // The cache is read under the state's lock, so the state can't change meanwhile
+        self.with_state(|state| {
+            self.selector_cache.get_or_compute(name, || {
+                selector(state).map_err(|e| crate::RstateError::serialization(e.to_string()))
+            })
+        })?
+    }
+
+    fn selector_stats(&self) -> Vec<crate::SelectorStats> {
+        self.selector_cache.stats()
+    }
+
+    fn redacted_paths(&self) -> Vec<String> {
+        self.redacted.clone()
+    }
+
+    fn action_kinds(&self) -> Result<ActionKinds> {
+        Ok(BuiltStateManager::action_kinds(self))
+    }
+
+    fn persisted_history(&self, after: u64, limit: usize) -> Result<crate::HistoryPage> {
+        match &self.journal {
+            Some(journal) => journal.page(after, limit),
+            None => Err(crate::RstateError::state("the store isn't event-sourced")),
+        }
+    }
+}
+
+impl<T> BuiltStateManager<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    // Handle `action`, then the follow-up actions its handlers return, breadth-first.
+    // `handled` is called with every action once handled.
+    fn handle_chain(
+        &self,
+        state: &mut T,
+        action: &Action,
+        mut handled: impl FnMut(&Action),
+    ) -> Result<()> {
+        let mut queue = VecDeque::from([action.clone()]);
+        let mut length = 0;
+        while let Some(next) = queue.pop_front() {
+            length += 1;
+            if length > MAX_CHAIN_LENGTH {
+                return Err(crate::RstateError::state(format!(
+                    "{}: more than {MAX_CHAIN_LENGTH} chained actions",
+                    action.kind
+                )));
+            }
+            let follow_ups = self.handle(state, &next)?;
+            handled(&next);
+            if let Next::Dispatch(follow_ups) = follow_ups {
+                queue.extend(follow_ups.into_iter().map(|follow_up| {
+                    match (follow_up.trace_id(), next.trace_id()) {
+                        (None, Some(trace_id)) => follow_up.with_trace_id(trace_id.to_owned()),
+                        _ => follow_up,
+                    }
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    // Find and execute the handler for `action`
+    fn handle(&self, state: &mut T, action: &Action) -> Result<Next> {
+        self.namespace.check(&action.kind)?;
+        let handler = self
+            .handlers
+            .get(&action.kind)
+            .or_else(|| self.slice_default(&action.kind))
+            .or(self.default_handler.as_ref());
+        match handler {
+            Some(handler) => catch_panic(&action.kind, || handler(state, action)),
+            // If no handler found and no default, silently ignore (state unchanged)
+            None => Ok(Next::Done),
+        }
+    }
+
+    // Trim the collections of `state` over their bound
+    fn apply_retentions(&self, state: &mut T) -> Result<()> {
+        if self.retentions.is_empty() {
+            return Ok(());
+        }
+        let to_error = |e: serde_json::Error| crate::RstateError::serialization(e.to_string());
+        let mut json = serde_json::to_value(&*state).map_err(to_error)?;
+        if self.retentions.apply(&mut json) {
+            *state = serde_json::from_value(json).map_err(to_error)?;
+        }
+        Ok(())
+    }
+
+    // Run `f` on `state`, restoring it from its `previous` snapshot if it fails or
+    // panics, so the changes of the handlers that ran before don't linger unsaved.
+    // The snapshot is taken anyway to detect changes, so `T` doesn't need to be `Clone`
+    // and nothing is copied unless a handler fails.
+    fn rollback_on_error<F>(&self, state: &mut T, previous: &JsonValue, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut T) -> Result<()>,
+    {
+        let result = f(state);
+        if result.is_err() {
+            *state = serde_json::from_value(self.without_derived(previous.clone()))
+                .map_err(|e| crate::RstateError::serialization(e.to_string()))?;
+        }
+        result
+    }
+
+    // The default handler of the innermost slice containing `kind`, if any
+    fn slice_default(&self, kind: &str) -> Option<&ActionHandler<T>> {
+        self.slice_defaults
+            .iter()
+            .filter(|(key, _)| {
+                kind.strip_prefix(key.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, handler)| handler)
+    }
+
+    // Spawn the async handler for `action`, if any
+    fn spawn_async(&self, action: &Action) -> Result<()> {
+        // Replayed actions already completed before
+        if self.replaying {
+            return Ok(());
+        }
+        let Some(handler) = self.async_handlers.get(&action.kind) else {
+            return Ok(());
+        };
+        let dispatcher = self.dispatcher_for(action, "async handlers")?;
+
+        let future = handler(action);
+        let completions = self.completions.clone();
+        let id = self.next_completion.fetch_add(1, Ordering::Relaxed);
+        let kind = action.kind.clone();
+        let trace_id = action.trace_id().map(str::to_owned);
+        tauri::async_runtime::spawn(async move {
+            let completion = match future.await {
+                Ok(completion) => completion,
+                Err(err) => {
+                    log::warn!(target: ACTION_LOG_TARGET, "{kind}: async handler failed: {err}");
+                    return;
+                }
+            };
+            if let Ok(mut completions) = completions.lock() {
+                completions.insert(id, completion);
+            }
+
+            let mut done = Action::with_json(
+                ASYNC_COMPLETE_ACTION,
+                serde_json::json!({ "kind": kind, "id": id }),
+            );
+            if let Some(trace_id) = trace_id {
+                done = done.with_trace_id(trace_id);
+            }
+            if let Err(err) = dispatcher(done) {
+                log::warn!(target: ACTION_LOG_TARGET, "{kind}: async completion failed: {err}");
+            }
+        });
+        Ok(())
+    }
+
+    // Spawn the effects registered for `action`, with the state it committed
+    fn spawn_effects(&self, action: &Action, snapshot: &JsonValue) -> Result<()> {
+        // The follow-ups of replayed actions are logged themselves
+        if self.replaying {
+            return Ok(());
+        }
+        let Some(effects) = self.effects.get(&action.kind) else {
+            return Ok(());
+        };
+        let mut dispatcher = self.dispatcher_for(action, "effects")?;
+        if let Some(trace_id) = action.trace_id() {
+            let trace_id = trace_id.to_owned();
+            dispatcher = Arc::new(move |follow_up: Action| {
+                let follow_up = match follow_up.trace_id() {
+                    Some(_) => follow_up,
+                    None => follow_up.with_trace_id(trace_id.clone()),
+                };
+                dispatcher(follow_up)
+            });
+        }
+
+        for effect in effects {
+            let kind = action.kind.clone();
+            let future = match effect(dispatcher.clone(), snapshot, action) {
+                Ok(future) => future,
+                Err(err) => {
+                    log::warn!(target: ACTION_LOG_TARGET, "{kind}: effect failed: {err}");
+                    continue;
+                }
+            };
+            tauri::async_runtime::spawn(async move {
+                if let Err(err) = future.await {
+                    log::warn!(target: ACTION_LOG_TARGET, "{kind}: effect failed: {err}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    // Fail if `action` has async handlers or effects, but no dispatcher to run them
+    fn check_dispatcher(&self, action: &Action) -> Result<()> {
+        if !self.replaying
+            && (self.async_handlers.contains_key(&action.kind)
+                || self.effects.contains_key(&action.kind))
+        {
+            self.dispatcher_for(action, "async handlers and effects")?;
+        }
+        Ok(())
+    }
+
+    // The dispatcher `what` (async handlers, effects) of `action` need
+    fn dispatcher_for(&self, action: &Action, what: &str) -> Result<Dispatcher> {
+        self.dispatcher.clone().ok_or_else(|| {
+            crate::RstateError::state(format!(
+                "{}: {what} need the state manager to be registered with the plugin",
+                action.kind
+            ))
+        })
+    }
+
+    // Apply the result of a completed async handler
+    fn complete(&self, state: &mut T, action: &Action) -> Result<()> {
+        let id = action
+            .payload
+            .as_ref()
+            .and_then(|payload| payload.get("id"))
+            .and_then(JsonValue::as_u64)
+            .ok_or_else(|| crate::RstateError::invalid_payload("missing async completion id"))?;
+        let completion = self
+            .completions
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?
+            .remove(&id);
+        match completion {
+            Some(completion) => catch_panic(&action.kind, || completion(state)),
+            // Unknown or already applied
+            None => Ok(()),
+        }
+    }
+
+    // Drop the last snapshot, as the state is about to change outside of a dispatch
+    fn forget_snapshot(&self) {
+        if let Ok(mut last) = self.last_snapshot.write() {
+            *last = None;
+        }
+        self.selector_cache.invalidate();
+    }
+
+    // Serialize `state`, along with the feature flags, deleted items and derived fields
+    fn snapshot(&self, state: &T) -> serde_json::Result<JsonValue> {
+        let mut snapshot = serde_json::to_value(state)?;
+        self.flags.insert_into(&mut snapshot);
+        self.trash.insert_into(&mut snapshot);
+        for (path, derive) in &self.derived {
+            if !has_room_for(&snapshot, path) {
+                log::warn!(
+                    target: ACTION_LOG_TARGET,
+                    "derived field {path} is not inside an object, skipped"
+                );
+                continue;
+            }
+            set_path(&mut snapshot, path, derive(state)?);
+        }
+        Ok(snapshot)
+    }
+
+    // Strip the derived fields off a serialized `state`
+    fn without_derived(&self, mut state: JsonValue) -> JsonValue {
+        for (path, _) in &self.derived {
+            remove_path(&mut state, path);
+        }
+        state
+    }
+
+    // Call the watchers whose slice changed between `previous` and `updated`
+    fn notify_watchers(&self, previous: &JsonValue, updated: &JsonValue) {
+        for (path, watcher) in &self.watchers {
+            let old = get_state(previous, path).unwrap_or(JsonValue::Null);
+            let new = get_state(updated, path).unwrap_or(JsonValue::Null);
+            if old != new {
+                watcher(&old, &new);
+            }
+        }
+    }
+
+    // Save the state, if persisted
+    fn save(&self, state: JsonValue) {
+        if let Some(storage) = &self.storage {
+            storage.save(self.without_derived(state));
+        }
+    }
+
+    // Persist the state changed by `action`: log the action if event sourced, save
+    // the state otherwise
+    fn persist(&self, action: &Action, state: JsonValue) {
+        let Some(journal) = &self.journal else {
+            return self.save(state);
+        };
+        if self.replaying {
+            return;
+        }
+        // Completions can't be replayed, their result is in the state only
+        let snapshot_due = action.is(ASYNC_COMPLETE_ACTION)
+            || journal.append(action).unwrap_or_else(|err| {
+                log::warn!(target: ACTION_LOG_TARGET, "failed to log action: {err}");
+                true
+            });
+        if snapshot_due {
+            if let Err(err) = self.snapshot_journal(state) {
+                log::warn!("failed to persist state: {err}");
+            }
+        }
+    }
+
+    // Save `state` as the snapshot of the logged actions, and truncate the log.
+    // Without storage, the log is kept whole.
+    fn snapshot_journal(&self, state: JsonValue) -> Result<()> {
+        let (Some(journal), Some(storage)) = (&self.journal, &self.storage) else {
+            return Ok(());
+        };
+        let mut snapshot = self.without_derived(state);
+        journal.stamp(&mut snapshot);
+        storage.flush(&snapshot)?;
+        journal.truncate()
+    }
+}
+
+// Whether the field at `path` can be set in `state` without replacing a part of it
+// that isn't an object
+fn has_room_for(state: &JsonValue, path: &str) -> bool {
+    let mut current = Some(state);
+    let parents = path.rsplit_once('.').map_or("", |(parents, _)| parents);
+    for segment in parents.split('.').filter(|segment| !segment.is_empty()) {
+        match current {
+            Some(JsonValue::Object(object)) => current = object.get(segment),
+            Some(_) => return false,
+            None => return true,
+        }
+    }
+    matches!(current, None | Some(JsonValue::Object(_)))
+}
+
+// Run `f` on the field `key` of `state`, as an `S`
+fn with_slice<T, S, F, V>(state: &mut T, key: &str, f: F) -> Result<V>
+where
+    T: Serialize + DeserializeOwned,
+    S: Serialize + DeserializeOwned,
+    F: FnOnce(&mut S) -> Result<V>,
+{
+    let serialization = |e: serde_json::Error| crate::RstateError::serialization(e.to_string());
+    let mut json = serde_json::to_value(&*state).map_err(serialization)?;
+    let field = json
+        .get_mut(key)
+        .ok_or_else(|| crate::RstateError::state(format!("No slice '{key}' in the state")))?;
+
+    let mut slice: S = serde_json::from_value(field.take()).map_err(serialization)?;
+    let value = f(&mut slice)?;
+    *field = serde_json::to_value(slice).map_err(serialization)?;
+    *state = serde_json::from_value(json).map_err(serialization)?;
+    Ok(value)
+}
+
+// Load the persisted state from `storage`, migrated and merged onto `initial_state`,
+// noting a schema change in `schema`. `None` if nothing was persisted.
+fn load_persisted<T>(
+    storage: &dyn StorageBackend,
+    migrations: &Migrations,
+    initial_state: &T,
+    schema: &mut Option<SchemaFingerprint>,
+) -> Result<Option<Loaded<T>>>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let Some(mut persisted) = storage.load()? else {
+        return Ok(None);
+    };
+    let seq = crate::event_log::take(&mut persisted);
+    let fingerprint = crate::schema::take(&mut persisted);
+    if let (Some(schema), Some(fingerprint)) = (schema, fingerprint)
+        && fingerprint != schema.fingerprint
+        && !migrations.outdated(&persisted)
+    {
+        log::warn!(
+            target: ACTION_LOG_TARGET,
+            "persisted state has schema {fingerprint}, but the state's is {}",
+            schema.fingerprint
+        );
+        schema.persisted = Some(fingerprint);
+    }
+    let persisted = migrations.migrate(persisted)?;
+
+    let to_error = |e: serde_json::Error| crate::RstateError::serialization(e.to_string());
+    let mut merged = serde_json::to_value(initial_state).map_err(to_error)?;
+    merge_persisted(&mut merged, persisted.clone());
+    let state = serde_json::from_value(merged).map_err(|e| {
+        crate::RstateError::serialization(format!("persisted state doesn't fit the state: {e}"))
+    })?;
+    Ok(Some(Loaded {
+        persisted,
+        state,
+        seq,
+    }))
+}
+
+// A persisted state loaded by `load_persisted`
+struct Loaded<T> {
+    // The persisted state, migrated
+    persisted: JsonValue,
+    // It merged onto the initial state
+    state: T,
+    // Sequence number of the last logged action it includes
+    seq: u64,
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
+    use crate::models::{ActionGuard, ActionSource, TagFrontend, check_guards, check_payload_size};
     use serde::{Deserialize, Serialize};
 
-    #[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
-    struct TestState {
-        counter: i32,
-        message: String,
+    #[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+    struct TestState {
+        counter: i32,
+        message: String,
+    }
+
+    #[test]
+    fn test_state_builder_basic() {
+        let mut manager = StateBuilder::new(TestState::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .on("SET_MESSAGE", |state, action| {
+                state.message = action.require_payload()?;
+                Ok(())
+            })
+            .build();
+
+        // Test initial state
+        let initial = manager.get_initial_state();
+        assert_eq!(initial["counter"], 0);
+        assert_eq!(initial["message"], "");
+
+        // Test INCREMENT
+        let action = Action::new("INCREMENT");
+        let result = manager.dispatch(&action).unwrap().state;
+        assert_eq!(result["counter"], 1);
+
+        // Test SET_MESSAGE
+        let action = Action::with_payload("SET_MESSAGE", "Hello").unwrap();
+        let result = manager.dispatch(&action).unwrap().state;
+        assert_eq!(result["message"], "Hello");
+
+        // Changes made outside of a dispatch aren't hidden by the last snapshot
+        manager.with_state_mut(|state| state.counter = 10).unwrap();
+        assert_eq!(manager.get_initial_state()["counter"], 10);
+        let outcome = manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        assert!(outcome.changed);
+        assert_eq!(outcome.state["counter"], 11);
+    }
+
+    #[test]
+    fn test_state_builder_default_handler() {
+        let mut manager = StateBuilder::new(TestState::default())
+            .on_default(|_state, action| Err(crate::RstateError::action_not_found(&action.kind)))
+            .build();
+
+        let action = Action::new("UNKNOWN");
+        let result = manager.dispatch(&action);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_state_builder_unknown_action_ignored() {
+        let mut manager = StateBuilder::new(TestState {
+            counter: 5,
+            message: "test".into(),
+        })
+        .on("INCREMENT", |state, _| {
+            state.counter += 1;
+            Ok(())
+        })
+        .build();
+
+        // Unknown action should be silently ignored
+        let action = Action::new("UNKNOWN");
+        let outcome = manager.dispatch(&action).unwrap();
+        assert_eq!(outcome.state["counter"], 5); // Unchanged
+        assert!(!outcome.changed);
+    }
+
+    #[test]
+    fn test_async_handler_completes_through_dispatcher() {
+        let mut manager = StateBuilder::new(TestState::default())
+            .on("FETCH", |state, _| {
+                state.message = "loading".into();
+                Ok(())
+            })
+            .on_async(
+                "FETCH",
+                |action| async move { action.require_payload::<String>() },
+                |state, message| {
+                    state.message = message;
+                    Ok(())
+                },
+            )
+            .build();
+
+        // Without a dispatcher, async handlers can't complete
+        assert!(
+            manager
+                .dispatch(&Action::with_json("FETCH", "x".into()))
+                .is_err()
+        );
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        manager.set_dispatcher(Arc::new(move |action| {
+            sender.lock().unwrap().send(action).unwrap();
+            Ok(JsonValue::Null)
+        }));
+
+        let state = manager
+            .dispatch(&Action::with_json("FETCH", "done".into()).with_trace_id("t"))
+            .unwrap()
+            .state;
+        assert_eq!(state["message"], "loading");
+
+        let completion = receiver
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap();
+        assert!(completion.is(ASYNC_COMPLETE_ACTION));
+        assert_eq!(completion.trace_id(), Some("t"));
+        let state = manager.dispatch(&completion).unwrap().state;
+        assert_eq!(state["message"], "done");
+    }
+
+    #[test]
+    fn test_slices_handle_prefixed_actions() {
+        #[derive(Serialize, Deserialize, Default)]
+        struct Composed {
+            counter: TestState,
+            todos: Vec<String>,
+        }
+
+        let todos = StateBuilder::new(Vec::<String>::new())
+            .on("ADD", |todos, action| {
+                todos.push(action.require_payload()?);
+                Ok(())
+            })
+            .on_default(|todos, _| {
+                todos.clear();
+                Ok(())
+            });
+        let counter = StateBuilder::new(TestState::default()).on("INCREMENT", |state, _| {
+            state.counter += 1;
+            Ok(())
+        });
+        let mut manager = StateBuilder::new(Composed::default())
+            .slice("todos", todos)
+            .slice("counter", counter)
+            .build();
+
+        manager
+            .dispatch(&Action::with_json("todos/ADD", "milk".into()))
+            .unwrap();
+        let state = manager
+            .dispatch(&Action::new("counter/INCREMENT"))
+            .unwrap()
+            .state;
+        assert_eq!(state["todos"], serde_json::json!(["milk"]));
+        assert_eq!(state["counter"]["counter"], 1);
+
+        // Unknown kinds under the prefix go to the slice's default handler
+        let state = manager.dispatch(&Action::new("todos/RESET")).unwrap().state;
+        assert_eq!(state["todos"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_effects_run_after_the_commit() {
+        #[derive(Serialize, Deserialize, Default)]
+        struct Composed {
+            todos: Vec<String>,
+        }
+
+        let todos = StateBuilder::new(Vec::<String>::new())
+            .on("ADD", |todos, action| {
+                todos.push(action.require_payload()?);
+                Ok(())
+            })
+            .effect("ADD", |dispatch, todos, _| async move {
+                // The snapshot of the slice, with the todo added
+                dispatch(Action::with_payload("todos/SAVED", todos.len())?)?;
+                Ok(())
+            });
+        let mut manager = StateBuilder::new(Composed::default())
+            .slice("todos", todos)
+            .effect("todos/ADD", |_, _, action| async move {
+                Err(crate::RstateError::state(action.kind))
+            })
+            .build();
+
+        // Without a dispatcher, effects can't dispatch follow-up actions, and the action
+        // fails before changing the state
+        assert!(
+            manager
+                .dispatch(&Action::with_json("todos/ADD", "milk".into()))
+                .is_err()
+        );
+        assert_eq!(manager.get_initial_state()["todos"], serde_json::json!([]));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        manager.set_dispatcher(Arc::new(move |action| {
+            sender.lock().unwrap().send(action).unwrap();
+            Ok(JsonValue::Null)
+        }));
+
+        // Effects don't run for failed actions
+        assert!(manager.dispatch(&Action::new("todos/ADD")).is_err());
+        manager
+            .dispatch(&Action::with_json("todos/ADD", "eggs".into()).with_trace_id("t"))
+            .unwrap();
+        let follow_up = receiver
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap();
+        assert!(follow_up.is("todos/SAVED"));
+        assert_eq!(follow_up.payload, Some(1.into()));
+        assert_eq!(follow_up.trace_id(), Some("t"));
+        // The failing effect is only logged
+        assert!(
+            receiver
+                .recv_timeout(std::time::Duration::from_millis(50))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_app_handlers_wait_for_the_app() {
+        // A stand-in for the app handle, which can't be created here
+        let mut manager = StateBuilder::new(TestState::default())
+            .on_with("RENAME", |app: &String, state, action| {
+                let name: String = action.require_payload()?;
+                state.message = format!("{app}: {name}");
+                Ok(())
+            })
+            .build();
+
+        assert!(
+            manager
+                .dispatch(&Action::with_json("RENAME", "x".into()))
+                .is_err()
+        );
+        // Not the app the handler needs
+        manager.set_app(Arc::new(()));
+        assert!(
+            manager
+                .dispatch(&Action::with_json("RENAME", "x".into()))
+                .is_err()
+        );
+        manager.set_app(Arc::new("app".to_owned()));
+        let state = manager
+            .dispatch(&Action::with_json("RENAME", "x".into()))
+            .unwrap()
+            .state;
+        assert_eq!(state["message"], "app: x");
+    }
+
+    #[test]
+    fn test_handlers_chain_follow_up_actions() {
+        let mut manager = StateBuilder::new(TestState::default())
+            .on_next("LOGIN_SUCCESS", |state, _| {
+                state.message = "logged in".into();
+                Ok(Next::Dispatch(vec![
+                    Action::new("LOAD_PREFERENCES"),
+                    Action::new("INCREMENT"),
+                ]))
+            })
+            .on_next("LOAD_PREFERENCES", |state, action| {
+                assert_eq!(action.trace_id(), Some("t"));
+                state.message.push_str(", preferences loaded");
+                Ok(Next::Dispatch(vec![Action::new("INCREMENT")]))
+            })
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .on_next("PING", |_, _| Ok(Next::Dispatch(vec![Action::new("PING")])))
+            .build();
+
+        let preview = manager
+            .simulate(&[Action::new("LOGIN_SUCCESS").with_trace_id("t")])
+            .unwrap();
+        let outcome = manager
+            .dispatch(&Action::new("LOGIN_SUCCESS").with_trace_id("t"))
+            .unwrap();
+        assert_eq!(outcome.state, preview);
+        assert_eq!(outcome.state["message"], "logged in, preferences loaded");
+        assert_eq!(outcome.state["counter"], 2);
+
+        // Endless chains are cut
+        assert!(manager.dispatch(&Action::new("PING")).is_err());
     }
 
     #[test]
-    fn test_state_builder_basic() {
+    fn test_failing_chains_roll_back() {
+        let mut manager = StateBuilder::new(TestState::default())
+            .on_next("CHECKOUT", |state, _| {
+                state.counter += 1;
+                Ok(Next::Dispatch(vec![Action::new("CHARGE")]))
+            })
+            .on("CHARGE", |state, _| {
+                state.message = "charged".into();
+                Err(crate::RstateError::rejected("card declined"))
+            })
+            .build();
+
+        assert!(manager.dispatch(&Action::new("CHECKOUT")).is_err());
+        let state = manager.get_initial_state();
+        assert_eq!(state["counter"], 0);
+        assert_eq!(state["message"], "");
+    }
+
+    #[test]
+    fn test_selectors_derive_values() {
+        #[derive(Serialize, Deserialize, Default)]
+        struct Composed {
+            counter: TestState,
+            todos: Vec<String>,
+        }
+
+        let todos = StateBuilder::new(Vec::<String>::new())
+            .on("ADD", |todos, action| {
+                todos.push(action.require_payload()?);
+                Ok(())
+            })
+            .selector("count", Vec::len);
+        let mut manager = StateBuilder::new(Composed::default())
+            .slice("todos", todos)
+            .selector("summary", |state| {
+                format!(
+                    "{} todos, counter at {}",
+                    state.todos.len(),
+                    state.counter.counter
+                )
+            })
+            .build();
+
+        manager
+            .dispatch(&Action::with_json("todos/ADD", "milk".into()))
+            .unwrap();
+        assert_eq!(manager.select("todos/count").unwrap(), 1);
+        assert_eq!(manager.select("summary").unwrap(), "1 todos, counter at 0");
+        assert!(manager.select("count").is_err());
+
+        // Cached until the state changes
+        manager.select("summary").unwrap();
+        manager.dispatch(&Action::new("todos/NOOP")).unwrap();
+        manager.select("summary").unwrap();
+        manager
+            .dispatch(&Action::with_json("todos/ADD", "eggs".into()))
+            .unwrap();
+        assert_eq!(manager.select("summary").unwrap(), "2 todos, counter at 0");
+        let summary = &manager.selector_stats()[0];
+        assert_eq!((summary.hits, summary.misses), (2, 2));
+    }
+
+    #[test]
+    fn test_derived_fields_are_serialized_but_not_saved() {
+        #[derive(Serialize, Deserialize, Default)]
+        struct Composed {
+            counter: TestState,
+            todos: Vec<String>,
+        }
+
+        let storage = crate::MemoryBackend::new();
+        let counter = StateBuilder::new(TestState::default())
+            .derive("doubled", |state| state.counter * 2)
+            // Not an object, so there is nowhere to put it
+            .derive("counter.value", |state| state.counter);
+        let mut manager = StateBuilder::new(Composed::default())
+            .on("todos/ADD", |state, action| {
+                state.todos.push(action.require_payload()?);
+                Ok(())
+            })
+            .slice("counter", counter)
+            .derive("stats.total", |state| state.todos.len())
+            .persist_with(storage.clone())
+            .build();
+
+        let outcome = manager
+            .dispatch(&Action::with_json("todos/ADD", "milk".into()))
+            .unwrap();
+        assert_eq!(outcome.state["stats"]["total"], 1);
+        assert_eq!(
+            manager.get_initial_state()["counter"],
+            serde_json::json!({ "counter": 0, "message": "", "doubled": 0 })
+        );
+        assert_eq!(
+            manager
+                .simulate(&[Action::with_json("todos/ADD", "eggs".into())])
+                .unwrap()["stats"]["total"],
+            2
+        );
+
+        manager.flush().unwrap();
+        let saved = storage.load().unwrap().unwrap();
+        assert_eq!(saved["todos"], serde_json::json!(["milk"]));
+        assert!(saved["stats"].get("total").is_none());
+        assert!(saved["counter"].get("doubled").is_none());
+    }
+
+    #[test]
+    fn test_modules_register_on_the_builder() {
+        struct Counter {
+            step: i32,
+        }
+
+        impl StateModule<TestState> for Counter {
+            fn register(self, builder: StateBuilder<TestState>) -> StateBuilder<TestState> {
+                builder.on("counter/STEP", move |state, _| {
+                    state.counter += self.step;
+                    Ok(())
+                })
+            }
+        }
+
+        let mut manager = StateBuilder::new(TestState::default())
+            .module(Counter { step: 5 })
+            .module(|builder: StateBuilder<TestState>| {
+                builder.on("SET_MESSAGE", |state, action| {
+                    state.message = action.require_payload()?;
+                    Ok(())
+                })
+            })
+            .build();
+
+        manager.dispatch(&Action::new("counter/STEP")).unwrap();
+        let state = manager
+            .dispatch(&Action::with_json("SET_MESSAGE", "hi".into()))
+            .unwrap()
+            .state;
+        assert_eq!(state, serde_json::json!({ "counter": 5, "message": "hi" }));
+    }
+
+    #[test]
+    fn test_namespace_is_enforced() {
+        let result = StateBuilder::new(TestState::default())
+            .namespace(Namespace::Required("app/"))
+            .on("INCREMENT", |_, _| Ok(()))
+            .try_build();
+        assert!(matches!(
+            result,
+            Err(crate::RstateError::InvalidActionKind(_))
+        ));
+
+        let mut manager = StateBuilder::new(TestState::default())
+            .namespace(Namespace::Required("app/"))
+            .on("app/INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .build();
+        assert_eq!(
+            manager
+                .dispatch(&Action::new("app/INCREMENT"))
+                .unwrap()
+                .state["counter"],
+            1
+        );
+        assert!(matches!(
+            manager.dispatch(&Action::new("INCREMENT")),
+            Err(crate::RstateError::InvalidActionKind(_))
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_handlers_are_reported() {
+        let duplicated = |on_duplicate| {
+            let todos = StateBuilder::new(Vec::<String>::new())
+                .on("ADD", |_, _| Ok(()))
+                .on("ADD", |_, _| Ok(()));
+            StateBuilder::new(TestState::default())
+                .on_duplicate(on_duplicate)
+                .on("INCREMENT", |state, _| {
+                    state.counter += 1;
+                    Ok(())
+                })
+                .on("INCREMENT", |state, _| {
+                    state.counter += 2;
+                    Ok(())
+                })
+                .on("INCREMENT", |_, _| Ok(()))
+                .slice("todos", todos)
+                .try_build()
+        };
+
+        let err = duplicated(OnDuplicate::Error).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Duplicate handlers for: INCREMENT, todos/ADD"
+        );
+        // The last handler wins otherwise
+        let mut manager = duplicated(OnDuplicate::Warn).unwrap();
+        let state = manager.dispatch(&Action::new("INCREMENT")).unwrap().state;
+        assert_eq!(state["counter"], 0);
+        assert!(duplicated(OnDuplicate::Overwrite).is_ok());
+    }
+
+    #[test]
+    fn test_debounced_saves_are_flushed() {
+        let path =
+            std::env::temp_dir().join(format!("rstate-debounce-{}.json", std::process::id()));
+        let mut manager = StateBuilder::new(TestState::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .persist(&path)
+            .save_debounce(std::time::Duration::from_secs(60))
+            .build();
+
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        // Still waiting for the debounce
+        assert!(!path.exists());
+
+        manager.flush().unwrap();
+        let saved: JsonValue = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved["counter"], 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_event_sourced_store_replays_logged_actions() {
+        let path =
+            std::env::temp_dir().join(format!("rstate-event-sourced-{}.jsonl", std::process::id()));
+        let storage = crate::MemoryBackend::new();
+        let build = || {
+            StateBuilder::new(TestState::default())
+                .on("INCREMENT", |state, _| {
+                    state.counter += 1;
+                    Ok(())
+                })
+                .persist_with(storage.clone())
+                .event_sourced(EventLog::new(&path).snapshot_every(3))
+                .build()
+        };
+
+        let mut manager = build();
+        for _ in 0..4 {
+            manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        }
+        // Snapshot of the first 3 actions, the 4th is in the log
+        let snapshot = storage.load().unwrap().unwrap();
+        assert_eq!(snapshot["counter"], 3);
+        assert_eq!(snapshot[crate::JOURNAL_KEY], 3);
+        drop(manager);
+
+        let mut manager = build();
+        assert_eq!(manager.get_initial_state()["counter"], 4);
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager.flush().unwrap();
+        assert_eq!(storage.load().unwrap().unwrap()["counter"], 5);
+        assert!(std::fs::read_to_string(&path).unwrap().is_empty());
+        assert_eq!(build().get_initial_state()["counter"], 5);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_action_kinds_are_listed() {
+        let todos = StateBuilder::new(Vec::<String>::new())
+            .on("ADD", |_, _| Ok(()))
+            .on_default(|_, _| Ok(()));
+        #[derive(Serialize, Deserialize, Default)]
+        struct Composed {
+            todos: Vec<String>,
+        }
+        let manager = StateBuilder::new(Composed::default())
+            .on("SAVE", |_, _| Ok(()))
+            .slice("todos", todos)
+            .flags(["beta"])
+            .build();
+
+        let kinds = manager.action_kinds();
+        assert_eq!(
+            kinds.kinds,
+            [
+                RESET_ACTION,
+                SET_FLAG_ACTION,
+                TOGGLE_FLAG_ACTION,
+                "SAVE",
+                "todos/ADD"
+            ]
+        );
+        assert_eq!(kinds.default_slices, ["todos"]);
+        assert!(!kinds.default_handler);
+    }
+
+    #[test]
+    fn test_typed_watcher_sees_changes_of_its_slice() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let watcher_seen = seen.clone();
         let mut manager = StateBuilder::new(TestState::default())
             .on("INCREMENT", |state, _| {
                 state.counter += 1;
@@ -332,51 +2798,516 @@ mod tests {
                 state.message = action.require_payload()?;
                 Ok(())
             })
-            .build();
+            .build()
+            .watch_typed("counter", move |old: &i32, new: &i32| {
+                watcher_seen.lock().unwrap().push((*old, *new));
+            });
 
-        // Test initial state
-        let initial = manager.get_initial_state();
-        assert_eq!(initial["counter"], 0);
-        assert_eq!(initial["message"], "");
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager
+            .dispatch(&Action::with_json("SET_MESSAGE", "hi".into()))
+            .unwrap();
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
 
-        // Test INCREMENT
-        let action = Action::new("INCREMENT");
-        let result = manager.dispatch(&action).unwrap();
-        assert_eq!(result["counter"], 1);
+        assert_eq!(*seen.lock().unwrap(), vec![(0, 1), (1, 2)]);
+    }
 
-        // Test SET_MESSAGE
-        let action = Action::with_payload("SET_MESSAGE", "Hello").unwrap();
-        let result = manager.dispatch(&action).unwrap();
-        assert_eq!(result["message"], "Hello");
+    #[test]
+    fn test_persisted_state_is_restored() {
+        let path = std::env::temp_dir().join(format!("rstate-builder-{}.json", std::process::id()));
+        let build = || {
+            StateBuilder::new(TestState::default())
+                .on("INCREMENT", |state, _| {
+                    state.counter += 1;
+                    Ok(())
+                })
+                .persist_with(crate::FileBackend::new(&path))
+                .build()
+        };
+
+        let mut manager = build();
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+
+        let restored = build();
+        assert_eq!(restored.get_state_clone().unwrap().counter, 2);
+
+        std::fs::remove_file(path).unwrap();
     }
 
     #[test]
-    fn test_state_builder_default_handler() {
+    fn test_memory_backend_persistence() {
+        let storage = crate::MemoryBackend::with_value(serde_json::json!({ "counter": 5 }));
         let mut manager = StateBuilder::new(TestState::default())
-            .on_default(|_state, action| Err(crate::RstateError::action_not_found(&action.kind)))
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .persist_with(storage.clone())
             .build();
+        assert_eq!(manager.get_state_clone().unwrap().counter, 5);
 
-        let action = Action::new("UNKNOWN");
-        let result = manager.dispatch(&action);
-        assert!(result.is_err());
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        assert_eq!(
+            storage.load().unwrap(),
+            Some(serde_json::json!({ "counter": 6, "message": "" }))
+        );
     }
 
     #[test]
-    fn test_state_builder_unknown_action_ignored() {
-        let mut manager = StateBuilder::new(TestState {
-            counter: 5,
-            message: "test".into(),
+    fn test_save_tap_reports_writes() {
+        let mut manager = StateBuilder::new(TestState::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .persist_with(crate::MemoryBackend::new())
+            .build();
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let tapped = writes.clone();
+        manager.set_save_tap(Arc::new(move |status, _| {
+            tapped.lock().unwrap().push(status.clone());
+        }));
+
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager.flush().unwrap();
+        let writes = writes.lock().unwrap();
+        assert_eq!(writes.len(), 2);
+        assert!(matches!(writes[0], SaveStatus::Saved { .. }));
+    }
+
+    #[test]
+    fn test_versioned_persistence_migrates() {
+        let storage = crate::MemoryBackend::with_value(serde_json::json!({ "count": 5 }));
+        let versioned = || {
+            StateBuilder::new(TestState::default())
+                .on("INCREMENT", |state, _| {
+                    state.counter += 1;
+                    Ok(())
+                })
+                .persist_with(storage.clone())
+                .version(2)
+                .migration(1, |mut state| {
+                    state["counter"] = state["count"].take();
+                    state
+                })
+        };
+
+        let mut manager = versioned().build();
+        assert_eq!(manager.get_state_clone().unwrap().counter, 5);
+        assert_eq!(manager.version(), Some(2));
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        let saved = storage.load().unwrap().unwrap();
+        assert_eq!(saved[crate::VERSION_KEY], 2);
+
+        // Already up to date: not migrated again
+        assert_eq!(versioned().build().get_state_clone().unwrap().counter, 6);
+        // Saved by a newer version
+        let newer = StateBuilder::new(TestState::default())
+            .persist_with(storage.clone())
+            .version(1)
+            .try_build();
+        assert!(newer.is_err());
+    }
+
+    #[test]
+    fn test_migration_dry_run_leaves_the_persisted_state() {
+        let persisted = serde_json::json!({ "count": 5 });
+        let storage = crate::MemoryBackend::with_value(persisted.clone());
+        let builder = |migrate: fn(JsonValue) -> JsonValue| {
+            StateBuilder::new(TestState::default())
+                .persist_with(storage.clone())
+                .version(2)
+                .migration(1, migrate)
+        };
+
+        let report = builder(|mut state| {
+            state["counter"] = state["count"].take();
+            state
         })
-        .on("INCREMENT", |state, _| {
-            state.counter += 1;
-            Ok(())
+        .dry_run_migrations();
+        assert!(report.is_ok());
+        assert_eq!(report.persisted_version, Some(1));
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(report.steps[0].touched, ["count", "counter"]);
+        assert_eq!(storage.load().unwrap(), Some(persisted));
+
+        // The result isn't a `TestState`
+        let report = builder(|mut state| {
+            state["counter"] = "five".into();
+            state
         })
-        .build();
+        .dry_run_migrations();
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].starts_with("migrated state doesn't fit the state"));
 
-        // Unknown action should be silently ignored
-        let action = Action::new("UNKNOWN");
-        let result = manager.dispatch(&action).unwrap();
-        assert_eq!(result["counter"], 5); // Unchanged
+        // Nothing persisted yet
+        let report = StateBuilder::new(TestState::default())
+            .persist_with(crate::MemoryBackend::new())
+            .version(2)
+            .dry_run_migrations();
+        assert!(report.is_ok());
+        assert_eq!(report.persisted_version, None);
+    }
+
+    #[test]
+    fn test_unloadable_persisted_states_are_left_alone() {
+        let persisted = serde_json::json!({ "counter": 5, "@@version": 1 });
+        let storage = crate::MemoryBackend::with_value(persisted.clone());
+        let builder = |migrate: fn(JsonValue) -> JsonValue| {
+            StateBuilder::new(TestState::default())
+                .on("INCREMENT", |state, _| {
+                    state.counter += 1;
+                    Ok(())
+                })
+                .persist_with(storage.clone())
+                .version(2)
+                .migration(1, migrate)
+        };
+
+        // A panicking migration is an error, not a panic
+        let err = builder(|_| panic!("boom")).try_build().err().unwrap();
+        assert!(matches!(err, crate::RstateError::HandlerPanic(_)));
+        // A migrated state that doesn't fit the state
+        let incompatible = |mut state: JsonValue| {
+            state["counter"] = "five".into();
+            state
+        };
+        assert!(builder(incompatible).try_build().is_err());
+
+        // `build` starts from the initial state, without overwriting the persisted one
+        let mut manager = builder(incompatible).build();
+        assert_eq!(manager.get_state_clone().unwrap().counter, 0);
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager.flush().unwrap();
+        assert_eq!(storage.load().unwrap(), Some(persisted.clone()));
+
+        // Saved by a newer version
+        let mut manager = StateBuilder::new(TestState::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .persist_with(storage.clone())
+            .version(0)
+            .build();
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager.flush().unwrap();
+        assert_eq!(storage.load().unwrap(), Some(persisted));
+    }
+
+    #[test]
+    fn test_schema_fingerprint_is_persisted_and_checked() {
+        let storage = crate::MemoryBackend::with_value(serde_json::json!({ "counter": 5 }));
+        let fingerprinted = |fingerprint: &str| {
+            StateBuilder::new(TestState::default())
+                .on("INCREMENT", |state, _| {
+                    state.counter += 1;
+                    Ok(())
+                })
+                .persist_with(storage.clone())
+                .schema_fingerprint(fingerprint)
+                .build()
+        };
+
+        // Saved before fingerprinting: nothing to compare with
+        let mut manager = fingerprinted("aaaa");
+        let schema = manager.schema().unwrap();
+        assert_eq!(
+            (schema.fingerprint.as_str(), schema.persisted),
+            ("aaaa", None)
+        );
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        let saved = storage.load().unwrap().unwrap();
+        assert_eq!(saved[crate::SCHEMA_KEY], "aaaa");
+        assert_eq!(fingerprinted("aaaa").schema().unwrap().persisted, None);
+
+        // The schema changed without a migration
+        let manager = fingerprinted("bbbb");
+        assert_eq!(manager.schema().unwrap().persisted.as_deref(), Some("aaaa"));
+        assert_eq!(manager.get_state_clone().unwrap().counter, 6);
+
+        // Expected after a version bump
+        let migrated = StateBuilder::new(TestState::default())
+            .persist_with(storage.clone())
+            .schema_fingerprint("bbbb")
+            .version(2)
+            .migration(1, |state| state)
+            .build();
+        assert_eq!(migrated.schema().unwrap().persisted, None);
+    }
+
+    #[test]
+    fn test_reset_restores_the_initial_state() {
+        let storage = crate::MemoryBackend::with_value(serde_json::json!({ "counter": 5 }));
+        let mut manager = StateBuilder::new(TestState::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .flags(["beta"])
+            .persist_with(storage.clone())
+            .build();
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager
+            .dispatch(&Action::with_json(crate::TOGGLE_FLAG_ACTION, "beta".into()))
+            .unwrap();
+
+        let state = manager.dispatch(&Action::new(RESET_ACTION)).unwrap().state;
+        assert_eq!(state["counter"], 0);
+        assert_eq!(state[crate::FLAGS_KEY]["beta"], false);
+        assert_eq!(storage.load().unwrap().unwrap()["counter"], 0);
+    }
+
+    #[test]
+    fn test_retain_bounds_collections_after_dispatch() {
+        let mut manager = StateBuilder::new(serde_json::json!({ "logs": [] }))
+            .on("LOG", |state, action| {
+                state["logs"]
+                    .as_array_mut()
+                    .unwrap()
+                    .push(action.require_payload()?);
+                Ok(())
+            })
+            .retain("logs", Retention::Newest(2))
+            .build();
+        for line in 1..=3 {
+            manager
+                .dispatch(&Action::with_payload("LOG", line).unwrap())
+                .unwrap();
+        }
+        assert_eq!(
+            manager.get_initial_state()["logs"],
+            serde_json::json!([2, 3])
+        );
+    }
+
+    #[test]
+    fn test_hydrate_replaces_the_state() {
+        let hydrate = |payload| Action::with_json(HYDRATE_ACTION, payload);
+        let snapshot = serde_json::json!({ "counter": 7, "message": "restored" });
+
+        let mut closed = StateBuilder::new(TestState::default()).build();
+        assert!(matches!(
+            closed.dispatch(&hydrate(snapshot.clone())),
+            Err(crate::RstateError::Rejected(_))
+        ));
+
+        let mut manager = StateBuilder::new(TestState::default())
+            .hydratable(true)
+            .build();
+        assert_eq!(
+            manager.dispatch(&hydrate(snapshot.clone())).unwrap().state,
+            snapshot
+        );
+        assert!(matches!(
+            manager.dispatch(&hydrate(serde_json::json!({ "counter": "seven" }))),
+            Err(crate::RstateError::InvalidPayload(_))
+        ));
+        assert_eq!(manager.get_initial_state(), snapshot);
+    }
+
+    #[test]
+    fn test_replace_state() {
+        let mut manager = StateBuilder::new(TestState::default()).build();
+
+        manager
+            .replace_state(serde_json::json!({ "counter": 7, "message": "hi" }))
+            .unwrap();
+        assert_eq!(manager.get_state_clone().unwrap().counter, 7);
+
+        // Invalid states are rejected and leave the state untouched
+        assert!(
+            manager
+                .replace_state(serde_json::json!({ "counter": "seven" }))
+                .is_err()
+        );
+        assert_eq!(manager.get_state_clone().unwrap().message, "hi");
+    }
+
+    #[test]
+    fn test_emit_policy_overrides() {
+        let window = std::time::Duration::from_millis(50);
+        let manager = StateBuilder::new(TestState::default())
+            .emit_policy("SCROLL", EmitPolicy::coalesced(window))
+            .emit_policy("SAVED", EmitPolicy::immediate().force())
+            .build();
+
+        assert_eq!(
+            manager.emit_policy("SCROLL").unwrap().coalesce_window(),
+            Some(window)
+        );
+        assert!(manager.emit_policy("SAVED").unwrap().is_forced());
+        assert_eq!(manager.emit_policy("INCREMENT"), None);
+    }
+
+    #[test]
+    fn test_simulate_leaves_state_untouched() {
+        let manager = StateBuilder::new(TestState::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .build();
+
+        let preview = manager
+            .simulate(&[Action::new("INCREMENT"), Action::new("INCREMENT")])
+            .unwrap();
+        assert_eq!(preview["counter"], 2);
+        assert_eq!(manager.get_initial_state()["counter"], 0);
+    }
+
+    #[cfg(feature = "macros")]
+    #[crate::handlers]
+    impl TestState {
+        fn increment(&mut self) {
+            self.counter += 1;
+        }
+
+        fn set_message(&mut self, message: String) {
+            self.message = message;
+        }
+
+        #[rstate(kind = "counter/SET")]
+        fn set(&mut self, counter: i32, message_suffix: Option<String>) -> Result<()> {
+            if counter < 0 {
+                return Err(crate::RstateError::invalid_payload("negative counter"));
+            }
+            self.counter = counter;
+            self.message.extend(message_suffix);
+            Ok(())
+        }
+
+        fn restart(&mut self) -> Result<Next> {
+            self.counter = 0;
+            Ok(Next::Dispatch(vec![Action::new("INCREMENT")]))
+        }
+
+        fn label(&self) -> String {
+            format!("{}: {}", self.message, self.counter)
+        }
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn test_handlers_from_methods() {
+        let mut manager = StateBuilder::new(TestState::default()).handlers().build();
+
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+        manager
+            .dispatch(&Action::with_payload("SET_MESSAGE", "count").unwrap())
+            .unwrap();
+        let set = |payload| Action::with_json("counter/SET", payload);
+        let state = manager
+            .dispatch(&set(
+                serde_json::json!({ "counter": 5, "messageSuffix": "!" }),
+            ))
+            .unwrap()
+            .state;
+        assert_eq!(state["counter"], 5);
+        assert!(
+            manager
+                .dispatch(&set(serde_json::json!({ "counter": -1 })))
+                .is_err()
+        );
+        assert_eq!(manager.with_state(TestState::label).unwrap(), "count!: 5");
+
+        // Follow-ups returned by a method are chained
+        let state = manager.dispatch(&Action::new("RESTART")).unwrap().state;
+        assert_eq!(state["counter"], 1);
+    }
+
+    #[test]
+    fn test_boxed_manager_downcasts() {
+        let manager = StateBuilder::new(TestState::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .build();
+        let store: crate::ManagedState = std::sync::RwLock::new(Box::new(manager));
+        let mut state_manager = store.write().unwrap();
+        state_manager.dispatch(&Action::new("INCREMENT")).unwrap();
+
+        let built = state_manager
+            .downcast_ref::<BuiltStateManager<TestState>>()
+            .unwrap();
+        assert_eq!(built.with_state(|state| state.counter).unwrap(), 1);
+        assert!(
+            state_manager
+                .downcast_mut::<crate::PinnedManager>()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_readers_share_the_lock() {
+        let store: crate::ManagedState =
+            std::sync::RwLock::new(Box::new(StateBuilder::new(TestState::default()).build()));
+        let state_manager = store.read().unwrap();
+        let built = state_manager
+            .downcast_ref::<BuiltStateManager<TestState>>()
+            .unwrap();
+
+        // Another thread reads while this one holds both locks for reading
+        let counter = built
+            .with_state(|_| {
+                std::thread::scope(|scope| {
+                    scope
+                        .spawn(|| store.read().unwrap().get_initial_state()["counter"].clone())
+                        .join()
+                        .unwrap()
+                })
+            })
+            .unwrap();
+        assert_eq!(counter, 0);
+    }
+
+    #[test]
+    fn test_handler_panic_rolls_back() {
+        let mut manager = StateBuilder::new(TestState::default())
+            .on("INCREMENT", |state, _| {
+                state.counter += 1;
+                Ok(())
+            })
+            .on("EXPLODE", |state, _| {
+                state.counter = 100;
+                panic!("boom")
+            })
+            .derive("doubled", |state| state.counter * 2)
+            .build();
+        manager.dispatch(&Action::new("INCREMENT")).unwrap();
+
+        let err = manager.dispatch(&Action::new("EXPLODE")).unwrap_err();
+        assert_eq!(err.to_string(), "Handler panicked: EXPLODE: boom");
+        assert_eq!(manager.get_initial_state()["counter"], 1);
+        assert_eq!(manager.get_initial_state()["doubled"], 2);
+        assert_eq!(
+            manager.dispatch(&Action::new("INCREMENT")).unwrap().state["counter"],
+            2
+        );
+    }
+
+    #[test]
+    fn test_guards_filter_on_source() {
+        let guards: Vec<ActionGuard> = vec![Box::new(|action| {
+            if action.kind.starts_with("RSTATE_RESTORE_") && action.source() != ActionSource::Replay
+            {
+                return Err(crate::RstateError::rejected(&action.kind));
+            }
+            Ok(())
+        })];
+
+        let restore = Action::new("RSTATE_RESTORE_SNAPSHOT");
+        assert!(matches!(
+            check_guards(
+                &guards,
+                &restore.clone().with_source(ActionSource::Frontend)
+            ),
+            Err(crate::RstateError::Rejected(_))
+        ));
+        assert!(check_guards(&guards, &restore.with_source(ActionSource::Replay)).is_ok());
+        assert!(check_guards(&guards, &Action::new("INCREMENT")).is_ok());
     }
 
     #[test]
@@ -385,6 +3316,7 @@ mod tests {
         let action = Action::new("TEST");
         assert_eq!(action.kind, "TEST");
         assert!(!action.has_payload());
+        assert_eq!(Action::from("TEST").kind, action.kind);
 
         // Test Action::with_payload
         let action = Action::with_payload("SET", 42i32).unwrap();
@@ -399,5 +3331,58 @@ mod tests {
         // Test payload_as
         let opt: Option<i32> = action.payload_as().unwrap();
         assert_eq!(opt, Some(42));
+
+        // Test trace id, as sent by the frontend
+        let action: Action = serde_json::from_value(serde_json::json!({
+            "kind": "SAVE",
+            "payload": null,
+            "meta": { "traceId": "click-42" }
+        }))
+        .unwrap();
+        assert_eq!(action.trace_id(), Some("click-42"));
+        assert_eq!(Action::new("SAVE").with_trace_id("x").trace_id(), Some("x"));
+        assert_eq!(Action::new("SAVE").trace_id(), None);
+
+        // Test source tagging
+        assert_eq!(Action::new("SAVE").source(), ActionSource::Rust);
+        let action = Action::new("SAVE")
+            .with_trace_id("x")
+            .with_source(ActionSource::Replay);
+        assert_eq!(action.source(), ActionSource::Replay);
+        assert_eq!(action.trace_id(), Some("x"));
+
+        // Test frontend metadata, which replaces whatever the frontend claimed
+        let action: Action = serde_json::from_value(serde_json::json!({
+            "kind": "SAVE",
+            "meta": { "source": "replay", "traceId": "t", "id": "forged", "window": "other" }
+        }))
+        .unwrap();
+        assert_eq!(action.dispatch_id(), Some("forged"));
+        let action = action.tag_frontend(Some("main"));
+        assert_eq!(action.source(), ActionSource::Frontend);
+        assert_eq!(action.trace_id(), Some("t"));
+        assert_eq!(action.origin_window(), Some("main"));
+        assert_ne!(action.dispatch_id(), Some("forged"));
+        assert!(action.timestamp().is_some());
+        assert_eq!(Action::new("SAVE").dispatch_id(), None);
+
+        // Test payload size limits, on the serialized payload
+        let action = Action::with_payload("SET_NAME", "abcd").unwrap();
+        assert!(check_payload_size(&action, Some(6)).is_ok());
+        assert!(check_payload_size(&action, None).is_ok());
+        assert!(check_payload_size(&Action::new("SAVE"), Some(0)).is_ok());
+        let err = check_payload_size(&action, Some(5)).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::RstateError::PayloadTooLarge {
+                size: 6,
+                limit: 5,
+                ..
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Payload of SET_NAME too large: 6 bytes (limit: 5)"
+        );
     }
 }
@@ -0,0 +1,904 @@
+//! Dispatch, change detection and emission of the stores.
+//!
+//! [`Rstate`](crate::Rstate) runs changes through [`commit`], which applies them under the store's
+//! lock and bumps its revision, then hand the outcome to the [`Publisher`], which turns
+//! it into an update (a patch, an envelope or the full state) and emits it now or once
+//! its coalescing window has passed.
+
+use serde_json::json;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Runtime};
+use tauri_plugin_rstate_core::{STATE_PATCH_EVENT, StatePatch, diff};
+
+use crate::change::{FloatComparison, StateUpdate, UNCHANGED_KEY, trim_unchanged};
+use crate::emit_policy::{Coalescer, EmitPolicy};
+use crate::error::catch_panic;
+use crate::listeners::Listeners;
+use crate::logging::ACTION_LOG_TARGET;
+use crate::models::{Action, ActionSource, DispatchOutcome, JsonValue, RstateManager};
+use crate::redaction::redact;
+use crate::schema::SchemaFingerprint;
+use crate::subscriptions::Subscriptions;
+use crate::transport::{EventTransport, UpdateTransport};
+use crate::watchers::Watchers;
+use crate::window_stores::event_window;
+use crate::{ManagedState, PluginOptions};
+
+pub use tauri_plugin_rstate_core::STATE_UPDATE_EVENT;
+
+// The app-wide store. Its state manager can be registered, replaced and unregistered
+// at runtime; dispatches in flight keep the manager they started with.
+#[derive(Default)]
+pub(crate) struct AppStore {
+    manager: RwLock<Option<Arc<ManagedState>>>,
+}
+
+impl AppStore {
+    pub(crate) fn get(&self) -> crate::Result<Arc<ManagedState>> {
+        self.manager
+            .read()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?
+            .clone()
+            .ok_or(crate::RstateError::NotRegistered)
+    }
+
+    pub(crate) fn is_registered(&self) -> bool {
+        self.manager.read().is_ok_and(|manager| manager.is_some())
+    }
+
+    // Register `manager`, failing if one is already registered
+    pub(crate) fn register(&self, manager: Box<dyn RstateManager>) -> crate::Result<()> {
+        let mut current = self
+            .manager
+            .write()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+        if current.is_some() {
+            return Err(crate::RstateError::AlreadyRegistered);
+        }
+        *current = Some(Arc::new(RwLock::new(manager)));
+        Ok(())
+    }
+
+    // Swap in `manager`, or unregister the current one with `None`.
+    // Returns the previous manager's store, if any.
+    pub(crate) fn replace(
+        &self,
+        manager: Option<Box<dyn RstateManager>>,
+    ) -> crate::Result<Option<Arc<ManagedState>>> {
+        let mut current = self
+            .manager
+            .write()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+        Ok(mem::replace(
+            &mut *current,
+            manager.map(|manager| Arc::new(RwLock::new(manager))),
+        ))
+    }
+}
+
+// Lock a store for a change
+pub(crate) fn lock(
+    store: &ManagedState,
+) -> crate::Result<RwLockWriteGuard<'_, Box<dyn RstateManager>>> {
+    store
+        .write()
+        .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))
+}
+
+// Lock a store for reading, alongside other readers
+pub(crate) fn read(
+    store: &ManagedState,
+) -> crate::Result<RwLockReadGuard<'_, Box<dyn RstateManager>>> {
+    store
+        .read()
+        .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))
+}
+
+// Read the full state of a store
+pub(crate) fn read_state(store: &ManagedState) -> crate::Result<JsonValue> {
+    Ok(read(store)?.get_initial_state())
+}
+
+// Read the full state of a store, with the redacted paths hidden
+pub(crate) fn read_redacted(store: &ManagedState) -> crate::Result<JsonValue> {
+    let state_guard = read(store)?;
+    let mut state = state_guard.get_initial_state();
+    redact(&mut state, &state_guard.redacted_paths());
+    Ok(state)
+}
+
+// Read the full state of a store along with its revision, under the same lock. If the
+// store is still at revision `known`, the state is left out for the unchanged sentinel.
+pub(crate) fn read_revision(
+    store: &ManagedState,
+    revision: &AtomicU64,
+    known: Option<u64>,
+) -> crate::Result<StateUpdate> {
+    let state_guard = read(store)?;
+    let revision = revision.load(Ordering::SeqCst);
+    let state = if known == Some(revision) {
+        json!({ UNCHANGED_KEY: revision })
+    } else {
+        state_guard.get_initial_state()
+    };
+    Ok(StateUpdate {
+        revision,
+        state,
+        trace_id: None,
+        meta: None,
+    })
+}
+
+// Schema fingerprint of a store's state
+pub(crate) fn schema(store: &ManagedState) -> crate::Result<Option<SchemaFingerprint>> {
+    Ok(read(store)?.schema())
+}
+
+// Version of a store's state, if its manager migrates persisted states
+pub(crate) fn version(store: &ManagedState) -> crate::Result<Option<u32>> {
+    Ok(read(store)?.version())
+}
+
+// Preview the state dispatching `actions` to a store would produce
+pub(crate) fn simulate(store: &ManagedState, actions: &[Action]) -> crate::Result<JsonValue> {
+    read(store)?.simulate(actions)
+}
+
+// Read the state of a store along with the state dispatching `actions` would produce,
+// both under the same lock
+pub(crate) fn preview(
+    store: &ManagedState,
+    actions: &[Action],
+) -> crate::Result<(JsonValue, JsonValue)> {
+    let state_guard = read(store)?;
+    let simulated = state_guard.simulate(actions)?;
+    Ok((state_guard.get_initial_state(), simulated))
+}
+
+// Compute the selector `name` of a store
+pub(crate) fn select(store: &ManagedState, name: &str) -> crate::Result<JsonValue> {
+    read(store)?.select(name)
+}
+
+// Cache metrics of the selectors of a store
+pub(crate) fn selector_stats(store: &ManagedState) -> crate::Result<Vec<crate::SelectorStats>> {
+    Ok(read(store)?.selector_stats())
+}
+
+// Action kinds a store handles
+pub(crate) fn action_kinds(store: &ManagedState) -> crate::Result<crate::ActionKinds> {
+    read(store)?.action_kinds()
+}
+
+// A page of the persisted action log of a store
+pub(crate) fn persisted_history(
+    store: &ManagedState,
+    after: u64,
+    limit: usize,
+) -> crate::Result<crate::HistoryPage> {
+    read(store)?.persisted_history(after, limit)
+}
+
+// Paths of a store's state hidden from the frontend
+pub(crate) fn redacted_paths(store: &ManagedState) -> crate::Result<Vec<String>> {
+    Ok(read(store)?.redacted_paths())
+}
+
+// Outcome of a change applied to a store
+pub(crate) struct Commit {
+    // The state before the change, if kept to build patches or trim updates
+    current: Option<JsonValue>,
+    updated: JsonValue,
+    // The revision before the change if the state changed, else the current revision
+    previous_revision: Result<u64, u64>,
+    policy: EmitPolicy,
+    floats: FloatComparison,
+    // Paths of the state hidden from the frontend
+    redacted: Vec<String>,
+}
+
+impl Commit {
+    pub(crate) fn changed(&self) -> bool {
+        self.previous_revision.is_ok()
+    }
+
+    // The new revision and state, if the state changed
+    pub(crate) fn change(&self) -> Option<(u64, &JsonValue)> {
+        let previous_revision = self.previous_revision.ok()?;
+        Some((previous_revision + 1, &self.updated))
+    }
+
+    // The store's revision after the commit
+    pub(crate) fn revision(&self) -> u64 {
+        match self.previous_revision {
+            Ok(previous_revision) => previous_revision + 1,
+            Err(revision) => revision,
+        }
+    }
+
+    // Only changes are emitted, unless forced
+    pub(crate) fn should_emit(&self) -> bool {
+        self.changed() || self.policy.is_forced()
+    }
+
+    // The commit as the frontend sees it, with the redacted paths hidden
+    fn redact(&self) -> Self {
+        let hide = |state: &JsonValue| {
+            let mut state = state.clone();
+            redact(&mut state, &self.redacted);
+            state
+        };
+        Self {
+            current: self.current.as_ref().map(hide),
+            updated: hide(&self.updated),
+            previous_revision: self.previous_revision,
+            policy: self.policy,
+            floats: self.floats,
+            redacted: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_state(self) -> (JsonValue, bool) {
+        let changed = self.changed();
+        (self.updated, changed)
+    }
+}
+
+// The combined outcome of dispatches run in a row on a store
+#[derive(Default)]
+pub(crate) struct Merged {
+    state: Option<JsonValue>,
+    changed: bool,
+}
+
+impl Merged {
+    pub(crate) fn add(&mut self, outcome: DispatchOutcome) {
+        self.changed |= outcome.changed;
+        self.state = Some(outcome.state);
+    }
+
+    // The outcome of all the dispatches, with the current state if none succeeded
+    pub(crate) fn finish(self, state_manager: &dyn RstateManager) -> DispatchOutcome {
+        let state = self
+            .state
+            .unwrap_or_else(|| state_manager.get_initial_state());
+        DispatchOutcome::new(state, self.changed)
+    }
+}
+
+// Modify a store with `mutate`. `action` is the action being applied, if any; its emit
+// policy overrides `default_policy`. The state before the change is only read if
+// `keep_previous` is set.
+pub(crate) fn commit<F>(
+    store: &ManagedState,
+    revision: &AtomicU64,
+    default_policy: EmitPolicy,
+    action: Option<&Action>,
+    keep_previous: bool,
+    mutate: F,
+) -> crate::Result<Commit>
+where
+    F: FnOnce(&mut dyn RstateManager) -> crate::Result<DispatchOutcome>,
+{
+    // Hold the lock for the minimum time necessary
+    let mut state_guard = lock(store)?;
+    let current = keep_previous.then(|| state_guard.get_initial_state());
+
+    // Apply the change. A panic must not poison the store's lock: it's reported as an
+    // error, and the previous state put back if it was kept and the manager supports
+    // replacing it.
+    let kind = action.map_or("update", |action| action.kind.as_str());
+    let outcome = catch_panic(kind, || mutate(state_guard.as_mut())).inspect_err(|err| {
+        if let (crate::RstateError::HandlerPanic(_), Some(current)) = (err, &current) {
+            if let Err(err) = state_guard.replace_state(current.clone()) {
+                log::warn!(target: ACTION_LOG_TARGET, "rolling back '{kind}': {err}");
+            }
+        }
+    })?;
+    let floats = state_guard.float_comparison();
+    let redacted = state_guard.redacted_paths();
+
+    // Bump the revision while still holding the lock, so revisions follow dispatch order
+    let previous_revision = if outcome.changed {
+        Ok(revision.fetch_add(1, Ordering::SeqCst))
+    } else {
+        Err(revision.load(Ordering::SeqCst))
+    };
+
+    let policy = action
+        .and_then(|action| state_guard.emit_policy(&action.kind))
+        .unwrap_or(default_policy);
+
+    Ok(Commit {
+        current,
+        updated: outcome.state,
+        previous_revision,
+        policy,
+        floats,
+        redacted,
+    })
+}
+
+// Build the patch payload turning `current` into `updated`, or `None` if the full
+// state is smaller
+fn patch_update(
+    current: &JsonValue,
+    updated: &JsonValue,
+    revision: u64,
+    action: Option<&Action>,
+) -> crate::Result<Option<JsonValue>> {
+    let patch = to_value(StatePatch {
+        revision,
+        patch: diff(current, updated),
+        trace_id: action.and_then(Action::trace_id).map(str::to_owned),
+        meta: action.and_then(|action| action.meta.clone()),
+    })?;
+    Ok((patch.to_string().len() < updated.to_string().len()).then_some(patch))
+}
+
+fn to_value(value: impl serde::Serialize) -> crate::Result<JsonValue> {
+    serde_json::to_value(value).map_err(|e| crate::RstateError::serialization(e.to_string()))
+}
+
+// Emits an update event to a single window
+type WindowEmitter = Box<dyn Fn(&str, &str, &JsonValue) -> crate::Result<()> + Send + Sync>;
+
+// Turns committed changes into updates, and hands them to the transports, or to their
+// window for the updates of window stores
+pub(crate) struct Publisher {
+    transports: Vec<Box<dyn UpdateTransport>>,
+    to_window: Option<WindowEmitter>,
+    trim_unchanged: bool,
+    envelope_updates: bool,
+    emit_patches: bool,
+    skip_dispatching_window: bool,
+    emit_policy: EmitPolicy,
+    coalescer: Coalescer,
+    subscriptions: Subscriptions,
+    watchers: Watchers,
+}
+
+impl Publisher {
+    // Take the emission options, along with the transports
+    pub(crate) fn new<R: Runtime>(
+        app: &AppHandle<R>,
+        options: &mut PluginOptions<R>,
+        listeners: &Arc<Listeners>,
+    ) -> Self {
+        let mut transports = mem::take(&mut options.transports);
+        let mut to_window: Option<WindowEmitter> = None;
+        if options.emit_events {
+            let transport = if options.skip_idle_windows {
+                EventTransport::listening_only(app.clone(), listeners.clone())
+            } else {
+                EventTransport::new(app.clone())
+            };
+            transports.insert(0, Box::new(transport));
+            let app = app.clone();
+            to_window = Some(Box::new(move |label, event, payload| {
+                app.emit_to(label, event, payload)
+                    .map_err(|err| crate::RstateError::Emit(err.to_string()))
+            }));
+        }
+
+        Self {
+            transports,
+            to_window,
+            trim_unchanged: options.trim_unchanged,
+            envelope_updates: options.envelope_updates,
+            emit_patches: options.emit_patches,
+            skip_dispatching_window: options.skip_dispatching_window,
+            emit_policy: options.emit_policy,
+            coalescer: Coalescer::default(),
+            subscriptions: Subscriptions::default(),
+            watchers: Watchers::default(),
+        }
+    }
+
+    // The global emit policy
+    pub(crate) fn emit_policy(&self) -> EmitPolicy {
+        self.emit_policy
+    }
+
+    // Whether commits must keep the previous state, to build patches or trim updates
+    pub(crate) fn keeps_previous(&self) -> bool {
+        self.emit_patches || self.trim_unchanged
+    }
+
+    // The channels subscribed to updates
+    pub(crate) fn subscriptions(&self) -> &Subscriptions {
+        &self.subscriptions
+    }
+
+    // The backend watchers of the state
+    pub(crate) fn watchers(&self) -> &Watchers {
+        &self.watchers
+    }
+
+    // Emit the update for `commit` under `event`
+    pub(crate) fn publish(
+        self: &Arc<Self>,
+        event: &str,
+        commit: &Commit,
+        action: Option<&Action>,
+    ) -> crate::Result<()> {
+        if commit.changed() {
+            self.watchers.notify(event, &commit.updated);
+        }
+        let redacted;
+        let commit = if commit.redacted.is_empty() {
+            commit
+        } else {
+            redacted = commit.redact();
+            &redacted
+        };
+        let policy = commit.policy;
+        if commit.changed() {
+            self.subscriptions.notify(event, &commit.updated);
+        }
+
+        // An immediate update supersedes a pending coalesced one
+        let superseded = policy.coalesce_window().is_none() && self.coalescer.take(event).is_some();
+
+        if self.emit_patches
+            && event == STATE_UPDATE_EVENT
+            && !superseded
+            && policy.coalesce_window().is_none()
+        {
+            if let (Ok(previous_revision), Some(current)) =
+                (commit.previous_revision, &commit.current)
+            {
+                let revision = previous_revision + 1;
+                if let Some(patch) = patch_update(current, &commit.updated, revision, action)? {
+                    return self.send(STATE_PATCH_EVENT, &patch);
+                }
+            }
+        }
+
+        let payload = if self.envelope_updates || self.trim_unchanged {
+            let current = commit.current.as_ref();
+            let (revision, state) = match (commit.previous_revision, current) {
+                (Ok(previous_revision), Some(current))
+                    if self.trim_unchanged && !superseded && policy.coalesce_window().is_none() =>
+                {
+                    let state =
+                        trim_unchanged(current, &commit.updated, previous_revision, commit.floats);
+                    (previous_revision + 1, state)
+                }
+                (Ok(previous_revision), _) => (previous_revision + 1, commit.updated.clone()),
+                (Err(revision), _) => (revision, commit.updated.clone()),
+            };
+            to_value(StateUpdate {
+                revision,
+                state,
+                trace_id: action.and_then(Action::trace_id).map(str::to_owned),
+                meta: action.and_then(|action| action.meta.clone()),
+            })?
+        } else {
+            commit.updated.clone()
+        };
+
+        // The dispatching window already has the state, from the command's return value
+        let origin = action
+            .filter(|action| {
+                self.skip_dispatching_window && action.source() == ActionSource::Frontend
+            })
+            .and_then(Action::origin_window);
+        match (policy.coalesce_window(), origin) {
+            (Some(window), _) => {
+                self.defer(event, payload, window);
+                Ok(())
+            }
+            (None, Some(origin)) => self.send_except(event, &payload, origin),
+            (None, None) => self.send(event, &payload),
+        }
+    }
+
+    // Emit the full `state` of `revision` under `event`, with the `redacted` paths
+    // hidden, superseding a pending coalesced update. Never trimmed nor sent as a patch.
+    pub(crate) fn publish_full(
+        &self,
+        event: &str,
+        revision: u64,
+        mut state: JsonValue,
+        redacted: &[String],
+        trace_id: Option<&str>,
+    ) -> crate::Result<()> {
+        self.coalescer.take(event);
+        self.watchers.notify(event, &state);
+        redact(&mut state, redacted);
+        self.subscriptions.notify(event, &state);
+        let payload = self.envelope(StateUpdate {
+            revision,
+            state,
+            trace_id: trace_id.map(str::to_owned),
+            meta: None,
+        })?;
+        self.send(event, &payload)
+    }
+
+    // The update payload carrying the full `state` of `revision`
+    pub(crate) fn full_payload(&self, revision: u64, state: JsonValue) -> crate::Result<JsonValue> {
+        self.envelope(StateUpdate {
+            revision,
+            state,
+            trace_id: None,
+            meta: None,
+        })
+    }
+
+    // The payload of `update`, enveloped if enabled
+    fn envelope(&self, update: StateUpdate) -> crate::Result<JsonValue> {
+        if self.envelope_updates || self.trim_unchanged {
+            to_value(update)
+        } else {
+            Ok(update.state)
+        }
+    }
+
+    // Hold `payload` back as the pending update for `event`, emitting the latest one
+    // once `window` has passed
+    fn defer(self: &Arc<Self>, event: &str, payload: JsonValue, window: Duration) {
+        if !self.coalescer.defer(event, payload) {
+            return;
+        }
+
+        let publisher = self.clone();
+        let event = event.to_owned();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(window).await;
+            if let Some(payload) = publisher.coalescer.take(&event) {
+                if let Err(err) = publisher.send(&event, &payload) {
+                    log::warn!(target: ACTION_LOG_TARGET, "coalesced update '{event}': {err}");
+                }
+            }
+        });
+    }
+
+    // Hand the update to every transport, or only emit it to its window if it's a
+    // window store's. All transports are tried even if one fails; the first error is
+    // returned.
+    fn send(&self, event: &str, payload: &JsonValue) -> crate::Result<()> {
+        if let Some(label) = event_window(event) {
+            return self.send_to_window(label, event, payload);
+        }
+        let mut result = Ok(());
+        for transport in &self.transports {
+            if let Err(err) = transport.send(event, payload) {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    // Hand the update to every transport, for every window but `window`
+    fn send_except(&self, event: &str, payload: &JsonValue, window: &str) -> crate::Result<()> {
+        if let Some(label) = event_window(event) {
+            if label == window {
+                return Ok(());
+            }
+            return self.send_to_window(label, event, payload);
+        }
+        let mut result = Ok(());
+        for transport in &self.transports {
+            if let Err(err) = transport.send_except(event, payload, window) {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    // Emit the update of a window store to its window only, keeping it off the
+    // transports, which may reach other windows or leave the app
+    fn send_to_window(&self, label: &str, event: &str, payload: &JsonValue) -> crate::Result<()> {
+        match &self.to_window {
+            Some(emit) => emit(label, event, payload),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TagFrontend;
+    use crate::transport::{ChannelTransport, Emission};
+    use serde_json::json;
+    use std::sync::Mutex;
+    use std::sync::mpsc::Receiver;
+
+    struct Counter(i64);
+
+    impl RstateManager for Counter {
+        fn get_initial_state(&self) -> JsonValue {
+            json!({ "counter": self.0, "label": "counter" })
+        }
+
+        fn dispatch(&mut self, action: &Action) -> crate::Result<DispatchOutcome> {
+            let changed = action.is("INCREMENT");
+            if changed {
+                self.0 += 1;
+            }
+            Ok(DispatchOutcome::new(self.get_initial_state(), changed))
+        }
+    }
+
+    fn publisher(trim_unchanged: bool) -> (Arc<Publisher>, Receiver<Emission>) {
+        let (transport, receiver) = ChannelTransport::new();
+        let publisher = Publisher {
+            transports: vec![Box::new(transport)],
+            to_window: None,
+            trim_unchanged,
+            envelope_updates: false,
+            emit_patches: false,
+            skip_dispatching_window: false,
+            emit_policy: EmitPolicy::default(),
+            coalescer: Coalescer::default(),
+            subscriptions: Subscriptions::default(),
+            watchers: Watchers::default(),
+        };
+        (Arc::new(publisher), receiver)
+    }
+
+    #[test]
+    fn test_app_store_registration() {
+        let app_store = AppStore::default();
+        assert!(matches!(
+            app_store.get(),
+            Err(crate::RstateError::NotRegistered)
+        ));
+
+        app_store.register(Box::new(Counter(1))).unwrap();
+        assert!(matches!(
+            app_store.register(Box::new(Counter(2))),
+            Err(crate::RstateError::AlreadyRegistered)
+        ));
+        let first = app_store.get().unwrap();
+
+        let previous = app_store.replace(Some(Box::new(Counter(2)))).unwrap();
+        assert!(Arc::ptr_eq(&previous.unwrap(), &first));
+        assert_eq!(read_state(&app_store.get().unwrap()).unwrap()["counter"], 2);
+        // A dispatch holding the previous store still sees its manager
+        assert_eq!(read_state(&first).unwrap()["counter"], 1);
+
+        assert!(app_store.replace(None).unwrap().is_some());
+        assert!(!app_store.is_registered());
+        app_store.register(Box::new(Counter(3))).unwrap();
+        assert!(app_store.is_registered());
+    }
+
+    #[test]
+    fn test_commit_and_publish() {
+        let store: ManagedState = RwLock::new(Box::new(Counter(0)));
+        let revision = AtomicU64::new(0);
+        let (publisher, receiver) = publisher(true);
+
+        let increment = Action::new("INCREMENT");
+        let committed = commit(
+            &store,
+            &revision,
+            publisher.emit_policy(),
+            Some(&increment),
+            publisher.keeps_previous(),
+            |manager| manager.dispatch(&increment),
+        )
+        .unwrap();
+        assert!(committed.should_emit());
+        publisher
+            .publish(STATE_UPDATE_EVENT, &committed, Some(&increment))
+            .unwrap();
+        assert_eq!(
+            committed.into_state(),
+            (json!({ "counter": 1, "label": "counter" }), true)
+        );
+        assert_eq!(revision.load(Ordering::SeqCst), 1);
+
+        let noop = Action::new("NOOP");
+        let committed = commit(
+            &store,
+            &revision,
+            EmitPolicy::default(),
+            None,
+            false,
+            |manager| manager.dispatch(&noop),
+        )
+        .unwrap();
+        assert!(!committed.should_emit());
+        assert_eq!(revision.load(Ordering::SeqCst), 1);
+
+        // Read along with the revision it belongs to
+        let update = read_revision(&store, &revision, Some(0)).unwrap();
+        assert_eq!((update.revision, &update.state["counter"]), (1, &json!(1)));
+        let update = read_revision(&store, &revision, Some(1)).unwrap();
+        assert_eq!(update.state, json!({ "$unchanged": 1 }));
+
+        let emissions: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(emissions.len(), 1);
+        assert_eq!(emissions[0].event, STATE_UPDATE_EVENT);
+        assert_eq!(emissions[0].payload["revision"], 1);
+        assert_eq!(emissions[0].payload["state"]["counter"], 1);
+    }
+
+    #[test]
+    fn test_redacted_paths_are_hidden_from_the_frontend() {
+        let manager = crate::StateBuilder::new(json!({ "token": "abc", "counter": 0 }))
+            .on("INCREMENT", |state, _| {
+                state["counter"] = json!(1);
+                Ok(())
+            })
+            .redact("token")
+            .build();
+        let store: ManagedState = RwLock::new(Box::new(manager));
+        let revision = AtomicU64::new(0);
+        let (publisher, receiver) = publisher(false);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        publisher
+            .watchers()
+            .add(
+                STATE_UPDATE_EVENT,
+                "",
+                &read_state(&store).unwrap(),
+                Arc::new(move |_, new| sink.lock().unwrap().push(new.clone())),
+            )
+            .unwrap();
+
+        let increment = Action::new("INCREMENT");
+        let committed = commit(
+            &store,
+            &revision,
+            publisher.emit_policy(),
+            Some(&increment),
+            publisher.keeps_previous(),
+            |manager| manager.dispatch(&increment),
+        )
+        .unwrap();
+        publisher
+            .publish(STATE_UPDATE_EVENT, &committed, Some(&increment))
+            .unwrap();
+        publisher
+            .publish_full(
+                STATE_UPDATE_EVENT,
+                1,
+                read_state(&store).unwrap(),
+                &redacted_paths(&store).unwrap(),
+                None,
+            )
+            .unwrap();
+
+        let emissions: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(emissions.len(), 2);
+        for emission in emissions {
+            assert_eq!(
+                emission.payload,
+                json!({ "token": crate::redaction::REDACTED, "counter": 1 })
+            );
+        }
+        assert_eq!(
+            read_redacted(&store).unwrap()["token"],
+            crate::redaction::REDACTED
+        );
+        // The backend still sees the value
+        assert_eq!(read_state(&store).unwrap()["token"], "abc");
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [json!({ "token": "abc", "counter": 1 })]
+        );
+    }
+
+    // Records the window each update skipped
+    struct Skipping(Arc<Mutex<Vec<Option<String>>>>);
+
+    impl UpdateTransport for Skipping {
+        fn send(&self, _event: &str, _payload: &JsonValue) -> crate::Result<()> {
+            self.0.lock().unwrap().push(None);
+            Ok(())
+        }
+
+        fn send_except(
+            &self,
+            _event: &str,
+            _payload: &JsonValue,
+            window: &str,
+        ) -> crate::Result<()> {
+            self.0.lock().unwrap().push(Some(window.to_owned()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_publish_skips_the_dispatching_window() {
+        let store: ManagedState = RwLock::new(Box::new(Counter(0)));
+        let revision = AtomicU64::new(0);
+        let skipped = Arc::default();
+        let publisher = Arc::new(Publisher {
+            transports: vec![Box::new(Skipping(Arc::clone(&skipped)))],
+            to_window: None,
+            trim_unchanged: false,
+            envelope_updates: false,
+            emit_patches: false,
+            skip_dispatching_window: true,
+            emit_policy: EmitPolicy::default(),
+            coalescer: Coalescer::default(),
+            subscriptions: Subscriptions::default(),
+            watchers: Watchers::default(),
+        });
+
+        let from_main = Action::new("INCREMENT").tag_frontend(Some("main"));
+        let from_rust = Action::new("INCREMENT");
+        for action in [&from_main, &from_rust] {
+            let committed = commit(
+                &store,
+                &revision,
+                publisher.emit_policy(),
+                Some(action),
+                false,
+                |manager| manager.dispatch(action),
+            )
+            .unwrap();
+            publisher
+                .publish(STATE_UPDATE_EVENT, &committed, Some(action))
+                .unwrap();
+        }
+        assert_eq!(*skipped.lock().unwrap(), [Some("main".to_owned()), None]);
+    }
+
+    #[test]
+    fn test_window_updates_only_reach_their_window() {
+        let store: ManagedState = RwLock::new(Box::new(Counter(0)));
+        let revision = AtomicU64::new(0);
+        let (transport, receiver) = ChannelTransport::new();
+        let emitted = Arc::new(Mutex::new(Vec::new()));
+        let to_window = Arc::clone(&emitted);
+        let publisher = Arc::new(Publisher {
+            transports: vec![Box::new(transport)],
+            to_window: Some(Box::new(move |label, event, _| {
+                to_window
+                    .lock()
+                    .unwrap()
+                    .push((label.to_owned(), event.to_owned()));
+                Ok(())
+            })),
+            trim_unchanged: false,
+            envelope_updates: false,
+            emit_patches: false,
+            skip_dispatching_window: true,
+            emit_policy: EmitPolicy::default(),
+            coalescer: Coalescer::default(),
+            subscriptions: Subscriptions::default(),
+            watchers: Watchers::default(),
+        });
+
+        let event = crate::window_event_name("doc-1");
+        let from_rust = Action::new("INCREMENT");
+        let from_itself = Action::new("INCREMENT").tag_frontend(Some("doc-1"));
+        for action in [&from_rust, &from_itself] {
+            let committed = commit(
+                &store,
+                &revision,
+                publisher.emit_policy(),
+                Some(action),
+                false,
+                |manager| manager.dispatch(action),
+            )
+            .unwrap();
+            publisher.publish(&event, &committed, Some(action)).unwrap();
+        }
+        publisher
+            .publish_full(&event, 2, json!(2), &[], None)
+            .unwrap();
+
+        // Never handed to the transports, and not sent back to the dispatching window
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(
+            *emitted.lock().unwrap(),
+            [
+                ("doc-1".to_owned(), event.clone()),
+                ("doc-1".to_owned(), event)
+            ]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! State updates streamed over IPC channels.
+//!
+//! The `subscribe` command takes a [`Channel`] and an optional key, and streams the
+//! state (or the value at the key, in dot notation) to it: once right away, then after
+//! every change. Unlike broadcast events, updates only reach the subscribing webview,
+//! in order, and a keyed subscription only receives its slice, when it changed:
+//!
+//! ```js
+//! import { Channel, invoke } from '@tauri-apps/api/core'
+//!
+//! const channel = new Channel()
+//! channel.onmessage = (theme) => applyTheme(theme)
+//! const id = await invoke('plugin:rstate|subscribe', { key: 'settings.theme', channel })
+//! // ...
+//! await invoke('plugin:rstate|unsubscribe', { id })
+//! ```
+//!
+//! Subscriptions end with `unsubscribe`, when their window is destroyed, or when the
+//! channel can no longer be reached.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tauri::ipc::Channel;
+
+use crate::models::{JsonValue, get_state};
+
+// A channel subscribed to a store's updates
+struct Subscription {
+    id: u64,
+    // Update event of the store
+    event: String,
+    window: String,
+    key: Option<String>,
+    // Last value sent, for keyed subscriptions
+    last: Option<JsonValue>,
+    channel: Channel<JsonValue>,
+}
+
+impl Subscription {
+    // Send the subscribed part of `state` if it changed.
+    // Returns `false` if the channel is gone.
+    fn send(&mut self, state: &JsonValue) -> bool {
+        let value = match &self.key {
+            Some(key) => {
+                let value = get_state(state, key).unwrap_or(JsonValue::Null);
+                if self.last.as_ref() == Some(&value) {
+                    return true;
+                }
+                self.last = Some(value.clone());
+                value
+            }
+            None => state.clone(),
+        };
+        self.channel.send(value).is_ok()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Subscriptions {
+    next_id: AtomicU64,
+    entries: Mutex<Vec<Subscription>>,
+}
+
+impl Subscriptions {
+    // Subscribe `channel` to the updates of the store emitting `event`, sending the
+    // current `state` right away. Returns the subscription id.
+    pub(crate) fn add(
+        &self,
+        event: &str,
+        window: &str,
+        key: Option<String>,
+        channel: Channel<JsonValue>,
+        state: &JsonValue,
+    ) -> crate::Result<u64> {
+        let mut subscription = Subscription {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            event: event.to_owned(),
+            window: window.to_owned(),
+            key,
+            last: None,
+            channel,
+        };
+        if !subscription.send(state) {
+            return Err(crate::RstateError::state("subscription channel is closed"));
+        }
+        let id = subscription.id;
+        self.entries
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?
+            .push(subscription);
+        Ok(id)
+    }
+
+    // Returns `true` if the subscription existed
+    pub(crate) fn remove(&self, id: u64) -> bool {
+        self.retain(|subscription| subscription.id != id)
+    }
+
+    // Drop the subscriptions of the window `label`
+    pub(crate) fn remove_window(&self, label: &str) {
+        self.retain(|subscription| subscription.window != label);
+    }
+
+    // Send the new `state` of the store emitting `event` to its subscribers
+    pub(crate) fn notify(&self, event: &str, state: &JsonValue) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        entries.retain_mut(|subscription| subscription.event != event || subscription.send(state));
+    }
+
+    // Keep the subscriptions matching `f`. Returns `true` if any was dropped.
+    fn retain(&self, f: impl FnMut(&Subscription) -> bool) -> bool {
+        let Ok(mut entries) = self.entries.lock() else {
+            return false;
+        };
+        let count = entries.len();
+        entries.retain(f);
+        entries.len() != count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tauri::ipc::InvokeResponseBody;
+
+    fn channel() -> (Channel<JsonValue>, Arc<Mutex<Vec<JsonValue>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let channel = Channel::new(move |body| {
+            if let InvokeResponseBody::Json(json) = body {
+                sink.lock()
+                    .unwrap()
+                    .push(serde_json::from_str(&json).unwrap());
+            }
+            Ok(())
+        });
+        (channel, received)
+    }
+
+    #[test]
+    fn test_keyed_subscription_receives_changes() {
+        let subscriptions = Subscriptions::default();
+        let (channel, received) = channel();
+        let state = json!({ "settings": { "theme": "light" }, "counter": 0 });
+        let id = subscriptions
+            .add(
+                "update",
+                "main",
+                Some("settings.theme".to_owned()),
+                channel,
+                &state,
+            )
+            .unwrap();
+
+        subscriptions.notify(
+            "update",
+            &json!({ "settings": { "theme": "light" }, "counter": 1 }),
+        );
+        subscriptions.notify(
+            "update",
+            &json!({ "settings": { "theme": "dark" }, "counter": 1 }),
+        );
+        subscriptions.notify("other", &json!({ "settings": { "theme": "blue" } }));
+        assert_eq!(*received.lock().unwrap(), [json!("light"), json!("dark")]);
+
+        assert!(subscriptions.remove(id));
+        assert!(!subscriptions.remove(id));
+    }
+}
@@ -0,0 +1,152 @@
+//! Integration points for building a sync layer on top of rstate.
+//!
+//! A cloud-sync layer needs two things from the store: to learn about local changes
+//! so it can push them, and to apply remote changes without echoing them back.
+//!
+//! - [`Builder::on_local_change`](crate::Builder::on_local_change) surfaces every local
+//!   change of the app-wide store as a [`LocalChange`].
+//! - [`Rstate::apply_remote_change`](crate::Rstate::apply_remote_change) applies a
+//!   [`RemoteChange`] as a special merge dispatch. It is not surfaced as a local change.
+//!   If the remote change was based on a value that was changed locally in the meantime,
+//!   the [`Builder::on_conflict`](crate::Builder::on_conflict) callback decides.
+//!
+//! ```rust,ignore
+//! Builder::new()
+//!     .on_local_change(move |change| outbox.push(change.clone()))
+//!     .on_conflict(|conflict| Resolution::Merged(merge_lists(&conflict.local, &conflict.remote)))
+//!
+//! // When the server pushes a change
+//! app.rstate().apply_remote_change(
+//!     RemoteChange::new("settings.theme", json!("dark")).with_base(json!("light")),
+//! )?;
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Action, JsonValue, get_state};
+
+/// Action kind of the merge dispatches made by
+/// [`Rstate::apply_remote_change`](crate::Rstate::apply_remote_change).
+pub const REMOTE_CHANGE_ACTION: &str = "@@rstate/REMOTE_CHANGE";
+
+/// A change of the app-wide store made locally.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalChange {
+    /// The action that caused the change, or `None` for [`Rstate::update`](crate::Rstate::update)
+    pub action: Option<Action>,
+    /// Revision of the store after the change
+    pub revision: u64,
+    /// The state after the change
+    pub state: JsonValue,
+}
+
+/// A change made elsewhere, setting the value at `path`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteChange {
+    /// Path of the changed value, in the dot notation of [`get_state`](crate::get_state)
+    pub path: String,
+    /// The new value
+    pub value: JsonValue,
+    /// The value the remote side changed, if known. A local value that differs from
+    /// it is a conflict.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<JsonValue>,
+}
+
+impl RemoteChange {
+    /// Create a change setting `path` to `value`, without conflict detection.
+    pub fn new(path: impl Into<String>, value: JsonValue) -> Self {
+        Self {
+            path: path.into(),
+            value,
+            base: None,
+        }
+    }
+
+    /// Set the value the remote side changed, enabling conflict detection.
+    #[must_use]
+    pub fn with_base(mut self, base: JsonValue) -> Self {
+        self.base = Some(base);
+        self
+    }
+}
+
+/// A remote change to a value that was also changed locally.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// Path of the conflicting value
+    pub path: String,
+    /// The value the remote side changed
+    pub base: JsonValue,
+    /// The current local value (`Null` if missing)
+    pub local: JsonValue,
+    /// The remote value
+    pub remote: JsonValue,
+}
+
+/// How to resolve a [`Conflict`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// Keep the local value, ignoring the remote change
+    KeepLocal,
+    /// Take the remote value (the default without an `on_conflict` callback)
+    TakeRemote,
+    /// Use a merged value
+    Merged(JsonValue),
+}
+
+/// Callback receiving local changes, see [`Builder::on_local_change`](crate::Builder::on_local_change).
+pub type LocalChangeHook = Box<dyn Fn(&LocalChange) + Send + Sync>;
+
+/// Callback resolving conflicts, see [`Builder::on_conflict`](crate::Builder::on_conflict).
+pub type ConflictResolver = Box<dyn Fn(&Conflict) -> Resolution + Send + Sync>;
+
+// Compute the value to store at the change's path, or `None` to keep the local one
+pub(crate) fn resolve(
+    state: &JsonValue,
+    change: &RemoteChange,
+    on_conflict: Option<&ConflictResolver>,
+) -> Option<JsonValue> {
+    let local = get_state(state, &change.path).unwrap_or(JsonValue::Null);
+    let conflict = match &change.base {
+        Some(base) if *base != local && local != change.value => Conflict {
+            path: change.path.clone(),
+            base: base.clone(),
+            local,
+            remote: change.value.clone(),
+        },
+        _ => return Some(change.value.clone()),
+    };
+
+    match on_conflict.map_or(Resolution::TakeRemote, |resolve| resolve(&conflict)) {
+        Resolution::KeepLocal => None,
+        Resolution::TakeRemote => Some(conflict.remote),
+        Resolution::Merged(value) => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve_detects_conflicts() {
+        let state = json!({ "settings": { "theme": "blue" } });
+
+        // Local value is still the base: no conflict
+        let change = RemoteChange::new("settings.theme", json!("dark")).with_base(json!("blue"));
+        assert_eq!(resolve(&state, &change, None), Some(json!("dark")));
+
+        // Local value changed in the meantime: the resolver decides
+        let change = RemoteChange::new("settings.theme", json!("dark")).with_base(json!("light"));
+        assert_eq!(resolve(&state, &change, None), Some(json!("dark")));
+        let keep_local: ConflictResolver = Box::new(|conflict| {
+            assert_eq!(conflict.local, json!("blue"));
+            Resolution::KeepLocal
+        });
+        assert_eq!(resolve(&state, &change, Some(&keep_local)), None);
+    }
+}
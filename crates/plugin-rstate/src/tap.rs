@@ -0,0 +1,175 @@
+//! Observability taps, for end-to-end tests.
+//!
+//! [`Rstate::tap`](crate::Rstate::tap) opens a channel receiving a [`TapEvent`] for
+//! everything observable the plugin does: every dispatch, every commit along with the
+//! decision to emit its update or not, and every write of a persisted state. Tests can
+//! then assert on the plugin's behavior, not just on the final state:
+//!
+//! ```rust,ignore
+//! let tap = app.rstate().tap();
+//! app.rstate().dispatch(Action::new("NOOP"))?;
+//! let commit = tap.try_iter().find(|event| matches!(event, TapEvent::Commit { .. }));
+//! assert!(matches!(commit, Some(TapEvent::Commit { emit: EmitDecision::Skipped, .. })));
+//! ```
+//!
+//! Nothing is recorded while no tap is open.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::RstateError;
+use crate::health::{SaveStatus, unix_millis};
+use crate::models::Action;
+
+/// Reports the writes of a manager's persisted state, along with how long they took.
+///
+/// See [`RstateManager::set_save_tap`](crate::RstateManager::set_save_tap).
+pub type SaveTap = Arc<dyn Fn(&SaveStatus, Duration) + Send + Sync>;
+
+/// Something the plugin did, received through [`Rstate::tap`](crate::Rstate::tap).
+///
+/// Stores are named after the event their updates are emitted under, e.g.
+/// [`STATE_UPDATE_EVENT`](crate::STATE_UPDATE_EVENT) for the app-wide store.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TapEvent {
+    /// An action was dispatched
+    Dispatch {
+        /// The action's kind
+        kind: String,
+        /// The action's trace id, if any
+        trace_id: Option<String>,
+        /// Whether the action changed the state
+        changed: bool,
+        /// The error the action failed with, if any
+        error: Option<String>,
+        /// When the dispatch finished, in milliseconds since the Unix epoch
+        at: u64,
+    },
+    /// A change (or a forced no-op) was committed to a store
+    Commit {
+        /// The store committed to
+        store: String,
+        /// The store's revision after the commit
+        revision: u64,
+        /// Whether the state changed
+        changed: bool,
+        /// Whether the update was emitted
+        emit: EmitDecision,
+        /// How long the commit took, waiting for the lock and running the handlers
+        elapsed: Duration,
+    },
+    /// A store's persisted state was written to its storage backend
+    Persist {
+        /// The store written
+        store: String,
+        /// The outcome of the write
+        status: SaveStatus,
+        /// How long the write took
+        elapsed: Duration,
+    },
+}
+
+/// Whether a commit's update was emitted, see [`TapEvent::Commit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitDecision {
+    /// The update was emitted (or deferred, if coalesced)
+    Emitted,
+    /// Nothing changed, and the emit policy doesn't force an update
+    Skipped,
+    /// The app-wide store isn't ready yet
+    NotReady,
+    /// Held back by a `batched` scope, which emits once when it ends
+    Batched,
+    /// Held back by a transaction, which emits once when it ends
+    Held,
+}
+
+// The event for a dispatch of `action`, reporting whether it changed the state
+pub(crate) fn dispatched(action: &Action, result: Result<bool, &RstateError>) -> TapEvent {
+    TapEvent::Dispatch {
+        kind: action.kind.clone(),
+        trace_id: action.trace_id().map(str::to_owned),
+        changed: matches!(result, Ok(true)),
+        error: result.err().map(ToString::to_string),
+        at: unix_millis(),
+    }
+}
+
+// The channels of the open taps
+#[derive(Default, Clone)]
+pub(crate) struct Taps(Arc<Mutex<Vec<Sender<TapEvent>>>>);
+
+impl Taps {
+    pub(crate) fn open(&self) -> Receiver<TapEvent> {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut taps) = self.0.lock() {
+            taps.push(sender);
+        }
+        receiver
+    }
+
+    // Send the event built by `event` to every open tap, forgetting the closed ones.
+    // The event is only built if a tap is open.
+    pub(crate) fn send(&self, event: impl FnOnce() -> TapEvent) {
+        let Ok(mut taps) = self.0.lock() else {
+            return;
+        };
+        if taps.is_empty() {
+            return;
+        }
+        let event = event();
+        taps.retain(|tap| tap.send(event.clone()).is_ok());
+    }
+
+    // The tap reporting the writes of `store`'s persisted state
+    pub(crate) fn saves_of(&self, store: String) -> SaveTap {
+        let taps = self.clone();
+        Arc::new(move |status, elapsed| {
+            taps.send(|| TapEvent::Persist {
+                store: store.clone(),
+                status: status.clone(),
+                elapsed,
+            });
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_taps_receive_events_while_open() {
+        let taps = Taps::default();
+        let mut built = 0;
+        taps.send(|| {
+            built += 1;
+            TapEvent::Dispatch {
+                kind: "NOOP".into(),
+                trace_id: None,
+                changed: false,
+                error: None,
+                at: 1,
+            }
+        });
+        assert_eq!(built, 0);
+
+        let tap = taps.open();
+        let closed = taps.open();
+        drop(closed);
+        taps.saves_of("rstate://state-update".into())(
+            &SaveStatus::Saved { at: 2 },
+            Duration::from_millis(3),
+        );
+        assert_eq!(
+            tap.try_recv().unwrap(),
+            TapEvent::Persist {
+                store: "rstate://state-update".into(),
+                status: SaveStatus::Saved { at: 2 },
+                elapsed: Duration::from_millis(3),
+            }
+        );
+        assert_eq!(taps.0.lock().unwrap().len(), 1);
+    }
+}
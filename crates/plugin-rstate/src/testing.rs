@@ -0,0 +1,496 @@
+//! Helpers for testing code that uses the plugin.
+//!
+//! # Mock apps
+//!
+//! The mock app helpers build an app on Tauri's `MockRuntime` with the plugin
+//! installed, so commands and other code calling
+//! [`app.rstate()`](crate::RstateExt::rstate) can be tested without a window system.
+//! They are behind the `test` feature, which enables Tauri's own `test` feature:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! tauri-plugin-rstate = { version = "0.1", features = ["test"] }
+//! ```
+//!
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::testing::{assert_state_eq, mock_app_with_state};
+//!
+//! #[test]
+//! fn test_increment_command() {
+//!     let app = mock_app_with_state(build_manager());
+//!     increment(app.handle().clone()).unwrap();
+//!     assert_state_eq(&app, "counter", json!(1));
+//! }
+//! ```
+//!
+//! # Record and replay
+//!
+//! Reducers get regression tests from recorded sessions. A [`Recorder`] wraps a
+//! state manager and records a [`Session`]: the state it started with, and every
+//! action dispatched to it with the state it led to. Saved
+//! as a JSON fixture, the session is then replayed by a [`Replayer`] against the
+//! current reducers, which must go through the same transitions, so refactoring
+//! handlers can't silently change what they do:
+//!
+//! ```rust,ignore
+//! // While using the app, e.g. in a debug build
+//! let manager = Recorder::new(build_manager()).save_to("tests/sessions/onboarding.json");
+//! tauri_plugin_rstate::Builder::new().state_manager(manager)
+//!
+//! // In a test
+//! #[test]
+//! fn test_onboarding_session() {
+//!     Replayer::load("tests/sessions/onboarding.json")
+//!         .unwrap()
+//!         .ignore("session.lastSeen")
+//!         .assert(&mut build_manager());
+//! }
+//! ```
+//!
+//! Only whether a step failed is compared, not its error message.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "test")]
+use tauri::test::{MockRuntime, mock_builder, mock_context, noop_assets};
+#[cfg(feature = "test")]
+use tauri::{App, Manager, Runtime};
+use tauri_plugin_rstate_core::diff;
+
+use crate::models::{Action, AnyAppHandle, DispatchOutcome, Dispatcher, JsonValue, RstateManager};
+use crate::persistence::{FileBackend, StorageBackend, remove_path};
+#[cfg(feature = "test")]
+use crate::{Builder, RstateExt};
+use crate::{Result, RstateError};
+
+/// Build a mock app with the plugin, registered with `manager` as the app-wide store.
+///
+/// # Panics
+///
+/// If the app fails to build, e.g. because the plugin's setup failed.
+#[cfg(feature = "test")]
+pub fn mock_app_with_state(manager: impl RstateManager) -> App<MockRuntime> {
+    mock_app(Builder::new().state_manager(manager))
+}
+
+/// Build a mock app with the plugin configured by `builder`.
+///
+/// # Panics
+///
+/// If the app fails to build, e.g. because the plugin's setup failed.
+#[cfg(feature = "test")]
+pub fn mock_app(builder: Builder<MockRuntime>) -> App<MockRuntime> {
+    mock_builder()
+        .plugin(builder.build())
+        .build(mock_context(noop_assets()))
+        .expect("failed to build the mock app")
+}
+
+/// Assert that the value at `key` (in dot notation) of the app-wide state is
+/// `expected`.
+///
+/// # Panics
+///
+/// If the value differs or is missing, or the state can't be read.
+#[cfg(feature = "test")]
+#[track_caller]
+pub fn assert_state_eq<R: Runtime>(app: &impl Manager<R>, key: &str, expected: JsonValue) {
+    let actual = app
+        .rstate()
+        .get_state(key)
+        .unwrap_or_else(|err| panic!("failed to read the state at '{key}': {err}"));
+    match actual {
+        Some(actual) => assert_eq!(actual, expected, "state at '{key}'"),
+        None => panic!("no state at '{key}', expected {expected}"),
+    }
+}
+
+/// A recorded sequence of state transitions, see the [module docs](self).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    /// The state before the first step
+    pub initial: JsonValue,
+    /// The steps, in order
+    pub steps: Vec<Step>,
+}
+
+impl Session {
+    /// Load a session saved with [`save`](Self::save).
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let value = FileBackend::new(&path)
+            .load()?
+            .ok_or_else(|| RstateError::state(format!("no session at {}", path.display())))?;
+        serde_json::from_value(value).map_err(|e| RstateError::serialization(e.to_string()))
+    }
+
+    /// Save the session as JSON at `path`.
+    pub fn save(&self, path: impl Into<PathBuf>) -> Result<()> {
+        let value =
+            serde_json::to_value(self).map_err(|e| RstateError::serialization(e.to_string()))?;
+        FileBackend::new(path).save(&value)
+    }
+}
+
+/// A step of a [`Session`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Step {
+    /// The dispatched action, `None` if the state was replaced as a whole
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<Action>,
+    /// The state after the step, if it succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<JsonValue>,
+    /// The error the step failed with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Step {
+    fn of(action: Option<&Action>, result: std::result::Result<&JsonValue, &RstateError>) -> Self {
+        Self {
+            action: action.cloned(),
+            state: result.ok().cloned(),
+            error: result.err().map(ToString::to_string),
+        }
+    }
+}
+
+/// A state manager recording the steps of a [`Session`], see the
+/// [module docs](self).
+///
+/// Reach it with [`Rstate::with_state_manager`](crate::Rstate::with_state_manager)
+/// and [`downcast_ref`](dyn RstateManager::downcast_ref) to get the session.
+pub struct Recorder<M> {
+    manager: M,
+    session: Session,
+    path: Option<PathBuf>,
+}
+
+impl<M: RstateManager> Recorder<M> {
+    /// Record the steps of `manager`, starting from its current state.
+    pub fn new(manager: M) -> Self {
+        let session = Session {
+            initial: manager.get_initial_state(),
+            steps: Vec::new(),
+        };
+        Self {
+            manager,
+            session,
+            path: None,
+        }
+    }
+
+    /// Save the session at `path` whenever the store is flushed, which happens when
+    /// the app exits.
+    #[must_use]
+    pub fn save_to(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// The session recorded so far.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// The recorded manager.
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+}
+
+impl<M: RstateManager> RstateManager for Recorder<M> {
+    fn get_initial_state(&self) -> JsonValue {
+        self.manager.get_initial_state()
+    }
+
+    fn dispatch(&mut self, action: &Action) -> Result<DispatchOutcome> {
+        let result = self.manager.dispatch(action);
+        self.session.steps.push(Step::of(
+            Some(action),
+            result.as_ref().map(|outcome| &outcome.state),
+        ));
+        result
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let flushed = self.manager.flush();
+        match &self.path {
+            Some(path) => flushed.and(self.session().save(path)),
+            None => flushed,
+        }
+    }
+
+    fn set_dispatcher(&mut self, dispatcher: Dispatcher) {
+        self.manager.set_dispatcher(dispatcher);
+    }
+
+    fn set_app(&mut self, app: AnyAppHandle) {
+        self.manager.set_app(app);
+    }
+
+    fn set_save_tap(&mut self, tap: crate::SaveTap) {
+        self.manager.set_save_tap(tap);
+    }
+
+    fn replace_state(&mut self, state: JsonValue) -> Result<()> {
+        let result = self.manager.replace_state(state.clone());
+        self.session
+            .steps
+            .push(Step::of(None, result.as_ref().map(|()| &state)));
+        result
+    }
+
+    fn emit_policy(&self, kind: &str) -> Option<crate::EmitPolicy> {
+        self.manager.emit_policy(kind)
+    }
+
+    fn float_comparison(&self) -> crate::FloatComparison {
+        self.manager.float_comparison()
+    }
+
+    fn last_save(&self) -> Option<crate::SaveStatus> {
+        self.manager.last_save()
+    }
+
+    fn schema(&self) -> Option<crate::SchemaFingerprint> {
+        self.manager.schema()
+    }
+
+    fn version(&self) -> Option<u32> {
+        self.manager.version()
+    }
+
+    fn simulate(&self, actions: &[Action]) -> Result<JsonValue> {
+        self.manager.simulate(actions)
+    }
+
+    fn select(&self, name: &str) -> Result<JsonValue> {
+        self.manager.select(name)
+    }
+
+    fn selector_stats(&self) -> Vec<crate::SelectorStats> {
+        self.manager.selector_stats()
+    }
+
+    fn redacted_paths(&self) -> Vec<String> {
+        self.manager.redacted_paths()
+    }
+
+    fn action_kinds(&self) -> Result<crate::ActionKinds> {
+        self.manager.action_kinds()
+    }
+
+    fn persisted_history(&self, after: u64, limit: usize) -> Result<crate::HistoryPage> {
+        self.manager.persisted_history(after, limit)
+    }
+}
+
+/// Replays a [`Session`] against a state manager, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    session: Session,
+    ignored: Vec<String>,
+}
+
+impl Replayer {
+    /// Replay `session`.
+    pub fn new(session: Session) -> Self {
+        Self {
+            session,
+            ignored: Vec::new(),
+        }
+    }
+
+    /// Replay the session saved at `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        Session::load(path).map(Self::new)
+    }
+
+    /// Ignore the value at `path` (in dot notation) when comparing states, e.g. for
+    /// timestamps.
+    #[must_use]
+    pub fn ignore(mut self, path: impl Into<String>) -> Self {
+        self.ignored.push(path.into());
+        self
+    }
+
+    /// Replay the session against `manager`, stopping at the first step whose outcome
+    /// differs from the recorded one.
+    ///
+    /// The manager's state is first replaced with the initial state of the session if
+    /// it differs.
+    pub fn verify<M: RstateManager + ?Sized>(
+        &self,
+        manager: &mut M,
+    ) -> std::result::Result<(), Box<Mismatch>> {
+        let initial = &self.session.initial;
+        if self.comparable(manager.get_initial_state()) != self.comparable(initial.clone()) {
+            manager
+                .replace_state(initial.clone())
+                .map_err(|err| Mismatch {
+                    step: None,
+                    kind: None,
+                    expected: Ok(initial.clone()),
+                    actual: Err(err.to_string()),
+                })?;
+        }
+
+        for (index, step) in self.session.steps.iter().enumerate() {
+            let actual = match (&step.action, &step.state) {
+                (Some(action), _) => manager.dispatch(action).map(|outcome| outcome.state),
+                (None, Some(state)) => manager
+                    .replace_state(state.clone())
+                    .map(|()| manager.get_initial_state()),
+                // A failed replacement changed nothing
+                (None, None) => continue,
+            }
+            .map(|state| self.comparable(state))
+            .map_err(|err| err.to_string());
+            let expected = match (&step.state, &step.error) {
+                (Some(state), _) => Ok(self.comparable(state.clone())),
+                (None, error) => Err(error.clone().unwrap_or_default()),
+            };
+            let same = match (&expected, &actual) {
+                (Ok(expected), Ok(actual)) => expected == actual,
+                (Err(_), Err(_)) => true,
+                _ => false,
+            };
+            if !same {
+                return Err(Box::new(Mismatch {
+                    step: Some(index),
+                    kind: step.action.as_ref().map(|action| action.kind.clone()),
+                    expected,
+                    actual,
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`verify`](Self::verify), panicking with the first mismatch.
+    ///
+    /// # Panics
+    ///
+    /// If a step's outcome differs from the recorded one.
+    #[track_caller]
+    pub fn assert<M: RstateManager + ?Sized>(&self, manager: &mut M) {
+        if let Err(mismatch) = self.verify(manager) {
+            panic!("{mismatch}");
+        }
+    }
+
+    // `state` without the ignored paths
+    fn comparable(&self, mut state: JsonValue) -> JsonValue {
+        for path in &self.ignored {
+            remove_path(&mut state, path);
+        }
+        state
+    }
+}
+
+/// A step whose outcome differs from the recorded one, see [`Replayer::verify`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Mismatch {
+    /// Index of the step in the session, `None` for the initial state
+    pub step: Option<usize>,
+    /// Kind of the step's action, `None` if the state was replaced as a whole
+    pub kind: Option<String>,
+    /// The recorded state, or error
+    pub expected: std::result::Result<JsonValue, String>,
+    /// The state the manager went to, or the error it failed with
+    pub actual: std::result::Result<JsonValue, String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.step, &self.kind) {
+            (None, _) => write!(f, "initial state")?,
+            (Some(step), Some(kind)) => write!(f, "step {step} ({kind})")?,
+            (Some(step), None) => write!(f, "step {step} (state replaced)")?,
+        }
+        match (&self.expected, &self.actual) {
+            (Ok(expected), Ok(actual)) => {
+                let changes = serde_json::to_string(&diff(expected, actual)).unwrap_or_default();
+                write!(f, ": state differs from the recorded one by {changes}")
+            }
+            (Ok(_), Err(err)) => write!(f, ": failed with '{err}', but succeeded when recorded"),
+            (Err(err), Ok(_)) => write!(f, ": succeeded, but failed with '{err}' when recorded"),
+            (Err(_), Err(_)) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StateBuilder;
+    use serde_json::json;
+
+    #[derive(Serialize, Deserialize, Default)]
+    struct Counter {
+        count: i64,
+        updated: u64,
+    }
+
+    fn build(step: i64) -> crate::BuiltStateManager<Counter> {
+        StateBuilder::new(Counter::default())
+            .on("ADD", move |state, action| {
+                let amount: i64 = action.require_payload()?;
+                if amount < 0 {
+                    return Err(RstateError::rejected("negative amount"));
+                }
+                state.count += amount * step;
+                state.updated = crate::health::unix_millis();
+                Ok(())
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_recorded_session_is_replayed() {
+        let mut recorder = Recorder::new(build(1));
+        for amount in [2, -1, 3] {
+            let _ = recorder.dispatch(&Action::with_json("ADD", json!(amount)));
+        }
+        let session = recorder.session().clone();
+        assert_eq!(session.steps.len(), 3);
+        assert_eq!(session.steps[2].state.as_ref().unwrap()["count"], 5);
+        assert!(session.steps[1].error.is_some());
+
+        // Round trip through a fixture
+        let path = std::env::temp_dir().join(format!("rstate-session-{}.json", std::process::id()));
+        session.save(&path).unwrap();
+        let replayer = Replayer::load(&path).unwrap().ignore("updated");
+        std::fs::remove_file(path).unwrap();
+
+        replayer.assert(&mut build(1));
+        let mismatch = replayer.verify(&mut build(2)).unwrap_err();
+        assert_eq!(mismatch.step, Some(0));
+        assert_eq!(mismatch.kind.as_deref(), Some("ADD"));
+        assert_eq!(
+            mismatch.to_string(),
+            r#"step 0 (ADD): state differs from the recorded one by [{"op":"replace","path":"/count","value":4}]"#
+        );
+    }
+
+    #[cfg(feature = "test")]
+    #[test]
+    fn test_mock_app_dispatches_to_the_manager() {
+        let app = mock_app_with_state(build(1));
+        assert_state_eq(&app, "count", json!(0));
+        app.rstate()
+            .dispatch(Action::with_json("ADD", json!(2)))
+            .unwrap();
+        assert_state_eq(app.handle(), "count", json!(2));
+    }
+}
@@ -0,0 +1,152 @@
+//! Per-kind handler timings.
+//!
+//! With [`Builder::time_actions`](crate::Builder::time_actions), the plugin measures
+//! how long the state manager takes to handle each action, and keeps the most recent
+//! samples of every kind. [`Rstate::action_timings`](crate::Rstate::action_timings)
+//! (and the `action_timings` command) report their percentiles, slowest kinds first,
+//! so performance work can target the few kinds that cost the most:
+//!
+//! ```json
+//! [
+//!   { "kind": "IMPORT_TRACKS", "count": 12, "p50Us": 8400, "p90Us": 15200, "p99Us": 41000, "maxUs": 41000 },
+//!   { "kind": "INCREMENT", "count": 5210, "p50Us": 3, "p90Us": 5, "p99Us": 11, "maxUs": 120 }
+//! ]
+//! ```
+//!
+//! Only the handler is timed, while the store's lock is held: waiting for the lock,
+//! guards and emitting the update are left out.
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::models::Action;
+
+// Number of samples kept per kind
+const SAMPLES: usize = 1024;
+
+/// Handler timing percentiles of an action kind, in microseconds.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionTiming {
+    /// The action kind
+    pub kind: String,
+    /// Number of actions of this kind handled since the app started
+    pub count: u64,
+    /// Median of the recent samples
+    pub p50_us: u64,
+    /// 90th percentile of the recent samples
+    pub p90_us: u64,
+    /// 99th percentile of the recent samples
+    pub p99_us: u64,
+    /// Slowest of the recent samples
+    pub max_us: u64,
+}
+
+#[derive(Default)]
+struct Samples {
+    count: u64,
+    recent: VecDeque<u64>,
+}
+
+#[derive(Default)]
+pub(crate) struct Timings {
+    kinds: Mutex<HashMap<String, Samples>>,
+}
+
+impl Timings {
+    // Run `f`, recording how long it took under the action's kind
+    pub(crate) fn time<T>(&self, action: &Action, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let output = f();
+        self.record(&action.kind, start.elapsed().as_micros() as u64);
+        output
+    }
+
+    fn record(&self, kind: &str, micros: u64) {
+        let Ok(mut kinds) = self.kinds.lock() else {
+            return;
+        };
+        let samples = match kinds.get_mut(kind) {
+            Some(samples) => samples,
+            None => kinds.entry(kind.to_owned()).or_default(),
+        };
+        samples.count += 1;
+        if samples.recent.len() == SAMPLES {
+            samples.recent.pop_front();
+        }
+        samples.recent.push_back(micros);
+    }
+
+    // The percentiles of every kind, slowest p99 first
+    pub(crate) fn report(&self) -> Vec<ActionTiming> {
+        let Ok(kinds) = self.kinds.lock() else {
+            return Vec::new();
+        };
+        let mut report: Vec<ActionTiming> = kinds
+            .iter()
+            .map(|(kind, samples)| {
+                let mut sorted: Vec<u64> = samples.recent.iter().copied().collect();
+                sorted.sort_unstable();
+                ActionTiming {
+                    kind: kind.clone(),
+                    count: samples.count,
+                    p50_us: percentile(&sorted, 50),
+                    p90_us: percentile(&sorted, 90),
+                    p99_us: percentile(&sorted, 99),
+                    max_us: sorted.last().copied().unwrap_or_default(),
+                }
+            })
+            .collect();
+        report.sort_by(|a, b| b.p99_us.cmp(&a.p99_us).then_with(|| a.kind.cmp(&b.kind)));
+        report
+    }
+}
+
+// The nearest-rank percentile of sorted samples
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings_report_percentiles() {
+        let timings = Timings::default();
+        for micros in 1..=100 {
+            timings.record("INCREMENT", micros);
+        }
+        timings.record("IMPORT", 5000);
+        for micros in 0..SAMPLES as u64 {
+            timings.record("TICK", micros % 2);
+        }
+
+        let report = timings.report();
+        let kinds: Vec<_> = report.iter().map(|timing| timing.kind.as_str()).collect();
+        assert_eq!(kinds, ["IMPORT", "INCREMENT", "TICK"]);
+        assert_eq!(
+            report[1],
+            ActionTiming {
+                kind: "INCREMENT".into(),
+                count: 100,
+                p50_us: 50,
+                p90_us: 90,
+                p99_us: 99,
+                max_us: 100,
+            }
+        );
+        assert_eq!((report[0].p50_us, report[0].max_us), (5000, 5000));
+
+        // Old samples make room for new ones; the count keeps going
+        timings.record("TICK", 7);
+        let tick = &timings.report()[2];
+        assert_eq!((tick.count, tick.max_us), (SAMPLES as u64 + 1, 7));
+    }
+}
@@ -0,0 +1,204 @@
+//! Scoped read tokens.
+//!
+//! An app embedding an untrusted webview (a third-party panel, a plugin UI) can let it
+//! read part of the app-wide state only. Mint a token for the webview's window covering
+//! some key prefixes with [`Rstate::mint_read_token`](crate::Rstate::mint_read_token),
+//! and hand it to the webview (e.g. through an initialization script):
+//!
+//! ```rust,ignore
+//! let token = app.rstate().mint_read_token("panel", ["player.track", "theme"]);
+//! WebviewWindowBuilder::new(&app, "panel", WebviewUrl::External(panel_url))
+//!     .initialization_script(&format!("window.__RSTATE_TOKEN__ = {token:?}"))
+//!     .build()?;
+//! ```
+//!
+//! From then on, every read command of that window (`get_initial_state`,
+//! `get_changes_since`, `get_state`, `get_selector` and `subscribe`) is rejected unless
+//! it carries the token, and only serves the app-wide state under the prefixes:
+//!
+//! ```js
+//! // "player.track.title" is covered; "player.queue" and "user" are rejected
+//! const title = await invoke('plugin:rstate|get_state', {
+//!   key: 'player.track.title',
+//!   token: window.__RSTATE_TOKEN__,
+//! })
+//! // Only `{ player: { track }, theme }`
+//! const state = await invoke('plugin:rstate|get_initial_state', {
+//!   token: window.__RSTATE_TOKEN__,
+//! })
+//! ```
+//!
+//! `get_changes_since` answers with the narrowed state rather than a patch, while
+//! selectors and subscriptions without a key are rejected, as they can't be narrowed
+//! to the prefixes. A token presented by another window is rejected too.
+//!
+//! Tokens only apply to commands: keep the window's capability to the
+//! `rstate:read-only` set so it can't dispatch, don't let it listen to the state
+//! events, and revoke the token with
+//! [`Rstate::revoke_read_token`](crate::Rstate::revoke_read_token) when the window
+//! goes away.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::models::JsonValue;
+use crate::{Result, RstateError};
+
+// What a token lets its window read
+struct Grant {
+    window: String,
+    prefixes: Vec<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct ReadTokens {
+    tokens: RwLock<HashMap<String, Grant>>,
+}
+
+impl ReadTokens {
+    pub(crate) fn mint(&self, window: String, prefixes: Vec<String>) -> String {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        if let Ok(mut tokens) = self.tokens.write() {
+            tokens.insert(token.clone(), Grant { window, prefixes });
+        }
+        token
+    }
+
+    pub(crate) fn revoke(&self, token: &str) -> bool {
+        self.tokens
+            .write()
+            .is_ok_and(|mut tokens| tokens.remove(token).is_some())
+    }
+
+    // What the window `label` may read, presenting `token`: `None` if the window has
+    // no token, and may read anything. Fails if the token isn't the window's, or if
+    // the window has one and didn't present it.
+    pub(crate) fn scope(&self, label: &str, token: Option<&str>) -> Result<Option<ReadScope>> {
+        let tokens = self
+            .tokens
+            .read()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
+        match token {
+            Some(token) => match tokens.get(token) {
+                Some(grant) if grant.window == label => Ok(Some(ReadScope {
+                    prefixes: grant.prefixes.clone(),
+                })),
+                _ => Err(RstateError::rejected("unknown read token")),
+            },
+            None if tokens.values().any(|grant| grant.window == label) => Err(
+                RstateError::rejected(format!("window '{label}' must present its read token")),
+            ),
+            None => Ok(None),
+        }
+    }
+}
+
+// The key prefixes of the app-wide state a window may read
+pub(crate) struct ReadScope {
+    prefixes: Vec<String>,
+}
+
+impl ReadScope {
+    // Fail unless the scope covers the dot-notation `key`
+    pub(crate) fn check(&self, key: Option<&str>) -> Result<()> {
+        match key {
+            Some(key) if self.prefixes.iter().any(|prefix| covers(prefix, key)) => Ok(()),
+            Some(key) => Err(RstateError::rejected(format!(
+                "read token doesn't cover '{key}'"
+            ))),
+            None => Err(RstateError::rejected(
+                "read token doesn't cover the full state",
+            )),
+        }
+    }
+
+    // The parts of `state` under the prefixes, at their place
+    pub(crate) fn filter(&self, state: &JsonValue) -> JsonValue {
+        let mut filtered = JsonValue::Object(Default::default());
+        for prefix in &self.prefixes {
+            let pointer = format!("/{}", prefix.replace('.', "/"));
+            let Some(value) = state.pointer(&pointer) else {
+                continue;
+            };
+            let mut parent = &mut filtered;
+            let mut segments = prefix.split('.').peekable();
+            while let Some(segment) = segments.next() {
+                let JsonValue::Object(map) = parent else {
+                    break;
+                };
+                if segments.peek().is_none() {
+                    map.insert(segment.to_owned(), value.clone());
+                    break;
+                }
+                parent = map
+                    .entry(segment)
+                    .or_insert_with(|| JsonValue::Object(Default::default()));
+            }
+        }
+        filtered
+    }
+}
+
+// Whether `key` is `prefix` or a key under it
+fn covers(prefix: &str, key: &str) -> bool {
+    key.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tokens_cover_their_prefixes() {
+        let tokens = ReadTokens::default();
+        let token = tokens.mint("panel".into(), vec!["player.track".into(), "theme".into()]);
+        let scope = tokens.scope("panel", Some(&token)).unwrap().unwrap();
+
+        assert!(scope.check(Some("theme")).is_ok());
+        assert!(scope.check(Some("player.track.title")).is_ok());
+        assert!(scope.check(Some("player.trackCount")).is_err());
+        assert!(scope.check(Some("player")).is_err());
+        assert!(scope.check(None).is_err());
+        assert!(tokens.scope("panel", Some("guess")).is_err());
+
+        assert!(tokens.revoke(&token));
+        assert!(!tokens.revoke(&token));
+        assert!(tokens.scope("panel", Some(&token)).is_err());
+    }
+
+    #[test]
+    fn test_tokens_are_bound_to_their_window() {
+        let tokens = ReadTokens::default();
+        let token = tokens.mint("panel".into(), vec!["theme".into()]);
+
+        // The window must present its token, and only it may
+        assert!(tokens.scope("panel", None).is_err());
+        assert!(tokens.scope("main", Some(&token)).is_err());
+        assert!(tokens.scope("main", None).unwrap().is_none());
+
+        let scope = tokens.scope("panel", Some(&token)).unwrap().unwrap();
+        let state = json!({ "theme": "dark", "user": { "name": "me" } });
+        assert_eq!(scope.filter(&state), json!({ "theme": "dark" }));
+    }
+
+    #[test]
+    fn test_filter_keeps_nested_prefixes_in_place() {
+        let scope = ReadScope {
+            prefixes: vec![
+                "player.track".into(),
+                "player.volume".into(),
+                "missing".into(),
+            ],
+        };
+        let state = json!({
+            "player": { "track": { "title": "Song" }, "volume": 3, "queue": [1, 2] },
+            "user": "me",
+        });
+        assert_eq!(
+            scope.filter(&state),
+            json!({ "player": { "track": { "title": "Song" }, "volume": 3 } })
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Transactions spanning several stores.
+//!
+//! A backend operation touching several stores (the app-wide store, slices registered
+//! with [`Rstate::register_slice`](crate::Rstate::register_slice), window stores)
+//! would otherwise emit an update per dispatch, and the frontend could render the
+//! stores out of step. [`Rstate::transaction`](crate::Rstate::transaction) holds the
+//! updates of every store it touches back until it ends, then emits them as an
+//! ordered burst: one update per changed store, in the order they were first
+//! touched, each with the full state and sharing the transaction's trace id:
+//!
+//! ```rust,ignore
+//! app.rstate().transaction(|tx| {
+//!     tx.dispatch(Action::with_payload("import/FINISH", &report)?)?;
+//!     tx.dispatch(Action::with_payload("ADD_RECENT", &report.path)?)?;
+//!     tx.dispatch_to_window("doc-1", Action::new("RELOAD"))?;
+//!     Ok(())
+//! })?;
+//! ```
+//!
+//! The trace id is only visible to the frontend with
+//! [`Builder::envelope_updates`](crate::Builder::envelope_updates). Dispatches from
+//! elsewhere to a store held by a transaction are held back as well, and included in
+//! its burst.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::Runtime;
+
+use crate::models::{Action, JsonValue};
+use crate::slices::slice_event_name;
+use crate::store::STATE_UPDATE_EVENT;
+use crate::window_stores::window_event_name;
+use crate::{Result, RstateError};
+
+// A store a transaction can touch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Store {
+    App,
+    Slice(String),
+    Window(String),
+}
+
+impl Store {
+    // The event the store's updates are emitted under
+    pub(crate) fn event(&self) -> String {
+        match self {
+            Self::App => STATE_UPDATE_EVENT.to_owned(),
+            Self::Slice(key) => slice_event_name(key),
+            Self::Window(label) => window_event_name(label),
+        }
+    }
+}
+
+// The events held back by running transactions, with the number of transactions
+// holding each and the store's revision when the first one took it
+#[derive(Default)]
+pub(crate) struct HeldEvents(Mutex<HashMap<String, (usize, u64)>>);
+
+impl HeldEvents {
+    pub(crate) fn contains(&self, event: &str) -> bool {
+        self.0.lock().is_ok_and(|held| held.contains_key(event))
+    }
+
+    fn hold(&self, event: String, revision: u64) -> Result<()> {
+        let mut held = self
+            .0
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
+        held.entry(event).or_insert((0, revision)).0 += 1;
+        Ok(())
+    }
+
+    // Stop holding `event`. Returns the revision the store had when it was first
+    // held, if no other transaction holds it anymore.
+    fn release(&self, event: &str) -> Option<u64> {
+        let mut held = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let (holders, revision) = held.get_mut(event)?;
+        *holders -= 1;
+        if *holders > 0 {
+            return None;
+        }
+        let revision = *revision;
+        held.remove(event);
+        Some(revision)
+    }
+}
+
+/// A transaction over the stores of the plugin, see [`Rstate::transaction`](crate::Rstate::transaction).
+pub struct Transaction<'a, R: Runtime> {
+    rstate: &'a crate::Rstate<R>,
+    trace_id: String,
+    touched: Mutex<Vec<Store>>,
+}
+
+impl<'a, R: Runtime> Transaction<'a, R> {
+    pub(crate) fn new(rstate: &'a crate::Rstate<R>) -> Self {
+        Self {
+            rstate,
+            trace_id: uuid::Uuid::new_v4().to_string(),
+            touched: Mutex::default(),
+        }
+    }
+
+    /// The trace id shared by the updates the transaction emits.
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// Dispatch an action to the app-wide store, or to the slice it is prefixed with.
+    /// Returns the new state of that store, which isn't emitted yet.
+    pub fn dispatch(&self, action: Action) -> Result<JsonValue> {
+        self.touch(self.rstate.store_of(&action))?;
+        self.rstate.dispatch(action)
+    }
+
+    /// Dispatch an action to the window `label`'s store. Returns its new state, which
+    /// isn't emitted yet.
+    pub fn dispatch_to_window(&self, label: &str, action: Action) -> Result<JsonValue> {
+        self.touch(Store::Window(label.to_owned()))?;
+        self.rstate.dispatch_to_window(label, action)
+    }
+
+    // Hold the updates of `store` back until the transaction ends
+    fn touch(&self, store: Store) -> Result<()> {
+        let mut touched = self
+            .touched
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
+        if touched.contains(&store) {
+            return Ok(());
+        }
+        let revision = self.rstate.store_revision(&store)?;
+        self.rstate.held_events().hold(store.event(), revision)?;
+        touched.push(store);
+        Ok(())
+    }
+
+    // Release every store touched and emit the ones that changed, in order. A store
+    // still held by another transaction is emitted when that one ends.
+    pub(crate) fn finish(self) -> Result<()> {
+        let touched = self.touched.into_inner().unwrap_or_else(|e| e.into_inner());
+        let mut result = Ok(());
+        for store in touched {
+            let Some(revision) = self.rstate.held_events().release(&store.event()) else {
+                continue;
+            };
+            let emitted = self.rstate.store_revision(&store).and_then(|current| {
+                if current == revision {
+                    return Ok(());
+                }
+                self.rstate.publish_store(&store, &self.trace_id)
+            });
+            result = result.and(emitted);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_held_events_count_their_holders() {
+        let held = HeldEvents::default();
+        let event = Store::Slice("import".into()).event();
+        assert_eq!(event, "rstate://slice-update/import");
+        assert!(!held.contains(&event));
+
+        held.hold(event.clone(), 3).unwrap();
+        held.hold(event.clone(), 5).unwrap();
+        assert!(held.contains(&event));
+        // The last transaction to let go gets the revision from before the first one
+        assert_eq!(held.release(&event), None);
+        assert_eq!(held.release(&event), Some(3));
+        assert!(!held.contains(&event));
+        assert_eq!(held.release(&event), None);
+    }
+}
@@ -0,0 +1,190 @@
+//! Pluggable transports for state update notifications.
+//!
+//! Whenever the state changes, the plugin hands the update to every registered
+//! [`UpdateTransport`]. By default this is just [`EventTransport`], which emits
+//! a Tauri event to all webviews, but you can add your own (e.g. a WebSocket
+//! bridge to a remote UI) or replace the default entirely.
+//!
+//! The updates of [window stores](crate::Rstate::create_window_store) are private to
+//! their window: they are emitted to it alone, and never handed to the transports.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::{Builder, ChannelTransport};
+//!
+//! let (transport, updates) = ChannelTransport::new();
+//!
+//! tauri::Builder::default()
+//!     .plugin(Builder::new().state_manager(manager).transport(transport).build())
+//!     .run(tauri::generate_context!())
+//!     .unwrap();
+//!
+//! std::thread::spawn(move || {
+//!     for update in updates {
+//!         println!("{} -> {}", update.event, update.payload);
+//!     }
+//! });
+//! ```
+
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use tauri::{AppHandle, Emitter, EventTarget, Runtime};
+
+use crate::Result;
+use crate::listeners::Listeners;
+use crate::models::JsonValue;
+
+/// A destination for state update notifications.
+///
+/// Implementations must not block for long: `send` is called on the dispatching
+/// thread, right after the state manager lock is released.
+pub trait UpdateTransport: Send + Sync + 'static {
+    /// Deliver an update `payload` published under the `event` name.
+    fn send(&self, event: &str, payload: &JsonValue) -> Result<()>;
+
+    /// Deliver an update to every window but `window`, which dispatched the action and
+    /// already has the new state.
+    ///
+    /// Used with [`Builder::skip_dispatching_window`](crate::Builder::skip_dispatching_window).
+    /// The default implementation delivers it to everyone through [`send`](Self::send).
+    fn send_except(&self, event: &str, payload: &JsonValue, window: &str) -> Result<()> {
+        let _ = window;
+        self.send(event, payload)
+    }
+}
+
+/// The default transport, emitting updates as Tauri events to all webviews.
+pub struct EventTransport<R: Runtime> {
+    app: AppHandle<R>,
+    listeners: Option<Arc<Listeners>>,
+}
+
+impl<R: Runtime> EventTransport<R> {
+    /// Create a transport emitting through the given app handle.
+    pub fn new(app: AppHandle<R>) -> Self {
+        Self {
+            app,
+            listeners: None,
+        }
+    }
+
+    // Create a transport skipping the windows without an active listener
+    pub(crate) fn listening_only(app: AppHandle<R>, listeners: Arc<Listeners>) -> Self {
+        Self {
+            app,
+            listeners: Some(listeners),
+        }
+    }
+}
+
+impl<R: Runtime> EventTransport<R> {
+    // Emit to the windows `include` accepts, and to the listening ones if filtered
+    fn emit_to_windows(
+        &self,
+        event: &str,
+        payload: &JsonValue,
+        include: impl Fn(&str) -> bool,
+    ) -> Result<()> {
+        let active = self.listeners.as_ref().map(|listeners| listeners.active());
+        if active.as_ref().is_some_and(|active| active.is_empty()) {
+            return Ok(());
+        }
+        // Listeners registered without a target can't be told apart by window
+        self.app
+            .emit_filter(event, payload, |target| match target {
+                EventTarget::Window { label }
+                | EventTarget::Webview { label }
+                | EventTarget::WebviewWindow { label }
+                | EventTarget::AnyLabel { label } => {
+                    include(label) && active.as_ref().is_none_or(|active| active.contains(label))
+                }
+                _ => true,
+            })
+            .map_err(|err| crate::RstateError::Emit(err.to_string()))
+    }
+}
+
+impl<R: Runtime> UpdateTransport for EventTransport<R> {
+    fn send(&self, event: &str, payload: &JsonValue) -> Result<()> {
+        if self.listeners.is_none() {
+            return self
+                .app
+                .emit(event, payload)
+                .map_err(|err| crate::RstateError::Emit(err.to_string()));
+        }
+        self.emit_to_windows(event, payload, |_| true)
+    }
+
+    fn send_except(&self, event: &str, payload: &JsonValue, window: &str) -> Result<()> {
+        self.emit_to_windows(event, payload, |label| label != window)
+    }
+}
+
+/// An update delivered through a [`ChannelTransport`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Emission {
+    /// The event name the update was published under
+    pub event: String,
+    /// The update payload
+    pub payload: JsonValue,
+}
+
+/// A transport forwarding updates to an in-process channel.
+///
+/// Useful for Rust-side consumers living outside the webview, and for tests
+/// that want to assert on what the plugin emitted.
+pub struct ChannelTransport {
+    sender: Sender<Emission>,
+}
+
+impl ChannelTransport {
+    /// Create a transport along with the receiving end of its channel.
+    pub fn new() -> (Self, Receiver<Emission>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self { sender }, receiver)
+    }
+}
+
+impl UpdateTransport for ChannelTransport {
+    fn send(&self, event: &str, payload: &JsonValue) -> Result<()> {
+        self.sender
+            .send(Emission {
+                event: event.to_owned(),
+                payload: payload.clone(),
+            })
+            .map_err(|err| crate::RstateError::Emit(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_channel_transport_records_emissions() {
+        let (transport, receiver) = ChannelTransport::new();
+
+        transport
+            .send("rstate://state-update", &json!({ "counter": 1 }))
+            .unwrap();
+        transport
+            .send("rstate://state-update", &json!({ "counter": 2 }))
+            .unwrap();
+
+        let emissions: Vec<Emission> = receiver.try_iter().collect();
+        assert_eq!(emissions.len(), 2);
+        assert_eq!(emissions[1].event, "rstate://state-update");
+        assert_eq!(emissions[1].payload, json!({ "counter": 2 }));
+    }
+
+    #[test]
+    fn test_channel_transport_fails_when_receiver_dropped() {
+        let (transport, receiver) = ChannelTransport::new();
+        drop(receiver);
+
+        let result = transport.send("rstate://state-update", &json!(null));
+        assert!(matches!(result, Err(crate::RstateError::Emit(_))));
+    }
+}
@@ -0,0 +1,208 @@
+//! Soft-deletes on collections.
+//!
+//! Declare collections (arrays in the state, by dot-notation path) with
+//! [`StateBuilder::soft_delete`](crate::StateBuilder::soft_delete). Their items are
+//! then deleted and restored by `id` with the built-in [`SOFT_DELETE_ACTION`] and
+//! [`RESTORE_ACTION`] actions, both taking `{ "path", "id" }`:
+//!
+//! ```rust,ignore
+//! let manager = StateBuilder::new(AppState::default())
+//!     .soft_delete("todos", Duration::from_secs(30 * 24 * 60 * 60))
+//!     .build();
+//!
+//! app.rstate().dispatch_with(SOFT_DELETE_ACTION, json!({ "path": "todos", "id": 3 }))?;
+//! app.rstate().dispatch_with(RESTORE_ACTION, json!({ "path": "todos", "id": 3 }))?;
+//! ```
+//!
+//! Deleted items are exposed under the [`TRASH_KEY`] key of the state, by path, and
+//! persisted along with it:
+//!
+//! ```json
+//! { "todos": [], "trash": { "todos": [{ "item": { "id": 3 }, "index": 0, "deletedAt": 1700000000000 }] } }
+//! ```
+//!
+//! Restored items go back to their original position. Items are purged for good once
+//! their TTL has passed, on the next dispatch.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::Result;
+use crate::models::{Action, JsonValue};
+
+/// Key of the deleted items in the state.
+pub const TRASH_KEY: &str = "trash";
+
+/// Kind of the action soft-deleting an item. Its payload is `{ "path", "id" }`.
+pub const SOFT_DELETE_ACTION: &str = "@@rstate/SOFT_DELETE";
+
+/// Kind of the action restoring a soft-deleted item. Its payload is `{ "path", "id" }`.
+pub const RESTORE_ACTION: &str = "@@rstate/RESTORE";
+
+#[derive(Deserialize)]
+struct Target {
+    path: String,
+    id: JsonValue,
+}
+
+// A deleted item, with its position in the collection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Trashed {
+    item: JsonValue,
+    index: usize,
+    // Milliseconds since the Unix epoch
+    deleted_at: u64,
+}
+
+struct Collection {
+    ttl: Duration,
+    items: Vec<Trashed>,
+}
+
+// The deleted items of every soft-delete collection, by path
+#[derive(Default)]
+pub(crate) struct Trash(BTreeMap<String, Collection>);
+
+impl Trash {
+    pub(crate) fn declare(&mut self, path: String, ttl: Duration) {
+        self.0.insert(
+            path,
+            Collection {
+                ttl,
+                items: Vec::new(),
+            },
+        );
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Take the deleted items of the declared collections from a full state
+    pub(crate) fn restore(&mut self, state: &JsonValue) {
+        for (path, collection) in &mut self.0 {
+            if let Some(items) = state[TRASH_KEY]
+                .get(path)
+                .and_then(|items| serde_json::from_value(items.clone()).ok())
+            {
+                collection.items = items;
+            }
+        }
+    }
+
+    // Drop the items whose TTL has passed at `now`
+    pub(crate) fn purge(&mut self, now: u64) {
+        for collection in self.0.values_mut() {
+            let ttl = collection.ttl.as_millis() as u64;
+            collection
+                .items
+                .retain(|trashed| trashed.deleted_at.saturating_add(ttl) > now);
+        }
+    }
+
+    pub(crate) fn handles(action: &Action) -> bool {
+        action.is(SOFT_DELETE_ACTION) || action.is(RESTORE_ACTION)
+    }
+
+    // Apply a soft-delete or restore action to `state`, at `now`
+    pub(crate) fn handle(
+        &mut self,
+        state: &mut JsonValue,
+        action: &Action,
+        now: u64,
+    ) -> Result<()> {
+        let Target { path, id } = action.require_payload()?;
+        let collection = self.0.get_mut(&path).ok_or_else(|| {
+            crate::RstateError::state(format!("Not a soft-delete collection: {path}"))
+        })?;
+        let items = state
+            .pointer_mut(&format!("/{}", path.replace('.', "/")))
+            .and_then(JsonValue::as_array_mut)
+            .ok_or_else(|| crate::RstateError::state(format!("Not an array: {path}")))?;
+        let not_found = || crate::RstateError::state(format!("No item {id} in {path}"));
+
+        if action.is(SOFT_DELETE_ACTION) {
+            let index = items
+                .iter()
+                .position(|item| item["id"] == id)
+                .ok_or_else(not_found)?;
+            collection.items.push(Trashed {
+                item: items.remove(index),
+                index,
+                deleted_at: now,
+            });
+        } else {
+            let position = collection
+                .items
+                .iter()
+                .position(|trashed| trashed.item["id"] == id)
+                .ok_or_else(not_found)?;
+            let trashed = collection.items.remove(position);
+            items.insert(trashed.index.min(items.len()), trashed.item);
+        }
+        Ok(())
+    }
+
+    // Add the deleted items to a serialized state
+    pub(crate) fn insert_into(&self, state: &mut JsonValue) {
+        if self.0.is_empty() {
+            return;
+        }
+        if let JsonValue::Object(state) = state {
+            let trash = self
+                .0
+                .iter()
+                .map(|(path, collection)| (path.clone(), serde_json::json!(collection.items)))
+                .collect();
+            state.insert(TRASH_KEY.to_owned(), JsonValue::Object(trash));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_soft_delete_restore_and_purge() {
+        let mut trash = Trash::default();
+        trash.declare("list.todos".to_owned(), Duration::from_secs(1));
+        let mut state = json!({ "list": { "todos": [{ "id": 1 }, { "id": 2 }, { "id": 3 }] } });
+        let target = |kind, id| Action::with_json(kind, json!({ "path": "list.todos", "id": id }));
+
+        trash
+            .handle(&mut state, &target(SOFT_DELETE_ACTION, 2), 0)
+            .unwrap();
+        trash
+            .handle(&mut state, &target(SOFT_DELETE_ACTION, 3), 500)
+            .unwrap();
+        assert_eq!(state["list"]["todos"], json!([{ "id": 1 }]));
+        assert!(
+            trash
+                .handle(&mut state, &target(SOFT_DELETE_ACTION, 2), 0)
+                .is_err()
+        );
+
+        trash
+            .handle(&mut state, &target(RESTORE_ACTION, 2), 0)
+            .unwrap();
+        assert_eq!(state["list"]["todos"], json!([{ "id": 1 }, { "id": 2 }]));
+
+        let mut snapshot = state.clone();
+        trash.insert_into(&mut snapshot);
+        assert_eq!(
+            snapshot[TRASH_KEY]["list.todos"],
+            json!([{ "item": { "id": 3 }, "index": 1, "deletedAt": 500 }])
+        );
+
+        trash.purge(1500);
+        assert!(
+            trash
+                .handle(&mut state, &target(RESTORE_ACTION, 3), 0)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Typed access to the app-wide store.
+//!
+//! [`Rstate::typed`](crate::Rstate::typed) returns a [`TypedRstate`], which hands the
+//! state out as the app's own state type instead of JSON:
+//!
+//! ```rust,ignore
+//! let store = app.rstate().typed::<AppState>()?;
+//! let counter = store.with_state(|state| state.counter)?;
+//! let state: AppState = store.dispatch("INCREMENT")?;
+//! ```
+
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use tauri::Runtime;
+
+use crate::Rstate;
+use crate::models::{Action, JsonValue};
+
+/// The app-wide store, with its state as a `T`.
+///
+/// The state still goes through JSON: every access deserializes a snapshot of it, so
+/// changes made to the values handed out don't affect the store. Dispatch actions to
+/// change it.
+pub struct TypedRstate<'a, R: Runtime, T> {
+    rstate: &'a Rstate<R>,
+    state: PhantomData<fn() -> T>,
+}
+
+impl<'a, R: Runtime, T: DeserializeOwned> TypedRstate<'a, R, T> {
+    // Fails if the current state isn't a `T`
+    pub(crate) fn new(rstate: &'a Rstate<R>) -> crate::Result<Self> {
+        let typed = Self {
+            rstate,
+            state: PhantomData,
+        };
+        typed.get_state_clone()?;
+        Ok(typed)
+    }
+
+    /// Get a copy of the current state.
+    pub fn get_state_clone(&self) -> crate::Result<T> {
+        from_state(self.rstate.get_initial_state()?)
+    }
+
+    /// Run `f` with the current state.
+    pub fn with_state<U>(&self, f: impl FnOnce(&T) -> U) -> crate::Result<U> {
+        Ok(f(&self.get_state_clone()?))
+    }
+
+    /// Dispatch an action, like [`Rstate::dispatch`], and return the new state.
+    pub fn dispatch(&self, action: impl Into<Action>) -> crate::Result<T> {
+        from_state(self.rstate.dispatch(action.into())?)
+    }
+}
+
+fn from_state<T: DeserializeOwned>(state: JsonValue) -> crate::Result<T> {
+    serde_json::from_value(state).map_err(|e| crate::RstateError::serialization(e.to_string()))
+}
@@ -0,0 +1,162 @@
+//! Backend watchers of the state.
+//!
+//! Backend components (a tray, menus, background workers) often react to a single
+//! value of the state. [`Rstate::watch`](crate::Rstate::watch) calls a closure with
+//! the old and new value at a path (dot notation) whenever it changes, without
+//! listening to Tauri events and parsing their payloads:
+//!
+//! ```rust,ignore
+//! let handle = app.rstate().watch("user.settings.theme", move |_old, new| {
+//!     tray.set_icon(Some(icon_for(new))).ok();
+//! })?;
+//! // ...
+//! handle.unwatch();
+//! ```
+//!
+//! Watchers are called once the update of the change is published, outside the
+//! store's lock, so they may read the state or dispatch actions.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::models::{JsonValue, get_state};
+
+type Callback = Arc<dyn Fn(&JsonValue, &JsonValue) + Send + Sync>;
+
+struct Watcher {
+    id: u64,
+    // Update event of the store
+    event: String,
+    path: String,
+    // Value at the path when last seen
+    last: JsonValue,
+    callback: Callback,
+}
+
+type Entries = Mutex<Vec<Watcher>>;
+
+/// Handle to a watcher added with [`Rstate::watch`](crate::Rstate::watch).
+///
+/// Dropping the handle doesn't remove the watcher.
+#[derive(Debug, Clone)]
+pub struct WatchHandle {
+    id: u64,
+    entries: Weak<Entries>,
+}
+
+impl WatchHandle {
+    /// Remove the watcher. Returns `false` if it was already removed.
+    pub fn unwatch(&self) -> bool {
+        let Some(entries) = self.entries.upgrade() else {
+            return false;
+        };
+        let Ok(mut entries) = entries.lock() else {
+            return false;
+        };
+        let count = entries.len();
+        entries.retain(|watcher| watcher.id != self.id);
+        entries.len() != count
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Watchers {
+    next_id: AtomicU64,
+    entries: Arc<Entries>,
+}
+
+impl Watchers {
+    // Watch the value at `path` of the store emitting `event`, whose current state is
+    // `state`
+    pub(crate) fn add(
+        &self,
+        event: &str,
+        path: &str,
+        state: &JsonValue,
+        callback: Callback,
+    ) -> crate::Result<WatchHandle> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.entries
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?
+            .push(Watcher {
+                id,
+                event: event.to_owned(),
+                path: path.to_owned(),
+                last: get_state(state, path).unwrap_or(JsonValue::Null),
+                callback,
+            });
+        Ok(WatchHandle {
+            id,
+            entries: Arc::downgrade(&self.entries),
+        })
+    }
+
+    // Call the watchers of the store emitting `event` whose value changed in its new
+    // `state`. The callbacks run after the watchers' lock is released, so they can
+    // add or remove watchers.
+    pub(crate) fn notify(&self, event: &str, state: &JsonValue) {
+        let changed: Vec<_> = {
+            let Ok(mut entries) = self.entries.lock() else {
+                return;
+            };
+            entries
+                .iter_mut()
+                .filter(|watcher| watcher.event == event)
+                .filter_map(|watcher| {
+                    let value = get_state(state, &watcher.path).unwrap_or(JsonValue::Null);
+                    if value == watcher.last {
+                        return None;
+                    }
+                    let old = std::mem::replace(&mut watcher.last, value.clone());
+                    Some((watcher.callback.clone(), old, value))
+                })
+                .collect()
+        };
+        for (callback, old, new) in changed {
+            callback(&old, &new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_watchers_see_changes_at_their_path() {
+        let watchers = Watchers::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let state = json!({ "settings": { "theme": "light" }, "counter": 0 });
+        let handle = watchers
+            .add(
+                "update",
+                "settings.theme",
+                &state,
+                Arc::new(move |old, new| sink.lock().unwrap().push((old.clone(), new.clone()))),
+            )
+            .unwrap();
+
+        watchers.notify(
+            "update",
+            &json!({ "settings": { "theme": "light" }, "counter": 1 }),
+        );
+        watchers.notify("update", &json!({ "settings": { "theme": "dark" } }));
+        watchers.notify("other", &json!({ "settings": { "theme": "blue" } }));
+        watchers.notify("update", &json!({}));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (json!("light"), json!("dark")),
+                (json!("dark"), JsonValue::Null)
+            ]
+        );
+
+        assert!(handle.unwatch());
+        assert!(!handle.unwatch());
+        watchers.notify("update", &json!({ "settings": { "theme": "dark" } }));
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,771 @@
+//! WebSocket bridge for mirroring state to external clients.
+//!
+//! Enabled with the `websocket` feature. The bridge runs a small WebSocket server
+//! next to the app so a companion web dashboard or a second device can follow
+//! the state of the desktop app:
+//!
+//! - Clients authenticate with the config's [token](WebSocketConfig::token), either
+//!   in the URL (`ws://127.0.0.1:7878/?token=<token>`) or as their first message
+//!   (`{"token": "<token>"}`). Connections failing to do so are closed.
+//! - Every client receives the current state right after authenticating, then every
+//!   state update, as `{"event": "rstate://state-update", "payload": <state>}`.
+//! - Clients may send actions (`{"kind": "INCREMENT", "payload": null}`), which
+//!   are dispatched only if their kind is allowed by the [`WebSocketConfig`].
+//!   Rejected or failing actions are answered with `{"error": "<message>"}`.
+//!
+//! Any web page open in the user's browser can connect to a local port, so
+//! connections from browsers are refused unless their `Origin` is
+//! [allowed](WebSocketConfig::allow_origin); clients that send no `Origin` header
+//! (anything but a browser) still need the token. The server binds to `127.0.0.1` by
+//! default. Binding to other interfaces exposes the state to the network, so only do
+//! that on trusted networks.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use tauri_plugin_rstate::{Builder, WebSocketConfig};
+//!
+//! tauri::Builder::default()
+//!     .plugin(
+//!         Builder::new()
+//!             .state_manager(manager)
+//!             .websocket(
+//!                 WebSocketConfig::new(([127, 0, 0, 1], 7878))
+//!                     .token(dashboard_token)
+//!                     .allow_origin("https://dashboard.example.com")
+//!                     .allow_dispatch(["INCREMENT"]),
+//!             )
+//!             .build(),
+//!     )
+//!     .run(tauri::generate_context!())
+//!     .unwrap();
+//! ```
+
+use base64::Engine;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::Result;
+use crate::models::{Action, ActionSource, JsonValue};
+use crate::transport::UpdateTransport;
+
+/// Default port of the WebSocket bridge.
+pub const DEFAULT_WEBSOCKET_PORT: u16 = 7878;
+
+// Magic GUID from RFC 6455, used to compute the handshake accept key
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// Upper bound for a single incoming message, to keep clients from exhausting memory
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+// Upper bounds for a line of the handshake request, and for its number of lines
+const MAX_HANDSHAKE_LINE: u64 = 8 * 1024;
+const MAX_HANDSHAKE_LINES: usize = 100;
+
+// Default number of connections served at once
+const DEFAULT_MAX_CONNECTIONS: usize = 16;
+
+// Time a client has to complete the handshake and authenticate
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A client whose socket stalls this long is disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Frames queued for a client's writer thread; a client falling further behind is dropped
+const CLIENT_QUEUE_LENGTH: usize = 64;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Configuration for the WebSocket bridge.
+///
+/// Clients must present the config's [token](Self::token), and browsers must come
+/// from an [allowed origin](Self::allow_origin). By default clients can only observe
+/// the state; dispatching has to be allowed explicitly, per action kind or for all
+/// kinds.
+#[derive(Clone)]
+pub struct WebSocketConfig {
+    addr: SocketAddr,
+    dispatch: DispatchPermission,
+    token: String,
+    origins: HashSet<String>,
+    max_connections: usize,
+}
+
+#[derive(Debug, Clone)]
+enum DispatchPermission {
+    Denied,
+    Kinds(HashSet<String>),
+    Any,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self::new(([127, 0, 0, 1], DEFAULT_WEBSOCKET_PORT))
+    }
+}
+
+impl WebSocketConfig {
+    /// Create a read-only configuration listening on `addr`, with a random token and
+    /// no allowed origin.
+    pub fn new(addr: impl Into<SocketAddr>) -> Self {
+        Self {
+            addr: addr.into(),
+            dispatch: DispatchPermission::Denied,
+            token: uuid::Uuid::new_v4().simple().to_string(),
+            origins: HashSet::new(),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+
+    /// Set the token clients must present, instead of a random one, e.g. to share it
+    /// with a companion app ahead of time.
+    #[must_use]
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = token.into();
+        self
+    }
+
+    /// The token clients must present, to hand to them.
+    pub fn get_token(&self) -> &str {
+        &self.token
+    }
+
+    /// Accept connections from browser pages of `origin` (e.g.
+    /// `"https://dashboard.example.com"`). Browser connections from any other origin
+    /// are refused.
+    #[must_use]
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origins.insert(origin.into());
+        self
+    }
+
+    /// Serve at most `connections` clients at once (default: 16); further connections
+    /// are refused.
+    #[must_use]
+    pub fn max_connections(mut self, connections: usize) -> Self {
+        self.max_connections = connections;
+        self
+    }
+
+    /// Allow clients to dispatch actions of the given kinds.
+    #[must_use]
+    pub fn allow_dispatch<I, S>(mut self, kinds: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut allowed = match self.dispatch {
+            DispatchPermission::Kinds(allowed) => allowed,
+            _ => HashSet::new(),
+        };
+        allowed.extend(kinds.into_iter().map(Into::into));
+        self.dispatch = DispatchPermission::Kinds(allowed);
+        self
+    }
+
+    /// Allow clients to dispatch actions of any kind.
+    #[must_use]
+    pub fn allow_any_dispatch(mut self) -> Self {
+        self.dispatch = DispatchPermission::Any;
+        self
+    }
+
+    fn can_dispatch(&self, kind: &str) -> bool {
+        match &self.dispatch {
+            DispatchPermission::Denied => false,
+            DispatchPermission::Kinds(allowed) => allowed.contains(kind),
+            DispatchPermission::Any => true,
+        }
+    }
+
+    // Requests without an `Origin` don't come from a browser page
+    fn allows_origin(&self, origin: Option<&str>) -> bool {
+        origin.is_none_or(|origin| self.origins.contains(origin))
+    }
+
+    fn is_token(&self, token: &str) -> bool {
+        // Compare in constant time, so the token can't be guessed byte by byte
+        token.len() == self.token.len()
+            && token
+                .bytes()
+                .zip(self.token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+// Leaves the token out
+impl fmt::Debug for WebSocketConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketConfig")
+            .field("addr", &self.addr)
+            .field("dispatch", &self.dispatch)
+            .field("origins", &self.origins)
+            .field("max_connections", &self.max_connections)
+            .finish_non_exhaustive()
+    }
+}
+
+// A connected client. Its frames are queued for its writer thread, the only one
+// writing to its socket, so frames never interleave and nobody waits on the network.
+struct Client {
+    id: u64,
+    frames: mpsc::SyncSender<Arc<[u8]>>,
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Transport pushing state updates to all connected WebSocket clients.
+///
+/// Created by the plugin when [`Builder::websocket`](crate::Builder::websocket) is used.
+pub struct WebSocketTransport {
+    clients: Clients,
+}
+
+impl UpdateTransport for WebSocketTransport {
+    fn send(&self, event: &str, payload: &JsonValue) -> Result<()> {
+        let frame: Arc<[u8]> =
+            encode_frame(OPCODE_TEXT, update_message(event, payload).as_bytes()).into();
+        let mut clients = self
+            .clients
+            .lock()
+            .map_err(|e| crate::RstateError::LockPoisoned(e.to_string()))?;
+        // Drop clients that went away or can't keep up, without waiting for them
+        clients.retain(|client| client.frames.try_send(frame.clone()).is_ok());
+        Ok(())
+    }
+}
+
+/// Start the WebSocket server and return the transport feeding it.
+pub(crate) fn start<R: Runtime>(
+    app: AppHandle<R>,
+    config: WebSocketConfig,
+) -> Result<WebSocketTransport> {
+    let listener = TcpListener::bind(config.addr)?;
+    let clients: Clients = Arc::default();
+    let config = Arc::new(config);
+
+    let accept_clients = clients.clone();
+    let connections = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            if connections.fetch_add(1, Ordering::SeqCst) >= config.max_connections {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                let _ = stream
+                    .write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n");
+                continue;
+            }
+            let app = app.clone();
+            let clients = accept_clients.clone();
+            let config = config.clone();
+            let connections = connections.clone();
+            thread::spawn(move || {
+                // A failing connection only affects that client
+                let _ = serve_client(app, stream, clients, &config);
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    Ok(WebSocketTransport { clients })
+}
+
+fn serve_client<R: Runtime>(
+    app: AppHandle<R>,
+    stream: TcpStream,
+    clients: Clients,
+    config: &WebSocketConfig,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    writer.set_read_timeout(Some(AUTH_TIMEOUT))?;
+    writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let request = handshake(&mut reader, &mut writer, config)?;
+    let authenticated = match request.token {
+        Some(token) => config.is_token(&token),
+        None => authenticate(&mut reader, config)?,
+    };
+    if !authenticated {
+        let reply = json!({ "error": "invalid token" }).to_string();
+        let _ = writer.write_all(&encode_frame(OPCODE_TEXT, reply.as_bytes()));
+        let _ = writer.write_all(&encode_frame(OPCODE_CLOSE, &1008u16.to_be_bytes()));
+        return writer.shutdown(Shutdown::Both);
+    }
+    writer.set_read_timeout(None)?;
+
+    let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
+    let (frames, queued) = mpsc::sync_channel(CLIENT_QUEUE_LENGTH);
+    let stream = writer.try_clone()?;
+    let writer_thread = thread::spawn(move || write_frames(stream, queued));
+    let send = |opcode, payload: &[u8]| {
+        frames
+            .send(encode_frame(opcode, payload).into())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    };
+
+    // Send the current state first, so the client doesn't have to wait for a change.
+    // The client is added under the same lock, so no update sent in between is missed.
+    {
+        let mut clients = clients
+            .lock()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        if let Some(state) = app.try_state::<crate::Rstate<R>>().and_then(|rstate| {
+            let state = rstate.get_initial_state().ok()?;
+            rstate.redact(crate::StoreScope::App, "", "", state).ok()
+        }) {
+            let message = update_message(crate::STATE_UPDATE_EVENT, &state);
+            send(OPCODE_TEXT, message.as_bytes())?;
+        }
+        clients.push(Client {
+            id,
+            frames: frames.clone(),
+        });
+    }
+
+    let served = serve_messages(&app, config, &mut reader, send);
+
+    // Close the queue, so the writer thread ends once it wrote the last frames
+    if let Ok(mut clients) = clients.lock() {
+        clients.retain(|client| client.id != id);
+    }
+    drop(frames);
+    let _ = writer_thread.join();
+    let _ = writer.shutdown(Shutdown::Both);
+    served
+}
+
+// Answer the messages of an authenticated client until it closes the connection,
+// queueing frames with `send`
+fn serve_messages<R: Runtime>(
+    app: &AppHandle<R>,
+    config: &WebSocketConfig,
+    reader: &mut impl Read,
+    send: impl Fn(u8, &[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    while let Some((opcode, message)) = read_message(reader)? {
+        match opcode {
+            OPCODE_TEXT => {
+                if let Err(err) = dispatch_message(app, config, &message) {
+                    let reply = json!({ "error": err.to_string() }).to_string();
+                    send(OPCODE_TEXT, reply.as_bytes())?;
+                }
+            }
+            OPCODE_PING => send(OPCODE_PONG, &message)?,
+            OPCODE_CLOSE => {
+                let _ = send(OPCODE_CLOSE, &message);
+                break;
+            }
+            // Binary messages and unsolicited pongs are ignored
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Write the frames queued for a client until the queue is closed. A failed write
+// shuts the connection down, which ends the client's reads too.
+fn write_frames(mut stream: TcpStream, frames: mpsc::Receiver<Arc<[u8]>>) {
+    for frame in frames {
+        if stream.write_all(&frame).is_err() {
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+}
+
+fn dispatch_message<R: Runtime>(
+    app: &AppHandle<R>,
+    config: &WebSocketConfig,
+    message: &[u8],
+) -> Result<()> {
+    let action: Action = serde_json::from_slice(message)
+        .map_err(|e| crate::RstateError::invalid_payload(e.to_string()))?;
+    if !config.can_dispatch(&action.kind) {
+        return Err(crate::RstateError::state(format!(
+            "dispatching '{}' over WebSocket is not allowed",
+            action.kind
+        )));
+    }
+
+    let rstate = app
+        .try_state::<crate::Rstate<R>>()
+        .ok_or(crate::RstateError::NotRegistered)?;
+    rstate.check_payload_size(&action)?;
+    rstate.dispatch(action.with_source(ActionSource::Remote))?;
+    Ok(())
+}
+
+fn update_message(event: &str, payload: &JsonValue) -> String {
+    json!({ "event": event, "payload": payload }).to_string()
+}
+
+// Read the first message of a client that didn't pass the token in the URL, which
+// must hold it. Returns whether it is the right one.
+fn authenticate(reader: &mut impl Read, config: &WebSocketConfig) -> io::Result<bool> {
+    let Some((OPCODE_TEXT, message)) = read_message(reader)? else {
+        return Ok(false);
+    };
+    let token = serde_json::from_slice::<JsonValue>(&message)
+        .ok()
+        .and_then(|message| Some(message.get("token")?.as_str()?.to_owned()));
+    Ok(token.is_some_and(|token| config.is_token(&token)))
+}
+
+// What the handshake request carried
+#[derive(Debug, Default)]
+struct Request {
+    token: Option<String>,
+}
+
+// Perform the HTTP upgrade handshake (RFC 6455, section 4.2), refusing browser pages
+// from origins that aren't allowed
+fn handshake(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    config: &WebSocketConfig,
+) -> io::Result<Request> {
+    let request_line = read_handshake_line(reader)?;
+    let token = request_line
+        .split(' ')
+        .nth(1)
+        .and_then(|target| target.split_once('?'))
+        .and_then(|(_, query)| {
+            query
+                .split('&')
+                .find_map(|param| param.strip_prefix("token="))
+        })
+        .map(ToOwned::to_owned);
+
+    let mut key = None;
+    let mut origin = None;
+    for _ in 0..MAX_HANDSHAKE_LINES {
+        let line = read_handshake_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_owned());
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    if !config.allows_origin(origin.as_deref()) {
+        writer.write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "origin not allowed",
+        ));
+    }
+    let Some(key) = key else {
+        writer.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a WebSocket request",
+        ));
+    };
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    Ok(Request { token })
+}
+
+// Read a line of the handshake request, without its line ending
+fn read_handshake_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    let read = reader.take(MAX_HANDSHAKE_LINE).read_line(&mut line)?;
+    if read == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "handshake line too long",
+        ));
+    }
+    line.truncate(line.trim_end().len());
+    Ok(line)
+}
+
+fn accept_key(key: &str) -> String {
+    let digest = sha1(format!("{key}{HANDSHAKE_GUID}").as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+// Read one complete (possibly fragmented) message.
+// Returns `None` once the client closed the connection.
+fn read_message(reader: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut message = Vec::new();
+    let mut message_opcode = None;
+
+    loop {
+        let mut header = [0u8; 2];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        let masked = header[1] & 0x80 != 0;
+        let len = match header[1] & 0x7F {
+            126 => {
+                let mut len = [0u8; 2];
+                reader.read_exact(&mut len)?;
+                u64::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0u8; 8];
+                reader.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => u64::from(len),
+        };
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|len| message.len() + len <= MAX_MESSAGE_SIZE)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message too large"))?;
+
+        let mut mask = [0u8; 4];
+        if masked {
+            reader.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload)?;
+        if masked {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+
+        // Control frames may be interleaved with fragments and are never fragmented
+        if opcode >= OPCODE_CLOSE {
+            return Ok(Some((opcode, payload)));
+        }
+        if opcode != OPCODE_CONTINUATION {
+            message_opcode = Some(opcode);
+        }
+        message.extend_from_slice(&payload);
+        if fin {
+            return Ok(message_opcode.map(|opcode| (opcode, message)));
+        }
+    }
+}
+
+// Encode a single unmasked server frame
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= usize::from(u16::MAX) => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// Minimal SHA-1, only used for the handshake accept key
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (chunk, word) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key_matches_rfc_example() {
+        // Example from RFC 6455, section 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_read_masked_fragmented_message() {
+        fn masked_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+            let mask = [1u8, 2, 3, 4];
+            let mut frame = vec![
+                if fin { 0x80 } else { 0 } | opcode,
+                0x80 | payload.len() as u8,
+            ];
+            frame.extend_from_slice(&mask);
+            frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+            frame
+        }
+
+        let mut bytes = masked_frame(false, OPCODE_TEXT, b"{\"kind\":");
+        bytes.extend(masked_frame(true, OPCODE_CONTINUATION, b"\"INCREMENT\"}"));
+        let mut reader = &bytes[..];
+
+        let (opcode, message) = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(opcode, OPCODE_TEXT);
+        assert_eq!(message, b"{\"kind\":\"INCREMENT\"}");
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_handshake_checks_origin_and_reads_token() {
+        let config = WebSocketConfig::default()
+            .token("secret")
+            .allow_origin("https://dashboard.example.com");
+        let request = |origin: &str| {
+            format!(
+                "GET /?v=1&token=secret HTTP/1.1\r\nHost: 127.0.0.1:7878\r\n{origin}\
+                 Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"
+            )
+        };
+
+        let mut response = Vec::new();
+        let allowed = request("Origin: https://dashboard.example.com\r\n");
+        let parsed = handshake(&mut allowed.as_bytes(), &mut response, &config).unwrap();
+        assert_eq!(parsed.token.as_deref(), Some("secret"));
+        assert!(response.starts_with(b"HTTP/1.1 101"));
+
+        let mut response = Vec::new();
+        let foreign = request("Origin: https://evil.example.com\r\n");
+        assert!(handshake(&mut foreign.as_bytes(), &mut response, &config).is_err());
+        assert!(response.starts_with(b"HTTP/1.1 403"));
+
+        // Not a browser
+        let mut response = Vec::new();
+        assert!(handshake(&mut request("").as_bytes(), &mut response, &config).is_ok());
+
+        let long = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(10_000));
+        assert!(handshake(&mut long.as_bytes(), &mut Vec::new(), &config).is_err());
+    }
+
+    #[test]
+    fn test_token_is_required() {
+        let config = WebSocketConfig::default().token("secret");
+        assert!(config.is_token("secret"));
+        assert!(!config.is_token("secrets"));
+        assert!(!config.is_token(""));
+        assert_ne!(
+            WebSocketConfig::default().get_token(),
+            WebSocketConfig::default().get_token()
+        );
+
+        let mut message = encode_frame(OPCODE_TEXT, br#"{"token":"secret"}"#);
+        assert!(authenticate(&mut &message[..], &config).unwrap());
+        message = encode_frame(OPCODE_TEXT, br#"{"kind":"INCREMENT"}"#);
+        assert!(!authenticate(&mut &message[..], &config).unwrap());
+    }
+
+    #[test]
+    fn test_dispatch_permissions() {
+        let config = WebSocketConfig::default();
+        assert!(!config.can_dispatch("INCREMENT"));
+
+        let config = config.allow_dispatch(["INCREMENT"]);
+        assert!(config.can_dispatch("INCREMENT"));
+        assert!(!config.can_dispatch("RESET"));
+
+        let config = config.allow_any_dispatch();
+        assert!(config.can_dispatch("RESET"));
+    }
+
+    #[test]
+    fn test_stalled_clients_are_dropped_without_blocking() {
+        let (frames, _stalled) = mpsc::sync_channel(CLIENT_QUEUE_LENGTH);
+        let (gone, _) = mpsc::sync_channel(CLIENT_QUEUE_LENGTH);
+        let transport = WebSocketTransport {
+            clients: Arc::new(Mutex::new(vec![
+                Client { id: 0, frames },
+                Client {
+                    id: 1,
+                    frames: gone,
+                },
+            ])),
+        };
+
+        transport.send("update", &json!({})).unwrap();
+        let clients = |transport: &WebSocketTransport| {
+            let clients = transport.clients.lock().unwrap();
+            clients.iter().map(|client| client.id).collect::<Vec<_>>()
+        };
+        assert_eq!(clients(&transport), [0]);
+
+        for _ in 0..CLIENT_QUEUE_LENGTH {
+            transport.send("update", &json!({})).unwrap();
+        }
+        assert!(clients(&transport).is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+//! Stores scoped to a single window.
+//!
+//! Document-per-window apps often need isolated state for each window. A window
+//! store is created for a window label with
+//! [`Rstate::create_window_store`](crate::Rstate::create_window_store) and lives
+//! next to the app-wide store. Its updates are emitted under a namespaced event
+//! name (see [`window_event_name`]), and the frontend reaches it by passing
+//! `scope: "window"` to the plugin commands.
+//!
+//! When the window is destroyed, its store is flushed (see
+//! [`RstateManager::flush`]) and dropped automatically.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! let window = tauri::WebviewWindowBuilder::new(app, "doc-1", tauri::WebviewUrl::default())
+//!     .build()?;
+//! app.rstate()
+//!     .create_window_store(window.label(), StateBuilder::new(Document::default()).build())?;
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::models::RstateManager;
+use crate::{ManagedState, Result, RstateError};
+
+/// Event name used for state updates of the store belonging to the window `label`.
+///
+/// These updates are only emitted to the window `label`, never to other windows nor
+/// to the [transports](crate::UpdateTransport).
+pub fn window_event_name(label: &str) -> String {
+    format!("{}/{}", crate::STATE_UPDATE_EVENT, label)
+}
+
+// The label of the window whose store emits `event`, if it's a window store's event
+pub(crate) fn event_window(event: &str) -> Option<&str> {
+    event
+        .strip_prefix(crate::STATE_UPDATE_EVENT)?
+        .strip_prefix('/')
+}
+
+// A window's store along with its revision counter
+pub(crate) struct WindowStore {
+    pub(crate) state: ManagedState,
+    pub(crate) revision: AtomicU64,
+}
+
+#[derive(Default)]
+pub(crate) struct WindowStores {
+    stores: Mutex<HashMap<String, Arc<WindowStore>>>,
+}
+
+impl WindowStores {
+    pub(crate) fn insert(
+        &self,
+        label: String,
+        state_manager: Box<dyn RstateManager>,
+    ) -> Result<()> {
+        let mut stores = self
+            .stores
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?;
+        if stores.contains_key(&label) {
+            return Err(RstateError::state(format!(
+                "window '{label}' already has a store"
+            )));
+        }
+        let store = WindowStore {
+            state: RwLock::new(state_manager),
+            revision: AtomicU64::new(0),
+        };
+        stores.insert(label, Arc::new(store));
+        Ok(())
+    }
+
+    // The store is handed out as an `Arc`, so it can be locked without
+    // holding on to the map (and blocking other windows)
+    pub(crate) fn get(&self, label: &str) -> Result<Arc<WindowStore>> {
+        self.stores
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?
+            .get(label)
+            .cloned()
+            .ok_or_else(|| RstateError::WindowStoreNotFound(label.to_owned()))
+    }
+
+    // Remove the store and flush it before it is dropped.
+    // Returns `false` if the window had no store.
+    pub(crate) fn close(&self, label: &str) -> Result<bool> {
+        let Some(store) = self
+            .stores
+            .lock()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?
+            .remove(label)
+        else {
+            return Ok(false);
+        };
+
+        store
+            .state
+            .write()
+            .map_err(|e| RstateError::LockPoisoned(e.to_string()))?
+            .flush()?;
+        Ok(true)
+    }
+
+    pub(crate) fn contains(&self, label: &str) -> bool {
+        self.stores
+            .lock()
+            .is_ok_and(|stores| stores.contains_key(label))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Action, DispatchOutcome, JsonValue};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct FlushCounter(Arc<AtomicUsize>);
+
+    impl RstateManager for FlushCounter {
+        fn get_initial_state(&self) -> JsonValue {
+            JsonValue::Null
+        }
+
+        fn dispatch(&mut self, _action: &Action) -> Result<DispatchOutcome> {
+            Ok(DispatchOutcome::unchanged(JsonValue::Null))
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_close_flushes_and_drops_store() {
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let stores = WindowStores::default();
+        stores
+            .insert("doc-1".into(), Box::new(FlushCounter(flushes.clone())))
+            .unwrap();
+        assert!(
+            stores
+                .insert("doc-1".into(), Box::new(FlushCounter(flushes.clone())))
+                .is_err()
+        );
+
+        assert!(stores.close("doc-1").unwrap());
+        assert_eq!(flushes.load(Ordering::SeqCst), 1);
+        assert!(!stores.contains("doc-1"));
+        assert!(matches!(
+            stores.get("doc-1"),
+            Err(RstateError::WindowStoreNotFound(_))
+        ));
+
+        // Closing again is a no-op
+        assert!(!stores.close("doc-1").unwrap());
+        assert_eq!(flushes.load(Ordering::SeqCst), 1);
+    }
+}